@@ -0,0 +1,69 @@
+//! Compares logging pre-formatted fields through `raw_table` with logging serialized records
+//!
+//! Run with `cargo bench --bench raw`.
+
+use std::{hint::black_box, num::NonZeroUsize};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use csv_logger::{CsvLoggerBuilder, CsvLoggerHandle, RotationPolicy};
+
+#[derive(serde::Serialize)]
+struct Tick {
+    pub seq: u64,
+    pub price: f64,
+    pub venue: &'static str,
+}
+
+fn handle(dir: &std::path::Path) -> CsvLoggerHandle {
+    let logger = CsvLoggerBuilder::new(
+        dir.to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(1_000_000).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .build();
+    CsvLoggerHandle::new(logger)
+}
+
+fn bench_log(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let logger = handle(dir.path());
+    let mut group = c.benchmark_group("log");
+    group.throughput(Throughput::Elements(1));
+
+    let mut seq = 0;
+    group.bench_function("serde", |b| {
+        b.iter(|| {
+            seq += 1;
+            let tick = Tick {
+                seq,
+                price: seq as f64 / 4.0,
+                venue: "xnys",
+            };
+            logger.log_to("serde", black_box(&tick));
+        })
+    });
+
+    let table = logger.raw_table("raw", &["seq", "price", "venue"]).unwrap();
+    group.bench_function("raw", |b| {
+        b.iter(|| {
+            seq += 1;
+            let price = seq as f64 / 4.0;
+            table.write(black_box(&[&seq, &price, &"xnys"]));
+        })
+    });
+
+    let table = logger
+        .raw_table("raw_str", &["seq", "price", "venue"])
+        .unwrap();
+    group.bench_function("raw_str", |b| {
+        b.iter(|| table.write_str_fields(black_box(&["1", "0.25", "xnys"])))
+    });
+
+    group.finish();
+    logger.flush();
+}
+
+criterion_group!(benches, bench_log);
+criterion_main!(benches);
@@ -0,0 +1,60 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, LitStr};
+
+/// Implements `table_log::LogRecord` for a `serde::Serialize` struct
+///
+/// The table is named by `#[csv(table = "...")]`.
+#[proc_macro_derive(CsvRecord, attributes(csv))]
+pub fn derive_csv_record(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let mut table: Option<LitStr> = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("csv")) {
+        attr.parse_nested_meta(|meta| {
+            if !meta.path.is_ident("table") {
+                return Err(meta.error("expected `table = \"...\"`"));
+            }
+            let name: LitStr = meta.value()?.parse()?;
+            if name.value().is_empty() {
+                return Err(syn::Error::new_spanned(
+                    &name,
+                    "the table name must not be empty",
+                ));
+            }
+            table = Some(name);
+            Ok(())
+        })?;
+    }
+    let Some(table) = table else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "missing `#[csv(table = \"...\")]` naming the table",
+        ));
+    };
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    // Borrowed records are logged for as long as their first lifetime
+    let caller = match input.generics.lifetimes().next() {
+        Some(param) => {
+            let lifetime = &param.lifetime;
+            quote!(#lifetime)
+        }
+        None => quote!('_),
+    };
+    Ok(quote! {
+        impl #impl_generics ::table_log::LogRecord<#caller> for #ident #ty_generics #where_clause {
+            fn table_name(&self) -> &'static str {
+                #table
+            }
+        }
+    })
+}
@@ -0,0 +1,7 @@
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/pass.rs");
+    t.compile_fail("tests/ui/missing_table.rs");
+    t.compile_fail("tests/ui/unknown_key.rs");
+}
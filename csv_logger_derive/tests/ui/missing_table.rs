@@ -0,0 +1,8 @@
+use csv_logger_derive::CsvRecord;
+
+#[derive(serde::Serialize, CsvRecord)]
+struct Request {
+    status: u16,
+}
+
+fn main() {}
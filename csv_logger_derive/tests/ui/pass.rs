@@ -0,0 +1,25 @@
+use csv_logger_derive::CsvRecord;
+use table_log::LogRecord;
+
+#[derive(serde::Serialize, CsvRecord)]
+#[csv(table = "requests")]
+struct Request<'caller> {
+    path: &'caller str,
+    status: u16,
+}
+
+#[derive(serde::Serialize, CsvRecord)]
+#[csv(table = "ticks")]
+struct Tick {
+    n: usize,
+}
+
+fn main() {
+    let path = String::from("/");
+    let request = Request {
+        path: &path,
+        status: 200,
+    };
+    assert_eq!(request.table_name(), "requests");
+    assert_eq!(Tick { n: 0 }.table_name(), "ticks");
+}
@@ -0,0 +1,9 @@
+use csv_logger_derive::CsvRecord;
+
+#[derive(serde::Serialize, CsvRecord)]
+#[csv(name = "requests")]
+struct Request {
+    status: u16,
+}
+
+fn main() {}
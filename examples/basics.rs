@@ -1,14 +1,15 @@
-use std::num::NonZeroUsize;
+use std::{num::NonZeroUsize, time::Duration};
 
-#[derive(serde::Serialize)]
+use csv_logger::CsvRecord;
+
+#[derive(serde::Serialize, CsvRecord)]
+#[csv(table = "test")]
 struct TestRecord<'caller> {
     pub s: &'caller str,
     pub n: usize,
-}
-impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
-    fn table_name(&self) -> &'static str {
-        "test"
-    }
+    /// `Duration` would be a nested struct; `csv_logger::ser` writes it as a single cell
+    #[serde(with = "csv_logger::ser::duration_millis")]
+    pub elapsed: Duration,
 }
 
 fn main() {
@@ -20,7 +21,15 @@ fn main() {
             max_epochs: 2,
         },
     );
-    table_log::log!(&TestRecord { s: "a", n: 0 });
-    table_log::log!(&TestRecord { s: "b", n: 1 });
+    table_log::log!(&TestRecord {
+        s: "a",
+        n: 0,
+        elapsed: Duration::from_millis(12),
+    });
+    table_log::log!(&TestRecord {
+        s: "b",
+        n: 1,
+        elapsed: Duration::from_millis(34),
+    });
     table_log::flush();
 }
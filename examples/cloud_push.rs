@@ -0,0 +1,56 @@
+use std::{num::NonZeroUsize, time::Duration};
+
+use csv_logger::{
+    BatchForwarder, BatchPolicy, BatchSink, BoxError, CsvLoggerBuilder, RotationPolicy,
+};
+
+#[derive(serde::Serialize)]
+struct TestRecord<'caller> {
+    pub s: &'caller str,
+    pub n: usize,
+}
+impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+    fn table_name(&self) -> &'static str {
+        "test"
+    }
+}
+
+/// Stands in for the client of a cloud logging service
+struct StdoutPush;
+impl BatchSink for StdoutPush {
+    fn push(&mut self, table: &str, rows: &[Vec<String>]) -> Result<(), BoxError> {
+        println!("{table}: {} rows", rows.len());
+        for row in rows {
+            println!("  {}", row.join(","));
+        }
+        Ok(())
+    }
+}
+
+fn main() {
+    let dir = tempfile::tempdir().unwrap();
+    let forwarder = BatchForwarder::new(
+        StdoutPush,
+        BatchPolicy {
+            max_rows: NonZeroUsize::new(4).unwrap(),
+            max_age: Duration::from_millis(100),
+            ..Default::default()
+        },
+    );
+    // The local files remain the source of truth
+    CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .batch_forwarder(forwarder)
+    .init()
+    .unwrap();
+    for n in 0..10 {
+        table_log::log!(&TestRecord { s: "a", n });
+    }
+    std::thread::sleep(Duration::from_millis(200));
+    table_log::flush();
+}
@@ -0,0 +1,67 @@
+use std::{
+    io,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use csv_logger::{CsvLoggerBuilder, RotatedFileDisposition, RotatedFileHandler, RotationPolicy};
+
+#[derive(serde::Serialize)]
+struct TestRecord<'caller> {
+    pub s: &'caller str,
+    pub n: usize,
+}
+impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+    fn table_name(&self) -> &'static str {
+        "test"
+    }
+}
+
+/// Stands in for an object storage client such as S3
+struct DirUploader {
+    bucket: PathBuf,
+}
+impl RotatedFileHandler for DirUploader {
+    fn handle(&self, table: &str, epoch: usize, path: &Path) -> io::Result<RotatedFileDisposition> {
+        let key = self.bucket.join(table).join(format!("{epoch}.csv"));
+        std::fs::create_dir_all(key.parent().unwrap())?;
+        std::fs::copy(path, key)?;
+        Ok(RotatedFileDisposition::Delete)
+    }
+}
+
+fn main() {
+    let dir = tempfile::tempdir().unwrap();
+    let bucket = tempfile::tempdir().unwrap();
+    CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(2).unwrap(),
+            max_epochs: 10,
+        },
+    )
+    .rotated_file_handler(DirUploader {
+        bucket: bucket.path().to_owned(),
+    })
+    .init()
+    .unwrap();
+    for n in 0..5 {
+        table_log::log!(&TestRecord { s: "a", n });
+    }
+    table_log::flush();
+
+    let uploaded = [0, 1].map(|epoch| bucket.path().join("test").join(format!("{epoch}.csv")));
+    let local = dir.path().join("test").join("0.csv");
+    for _ in 0..100 {
+        if uploaded.iter().all(|p| p.exists()) && !local.exists() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    for path in &uploaded {
+        assert!(path.exists());
+        println!("{}", std::fs::read_to_string(path).unwrap());
+    }
+    assert!(!local.exists());
+}
@@ -0,0 +1,30 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::{CsvLoggerBuilder, RotationPolicy};
+
+/// Registers the logger and flushes it periodically from a Tokio task instead of an OS thread
+///
+/// Must be called from within a Tokio runtime.
+pub fn init_async(output_dir: PathBuf, rotation: RotationPolicy) -> tokio::task::JoinHandle<()> {
+    CsvLoggerBuilder::new(output_dir, rotation)
+        .init_async()
+        .expect("Failed to initialize the logger")
+}
+
+/// Flushes the registered logger without blocking the async runtime
+pub async fn flush_async() {
+    tokio::task::spawn_blocking(table_log::flush)
+        .await
+        .expect("Failed to flush");
+}
+
+pub(crate) async fn flush_periodically(period: Duration) {
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick completes immediately
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        flush_async().await;
+    }
+}
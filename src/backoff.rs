@@ -0,0 +1,36 @@
+use std::{num::NonZeroUsize, time::Duration};
+
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    pub max_attempts: NonZeroUsize,
+    pub initial: Duration,
+    pub max: Duration,
+}
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            max_attempts: NonZeroUsize::new(5).unwrap(),
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+        }
+    }
+}
+impl Backoff {
+    pub(crate) fn retry<T, E>(&self, mut f: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+        let mut delay = self.initial;
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Ok(v) => return Ok(v),
+                Err(e) => {
+                    if self.max_attempts.get() <= attempt {
+                        return Err(e);
+                    }
+                }
+            }
+            std::thread::sleep(delay);
+            delay = delay.saturating_mul(2).min(self.max);
+            attempt += 1;
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+
+use crate::{
+    backoff::Backoff,
+    clock::Clock,
+    error::{self, CsvLoggerError, ErrorHandler},
+    row::Row,
+    stats,
+};
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Receives rows in batches, e.g. to push them to a cloud logging service
+pub trait BatchSink: Send {
+    fn push(&mut self, table: &str, rows: &[Vec<String>]) -> Result<(), BoxError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchPolicy {
+    /// A batch is pushed once it has this many rows
+    pub max_rows: NonZeroUsize,
+    /// A batch is pushed once its oldest row has waited this long
+    pub max_age: Duration,
+    /// A batch is dropped once all attempts fail
+    pub backoff: Backoff,
+    /// Rows waiting for the background thread; rows beyond that are dropped
+    pub queue_capacity: usize,
+}
+impl Default for BatchPolicy {
+    fn default() -> Self {
+        Self {
+            max_rows: NonZeroUsize::new(500).unwrap(),
+            max_age: Duration::from_secs(5),
+            backoff: Backoff::default(),
+            queue_capacity: 4096,
+        }
+    }
+}
+
+/// Forwards every row written to the local files to a [`BatchSink`] from a background thread
+pub struct BatchForwarder {
+    sink: Box<dyn BatchSink>,
+    policy: BatchPolicy,
+    dropped: Arc<AtomicU64>,
+}
+impl BatchForwarder {
+    pub fn new(sink: impl BatchSink + 'static, policy: BatchPolicy) -> Self {
+        Self {
+            sink: Box::new(sink),
+            policy,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Counts rows dropped because the queue was full or every push attempt failed
+    pub fn dropped_rows(&self) -> Arc<AtomicU64> {
+        self.dropped.clone()
+    }
+
+    pub(crate) fn spawn(self, error_handler: ErrorHandler, clock: Arc<dyn Clock>) -> BatchWorker {
+        let (tx, rx) = crossbeam_channel::bounded(self.policy.queue_capacity);
+        let dropped = self.dropped.clone();
+        let mut batcher = Batcher {
+            sink: self.sink,
+            policy: self.policy,
+            batches: HashMap::new(),
+            dropped: self.dropped,
+            error_handler,
+            clock,
+        };
+        std::thread::Builder::new()
+            .name("CsvLogger::batch()".to_string())
+            .spawn(move || batcher.run(rx))
+            .expect("Failed to spawn the batching worker thread");
+        BatchWorker { tx, dropped }
+    }
+}
+
+enum BatchMessage {
+    Row(Row),
+    Flush,
+}
+
+pub(crate) struct BatchWorker {
+    tx: Sender<BatchMessage>,
+    dropped: Arc<AtomicU64>,
+}
+impl BatchWorker {
+    pub fn send(&self, row: Row) {
+        if self.tx.try_send(BatchMessage::Row(row)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            stats::count_dropped(1);
+        }
+    }
+
+    pub fn flush(&self) {
+        let _ = self.tx.try_send(BatchMessage::Flush);
+    }
+}
+
+struct Batch {
+    rows: Vec<Vec<String>>,
+    since: Instant,
+}
+
+struct Batcher {
+    sink: Box<dyn BatchSink>,
+    policy: BatchPolicy,
+    batches: HashMap<Cow<'static, str>, Batch>,
+    dropped: Arc<AtomicU64>,
+    error_handler: ErrorHandler,
+    clock: Arc<dyn Clock>,
+}
+impl Batcher {
+    fn run(&mut self, rx: Receiver<BatchMessage>) {
+        loop {
+            let deadline = self
+                .batches
+                .values()
+                .map(|batch| batch.since + self.policy.max_age)
+                .min();
+            let msg = match deadline {
+                None => match rx.recv() {
+                    Ok(msg) => Some(msg),
+                    Err(_) => break,
+                },
+                Some(deadline) => match rx
+                    .recv_timeout(deadline.saturating_duration_since(self.clock.instant()))
+                {
+                    Ok(msg) => Some(msg),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+            };
+            match msg {
+                Some(BatchMessage::Row(row)) => {
+                    let now = self.clock.instant();
+                    let batch = self
+                        .batches
+                        .entry(row.table.clone())
+                        .or_insert_with(|| Batch {
+                            rows: vec![],
+                            since: now,
+                        });
+                    batch.rows.push(row.fields);
+                    if self.policy.max_rows.get() <= batch.rows.len() {
+                        self.push(&row.table);
+                    }
+                }
+                Some(BatchMessage::Flush) => self.push_all(),
+                None => {
+                    let now = self.clock.instant();
+                    let expired = self
+                        .batches
+                        .iter()
+                        .filter(|(_, batch)| batch.since + self.policy.max_age <= now)
+                        .map(|(table, _)| table.clone())
+                        .collect::<Vec<_>>();
+                    for table in expired {
+                        self.push(&table);
+                    }
+                }
+            }
+        }
+        self.push_all();
+    }
+
+    fn push_all(&mut self) {
+        let tables = self.batches.keys().cloned().collect::<Vec<_>>();
+        for table in tables {
+            self.push(&table);
+        }
+    }
+
+    fn push(&mut self, table: &str) {
+        let Some(batch) = self.batches.remove(table) else {
+            return;
+        };
+        let sink = &mut self.sink;
+        let res = self.policy.backoff.retry(|| sink.push(table, &batch.rows));
+        if let Err(e) = res {
+            let n = batch.rows.len();
+            self.dropped.fetch_add(n as u64, Ordering::Relaxed);
+            stats::count_dropped(n);
+            error::report_at(
+                &self.error_handler,
+                self.clock.now(),
+                CsvLoggerError::Sink {
+                    source: io::Error::other(e),
+                },
+            );
+        }
+    }
+}
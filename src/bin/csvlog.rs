@@ -0,0 +1,221 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    io::{self, Write},
+    path::PathBuf,
+    process::ExitCode,
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand};
+use csv_logger::{
+    export::merge_table,
+    namespace_dir,
+    reader::{last_n, list_tables, TableReader},
+    verify::check_table,
+};
+
+/// Exit code for verification failures; operational errors exit with 2
+const VERIFY_FAILED: u8 = 1;
+const ERROR: u8 = 2;
+const TAIL_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Inspects the output directory of csv_logger
+#[derive(Debug, Parser)]
+#[command(name = "csvlog")]
+struct Cli {
+    /// The namespace the tables were written under
+    #[arg(long, global = true)]
+    namespace: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Lists the tables
+    Tables { dir: PathBuf },
+    /// Prints the rows of a table with a header
+    Cat {
+        dir: PathBuf,
+        table: String,
+        /// Only the last N rows
+        #[arg(long, value_name = "N")]
+        last: Option<usize>,
+        /// Only the rows of epoch N
+        #[arg(long, value_name = "N")]
+        epoch: Option<usize>,
+    },
+    /// Concatenates the epochs of a table into one CSV file
+    Merge {
+        dir: PathBuf,
+        table: String,
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Prepend an `epoch` column unless the rows carry one
+        #[arg(long)]
+        include_epoch: bool,
+    },
+    /// Checks the epoch files of a table; exits with 1 if any is bad
+    Verify { dir: PathBuf, table: String },
+    /// Prints the last rows of a table
+    Tail {
+        dir: PathBuf,
+        table: String,
+        /// Keep printing rows as they are flushed
+        #[arg(short, long)]
+        follow: bool,
+        #[arg(short = 'n', long, default_value_t = 10)]
+        lines: usize,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli.command, cli.namespace.as_deref()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("csvlog: {e}");
+            ExitCode::from(ERROR)
+        }
+    }
+}
+
+fn run(command: Command, namespace: Option<&str>) -> Result<ExitCode, Box<dyn Error>> {
+    let mut out = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(io::stdout().lock());
+    match command {
+        Command::Tables { dir } => {
+            out.write_record(["table", "current_epoch", "epoch_files", "total_bytes"])?;
+            for table in list_tables(dir, namespace, false)? {
+                out.write_record([
+                    table.name,
+                    table
+                        .current_epoch
+                        .map(|e| e.to_string())
+                        .unwrap_or_default(),
+                    table.epoch_files.to_string(),
+                    table.total_bytes.to_string(),
+                ])?;
+            }
+        }
+        Command::Cat {
+            dir,
+            table,
+            last,
+            epoch,
+        } => {
+            let mut reader = TableReader::open(&dir, namespace, &table)?;
+            if let Some(epoch) = epoch {
+                reader = reader.select_epochs(epoch..=epoch);
+            }
+            if let Some(header) = reader.header()? {
+                out.write_record(&header)?;
+            }
+            match (last, epoch) {
+                (Some(n), None) => {
+                    for record in last_n(&dir, namespace, &table, n)? {
+                        out.write_record(&record)?;
+                    }
+                }
+                (Some(n), Some(_)) => {
+                    let mut records = VecDeque::with_capacity(n);
+                    for record in reader.records() {
+                        records.push_back(record?);
+                        if n < records.len() {
+                            records.pop_front();
+                        }
+                    }
+                    for record in records {
+                        out.write_record(&record)?;
+                    }
+                }
+                (None, _) => {
+                    for record in reader.records() {
+                        out.write_record(&record?)?;
+                    }
+                }
+            }
+        }
+        Command::Merge {
+            dir,
+            table,
+            output,
+            include_epoch,
+        } => {
+            let file = io::BufWriter::new(std::fs::File::create(output)?);
+            let stats = merge_table(namespace_dir(dir, namespace)?, &table, file, include_epoch)?;
+            eprintln!(
+                "{} rows from {} epochs, {} bytes",
+                stats.rows, stats.epochs, stats.bytes
+            );
+        }
+        Command::Verify { dir, table } => {
+            let report = check_table(namespace_dir(dir, namespace)?, &table)?;
+            out.write_record([
+                "epoch",
+                "rows",
+                "header",
+                "bad_rows",
+                "truncated",
+                "checksum",
+            ])?;
+            for epoch in &report.epochs {
+                let header = match (&epoch.header, epoch.header_consistent) {
+                    (None, _) => "missing",
+                    (Some(_), true) => "ok",
+                    (Some(_), false) => "inconsistent",
+                };
+                let bad_rows = epoch
+                    .bad_rows
+                    .iter()
+                    .map(|line| line.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let checksum = match epoch.checksum_ok {
+                    Some(true) => "match",
+                    Some(false) => "mismatch",
+                    None => "none",
+                };
+                out.write_record([
+                    epoch.epoch.to_string().as_str(),
+                    epoch.rows.to_string().as_str(),
+                    header,
+                    bad_rows.as_str(),
+                    epoch.truncated.to_string().as_str(),
+                    checksum,
+                ])?;
+            }
+            out.flush()?;
+            if !report.is_ok() {
+                return Ok(ExitCode::from(VERIFY_FAILED));
+            }
+        }
+        Command::Tail {
+            dir,
+            table,
+            follow,
+            lines,
+        } => {
+            let reader = TableReader::open(&dir, namespace, &table)?;
+            if let Some(header) = reader.header()? {
+                out.write_record(&header)?;
+            }
+            if !follow {
+                for record in last_n(&dir, namespace, &table, lines)? {
+                    out.write_record(&record)?;
+                }
+            } else {
+                let existing = reader.records().count();
+                let skip = existing.saturating_sub(lines);
+                for record in reader.tail(TAIL_POLL_INTERVAL).skip(skip) {
+                    out.write_record(&record?)?;
+                    out.flush()?;
+                }
+            }
+        }
+    }
+    out.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,1230 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::SocketAddr,
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    backoff::Backoff,
+    batch::BatchForwarder,
+    cap::Cap,
+    chain::{FilterChain, LevelFilter, RateLimitFilter, SampleFilter},
+    channel::Backpressure,
+    checksum::ChecksumSidecar,
+    clock::{Clock, SystemClock},
+    config::{Checks, ConfigError, ConfigProblem},
+    context::{self, EPOCH_COLUMN},
+    dedup::REPEAT_COUNT_COLUMN,
+    env::{self, EnvConfig},
+    error::{default_error_handler, CsvLoggerError, ErrorHandler},
+    expand,
+    filter::{RowFilter, RowFilters, TableSet},
+    flush_report::FlushCallback,
+    flusher::{FlusherHandle, FlusherThread, Schedule},
+    free_space::{FreeSpace, FreeSpacePolicy, OsSpace, SpaceProvider},
+    health::Health,
+    layout::{self, Layout},
+    level::Level,
+    metadata::Metadata,
+    network_fs::NetworkFs,
+    nonblocking,
+    observer::{self, Events, LoggerObserver},
+    private_dirs::PrivateFs,
+    redact::{Mask, Redactor},
+    rename::HeaderCase,
+    rotated::{RotatedFileHandler, RotatedFileWorker},
+    sequence::SEQUENCE_COLUMN,
+    ser::BytesEncoding,
+    shared::CsvLoggerHandle,
+    sink::{stream::StreamSink, tcp::TcpConnector, unix::UnixConnector, SinkFormat, SinkLogger},
+    spool::{self, Spool},
+    storage::Backend,
+    table_dir::{namespace_dir, TableFiles},
+    tee::FailoverTee,
+    timestamp::TimestampConfig,
+    watchdog::{Watchdog, WatchdogPolicy},
+    ConflictPolicy, CsvLogger, ResumePolicy, RotationPolicy, SchemaPolicy, FLUSH_INTERVAL,
+    TRUNCATION_MARKER,
+};
+
+#[derive(Debug, Clone, Default)]
+pub enum OutputTarget {
+    /// Rotated CSV files under the output directory
+    #[default]
+    Files,
+    /// Rows streamed as CSV lines prefixed by the table name; rotation is bypassed
+    Tcp(SocketAddr),
+    /// Like [`OutputTarget::Tcp`] but over a Unix domain socket
+    UnixSocket(PathBuf),
+    /// One journal entry per row with a `TABLE` field and one field per column; rotation is ignored
+    #[cfg(feature = "journald")]
+    Journald,
+    /// `table=<name> col1=v1 ...` messages sent to a syslog daemon; rotation is ignored
+    #[cfg(feature = "syslog")]
+    Syslog {
+        facility: crate::sink::syslog::Facility,
+        process_name: String,
+        transport: crate::sink::syslog::SyslogTransport,
+    },
+}
+
+pub struct CsvLoggerBuilder {
+    output_dir: PathBuf,
+    namespace: Option<String>,
+    rotation: RotationPolicy,
+    output_target: OutputTarget,
+    flush_interval: Duration,
+    table_flush_intervals: HashMap<String, Duration>,
+    idle_close: Option<Duration>,
+    max_open_tables: Option<NonZeroUsize>,
+    auto_flush: bool,
+    flush_on_panic: bool,
+    flusher_thread: FlusherThread,
+    sync_durable: bool,
+    durable_rotation: bool,
+    spool_dir: Option<PathBuf>,
+    spool_max_bytes: u64,
+    conflict_policy: Option<ConflictPolicy>,
+    layout: Layout,
+    clock: Option<Arc<dyn Clock>>,
+    expand_path: bool,
+    private_dirs: bool,
+    tighten_existing_dirs: bool,
+    network_fs_mode: bool,
+    free_space: Option<FreeSpacePolicy>,
+    space_provider: Option<Box<dyn SpaceProvider>>,
+    health_interval: Option<Duration>,
+    on_flush: Option<FlushCallback>,
+    observers: Vec<Arc<dyn LoggerObserver>>,
+    watchdog: Option<WatchdogPolicy>,
+    storage: Backend,
+    max_buffered_rows: NonZeroUsize,
+    sink_format: SinkFormat,
+    rotated_file_handler: Option<Arc<dyn RotatedFileHandler>>,
+    rotated_file_backoff: Backoff,
+    checksums: bool,
+    #[cfg(feature = "encryption")]
+    encryption_keys: Option<Arc<dyn crate::KeyProvider>>,
+    #[cfg(feature = "encryption")]
+    rotated_compression: Option<crate::RotatedCompression>,
+    failover_tee: Option<FailoverTee>,
+    batch_forwarder: Option<BatchForwarder>,
+    resume_policy: ResumePolicy,
+    repair_on_resume: bool,
+    timestamp: Option<TimestampConfig>,
+    sequence: bool,
+    hostname: bool,
+    pid: bool,
+    thread_info: bool,
+    epoch_column: bool,
+    metadata_header: bool,
+    app_info: Option<(String, String)>,
+    schema_policy: SchemaPolicy,
+    flatten_nested: bool,
+    max_nesting_depth: usize,
+    column_orders: HashMap<String, Vec<String>>,
+    redactions: HashMap<String, Vec<String>>,
+    redactor: Arc<dyn Redactor>,
+    column_renames: HashMap<String, HashMap<String, String>>,
+    header_case: HeaderCase,
+    max_field_bytes: Option<usize>,
+    truncation_marker: String,
+    single_line_fields: bool,
+    bytes_encoding: Option<BytesEncoding>,
+    sampling: HashMap<String, f64>,
+    sample_seed: Option<u64>,
+    allowed_tables: Option<TableSet>,
+    denied_tables: TableSet,
+    rate_limits: HashMap<String, f64>,
+    filter_chain: Option<FilterChain>,
+    dedup_tables: HashSet<String>,
+    filters: RowFilters,
+    caps: HashMap<String, u64>,
+    error_handler: ErrorHandler,
+}
+impl CsvLoggerBuilder {
+    pub fn new(output_dir: PathBuf, rotation: RotationPolicy) -> Self {
+        Self {
+            output_dir,
+            namespace: None,
+            rotation,
+            output_target: OutputTarget::default(),
+            flush_interval: FLUSH_INTERVAL,
+            table_flush_intervals: HashMap::new(),
+            idle_close: None,
+            max_open_tables: None,
+            auto_flush: true,
+            flush_on_panic: false,
+            flusher_thread: FlusherThread::default(),
+            sync_durable: false,
+            durable_rotation: false,
+            spool_dir: None,
+            spool_max_bytes: spool::MAX_BYTES,
+            conflict_policy: None,
+            layout: Layout::default(),
+            clock: None,
+            expand_path: false,
+            private_dirs: false,
+            tighten_existing_dirs: true,
+            network_fs_mode: false,
+            free_space: None,
+            space_provider: None,
+            health_interval: None,
+            on_flush: None,
+            observers: vec![],
+            watchdog: None,
+            storage: Backend::default(),
+            max_buffered_rows: NonZeroUsize::new(1024).unwrap(),
+            sink_format: SinkFormat::default(),
+            rotated_file_handler: None,
+            rotated_file_backoff: Backoff::default(),
+            checksums: false,
+            #[cfg(feature = "encryption")]
+            encryption_keys: None,
+            #[cfg(feature = "encryption")]
+            rotated_compression: None,
+            failover_tee: None,
+            batch_forwarder: None,
+            resume_policy: ResumePolicy::default(),
+            repair_on_resume: false,
+            timestamp: None,
+            sequence: false,
+            hostname: false,
+            pid: false,
+            thread_info: false,
+            epoch_column: false,
+            metadata_header: false,
+            app_info: None,
+            schema_policy: SchemaPolicy::default(),
+            flatten_nested: false,
+            max_nesting_depth: 3,
+            column_orders: HashMap::new(),
+            redactions: HashMap::new(),
+            redactor: Arc::new(Mask),
+            column_renames: HashMap::new(),
+            header_case: HeaderCase::AsIs,
+            max_field_bytes: None,
+            truncation_marker: TRUNCATION_MARKER.to_string(),
+            single_line_fields: false,
+            bytes_encoding: None,
+            sampling: HashMap::new(),
+            sample_seed: None,
+            allowed_tables: None,
+            denied_tables: TableSet::default(),
+            rate_limits: HashMap::new(),
+            filter_chain: None,
+            dedup_tables: HashSet::new(),
+            filters: RowFilters::default(),
+            caps: HashMap::new(),
+            error_handler: default_error_handler(),
+        }
+    }
+
+    pub fn output_target(mut self, target: OutputTarget) -> Self {
+        self.output_target = target;
+        self
+    }
+
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// Flushes `table` every `interval` instead of every `flush_interval`
+    ///
+    /// Only applies to [`OutputTarget::Files`] with `auto_flush` on.
+    pub fn table_flush_interval(mut self, table: &str, interval: Duration) -> Self {
+        self.table_flush_intervals
+            .insert(table.to_string(), interval);
+        self
+    }
+
+    /// Closes the file of a table that nothing was written to for `idle_close`, checked on every
+    /// flush
+    ///
+    /// The next record reopens the table and appends to the same epoch.
+    pub fn idle_close(mut self, idle_close: Option<Duration>) -> Self {
+        self.idle_close = idle_close;
+        self
+    }
+
+    /// Keeps at most `max_open_tables` files open, closing the least recently written table to
+    /// open another
+    ///
+    /// Bounds the memory of loggers writing to many tables named at runtime. The next record
+    /// reopens a closed table and appends to the same epoch, unless more than `max_open_tables`
+    /// tables were closed since: the least recently written closed tables are forgotten, their
+    /// epochs closed, and their next records open them afresh per the [`ResumePolicy`].
+    pub fn max_open_tables(mut self, max_open_tables: Option<NonZeroUsize>) -> Self {
+        self.max_open_tables = max_open_tables;
+        self
+    }
+
+    /// Whether to spawn a thread flushing every `flush_interval`
+    ///
+    /// When off, rows only become durable on explicit [`table_log::flush`] calls.
+    pub fn auto_flush(mut self, auto_flush: bool) -> Self {
+        self.auto_flush = auto_flush;
+        self
+    }
+
+    /// Whether registering the logger installs a panic hook flushing its tables when any thread
+    /// panics, so the last rows survive a panic that aborts the process
+    ///
+    /// The hook also runs for panics that are caught, spends at most 2 seconds, skips the logger
+    /// if another thread holds it, e.g. the panicking one, and then calls the hook installed
+    /// before it. It is installed once per process and flushes whichever logger is registered.
+    /// Off by default.
+    pub fn flush_on_panic(mut self, flush_on_panic: bool) -> Self {
+        self.flush_on_panic = flush_on_panic;
+        self
+    }
+
+    /// Names the flushing thread, `CsvLogger::flush()` by default
+    pub fn flusher_thread_name(mut self, name: impl Into<String>) -> Self {
+        self.flusher_thread.name = name.into();
+        self
+    }
+
+    /// Lowers the flushing thread's priority by setting its nice value
+    ///
+    /// Only takes effect on Linux. The thread keeps running at the default priority on failure,
+    /// which is reported to the error handler.
+    #[cfg(feature = "thread-priority")]
+    pub fn flusher_nice(mut self, nice: i32) -> Self {
+        self.flusher_thread.nice = Some(nice);
+        self
+    }
+
+    /// Writes the tables under `<output_dir>/<namespace>` instead, e.g. for one of several tenants
+    /// sharing the output directory, each with its own [`crate::CsvLoggerHandle`]
+    ///
+    /// Everything the logger keeps per output directory moves along. Readers take the namespace to
+    /// find the tables; see [`crate::namespace_dir`].
+    pub fn namespace(mut self, namespace: Option<String>) -> Self {
+        self.namespace = namespace;
+        self
+    }
+
+    /// Resolves conflicts over the lock of a table with other loggers per `policy`
+    ///
+    /// The locks are advisory and held while the table is open. Without a policy a table's lock is
+    /// taken if it is free and the table is logged to regardless.
+    pub fn conflict_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.conflict_policy = Some(policy);
+        self
+    }
+
+    /// Lays the files of tables out per `layout`, [`Layout::PerTableDir`] by default
+    ///
+    /// Readers in other processes tell a flat or date-bucketed output directory by the `layout`
+    /// file the logger writes into it.
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Tells the time to the logger and its flushing thread instead of the system clock
+    ///
+    /// Timestamps, flush intervals, heartbeats, free space checks, the watchdog, rate limits,
+    /// forwarder retries, recorded errors and the date buckets of [`Layout::DateBuckets`] go by it. See [`crate::ManualClock`] for tests.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        let clock: Arc<dyn Clock> = Arc::new(clock);
+        self.flusher_thread.clock = clock.clone();
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Expands a leading `~` and `$VAR` or `${VAR}` in the output directory when the logger is
+    /// registered, failing if a variable is not set
+    ///
+    /// Off by default, taking the directory literally.
+    pub fn expand_path(mut self, expand: bool) -> Self {
+        self.expand_path = expand;
+        self
+    }
+
+    /// On Unix, creates the output directory and the directories in it `0700` and log files
+    /// `0600`, so other users cannot read the logs
+    ///
+    /// The spool directory and its files get the same modes. Other files, e.g. of sequences, are
+    /// only kept private by their directories. Ignored for in-memory storage.
+    pub fn private_dirs(mut self, private: bool) -> Self {
+        self.private_dirs = private;
+        self
+    }
+
+    /// Whether [`Self::private_dirs`] also restricts directories already there, unless they
+    /// belong to another user; on by default
+    pub fn tighten_existing_dirs(mut self, tighten: bool) -> Self {
+        self.tighten_existing_dirs = tighten;
+        self
+    }
+
+    /// For output directories on CIFS or NFS mounts, replaces files by renaming a temporary file
+    /// over them and deletes them by moving them into `.trash` in the output directory
+    ///
+    /// Flushes delete what is in the trash, retrying files other hosts still have open. Once the
+    /// trash is full, files are removed in place again. Off by default.
+    pub fn network_fs_mode(mut self, network: bool) -> Self {
+        self.network_fs_mode = network;
+        self
+    }
+
+    /// Checks the free space of the output directory's filesystem when flushing and makes room
+    /// per `policy`
+    ///
+    /// Emits [`crate::LoggerEvent::LowFreeSpace`] while space is short.
+    pub fn free_space(mut self, policy: FreeSpacePolicy) -> Self {
+        self.free_space = Some(policy);
+        self
+    }
+
+    /// Where [`Self::free_space`] learns the free space from, [`OsSpace`] by default
+    pub fn space_provider(mut self, provider: impl SpaceProvider + 'static) -> Self {
+        self.space_provider = Some(Box::new(provider));
+        self
+    }
+
+    /// Logs a heartbeat row to [`crate::HEALTH_TABLE`] on the first flush after every `interval`
+    ///
+    /// Heartbeats hold the number of tables open as well as the records written, bytes written
+    /// and records dropped across loggers and the last error reported since the previous
+    /// heartbeat. `None` turns them off, which is the default.
+    pub fn health_interval(mut self, interval: Option<Duration>) -> Self {
+        self.health_interval = interval;
+        self
+    }
+
+    /// Calls `callback` after every flush with what each table flushed
+    ///
+    /// Tables flushed on their own interval are reported together at each tick. Panics in
+    /// `callback` are caught.
+    pub fn on_flush(mut self, callback: FlushCallback) -> Self {
+        self.on_flush = Some(callback);
+        self
+    }
+
+    /// Notifies `observer` of table creations, rotations, flushes, epoch deletions and errors
+    ///
+    /// Observers are called in the order added. Loggers shared through a handle notify them once
+    /// unlocked; errors are notified from whichever thread reports them.
+    pub fn observer(mut self, observer: impl LoggerObserver + 'static) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Reports [`CsvLoggerError::FlushStalled`] when the flushing thread spawned by
+    /// [`CsvLoggerBuilder::init`] misses `missed_intervals` flush intervals in a row
+    ///
+    /// Checked every 64 records logged and reported at most once per threshold. A respawned
+    /// thread is not reachable through the [`FlusherHandle`] returned by `init`. Off by default.
+    pub fn flush_watchdog(mut self, policy: Option<WatchdogPolicy>) -> Self {
+        self.watchdog = policy;
+        self
+    }
+
+    /// Keeps the files of the logger in memory, e.g. for tests to make exact assertions on
+    ///
+    /// The spool goes to the same storage. Epochs resumed from it are not repaired.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn storage(mut self, storage: crate::storage::MemStorage) -> Self {
+        self.storage = Backend::Memory(storage);
+        self
+    }
+
+    /// Whether [`crate::log_durable`] also syncs the table's file to disk
+    pub fn sync_durable(mut self, sync_durable: bool) -> Self {
+        self.sync_durable = sync_durable;
+        self
+    }
+
+    /// Whether rotating syncs the outgoing log file and its directory to disk, so a rotated
+    /// epoch survives a crash whole
+    ///
+    /// The directory is synced again once the next epoch's file is created. Off by default.
+    pub fn durable_rotation(mut self, durable_rotation: bool) -> Self {
+        self.durable_rotation = durable_rotation;
+        self
+    }
+
+    /// Where rows go while writing a table's log file fails, e.g. while a network mount is down;
+    /// `None`, the default, lets such rows fail the logger
+    ///
+    /// Each table spools to `<dir>/<table>.csv`, so loggers of different output directories need
+    /// different spool directories. Flushing retries the log file, so the flushing thread retries
+    /// it in the background, and replays the spooled rows into the current epoch in order once it
+    /// takes them again. Spool files left by an earlier run are replayed the same way, and a
+    /// dropped logger spools the rows still held back from its log files. Rows of a table whose
+    /// log file cannot be created are reported and dropped until it can.
+    pub fn spool_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.spool_dir = dir;
+        self
+    }
+
+    /// Size of a table's spool file beyond which its oldest rows are dropped; 64 MiB by default
+    pub fn spool_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.spool_max_bytes = max_bytes;
+        self
+    }
+
+    /// Rows kept while a remote target is unreachable; the oldest rows are dropped beyond that
+    pub fn max_buffered_rows(mut self, rows: NonZeroUsize) -> Self {
+        self.max_buffered_rows = rows;
+        self
+    }
+
+    pub fn sink_format(mut self, format: SinkFormat) -> Self {
+        self.sink_format = format;
+        self
+    }
+
+    pub fn rotated_file_handler(mut self, handler: impl RotatedFileHandler + 'static) -> Self {
+        self.rotated_file_handler = Some(Arc::new(handler));
+        self
+    }
+
+    pub fn rotated_file_backoff(mut self, backoff: Backoff) -> Self {
+        self.rotated_file_backoff = backoff;
+        self
+    }
+
+    /// Writes a SHA-256 sidecar `<epoch>.csv.sha256` for each rotated epoch file
+    ///
+    /// Hashing runs before the rotated file handler sees the file.
+    pub fn checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    /// Encrypts each rotated epoch file into `<epoch>.csv.enc` with the current key of `keys`,
+    /// deleting the plaintext; the active epoch stays plaintext
+    ///
+    /// Encryption runs after the rotated file handler, unless that deletes the file. Readers in
+    /// this process decrypt with `keys`, others need [`crate::set_decryption_keys`].
+    #[cfg(feature = "encryption")]
+    pub fn encrypt_rotated(mut self, keys: impl crate::KeyProvider + 'static) -> Self {
+        self.encryption_keys = Some(Arc::new(keys));
+        self
+    }
+
+    /// Compresses each rotated epoch file before encrypting it, e.g. into `<epoch>.csv.gz.enc`
+    ///
+    /// Only takes effect with [`Self::encrypt_rotated`].
+    #[cfg(feature = "encryption")]
+    pub fn compress_before_encryption(mut self, compression: crate::RotatedCompression) -> Self {
+        self.rotated_compression = Some(compression);
+        self
+    }
+
+    /// Also forwards every row to a remote sink without risking the local files
+    pub fn failover_tee(mut self, tee: FailoverTee) -> Self {
+        self.failover_tee = Some(tee);
+        self
+    }
+
+    /// Also pushes every row in batches to a [`crate::BatchSink`]
+    pub fn batch_forwarder(mut self, forwarder: BatchForwarder) -> Self {
+        self.batch_forwarder = Some(forwarder);
+        self
+    }
+
+    pub fn resume_policy(mut self, policy: ResumePolicy) -> Self {
+        self.resume_policy = policy;
+        self
+    }
+
+    /// Cuts a partial final row off the resumed epoch under [`ResumePolicy::AppendToLast`]
+    pub fn repair_on_resume(mut self, repair: bool) -> Self {
+        self.repair_on_resume = repair;
+        self
+    }
+
+    /// Adds a timestamp column before the fields of every record
+    pub fn timestamp(mut self, config: TimestampConfig) -> Self {
+        self.timestamp = Some(config);
+        self
+    }
+
+    /// Adds a `seq` column numbering the rows of each table across epochs and restarts
+    ///
+    /// The number goes first, before any timestamp. It never goes backwards but an unclean shutdown
+    /// may leave a gap.
+    pub fn sequence(mut self, sequence: bool) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Appends a `hostname` column to every row, before the columns of [`crate::set_context`]
+    pub fn with_hostname(mut self) -> Self {
+        self.hostname = true;
+        self
+    }
+
+    /// Appends a `pid` column to every row, before the columns of [`crate::set_context`]
+    pub fn with_pid(mut self) -> Self {
+        self.pid = true;
+        self
+    }
+
+    /// Appends `thread_name` and `thread_id` columns of the logging thread to every row, after the
+    /// columns of [`crate::set_context`]
+    ///
+    /// Unnamed threads leave `thread_name` empty. Thread ids are numbered from 1 in the order
+    /// threads first log with thread info, and are never reused within the process.
+    pub fn with_thread_info(mut self) -> Self {
+        self.thread_info = true;
+        self
+    }
+
+    /// Appends an `epoch` column to every row with the epoch of the file the row is written to
+    ///
+    /// Tells where rows came from once epochs are merged; [`crate::export::merge_table`] then does
+    /// not add its own. The column comes last but for `repeat_count` and cannot be renamed.
+    pub fn include_epoch_column(mut self, include: bool) -> Self {
+        self.epoch_column = include;
+        self
+    }
+
+    /// Starts each epoch file with `# key: value` lines describing it, above the header
+    ///
+    /// The lines give the crate version as `csv_logger`, the application per [`Self::app_info`], the host, when
+    /// the process built its first logger, the rotation policy and the epoch. Readers of this crate
+    /// skip them; see [`crate::reader::EpochInfo::metadata`]. Off by default for strict CSV
+    /// consumers.
+    pub fn metadata_header(mut self, metadata_header: bool) -> Self {
+        self.metadata_header = metadata_header;
+        self
+    }
+
+    /// The application named in the lines of [`Self::metadata_header`]
+    pub fn app_info(mut self, name: impl Into<String>, version: impl Into<String>) -> Self {
+        self.app_info = Some((name.into(), version.into()));
+        self
+    }
+
+    pub fn schema_policy(mut self, policy: SchemaPolicy) -> Self {
+        self.schema_policy = policy;
+        self
+    }
+
+    /// Flattens nested structs and maps into `outer.inner` columns instead of failing on them
+    ///
+    /// Sequences are still an error.
+    pub fn flatten_nested(mut self, flatten: bool) -> Self {
+        self.flatten_nested = flatten;
+        self
+    }
+
+    /// Levels of nesting [`CsvLoggerBuilder::flatten_nested`] goes into; 3 by default
+    pub fn max_nesting_depth(mut self, depth: usize) -> Self {
+        self.max_nesting_depth = depth;
+        self
+    }
+
+    /// Moves the listed columns of `table` to the front in the given order
+    ///
+    /// Columns not listed keep their relative order after them. Applies to the timestamp, `seq`
+    /// and context columns too.
+    pub fn column_order(mut self, table: &str, order: Vec<String>) -> Self {
+        self.column_orders.insert(table.to_string(), order);
+        self
+    }
+
+    /// Writes the listed columns of `table` under new names, mapping field names to column names
+    ///
+    /// Only the header changes; other settings still name columns by their field names. Takes
+    /// precedence over [`Self::header_case`]. `seq` and `epoch` cannot be renamed. See
+    /// [`crate::reader::TableReader::original_names`] to deserialize the renamed columns.
+    pub fn column_renames(mut self, table: &str, renames: HashMap<String, String>) -> Self {
+        self.column_renames.insert(table.to_string(), renames);
+        self
+    }
+
+    /// Cases the columns of every header, timestamp and context columns included; as is by default
+    pub fn header_case(mut self, case: HeaderCase) -> Self {
+        self.header_case = case;
+        self
+    }
+
+    /// Passes the values of the listed columns of `table` through the redactor
+    ///
+    /// Redacted values are what every output sees, tees and batch sinks included.
+    pub fn redact(mut self, table: &str, columns: Vec<String>) -> Self {
+        self.redactions.insert(table.to_string(), columns);
+        self
+    }
+
+    /// [`Mask`] by default
+    pub fn redactor(mut self, redactor: impl Redactor + 'static) -> Self {
+        self.redactor = Arc::new(redactor);
+        self
+    }
+
+    /// Truncates longer fields to at most `bytes` followed by the truncation marker
+    pub fn max_field_bytes(mut self, bytes: usize) -> Self {
+        self.max_field_bytes = Some(bytes);
+        self
+    }
+
+    /// Appended to truncated fields with `{n}` replaced by the number of bytes cut;
+    /// `…[truncated {n} bytes]` by default
+    pub fn truncation_marker(mut self, marker: impl Into<String>) -> Self {
+        self.truncation_marker = marker.into();
+        self
+    }
+
+    /// Escapes newlines in fields as `\n` and `\r` so that every row is one line
+    ///
+    /// Backslashes are escaped as `\\`. [`crate::reader`] reverses the escaping.
+    pub fn single_line_fields(mut self, single_line: bool) -> Self {
+        self.single_line_fields = single_line;
+        self
+    }
+
+    /// Encodes every byte string a record serializes, such as `serde_bytes` fields
+    ///
+    /// Without it, byte strings are written as UTF-8. For individual fields see
+    /// [`crate::ser::bytes_hex`] and [`crate::ser::bytes_base64`].
+    pub fn bytes_encoding(mut self, encoding: BytesEncoding) -> Self {
+        self.bytes_encoding = Some(encoding);
+        self
+    }
+
+    /// Keeps each record of `table` with probability `rate` and drops the rest
+    pub fn table_sampling(mut self, table: &str, rate: f64) -> Self {
+        self.sampling.insert(table.to_string(), rate);
+        self
+    }
+
+    /// Makes sampling decisions reproducible
+    pub fn sample_seed(mut self, seed: u64) -> Self {
+        self.sample_seed = Some(seed);
+        self
+    }
+
+    /// Only logs to these tables; a pattern ending in `*` matches table names by prefix
+    ///
+    /// Records of other tables are dropped before any file is created.
+    pub fn allowed_tables(mut self, tables: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.allowed_tables = Some(TableSet::new(tables));
+        self
+    }
+
+    /// Drops records of these tables, even allowed ones; patterns as in
+    /// [`CsvLoggerBuilder::allowed_tables`]
+    pub fn denied_tables(mut self, tables: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.denied_tables = TableSet::new(tables);
+        self
+    }
+
+    /// Drops records of `table` beyond `max_records_per_sec`, allowing bursts of one second
+    ///
+    /// The number of dropped records is reported as [`CsvLoggerError::RateLimited`] once logging
+    /// resumes or on flush.
+    pub fn table_rate_limit(mut self, table: &str, max_records_per_sec: f64) -> Self {
+        self.rate_limits
+            .insert(table.to_string(), max_records_per_sec);
+        self
+    }
+
+    /// Runs `chain` on every record after the filters of the builder
+    ///
+    /// The logger's chain is a [`LevelFilter`], then a [`RateLimitFilter`] and a [`SampleFilter`]
+    /// for the rate limits and sampling set on the builder or the environment, then `chain`.
+    pub fn filter_chain(mut self, chain: FilterChain) -> Self {
+        self.filter_chain = Some(chain);
+        self
+    }
+
+    /// Collapses runs of identical records of `table` into one row with a `repeat_count` column
+    ///
+    /// The row of a run is written once a different record comes or on flush, so a run spanning a
+    /// flush is split into two rows. Timestamp, sequence and context columns are not compared.
+    pub fn dedup_consecutive(mut self, table: &str, dedup: bool) -> Self {
+        match dedup {
+            true => self.dedup_tables.insert(table.to_string()),
+            false => self.dedup_tables.remove(table),
+        };
+        self
+    }
+
+    /// Drops rows of `table`, or of every table if `None`, for which `filter` returns `false`
+    ///
+    /// The filter sees the row as serialized from the record, before any column is added. Rows
+    /// must pass every filter. See [`crate::set_filter`] to replace filters at runtime.
+    pub fn filter(mut self, table: Option<&str>, filter: RowFilter) -> Self {
+        self.filters.push(table, filter);
+        self
+    }
+
+    /// Writes at most `max_total_records` rows to `table` over its lifetime, then one row saying
+    /// so and nothing more
+    ///
+    /// The count survives rotation and restarts; see [`crate::reset_table_cap`] to start over.
+    pub fn table_cap(mut self, table: &str, max_total_records: u64) -> Self {
+        self.caps.insert(table.to_string(), max_total_records);
+        self
+    }
+
+    pub fn error_handler(
+        mut self,
+        handler: impl Fn(&CsvLoggerError) + Send + Sync + 'static,
+    ) -> Self {
+        self.error_handler = Arc::new(handler);
+        self
+    }
+
+    /// Builds the logger without registering it
+    ///
+    /// Panics where [`Self::try_build`] fails.
+    pub fn build(self) -> CsvLogger {
+        self.try_build().expect("Failed to build the logger")
+    }
+
+    /// Builds the logger without registering it, failing like the `init` methods on invalid
+    /// settings, see [`Self::validate`], and if the layout of the output directory cannot be
+    /// recorded
+    pub fn try_build(self) -> io::Result<CsvLogger> {
+        self.validated()?;
+        self.build_validated()
+    }
+
+    fn build_validated(mut self) -> io::Result<CsvLogger> {
+        if let Some(namespace) = self.namespace.take() {
+            self.output_dir = namespace_dir(&self.output_dir, Some(&namespace))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid namespace"))?;
+        }
+        let clock = self.clock.clone().unwrap_or_else(|| Arc::new(SystemClock));
+        let health = self.health_interval.map(|interval| {
+            let (health, error_handler) =
+                Health::new(interval, clock.instant(), self.error_handler.clone());
+            self.error_handler = error_handler;
+            health
+        });
+        if !self.observers.is_empty() {
+            self.error_handler =
+                observer::observe_errors(self.observers.clone(), self.error_handler);
+        }
+        let error_handler = &self.error_handler;
+        let mut rotated_file_handlers: Vec<Arc<dyn RotatedFileHandler>> = vec![];
+        if self.checksums {
+            rotated_file_handlers.push(Arc::new(ChecksumSidecar::new(self.storage.clone())));
+        }
+        rotated_file_handlers.extend(self.rotated_file_handler);
+        #[cfg(feature = "encryption")]
+        if let Some(keys) = self.encryption_keys {
+            crate::encryption::set_keys(&self.output_dir, keys.clone());
+            rotated_file_handlers.push(Arc::new(crate::encryption::Encryptor::new(
+                keys,
+                self.rotated_compression,
+                self.storage.clone(),
+                self.checksums,
+            )));
+        }
+        let rotated_files = (!rotated_file_handlers.is_empty()).then(|| {
+            RotatedFileWorker::spawn(
+                rotated_file_handlers,
+                self.rotated_file_backoff,
+                error_handler.clone(),
+                self.storage.clone(),
+            )
+        });
+        let tee = self
+            .failover_tee
+            .map(|tee| tee.spawn(error_handler.clone(), clock.clone()));
+        let batch = self
+            .batch_forwarder
+            .map(|forwarder| forwarder.spawn(error_handler.clone(), clock.clone()));
+        layout::mark(&self.storage, &self.output_dir, self.layout)?;
+        let mut logger = CsvLogger::new(self.output_dir, self.rotation);
+        logger.files = TableFiles::new(self.layout, clock.clone());
+        logger.rotated_files = rotated_files;
+        logger.tee = tee;
+        logger.batch = batch;
+        logger.resume = self.resume_policy;
+        logger.repair_on_resume = self.repair_on_resume;
+        logger.timestamp = self.timestamp;
+        logger.sequence = self.sequence;
+        logger.schema_policy = self.schema_policy;
+        logger.flatten_depth = self.flatten_nested.then_some(self.max_nesting_depth);
+        logger.column_orders = self.column_orders;
+        logger.redactions = self.redactions;
+        logger.redactor = self.redactor;
+        logger.column_renames = self.column_renames;
+        logger.header_case = self.header_case;
+        logger.max_field_bytes = self.max_field_bytes;
+        logger.truncation_marker = self.truncation_marker;
+        logger.single_line_fields = self.single_line_fields;
+        logger.bytes_encoding = self.bytes_encoding;
+        logger.allowed_tables = self.allowed_tables;
+        logger.denied_tables = self.denied_tables;
+        let mut chain = FilterChain::new().push(LevelFilter);
+        if !self.rate_limits.is_empty() {
+            chain = chain.push(RateLimitFilter::new(self.rate_limits).with_clock(clock.clone()));
+        }
+        if !self.sampling.is_empty() {
+            chain = chain.push(SampleFilter::new(self.sampling, self.sample_seed));
+        }
+        logger.filter_chain = chain.extend(self.filter_chain.unwrap_or_default());
+        logger.dedup_tables = self.dedup_tables;
+        logger.filters = self.filters;
+        logger.flush_interval = self.flush_interval;
+        logger.flushed_at = clock.instant();
+        logger.clock = clock;
+        logger.sync_durable = self.sync_durable;
+        logger.durable_rotation = self.durable_rotation;
+        logger.spool = self.spool_dir.map(|dir| {
+            let storage = match (&self.storage, self.private_dirs) {
+                #[cfg(any(test, feature = "test-util"))]
+                (Backend::Memory(storage), _) => Backend::Memory(storage.clone()),
+                (_, true) => {
+                    Backend::Private(PrivateFs::new(dir.clone(), self.tighten_existing_dirs))
+                }
+                (_, false) => Backend::Real,
+            };
+            Spool::new(dir, self.spool_max_bytes, storage)
+        });
+        logger.conflict_policy = self.conflict_policy;
+        logger.free_space = self.free_space.map(|policy| {
+            let provider = self.space_provider.unwrap_or_else(|| Box::new(OsSpace));
+            FreeSpace::new(policy, provider)
+        });
+        let storage = match self.storage {
+            Backend::Real if self.private_dirs => Backend::Private(PrivateFs::new(
+                logger.output_dir.clone(),
+                self.tighten_existing_dirs,
+            )),
+            storage => storage,
+        };
+        logger.storage = match self.network_fs_mode {
+            true => Backend::Network(NetworkFs::new(storage, &logger.output_dir)),
+            false => storage,
+        };
+        logger.health = health;
+        logger.on_flush = self.on_flush;
+        logger.events = Events::new(self.observers);
+        logger.watchdog = self
+            .watchdog
+            .filter(|_| self.auto_flush)
+            .map(|policy| Watchdog::new(policy, self.flush_interval));
+        logger.flush_intervals = self.table_flush_intervals;
+        logger.idle_close = self.idle_close;
+        logger.max_open_tables = self.max_open_tables;
+        logger.caps = self
+            .caps
+            .into_iter()
+            .map(|(table, max)| (table, Cap::new(max)))
+            .collect();
+        if self.hostname {
+            let hostname = gethostname::gethostname().to_string_lossy().into_owned();
+            logger.context.push(("hostname", hostname));
+        }
+        if self.pid {
+            logger.context.push(("pid", std::process::id().to_string()));
+        }
+        logger.thread_info = self.thread_info;
+        logger.epoch_column = self.epoch_column;
+        if self.metadata_header {
+            logger.metadata = Some(Metadata::new(self.app_info, &logger.rotation));
+        }
+        logger.error_handler = self.error_handler;
+        Ok(logger)
+    }
+
+    fn build_logger(self) -> io::Result<Box<dyn table_log::Logger>> {
+        let max_pending = self.max_buffered_rows.get();
+        Ok(match self.output_target.clone() {
+            OutputTarget::Files => {
+                let handle = CsvLoggerHandle::new(self.build_validated()?);
+                handle.register();
+                Box::new(handle)
+            }
+            OutputTarget::Tcp(addr) => SinkLogger::new(
+                StreamSink::new(TcpConnector::new(addr), self.sink_format, max_pending),
+                self.error_handler,
+            )
+            .register(),
+            OutputTarget::UnixSocket(path) => SinkLogger::new(
+                StreamSink::new(UnixConnector::new(path)?, self.sink_format, max_pending),
+                self.error_handler,
+            )
+            .register(),
+            #[cfg(feature = "journald")]
+            OutputTarget::Journald => {
+                use crate::sink::journald::{JournaldSink, JOURNALD_SOCKET};
+
+                let sink = JournaldSink::new(JOURNALD_SOCKET)?;
+                crate::error::report(
+                    &self.error_handler,
+                    CsvLoggerError::IgnoredOption { option: "rotation" },
+                );
+                SinkLogger::new(sink, self.error_handler).register()
+            }
+            #[cfg(feature = "syslog")]
+            OutputTarget::Syslog {
+                facility,
+                process_name,
+                transport,
+            } => {
+                let sink =
+                    crate::sink::syslog::SyslogSink::new(transport, facility, &process_name)?;
+                SinkLogger::new(sink, self.error_handler).register()
+            }
+        })
+    }
+
+    /// Fills in settings left alone from the environment, returning the minimum level it sets;
+    /// see [`crate::env`]
+    fn apply_env(&mut self, env: EnvConfig) -> Option<Level> {
+        if env.off {
+            self.allowed_tables = Some(TableSet::default());
+        } else if let (None, Some(tables)) = (&self.allowed_tables, env.tables) {
+            self.allowed_tables = Some(TableSet::new(tables));
+        }
+        for (table, rate) in env.sampling {
+            self.sampling.entry(table).or_insert(rate);
+        }
+        env.level
+    }
+
+    /// Applies the environment and expands the output directory before validating the settings,
+    /// returning the minimum level to set once the logger is registered
+    fn prepare(&mut self) -> io::Result<Option<Level>> {
+        let level = match env::from_env()? {
+            Some(env) => self.apply_env(env),
+            None => None,
+        };
+        self.expand_output_dir()?;
+        self.validated()?;
+        Ok(level)
+    }
+
+    /// Resolves the output directory if [`Self::expand_path`]
+    fn expand_output_dir(&mut self) -> io::Result<()> {
+        if !self.expand_path {
+            return Ok(());
+        }
+        self.output_dir = expand::expand(&self.output_dir)?;
+        self.expand_path = false;
+        Ok(())
+    }
+
+    /// Checks the settings made on the builder, listing every problem
+    ///
+    /// Run by [`Self::try_build`] and, once the `CSV_LOGGER` environment variable is applied, by
+    /// the `init` methods before anything is registered, failing with
+    /// [`io::ErrorKind::InvalidInput`] wrapping the [`ConfigError`].
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut checks = Checks::default();
+        if self.auto_flush {
+            checks.flush_interval(self.flush_interval);
+            for (table, &interval) in &self.table_flush_intervals {
+                checks.table_flush_interval(table, interval);
+            }
+        }
+        for (table, &rate) in &self.sampling {
+            checks.sample_rate(table, rate);
+        }
+        for (table, &max_records_per_sec) in &self.rate_limits {
+            checks.rate_limit(table, max_records_per_sec);
+        }
+        // Compared with the output directory as it is once expanded
+        let output_dir = match self.expand_path {
+            true => expand::expand(&self.output_dir).unwrap_or_else(|_| self.output_dir.clone()),
+            false => self.output_dir.clone(),
+        };
+        if let Some(dir) = self.spool_dir.as_ref().filter(|dir| **dir == output_dir) {
+            checks.push(ConfigProblem::SpoolDirIsOutputDir { dir: dir.clone() });
+        }
+        if self.spool_dir.is_some() && self.spool_max_bytes == 0 {
+            checks.push(ConfigProblem::ZeroSpoolMaxBytes);
+        }
+        if self.flatten_nested && self.max_nesting_depth == 0 {
+            checks.push(ConfigProblem::ZeroMaxNestingDepth);
+        }
+        for (table, &max_total_records) in &self.caps {
+            if max_total_records == 0 {
+                checks.push(ConfigProblem::ZeroTableCap {
+                    table: table.clone(),
+                });
+            }
+        }
+        if let Some(policy) = &self.free_space {
+            if policy.critical_free_bytes > policy.min_free_bytes {
+                checks.push(ConfigProblem::FreeSpaceThresholds {
+                    min_free_bytes: policy.min_free_bytes,
+                    critical_free_bytes: policy.critical_free_bytes,
+                });
+            }
+        }
+        if self
+            .health_interval
+            .is_some_and(|interval| interval.is_zero())
+        {
+            checks.push(ConfigProblem::ZeroHealthInterval);
+        }
+        if self
+            .watchdog
+            .is_some_and(|policy| policy.missed_intervals == 0)
+        {
+            checks.push(ConfigProblem::ZeroMissedIntervals);
+        }
+        if self.max_field_bytes == Some(0) {
+            checks.push(ConfigProblem::ZeroMaxFieldBytes);
+        }
+        if let Some(namespace) = self
+            .namespace
+            .as_ref()
+            .filter(|namespace| !crate::is_valid_table_name(namespace))
+        {
+            checks.push(ConfigProblem::InvalidNamespace {
+                namespace: namespace.clone(),
+            });
+        }
+        if self.app_info.is_some() && !self.metadata_header {
+            checks.push(ConfigProblem::AppInfoWithoutMetadata);
+        }
+        if self
+            .idle_close
+            .is_some_and(|idle_close| idle_close.is_zero())
+        {
+            checks.push(ConfigProblem::ZeroIdleClose);
+        }
+        for (table, renames) in &self.column_renames {
+            checks.column_renames(table, renames, &self.injected_columns(table));
+        }
+        checks.finish()
+    }
+
+    /// The columns the logger adds to the rows of `table`, with the context set so far
+    fn injected_columns(&self, table: &str) -> Vec<&str> {
+        let mut columns: Vec<&str> = context::keys();
+        if let Some(timestamp) = &self.timestamp {
+            columns.push(&timestamp.column);
+        }
+        let added = [
+            (self.sequence, SEQUENCE_COLUMN),
+            (self.hostname, "hostname"),
+            (self.pid, "pid"),
+            (self.thread_info, "thread_name"),
+            (self.thread_info, "thread_id"),
+            (self.epoch_column, EPOCH_COLUMN),
+            (self.dedup_tables.contains(table), REPEAT_COUNT_COLUMN),
+        ];
+        columns.extend(added.into_iter().filter(|(on, _)| *on).map(|(_, c)| c));
+        columns
+    }
+
+    fn validated(&self) -> io::Result<()> {
+        self.validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Panics if a logger is already registered, before anything is built or installed
+    fn ensure_unregistered() {
+        let registered = table_log::GLOBAL_LOG.lock().unwrap().has_logger();
+        if registered {
+            panic!("Only one logger can be registered at a time");
+        }
+    }
+
+    fn register(mut self) -> io::Result<Duration> {
+        Self::ensure_unregistered();
+        let level = self.prepare()?;
+        let flush_interval = self.flush_interval;
+        if self.flush_on_panic {
+            crate::panic_flush::install();
+        }
+        let logger = self.build_logger()?;
+        let mut log = table_log::GLOBAL_LOG.lock().unwrap();
+        // Another thread may have registered one while this one was built
+        if log.has_logger() {
+            panic!("Only one logger can be registered at a time");
+        }
+        log.register(logger);
+        if let Some(level) = level {
+            crate::set_min_level(level);
+        }
+        Ok(flush_interval)
+    }
+
+    /// Registers the logger globally
+    ///
+    /// Settings are also read from the `CSV_LOGGER` environment variable, failing on invalid
+    /// syntax: `off`, `tables=a,b`, `sample=a:0.1` and `level=debug`, separated by `;`. Except for
+    /// `off`, settings made on the builder take precedence.
+    ///
+    /// Returns the handle to the flushing thread unless `auto_flush` is off, failing and
+    /// unregistering the logger if the thread cannot be spawned.
+    pub fn init(self) -> io::Result<Option<FlusherHandle>> {
+        let auto_flush = self.auto_flush;
+        let flusher_thread = FlusherThread {
+            error_handler: self.error_handler.clone(),
+            ..self.flusher_thread.clone()
+        };
+        let per_table = matches!(self.output_target, OutputTarget::Files)
+            && !self.table_flush_intervals.is_empty();
+        let tick = self
+            .table_flush_intervals
+            .values()
+            .fold(self.flush_interval, |tick, &interval| tick.min(interval));
+        let flush_interval = self.register()?;
+        if !auto_flush {
+            return Ok(None);
+        }
+        let schedule = match per_table {
+            true => Schedule::PerTable(tick),
+            false => Schedule::Global(flush_interval),
+        };
+        match schedule.spawn(flusher_thread) {
+            Ok(flusher) => Ok(Some(flusher)),
+            Err(e) => {
+                table_log::GLOBAL_LOG.lock().unwrap().remove_logger();
+                Err(e)
+            }
+        }
+    }
+
+    /// Registers a channel logger of `capacity` rows whose receiver a background thread writes to
+    /// disk
+    ///
+    /// Records are serialized on the logging thread. Besides [`table_log::log!`], which waits or
+    /// drops rows per `backpressure` when the channel is full, see [`crate::nonblocking::log`]
+    /// which skips the global logger lock and never waits. Only one writer can be started per
+    /// process.
+    pub fn init_nonblocking(
+        mut self,
+        capacity: usize,
+        backpressure: Backpressure,
+    ) -> io::Result<()> {
+        Self::ensure_unregistered();
+        let level = self.prepare()?;
+        let flush_interval = self.flush_interval;
+        nonblocking::start(
+            self.build_validated()?,
+            capacity,
+            backpressure,
+            flush_interval,
+        )?;
+        if let Some(level) = level {
+            crate::set_min_level(level);
+        }
+        Ok(())
+    }
+
+    /// Like [`CsvLoggerBuilder::init`] but flushes from a Tokio task
+    ///
+    /// Must be called from within a Tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub fn init_async(self) -> io::Result<tokio::task::JoinHandle<()>> {
+        let flush_interval = self.register()?;
+        Ok(tokio::spawn(crate::async_flush::flush_periodically(
+            flush_interval,
+        )))
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    row::Row,
+    shared,
+    storage::{self, Storage},
+    table_dir::TableFiles,
+};
+
+fn count_file_path(files: &TableFiles, output_dir: impl AsRef<Path>, table_name: &str) -> PathBuf {
+    files.table_file(output_dir, table_name, "count")
+}
+
+/// Lets records of `table` be written again after its cap was reached in the registered logger
+pub fn reset_table_cap(table: &str) {
+    shared::with_registered(|logger| logger.reset_cap(table));
+}
+
+/// The lifetime limit on the rows of a table, counted across epochs and restarts
+pub(crate) struct Cap {
+    max: u64,
+    /// Read on first use from the count file of the output directory it is kept for, which is
+    /// read again if the table moves, e.g. under `pid-<pid>`
+    count: Option<(PathBuf, u64)>,
+    /// Whether the count changed since it was last written to the count file
+    unsaved: bool,
+}
+impl Cap {
+    pub fn new(max: u64) -> Self {
+        Self {
+            max,
+            count: None,
+            unsaved: false,
+        }
+    }
+
+    fn count(
+        &mut self,
+        storage: &impl Storage,
+        files: &TableFiles,
+        output_dir: &Path,
+        table_name: &str,
+    ) -> u64 {
+        if let Some((dir, count)) = &self.count {
+            if dir == output_dir {
+                return *count;
+            }
+        }
+        let count = storage
+            .read(&count_file_path(files, output_dir, table_name))
+            .ok()
+            .and_then(|count| String::from_utf8(count).ok()?.trim().parse().ok())
+            .unwrap_or_default();
+        self.count = Some((output_dir.to_path_buf(), count));
+        self.unsaved = false;
+        count
+    }
+
+    /// Whether the terminal row is written so records are dropped
+    pub fn exhausted(
+        &mut self,
+        storage: &impl Storage,
+        files: &TableFiles,
+        output_dir: &Path,
+        table_name: &str,
+    ) -> bool {
+        self.max < self.count(storage, files, output_dir, table_name)
+    }
+
+    /// Counts a row about to be written; once the cap is reached, the terminal row to write
+    /// instead, with the columns of `row` and only its first field set
+    ///
+    /// The count is persisted by [`Cap::save`].
+    pub fn admit(
+        &mut self,
+        storage: &impl Storage,
+        files: &TableFiles,
+        output_dir: &Path,
+        table_name: &str,
+        row: &Row,
+    ) -> Option<Row> {
+        let count = self.count(storage, files, output_dir, table_name);
+        if let Some((_, kept)) = &mut self.count {
+            *kept = count + 1;
+        }
+        self.unsaved = true;
+        if count != self.max {
+            return None;
+        }
+        let mut fields = vec![String::new(); row.fields.len().max(1)];
+        fields[0] = format!("[cap of {} records reached]", self.max);
+        Some(Row {
+            table: row.table.clone(),
+            header: row.header.clone(),
+            fields,
+        })
+    }
+
+    /// Writes the count to the count file it is kept for if it changed, on flush and rotation
+    pub fn save(
+        &mut self,
+        storage: &impl Storage,
+        files: &TableFiles,
+        table_name: &str,
+    ) -> io::Result<()> {
+        let Some((output_dir, count)) = self.count.as_ref().filter(|_| self.unsaved) else {
+            return Ok(());
+        };
+        storage.replace(
+            &count_file_path(files, output_dir, table_name),
+            count.to_string().as_bytes(),
+        )?;
+        self.unsaved = false;
+        Ok(())
+    }
+
+    pub fn reset(&mut self, output_dir: &Path) {
+        self.count = Some((output_dir.to_path_buf(), 0));
+        self.unsaved = false;
+    }
+}
+
+pub(crate) fn remove_count(
+    storage: &impl Storage,
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> io::Result<()> {
+    storage::remove_if_exists(storage, &count_file_path(files, output_dir, table_name))?;
+    Ok(())
+}
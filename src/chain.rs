@@ -0,0 +1,312 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    error::{self, ErrorHandler},
+    level::{self, Level},
+    rate_limit::RateLimiter,
+    row::Row,
+    sampling::Sampler,
+    telemetry, CsvLoggerError,
+};
+
+/// What a [`RecordFilter`] decides about a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Keep,
+    Drop,
+    /// Drop the row and count it under `reason` in [`FilterChain::drops`]
+    DropCounted(&'static str),
+}
+
+/// A step of a [`FilterChain`]
+pub trait RecordFilter: Send {
+    /// Decides about a record by its table and the level it was logged at, if any, before it is
+    /// serialized
+    fn filter_table(&mut self, _table: &str, _level: Option<Level>) -> Verdict {
+        Verdict::Keep
+    }
+
+    /// Decides like [`Self::filter_table`] about records of `tables` logged one after another at
+    /// `level`, dropping all of them if it would drop any, without changing any state
+    ///
+    /// Lets a [`crate::transaction`] check its rows before any takes a sample or a rate limit
+    /// token. Filters that cannot tell keep them.
+    fn peek_tables(&self, _tables: &[&str], _level: Option<Level>) -> Verdict {
+        Verdict::Keep
+    }
+
+    /// Decides about a row as serialized from the record, before any column is added
+    fn filter(&mut self, _table: &str, _row: &Row) -> Verdict {
+        Verdict::Keep
+    }
+
+    /// Whether [`Self::filter`] may drop rows; filters deciding by the table alone return `false`
+    /// so rows written through [`crate::raw::TableHandle`] need not be built for them
+    fn filters_rows(&self) -> bool {
+        true
+    }
+
+    /// Called when the logger flushes
+    fn flush(&mut self) {}
+
+    /// Errors to report, taken after every row and flush
+    fn take_errors(&mut self) -> Vec<CsvLoggerError> {
+        Vec::new()
+    }
+}
+
+/// Filters run in order on every record until one drops it
+///
+/// Every filter first decides by the table name alone, before the record is serialized; the
+/// filters that keep it then decide by its row.
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<Box<dyn RecordFilter>>,
+    drops: HashMap<&'static str, u64>,
+}
+impl FilterChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(mut self, filter: impl RecordFilter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Rows dropped so far by reason
+    pub fn drops(&self) -> &HashMap<&'static str, u64> {
+        &self.drops
+    }
+
+    /// Runs the filters of `chain` after those of this chain
+    pub fn extend(mut self, chain: FilterChain) -> Self {
+        self.filters.extend(chain.filters);
+        self
+    }
+
+    /// Whether to serialize a record of `table` logged at `level`
+    pub(crate) fn keep_table(
+        &mut self,
+        table: &str,
+        level: Option<Level>,
+        error_handler: &ErrorHandler,
+    ) -> bool {
+        self.run(error_handler, |filter| filter.filter_table(table, level))
+    }
+
+    /// Whether to serialize records of `tables` logged one after another at `level`, see
+    /// [`RecordFilter::peek_tables`]
+    pub(crate) fn keep_tables(&mut self, tables: &[&str], level: Option<Level>) -> bool {
+        for filter in &self.filters {
+            match filter.peek_tables(tables, level) {
+                Verdict::Keep => (),
+                Verdict::Drop => return false,
+                Verdict::DropCounted(reason) => {
+                    *self.drops.entry(reason).or_default() += 1;
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Whether any filter decides by rows, see [`RecordFilter::filters_rows`]
+    pub(crate) fn filters_rows(&self) -> bool {
+        self.filters.iter().any(|filter| filter.filters_rows())
+    }
+
+    pub(crate) fn keep(&mut self, table: &str, row: &Row, error_handler: &ErrorHandler) -> bool {
+        self.run(error_handler, |filter| filter.filter(table, row))
+    }
+
+    fn run(
+        &mut self,
+        error_handler: &ErrorHandler,
+        mut decide: impl FnMut(&mut dyn RecordFilter) -> Verdict,
+    ) -> bool {
+        for filter in &mut self.filters {
+            let verdict = decide(filter.as_mut());
+            filter
+                .take_errors()
+                .into_iter()
+                .for_each(|e| error::report(error_handler, e));
+            match verdict {
+                Verdict::Keep => (),
+                Verdict::Drop => return false,
+                Verdict::DropCounted(reason) => {
+                    *self.drops.entry(reason).or_default() += 1;
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    pub(crate) fn flush(&mut self, error_handler: &ErrorHandler) {
+        for filter in &mut self.filters {
+            filter.flush();
+            filter
+                .take_errors()
+                .into_iter()
+                .for_each(|e| error::report(error_handler, e));
+        }
+    }
+}
+
+/// Keeps each row of the listed tables with the table's probability; drops are counted as
+/// `sampled`
+pub struct SampleFilter {
+    rates: HashMap<String, f64>,
+    sampler: Sampler,
+}
+impl SampleFilter {
+    /// A `seed` makes the decisions reproducible
+    pub fn new(rates: impl IntoIterator<Item = (String, f64)>, seed: Option<u64>) -> Self {
+        Self {
+            rates: rates.into_iter().collect(),
+            sampler: Sampler::new(seed),
+        }
+    }
+}
+impl RecordFilter for SampleFilter {
+    fn filter_table(&mut self, table: &str, _level: Option<Level>) -> Verdict {
+        let Some(&rate) = self.rates.get(table) else {
+            return Verdict::Keep;
+        };
+        if self.sampler.keep(rate) {
+            return Verdict::Keep;
+        }
+        telemetry::sampled_out();
+        Verdict::DropCounted("sampled")
+    }
+
+    fn peek_tables(&self, tables: &[&str], _level: Option<Level>) -> Verdict {
+        // The decisions the sampler would make next
+        let mut sampler = self.sampler.clone();
+        for table in tables {
+            let Some(&rate) = self.rates.get(*table) else {
+                continue;
+            };
+            if !sampler.keep(rate) {
+                return Verdict::DropCounted("sampled");
+            }
+        }
+        Verdict::Keep
+    }
+
+    fn filters_rows(&self) -> bool {
+        false
+    }
+}
+
+/// Drops rows of the listed tables beyond their records per second, allowing bursts of one
+/// second; drops are counted as `rate_limited`
+///
+/// The number of dropped rows is reported as [`CsvLoggerError::RateLimited`] once rows pass again
+/// or on flush.
+pub struct RateLimitFilter {
+    limiters: HashMap<String, RateLimiter>,
+    errors: Vec<CsvLoggerError>,
+    clock: Arc<dyn Clock>,
+}
+impl RateLimitFilter {
+    pub fn new(max_records_per_sec: impl IntoIterator<Item = (String, f64)>) -> Self {
+        let limiters = max_records_per_sec
+            .into_iter()
+            .map(|(table, rate)| (table, RateLimiter::new(rate)))
+            .collect();
+        Self {
+            limiters,
+            errors: vec![],
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Refills the buckets by the time of `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn take_suppressed(table: &str, limiter: &mut RateLimiter) -> Option<CsvLoggerError> {
+        let suppressed = limiter.take_suppressed();
+        (suppressed != 0).then(|| CsvLoggerError::RateLimited {
+            table: table.to_string().into(),
+            suppressed,
+        })
+    }
+}
+impl RecordFilter for RateLimitFilter {
+    fn filter_table(&mut self, table: &str, _level: Option<Level>) -> Verdict {
+        let Some(limiter) = self.limiters.get_mut(table) else {
+            return Verdict::Keep;
+        };
+        if !limiter.acquire(self.clock.instant()) {
+            telemetry::rate_limited();
+            return Verdict::DropCounted("rate_limited");
+        }
+        self.errors.extend(Self::take_suppressed(table, limiter));
+        Verdict::Keep
+    }
+
+    fn peek_tables(&self, tables: &[&str], _level: Option<Level>) -> Verdict {
+        let now = self.clock.instant();
+        let mut records = HashMap::<&str, f64>::new();
+        for &table in tables {
+            *records.entry(table).or_default() += 1.0;
+        }
+        let limited = records.iter().any(|(&table, &n)| {
+            self.limiters
+                .get(table)
+                .is_some_and(|limiter| limiter.available(now) < n)
+        });
+        match limited {
+            true => Verdict::DropCounted("rate_limited"),
+            false => Verdict::Keep,
+        }
+    }
+
+    fn flush(&mut self) {
+        for (table, limiter) in &mut self.limiters {
+            self.errors.extend(Self::take_suppressed(table, limiter));
+        }
+    }
+
+    fn take_errors(&mut self) -> Vec<CsvLoggerError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn filters_rows(&self) -> bool {
+        false
+    }
+}
+
+/// Drops records logged by [`crate::log_leveled`] below the minimum level of their table; drops
+/// are counted as `level`
+///
+/// Always the first filter of the logger's chain, see [`crate::set_min_level`] and
+/// [`crate::set_table_level`].
+pub struct LevelFilter;
+impl RecordFilter for LevelFilter {
+    fn filter_table(&mut self, table: &str, level: Option<Level>) -> Verdict {
+        match level {
+            Some(level) if !level::enabled(table, level) => Verdict::DropCounted("level"),
+            _ => Verdict::Keep,
+        }
+    }
+
+    fn peek_tables(&self, tables: &[&str], level: Option<Level>) -> Verdict {
+        match level {
+            Some(level) if tables.iter().any(|table| !level::enabled(table, level)) => {
+                Verdict::DropCounted("level")
+            }
+            _ => Verdict::Keep,
+        }
+    }
+
+    fn filters_rows(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    borrow::Cow,
+    sync::{Arc, Mutex, Weak},
+};
+
+use crossbeam_channel::{Receiver, Sender};
+
+use crate::{
+    error::{self, ErrorHandler},
+    level,
+    row::{Row, RowFormat, SerializeError},
+    stats,
+};
+
+/// The length of the queue of the registered channel logger; its sender is held weakly so the
+/// receiver still sees it disconnect
+static DEPTH: Mutex<Option<Box<dyn Fn() -> Option<usize> + Send>>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Block the logging thread until the receiver catches up
+    #[default]
+    Block,
+    /// Drop the row when the channel is full
+    Drop,
+}
+
+/// What a channel logger sends for a record
+pub(crate) trait Queued: Send + Sized + 'static {
+    /// Serializes the record on the logging thread
+    fn from_record(
+        record: &dyn table_log::LogRecord,
+        format: RowFormat,
+    ) -> Result<Self, SerializeError>;
+
+    /// Waits for the receiver to write out what was sent before
+    fn flush(_tx: &Sender<Self>) {}
+}
+impl Queued for Row {
+    fn from_record(
+        record: &dyn table_log::LogRecord,
+        _format: RowFormat,
+    ) -> Result<Self, SerializeError> {
+        Row::serialize(record).map_err(|e| SerializeError {
+            table: Cow::Borrowed(record.table_name()),
+            message: e.to_string(),
+        })
+    }
+}
+
+/// Registers a logger that sends every serialized row to the returned receiver instead of disk
+pub fn init_channel(capacity: usize, backpressure: Backpressure) -> Receiver<Row> {
+    let error_handler = error::default_error_handler();
+    register(capacity, backpressure, RowFormat::default(), error_handler).1
+}
+
+/// Registers a logger that sends what it serializes of every record to the returned receiver,
+/// reporting records that fail to serialize to `error_handler`
+///
+/// The sender is returned weakly; the receiver disconnects once the logger is removed.
+pub(crate) fn register<T: Queued>(
+    capacity: usize,
+    backpressure: Backpressure,
+    format: RowFormat,
+    error_handler: ErrorHandler,
+) -> (Weak<Sender<T>>, Receiver<T>) {
+    let (tx, rx) = crossbeam_channel::bounded(capacity);
+    let tx = Arc::new(tx);
+    let logger = ChannelLogger {
+        tx: tx.clone(),
+        backpressure,
+        format,
+        error_handler,
+    };
+    let mut log = table_log::GLOBAL_LOG.lock().unwrap();
+    if log.has_logger() {
+        panic!("Only one logger can be registered at a time");
+    }
+    log.register(Box::new(logger));
+    let weak = Arc::downgrade(&tx);
+    let depth = weak.clone();
+    *DEPTH.lock().unwrap() = Some(Box::new(move || Some(depth.upgrade()?.len())));
+    (weak, rx)
+}
+
+/// Sends `item` per `backpressure`, counting it as dropped if the channel does not take it
+pub(crate) fn send<T>(tx: &Sender<T>, item: T, backpressure: Backpressure) {
+    let sent = match backpressure {
+        Backpressure::Block => tx.send(item).is_ok(),
+        Backpressure::Drop => tx.try_send(item).is_ok(),
+    };
+    if !sent {
+        stats::count_dropped(1);
+    }
+}
+
+/// Rows waiting in the channel of the registered channel logger, if any
+pub(crate) fn queue_depth() -> Option<usize> {
+    let depth = DEPTH.lock().unwrap_or_else(|e| e.into_inner());
+    (depth.as_ref()?)()
+}
+
+struct ChannelLogger<T> {
+    tx: Arc<Sender<T>>,
+    backpressure: Backpressure,
+    format: RowFormat,
+    error_handler: ErrorHandler,
+}
+impl<T: Queued> table_log::Logger for ChannelLogger<T> {
+    fn log(&mut self, record: &dyn table_log::LogRecord) {
+        if !level::record_enabled(record.table_name()) {
+            return;
+        }
+        match T::from_record(record, self.format) {
+            Ok(item) => send(&self.tx, item, self.backpressure),
+            Err(e) => {
+                stats::count_dropped(1);
+                error::report(&self.error_handler, e.into());
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        T::flush(&self.tx);
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    rotated::{RotatedFileDisposition, RotatedFileHandler},
+    storage::{Backend, Storage},
+};
+
+/// Writes `<epoch>.csv.sha256` next to each rotated epoch file
+pub(crate) struct ChecksumSidecar {
+    storage: Backend,
+}
+impl ChecksumSidecar {
+    pub fn new(storage: Backend) -> Self {
+        Self { storage }
+    }
+}
+impl RotatedFileHandler for ChecksumSidecar {
+    fn handle(
+        &self,
+        _table: &str,
+        _epoch: usize,
+        path: &Path,
+    ) -> io::Result<RotatedFileDisposition> {
+        let digest = sha256(&self.storage.read(path)?);
+        let tmp = sidecar_path(path).with_extension("sha256.tmp");
+        self.storage
+            .replace(&tmp, sidecar_line(&digest, path).as_bytes())?;
+        self.storage.rename(&tmp, &sidecar_path(path))?;
+        Ok(RotatedFileDisposition::Keep)
+    }
+}
+
+pub(crate) fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".sha256");
+    PathBuf::from(sidecar)
+}
+
+/// Lowercase hex digest of the file
+pub(crate) fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Lowercase hex digest of `bytes`
+pub(crate) fn sha256(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The contents of the sidecar of the file at `path`
+pub(crate) fn sidecar_line(digest: &str, path: &Path) -> String {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    format!("{digest}  {file_name}\n")
+}
+
+/// Digest recorded in the sidecar of the file, if any
+pub(crate) fn read_sidecar(path: &Path) -> io::Result<Option<String>> {
+    let sidecar = match std::fs::read_to_string(sidecar_path(path)) {
+        Ok(sidecar) => sidecar,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    Ok(sidecar.split_whitespace().next().map(String::from))
+}
@@ -0,0 +1,98 @@
+//! The time as seen by a logger and its flushing thread, replaceable to drive them in tests
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    thread::Thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Tells the time to a logger
+pub trait Clock: Send + Sync {
+    /// The wall-clock time, e.g. for timestamps
+    fn now(&self) -> SystemTime;
+    /// The monotonic time, e.g. for flush intervals
+    fn instant(&self) -> Instant;
+    /// Blocks the thread for up to `timeout` of this clock's time or until it is unparked
+    fn park_timeout(&self, timeout: Duration) {
+        std::thread::park_timeout(timeout);
+    }
+}
+
+/// The clock of the operating system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl fmt::Debug for dyn Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Clock").field("now", &self.now()).finish()
+    }
+}
+
+/// A clock that only moves when told to
+///
+/// Clones share the time. Threads parked on the clock, such as the flushing thread, wake up
+/// whenever it is advanced.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    state: Arc<Mutex<ManualState>>,
+}
+#[derive(Debug)]
+struct ManualState {
+    now: SystemTime,
+    instant: Instant,
+    parked: Vec<Thread>,
+}
+impl ManualClock {
+    pub fn new(now: SystemTime) -> Self {
+        let state = ManualState {
+            now,
+            instant: Instant::now(),
+            parked: vec![],
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let parked = {
+            let mut state = self.state.lock().unwrap();
+            state.now += by;
+            state.instant += by;
+            std::mem::take(&mut state.parked)
+        };
+        parked.iter().for_each(Thread::unpark);
+    }
+}
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        self.state.lock().unwrap().now
+    }
+
+    fn instant(&self) -> Instant {
+        self.state.lock().unwrap().instant
+    }
+
+    /// Parks until the clock is advanced or the thread is unparked, however long `timeout`
+    fn park_timeout(&self, _timeout: Duration) {
+        {
+            let mut state = self.state.lock().unwrap();
+            let current = std::thread::current();
+            if !state.parked.iter().any(|t| t.id() == current.id()) {
+                state.parked.push(current);
+            }
+        }
+        // Returns at once if advanced since the thread was added
+        std::thread::park();
+    }
+}
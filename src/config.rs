@@ -0,0 +1,268 @@
+//! Checking the settings of a [`crate::CsvLoggerBuilder`] before a logger is registered
+//!
+//! Every problem is collected so that one failed `init` lists all of them.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::PathBuf,
+    time::Duration,
+};
+
+/// One invalid setting or combination of settings
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigProblem {
+    /// `flush_interval` is zero while `auto_flush` is on
+    ZeroFlushInterval,
+    /// The flush interval of `table` is zero while `auto_flush` is on
+    ZeroTableFlushInterval { table: String },
+    /// The sampling rate of `table` is not in (0, 1]
+    SampleRate { table: String, rate: f64 },
+    /// The rate limit of `table` is not a positive number of records per second
+    RateLimit {
+        table: String,
+        max_records_per_sec: f64,
+    },
+    /// The spool would write its files among the tables
+    SpoolDirIsOutputDir { dir: PathBuf },
+    /// `critical_free_bytes` is above `min_free_bytes`, so deleting epochs never runs first
+    FreeSpaceThresholds {
+        min_free_bytes: u64,
+        critical_free_bytes: u64,
+    },
+    /// `health_interval` is zero
+    ZeroHealthInterval,
+    /// The watchdog would warn before the first scheduled flush could happen
+    ZeroMissedIntervals,
+    /// Every non-empty field would be replaced by the truncation marker
+    ZeroMaxFieldBytes,
+    /// `column` of `table` is renamed to nothing
+    EmptyColumnRename { table: String, column: String },
+    /// Several columns of `table` are renamed to `name`, so readers cannot tell them apart
+    DuplicateColumnRename { table: String, name: String },
+    /// A column of `table` is renamed to a column the logger adds, e.g. `seq`, the timestamp or
+    /// a context column
+    InjectedColumnRename { table: String, column: String },
+    /// The namespace is empty, `.`, `..` or contains path separators
+    InvalidNamespace { namespace: String },
+    /// `app_info` is set but `metadata_header` is off, so it is never written
+    AppInfoWithoutMetadata,
+    /// `idle_close` is zero, closing every table as soon as it is flushed
+    ZeroIdleClose,
+    /// `spool_max_bytes` is zero while spooling, so every spooled row is dropped
+    ZeroSpoolMaxBytes,
+    /// `max_nesting_depth` is zero while `flatten_nested` is on, so every nested field is an error
+    ZeroMaxNestingDepth,
+    /// The cap of `table` is zero, so none of its records are ever written
+    ZeroTableCap { table: String },
+}
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigProblem::ZeroFlushInterval => {
+                write!(f, "flush_interval is zero with auto_flush on")
+            }
+            ConfigProblem::ZeroTableFlushInterval { table } => {
+                write!(
+                    f,
+                    "Flush interval of table `{table}` is zero with auto_flush on"
+                )
+            }
+            ConfigProblem::SampleRate { table, rate } => {
+                write!(
+                    f,
+                    "Sampling rate {rate} of table `{table}` is not in (0, 1]"
+                )
+            }
+            ConfigProblem::RateLimit {
+                table,
+                max_records_per_sec,
+            } => write!(
+                f,
+                "Rate limit {max_records_per_sec} of table `{table}` is not a positive number"
+            ),
+            ConfigProblem::SpoolDirIsOutputDir { dir } => {
+                write!(
+                    f,
+                    "Spool directory {} is the output directory",
+                    dir.display()
+                )
+            }
+            ConfigProblem::FreeSpaceThresholds {
+                min_free_bytes,
+                critical_free_bytes,
+            } => write!(
+                f,
+                "critical_free_bytes {critical_free_bytes} is above min_free_bytes {min_free_bytes}"
+            ),
+            ConfigProblem::ZeroHealthInterval => write!(f, "health_interval is zero"),
+            ConfigProblem::ZeroMissedIntervals => {
+                write!(f, "Watchdog missed_intervals is zero")
+            }
+            ConfigProblem::ZeroMaxFieldBytes => write!(f, "max_field_bytes is zero"),
+            ConfigProblem::EmptyColumnRename { table, column } => {
+                write!(
+                    f,
+                    "Column `{column}` of table `{table}` is renamed to nothing"
+                )
+            }
+            ConfigProblem::InjectedColumnRename { table, column } => {
+                write!(
+                    f,
+                    "A column of table `{table}` is renamed to `{column}`, which the logger adds"
+                )
+            }
+            ConfigProblem::InvalidNamespace { namespace } => {
+                write!(f, "Invalid namespace `{namespace}`")
+            }
+            ConfigProblem::AppInfoWithoutMetadata => {
+                write!(f, "app_info is set without metadata_header")
+            }
+            ConfigProblem::ZeroIdleClose => write!(f, "idle_close is zero"),
+            ConfigProblem::ZeroSpoolMaxBytes => write!(f, "spool_max_bytes is zero"),
+            ConfigProblem::ZeroMaxNestingDepth => {
+                write!(f, "max_nesting_depth is zero with flatten_nested on")
+            }
+            ConfigProblem::ZeroTableCap { table } => write!(f, "Cap of table `{table}` is zero"),
+            ConfigProblem::DuplicateColumnRename { table, name } => {
+                write!(
+                    f,
+                    "Several columns of table `{table}` are renamed to `{name}`"
+                )
+            }
+        }
+    }
+}
+
+/// Every problem found with the settings of a builder
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub problems: Vec<ConfigProblem>,
+}
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid logger configuration: ")?;
+        for (i, problem) in self.problems.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{problem}")?;
+        }
+        Ok(())
+    }
+}
+impl std::error::Error for ConfigError {}
+
+/// Collects the problems of one configuration
+#[derive(Debug, Default)]
+pub(crate) struct Checks {
+    problems: Vec<ConfigProblem>,
+}
+impl Checks {
+    pub fn flush_interval(&mut self, interval: Duration) {
+        if interval.is_zero() {
+            self.problems.push(ConfigProblem::ZeroFlushInterval);
+        }
+    }
+
+    pub fn table_flush_interval(&mut self, table: &str, interval: Duration) {
+        if interval.is_zero() {
+            self.problems.push(ConfigProblem::ZeroTableFlushInterval {
+                table: table.to_string(),
+            });
+        }
+    }
+
+    pub fn sample_rate(&mut self, table: &str, rate: f64) {
+        if !(rate > 0.0 && rate <= 1.0) {
+            self.problems.push(ConfigProblem::SampleRate {
+                table: table.to_string(),
+                rate,
+            });
+        }
+    }
+
+    pub fn rate_limit(&mut self, table: &str, max_records_per_sec: f64) {
+        if !(max_records_per_sec > 0.0 && max_records_per_sec.is_finite()) {
+            self.problems.push(ConfigProblem::RateLimit {
+                table: table.to_string(),
+                max_records_per_sec,
+            });
+        }
+    }
+
+    /// `injected` are the columns the logger adds to rows of `table`
+    pub fn column_renames(
+        &mut self,
+        table: &str,
+        renames: &HashMap<String, String>,
+        injected: &[&str],
+    ) {
+        let mut names = HashSet::new();
+        for (column, name) in renames {
+            if injected.contains(&name.as_str()) {
+                self.problems.push(ConfigProblem::InjectedColumnRename {
+                    table: table.to_string(),
+                    column: name.clone(),
+                });
+            } else if name.is_empty() {
+                self.problems.push(ConfigProblem::EmptyColumnRename {
+                    table: table.to_string(),
+                    column: column.clone(),
+                });
+            } else if !names.insert(name) {
+                self.problems.push(ConfigProblem::DuplicateColumnRename {
+                    table: table.to_string(),
+                    name: name.clone(),
+                });
+            }
+        }
+    }
+
+    pub fn push(&mut self, problem: ConfigProblem) {
+        self.problems.push(problem);
+    }
+
+    pub fn finish(mut self) -> Result<(), ConfigError> {
+        match self.problems.is_empty() {
+            true => Ok(()),
+            false => {
+                // Table settings come out of hash maps
+                self.problems
+                    .sort_by_cached_key(|problem| problem.to_string());
+                Err(ConfigError {
+                    problems: self.problems,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checks() {
+        let mut checks = Checks::default();
+        checks.flush_interval(Duration::from_secs(1));
+        checks.sample_rate("a", 1.0);
+        checks.rate_limit("a", 0.5);
+        assert!(checks.finish().is_ok());
+
+        let mut checks = Checks::default();
+        checks.sample_rate("a", 0.0);
+        checks.sample_rate("b", f64::NAN);
+        checks.rate_limit("a", f64::INFINITY);
+        checks.table_flush_interval("a", Duration::ZERO);
+        let problems = checks.finish().unwrap_err().problems;
+        assert_eq!(problems.len(), 4);
+        assert!(problems.contains(&ConfigProblem::RateLimit {
+            table: "a".to_string(),
+            max_records_per_sec: f64::INFINITY,
+        }));
+        assert!(problems.contains(&ConfigProblem::ZeroTableFlushInterval {
+            table: "a".to_string(),
+        }));
+    }
+}
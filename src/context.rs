@@ -0,0 +1,80 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+
+use crate::row::Row;
+
+pub(crate) const EPOCH_COLUMN: &str = "epoch";
+
+static CONTEXT: Mutex<Vec<(&'static str, String)>> = Mutex::new(Vec::new());
+
+/// The id of the next thread to log with thread info
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// The id of the calling thread, given out on its first row
+    static THREAD_ID: String = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed).to_string();
+}
+
+/// Appends a column with `value` to every row of every table from now on
+///
+/// Columns keep the order in which their keys were first set; setting a key again only changes
+/// the value for subsequent rows.
+pub fn set_context(key: &'static str, value: String) {
+    let mut context = CONTEXT.lock().unwrap();
+    match context.iter_mut().find(|(k, _)| *k == key) {
+        Some((_, v)) => *v = value,
+        None => context.push((key, value)),
+    }
+}
+
+/// The keys of the global context set so far
+pub(crate) fn keys() -> Vec<&'static str> {
+    let context = CONTEXT.lock().unwrap();
+    context.iter().map(|(key, _)| *key).collect()
+}
+
+/// Appends the built-in context of the logger followed by the global context
+pub(crate) fn append(row: &mut Row, builtin: &[(&'static str, String)]) {
+    let context = CONTEXT.lock().unwrap();
+    for (key, value) in builtin.iter().chain(context.iter()) {
+        if !row.header.is_empty() {
+            row.header.push(key.to_string());
+        }
+        row.fields.push(value.clone());
+    }
+}
+
+/// Appends the name and the id of the calling thread
+pub(crate) fn append_thread_info(row: &mut Row) {
+    let thread = std::thread::current();
+    let id = THREAD_ID.with(String::clone);
+    for (key, value) in [
+        ("thread_name", thread.name().unwrap_or_default().to_string()),
+        ("thread_id", id),
+    ] {
+        if !row.header.is_empty() {
+            row.header.push(key.to_string());
+        }
+        row.fields.push(value);
+    }
+}
+
+/// Appends the epoch of the table, which [`set_epoch`] corrects once the row is written
+///
+/// Only the repeat count of deduplicated tables comes after it.
+pub(crate) fn append_epoch(row: &mut Row, epoch: usize) {
+    if !row.header.is_empty() {
+        row.header.push(EPOCH_COLUMN.to_string());
+    }
+    row.fields.push(epoch.to_string());
+}
+
+/// Sets the epoch appended by [`append_epoch`] to that of the file the row is written to
+pub(crate) fn set_epoch(row: &mut Row, epoch: usize, dedup: bool) {
+    let index = row.fields.len().checked_sub(1 + usize::from(dedup));
+    if let Some(field) = index.and_then(|index| row.fields.get_mut(index)) {
+        *field = epoch.to_string();
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    fmt::{self, Write},
+    path::PathBuf,
+    sync::TryLockError,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{channel, error, flusher, shared, LoggerStats};
+
+/// How long to wait for the registered logger before reporting it busy
+const LOCK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Describes the state of the logging machinery for support tickets
+///
+/// Takes each lock only to copy what it guards and tolerates poisoned locks, so it works even
+/// after a logging thread panicked.
+pub fn debug_dump() -> String {
+    let mut dump = String::new();
+    write_dump(&mut dump).expect("Writing to a `String` does not fail");
+    dump
+}
+
+/// What is copied out of the registered file logger
+struct LoggerState {
+    output_dir: PathBuf,
+    max_records: usize,
+    max_epochs: usize,
+    stats: LoggerStats,
+}
+
+enum Registered {
+    None,
+    Busy,
+    Logger(LoggerState),
+}
+
+fn registered() -> Registered {
+    let Some(logger) = shared::registered() else {
+        return Registered::None;
+    };
+    let start = Instant::now();
+    let logger = loop {
+        match logger.try_lock() {
+            Ok(logger) => break logger,
+            Err(TryLockError::Poisoned(e)) => break e.into_inner(),
+            Err(TryLockError::WouldBlock) if start.elapsed() < LOCK_TIMEOUT => {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Err(TryLockError::WouldBlock) => return Registered::Busy,
+        }
+    };
+    Registered::Logger(LoggerState {
+        output_dir: logger.output_dir.clone(),
+        max_records: logger.rotation.max_records.get(),
+        max_epochs: logger.rotation.max_epochs,
+        stats: logger.stats(),
+    })
+}
+
+fn write_dump(f: &mut impl Write) -> fmt::Result {
+    writeln!(
+        f,
+        "csv_logger debug dump at {}",
+        format_time(SystemTime::now())
+    )?;
+    match registered() {
+        Registered::None => writeln!(f, "file logger: none registered")?,
+        Registered::Busy => writeln!(f, "file logger: busy for over {LOCK_TIMEOUT:?}")?,
+        Registered::Logger(state) => write_logger(f, &state)?,
+    }
+
+    match flusher::scheduled_status() {
+        None => writeln!(f, "flusher: not scheduled")?,
+        Some(status) => {
+            let running = match status.running() {
+                true => "running",
+                false => "stopped",
+            };
+            let last_run = status.last_run().map(format_time);
+            let last_run = last_run.as_deref().unwrap_or("never");
+            writeln!(f, "flusher: {running}, last ran {last_run}")?;
+        }
+    }
+    if let Some(depth) = channel::queue_depth() {
+        writeln!(f, "channel queue: {depth} rows")?;
+    }
+
+    let errors = error::recent_errors();
+    writeln!(f, "recent errors: {}", errors.len())?;
+    for error in errors {
+        let at = format_time(error.at);
+        writeln!(f, "  {at} [{}] {}", error.kind, error.message)?;
+    }
+    Ok(())
+}
+
+fn write_logger(f: &mut impl Write, state: &LoggerState) -> fmt::Result {
+    writeln!(f, "output dir: {}", state.output_dir.display())?;
+    writeln!(
+        f,
+        "rotation: {} records per epoch, {} epochs kept",
+        state.max_records, state.max_epochs
+    )?;
+    writeln!(f, "tables: {}", state.stats.tables.len())?;
+    for (table, stats) in &state.stats.tables {
+        let last_flush = stats.last_flush.map(format_time);
+        let last_flush = last_flush.as_deref().unwrap_or("never");
+        writeln!(
+            f,
+            "  {table}: epoch {}, {} records, {} bytes, last flushed {last_flush}",
+            stats.epoch, stats.epoch_records, stats.epoch_bytes
+        )?;
+    }
+    for (table, dropped) in &state.stats.dropped {
+        writeln!(f, "  {table}: {dropped} records dropped")?;
+    }
+    Ok(())
+}
+
+fn format_time(time: SystemTime) -> String {
+    let time = chrono::DateTime::<chrono::Utc>::from(time);
+    time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
@@ -0,0 +1,47 @@
+use crate::row::Row;
+
+pub(crate) const REPEAT_COUNT_COLUMN: &str = "repeat_count";
+
+/// Appends the repeat count of a row, starting at one
+pub(crate) fn append_count(row: &mut Row) {
+    if !row.header.is_empty() {
+        row.header.push(REPEAT_COUNT_COLUMN.to_string());
+    }
+    row.fields.push(1.to_string());
+}
+
+/// The last row of a table, held back while identical records keep coming
+pub(crate) struct Held {
+    /// The record as serialized, before any column is added
+    record: Row,
+    /// The row to write, ending with its repeat count
+    row: Row,
+    count: u64,
+}
+impl Held {
+    pub fn new(record: Row, row: Row) -> Self {
+        Self {
+            record,
+            row,
+            count: 1,
+        }
+    }
+
+    /// Counts `record` if it is identical to the held one
+    pub fn repeat(&mut self, record: &Row) -> bool {
+        if self.record != *record {
+            return false;
+        }
+        self.count += 1;
+        true
+    }
+
+    /// The row with its final repeat count
+    pub fn into_row(self) -> Row {
+        let mut row = self.row;
+        if let Some(count) = row.fields.last_mut() {
+            *count = self.count.to_string();
+        }
+        row
+    }
+}
@@ -0,0 +1,122 @@
+//! Where an app logs by the convention of the platform, see [`crate::init_default`]
+
+use std::path::PathBuf;
+
+use crate::{CsvLoggerBuilder, RotationPolicy};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Platform {
+    Linux,
+    MacOs,
+    Windows,
+}
+impl Platform {
+    const CURRENT: Self = if cfg!(target_os = "macos") {
+        Self::MacOs
+    } else if cfg!(windows) {
+        Self::Windows
+    } else {
+        Self::Linux
+    };
+}
+
+/// Registers a logger writing to the log directory of `app_name` by the convention of the
+/// platform, creating the directory if needed
+///
+/// - Linux and other Unix: `$XDG_STATE_HOME/<app>/logs`, or `~/.local/state/<app>/logs`
+/// - macOS: `~/Library/Logs/<app>`
+/// - Windows: `%LOCALAPPDATA%\<app>\logs`
+///
+/// See [`crate::output_dir`] for the directory chosen.
+pub fn init_default(app_name: &str, rotation: RotationPolicy) {
+    let output_dir = resolve(Platform::CURRENT, app_name, |name| std::env::var(name).ok())
+        .expect("Failed to find the home directory");
+    std::fs::create_dir_all(&output_dir).expect("Failed to create the output directory");
+    CsvLoggerBuilder::new(output_dir, rotation)
+        .init()
+        .expect("Failed to initialize the logger");
+}
+
+/// `None` if the variables the platform relies on are unset or empty
+fn resolve(
+    platform: Platform,
+    app_name: &str,
+    var: impl Fn(&str) -> Option<String>,
+) -> Option<PathBuf> {
+    // Relative values are invalid per the XDG spec
+    let dir = |name| var(name).map(PathBuf::from).filter(|dir| dir.is_absolute());
+    Some(match platform {
+        Platform::Linux => dir("XDG_STATE_HOME")
+            .or_else(|| Some(dir("HOME")?.join(".local").join("state")))?
+            .join(app_name)
+            .join("logs"),
+        Platform::MacOs => dir("HOME")?.join("Library").join("Logs").join(app_name),
+        Platform::Windows => dir("LOCALAPPDATA")?.join(app_name).join("logs"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn resolve_with(platform: Platform, vars: &[(&str, &str)]) -> Option<PathBuf> {
+        let vars = vars.iter().copied().collect::<HashMap<_, _>>();
+        resolve(platform, "app", |name| {
+            vars.get(name).map(|v| v.to_string())
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_linux() {
+        let home = tempfile::tempdir().unwrap();
+        let home = home.path().to_str().unwrap();
+        let state = format!("{home}/state");
+        assert_eq!(
+            resolve_with(
+                Platform::Linux,
+                &[("XDG_STATE_HOME", &state), ("HOME", home)]
+            ),
+            Some(PathBuf::from(format!("{home}/state/app/logs")))
+        );
+        let fallback = Some(PathBuf::from(format!("{home}/.local/state/app/logs")));
+        assert_eq!(resolve_with(Platform::Linux, &[("HOME", home)]), fallback);
+        assert_eq!(
+            resolve_with(
+                Platform::Linux,
+                &[("XDG_STATE_HOME", "relative"), ("HOME", home)]
+            ),
+            fallback
+        );
+        assert_eq!(
+            resolve_with(Platform::Linux, &[("XDG_STATE_HOME", ""), ("HOME", home)]),
+            fallback
+        );
+        assert_eq!(resolve_with(Platform::Linux, &[]), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_macos() {
+        assert_eq!(
+            resolve_with(Platform::MacOs, &[("HOME", "/Users/me")]),
+            Some(PathBuf::from("/Users/me/Library/Logs/app"))
+        );
+        assert_eq!(resolve_with(Platform::MacOs, &[]), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows() {
+        assert_eq!(
+            resolve_with(
+                Platform::Windows,
+                &[("LOCALAPPDATA", r"C:\Users\me\AppData\Local")]
+            ),
+            Some(PathBuf::from(r"C:\Users\me\AppData\Local\app\logs"))
+        );
+        assert_eq!(resolve_with(Platform::Windows, &[]), None);
+    }
+}
@@ -0,0 +1,277 @@
+//! Encrypting rotated epoch files at rest with AES-256-GCM
+//!
+//! `<epoch>.csv` becomes `<epoch>.csv.enc`: the magic `CSVLENC1`, the length of the key id as a
+//! byte, the key id, a 12-byte nonce and then the sealed file, with everything before the nonce
+//! authenticated as well. The key id lets files outlive the key they were encrypted with.
+//!
+//! With [`RotatedCompression`] the file is compressed before it is sealed, e.g. into
+//! `<epoch>.csv.gz.enc`, since ciphertext does not compress.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Nonce,
+};
+
+use crate::{
+    checksum,
+    rotated::{RotatedFileDisposition, RotatedFileHandler},
+    storage::{self, Backend, OpenMode, Storage},
+};
+
+const MAGIC: &[u8] = b"CSVLENC1";
+const NONCE_LEN: usize = 12;
+
+/// An AES-256 key
+pub type EncryptionKey = [u8; 32];
+
+/// Hands out the keys of epoch files by id
+pub trait KeyProvider: Send + Sync {
+    /// The id and key to encrypt with from now on
+    fn current(&self) -> io::Result<(String, EncryptionKey)>;
+    /// The key of files encrypted under `key_id`, e.g. before the key was rotated
+    fn key(&self, key_id: &str) -> io::Result<EncryptionKey>;
+}
+/// A single key with the empty id
+impl KeyProvider for EncryptionKey {
+    fn current(&self) -> io::Result<(String, EncryptionKey)> {
+        Ok((String::new(), *self))
+    }
+
+    fn key(&self, key_id: &str) -> io::Result<EncryptionKey> {
+        match key_id {
+            "" => Ok(*self),
+            _ => Err(unknown_key(key_id)),
+        }
+    }
+}
+
+fn unknown_key(key_id: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("No key `{key_id}`"))
+}
+
+static KEYS: Mutex<BTreeMap<PathBuf, Arc<dyn KeyProvider>>> = Mutex::new(BTreeMap::new());
+
+/// Lets readers of `output_dir` and the directories below it decrypt `.enc` epoch files
+///
+/// A logger encrypting rotated files sets its keys for its own output directory.
+pub fn set_decryption_keys(output_dir: impl AsRef<Path>, keys: impl KeyProvider + 'static) {
+    set_keys(output_dir.as_ref(), Arc::new(keys));
+}
+
+pub(crate) fn set_keys(output_dir: &Path, keys: Arc<dyn KeyProvider>) {
+    KEYS.lock().unwrap().insert(output_dir.to_path_buf(), keys);
+}
+
+/// How to compress rotated epoch files before encrypting them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotatedCompression {
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+impl RotatedCompression {
+    fn compress(self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(plaintext)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => zstd::encode_all(plaintext, 0),
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            #[cfg(feature = "gzip")]
+            Self::Gzip => ".gz",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => ".zst",
+        }
+    }
+}
+
+/// Encrypts each rotated epoch file into `<epoch>.csv.enc`, deleting the plaintext
+pub(crate) struct Encryptor {
+    keys: Arc<dyn KeyProvider>,
+    compression: Option<RotatedCompression>,
+    storage: Backend,
+    checksums: bool,
+}
+impl Encryptor {
+    pub fn new(
+        keys: Arc<dyn KeyProvider>,
+        compression: Option<RotatedCompression>,
+        storage: Backend,
+        checksums: bool,
+    ) -> Self {
+        Self {
+            keys,
+            compression,
+            storage,
+            checksums,
+        }
+    }
+}
+impl RotatedFileHandler for Encryptor {
+    /// The sealed file and its sidecar are durable before the plaintext is deleted
+    fn handle(
+        &self,
+        _table: &str,
+        _epoch: usize,
+        path: &Path,
+    ) -> io::Result<RotatedFileDisposition> {
+        let (key_id, key) = self.keys.current()?;
+        let mut plaintext = self.storage.read(path)?;
+        let mut dest = path.as_os_str().to_owned();
+        if let Some(compression) = self.compression {
+            plaintext = compression.compress(&plaintext)?;
+            dest.push(compression.extension());
+        }
+        let sealed = encrypt(&key_id, &key, &plaintext)?;
+        let dest = encrypted_path(Path::new(&dest));
+        self.storage.replace(&dest, &sealed)?;
+        self.storage.open(&dest, OpenMode::Append)?.sync_all()?;
+        // The digest of the plaintext no longer describes any file
+        storage::remove_if_exists(&self.storage, &checksum::sidecar_path(path))?;
+        if self.checksums {
+            let digest = checksum::sha256(&sealed);
+            self.storage.replace(
+                &checksum::sidecar_path(&dest),
+                checksum::sidecar_line(&digest, &dest).as_bytes(),
+            )?;
+        }
+        if let Some(dir) = dest.parent() {
+            self.storage.sync_dir(dir)?;
+        }
+        Ok(RotatedFileDisposition::Delete)
+    }
+}
+
+pub(crate) fn encrypted_path(path: &Path) -> PathBuf {
+    let mut encrypted = path.as_os_str().to_owned();
+    encrypted.push(".enc");
+    PathBuf::from(encrypted)
+}
+
+/// Where the plaintext epoch file at `path` might have been sealed to
+pub(crate) fn sealed_paths(path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    ["", ".gz", ".zst"].into_iter().map(|compression| {
+        let mut compressed = path.as_os_str().to_owned();
+        compressed.push(compression);
+        encrypted_path(Path::new(&compressed))
+    })
+}
+
+fn encrypt(key_id: &str, key: &EncryptionKey, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+    let id_len = u8::try_from(key_id.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Key ids are at most 255 bytes long",
+        )
+    })?;
+    let mut sealed = [MAGIC, &[id_len], key_id.as_bytes()].concat();
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: plaintext,
+        aad: &sealed,
+    };
+    let ciphertext = Aes256Gcm::new(key.into())
+        .encrypt(&nonce, payload)
+        .map_err(|_| io::Error::other("Failed to encrypt"))?;
+    sealed.extend_from_slice(&nonce);
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypts the epoch file at `path` with the keys set for its output directory
+pub(crate) fn decrypt_file(path: &Path, mut file: File) -> io::Result<Vec<u8>> {
+    let keys = {
+        let keys = KEYS.lock().unwrap();
+        path.ancestors().find_map(|dir| keys.get(dir).cloned())
+    };
+    let Some(keys) = keys else {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("No decryption keys set for {}", path.display()),
+        ));
+    };
+    let mut sealed = vec![];
+    file.read_to_end(&mut sealed)?;
+    decrypt(&*keys, &sealed)
+}
+
+fn decrypt(keys: &dyn KeyProvider, sealed: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let rest = sealed
+        .strip_prefix(MAGIC)
+        .ok_or_else(|| invalid("Not an encrypted epoch file"))?;
+    let (&id_len, rest) = rest
+        .split_first()
+        .ok_or_else(|| invalid("Truncated header"))?;
+    let header_len = MAGIC.len() + 1 + id_len as usize;
+    if sealed.len() < header_len + NONCE_LEN {
+        return Err(invalid("Truncated header"));
+    }
+    let key_id = std::str::from_utf8(&rest[..id_len as usize])
+        .map_err(|_| invalid("Key id is not UTF-8"))?;
+    let key = keys.key(key_id)?;
+    let (aad, rest) = sealed.split_at(header_len);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+    Aes256Gcm::new(&key.into())
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| invalid("Wrong key or tampered epoch file"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keys rotated from `old` to `new`
+    struct Rotated;
+    impl KeyProvider for Rotated {
+        fn current(&self) -> io::Result<(String, EncryptionKey)> {
+            Ok(("new".to_string(), [2; 32]))
+        }
+
+        fn key(&self, key_id: &str) -> io::Result<EncryptionKey> {
+            match key_id {
+                "old" => Ok([1; 32]),
+                "new" => Ok([2; 32]),
+                _ => Err(unknown_key(key_id)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let plaintext = b"n\n0\n";
+        let old = encrypt("old", &[1; 32], plaintext).unwrap();
+        assert_eq!(decrypt(&Rotated, &old).unwrap(), plaintext);
+        let (key_id, key) = Rotated.current().unwrap();
+        let new = encrypt(&key_id, &key, plaintext).unwrap();
+        assert_eq!(decrypt(&Rotated, &new).unwrap(), plaintext);
+
+        assert!(decrypt(&[1u8; 32], &new).is_err());
+        let mut tampered = new.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(decrypt(&Rotated, &tampered).is_err());
+        assert!(decrypt(&Rotated, &new[..MAGIC.len() + 4]).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Logging settings from the `CSV_LOGGER` environment variable
+//!
+//! The variable holds directives separated by `;`:
+//!
+//! - `off` drops every record, whatever the builder says
+//! - `tables=a,b` only logs to the listed tables; patterns as in
+//!   [`CsvLoggerBuilder::allowed_tables`](crate::CsvLoggerBuilder::allowed_tables)
+//! - `sample=a:0.1,b:0.5` samples the listed tables at the given rates
+//! - `level=debug` sets the minimum level, see [`set_min_level`](crate::set_min_level)
+//!
+//! Except for `off`, settings made on the builder take precedence.
+
+use std::io;
+
+use crate::level::Level;
+
+pub(crate) const ENV_VAR: &str = "CSV_LOGGER";
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct EnvConfig {
+    pub off: bool,
+    pub tables: Option<Vec<String>>,
+    pub sampling: Vec<(String, f64)>,
+    pub level: Option<Level>,
+}
+
+/// Reads the variable; unset means no settings
+pub(crate) fn from_env() -> io::Result<Option<EnvConfig>> {
+    let Ok(value) = std::env::var(ENV_VAR) else {
+        return Ok(None);
+    };
+    parse(&value).map(Some).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid `{ENV_VAR}={value}`: {e}"),
+        )
+    })
+}
+
+fn parse(value: &str) -> Result<EnvConfig, String> {
+    let mut config = EnvConfig::default();
+    for directive in value.split(';').map(str::trim).filter(|d| !d.is_empty()) {
+        if directive == "off" {
+            config.off = true;
+            continue;
+        }
+        let Some((key, value)) = directive.split_once('=') else {
+            return Err(format!("Unknown directive `{directive}`"));
+        };
+        match key.trim() {
+            "tables" => {
+                let tables = value.split(',').map(str::trim).filter(|t| !t.is_empty());
+                config.tables = Some(tables.map(String::from).collect());
+            }
+            "sample" => {
+                for entry in value.split(',') {
+                    let rate = entry
+                        .split_once(':')
+                        .and_then(|(table, rate)| Some((table.trim(), rate.trim().parse().ok()?)))
+                        .filter(|(table, rate)| !table.is_empty() && (0.0..=1.0).contains(rate));
+                    let Some((table, rate)) = rate else {
+                        return Err(format!(
+                            "Expected `table:rate` with a rate in [0, 1], got `{entry}`"
+                        ));
+                    };
+                    config.sampling.push((table.to_string(), rate));
+                }
+            }
+            "level" => {
+                let level =
+                    Level::parse(value.trim()).ok_or_else(|| format!("Unknown level `{value}`"))?;
+                config.level = Some(level);
+            }
+            key => return Err(format!("Unknown key `{key}`")),
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("").unwrap(), EnvConfig::default());
+        assert!(parse("off").unwrap().off);
+        assert_eq!(
+            parse("tables=requests, errors ; sample=requests:0.1;level=DEBUG").unwrap(),
+            EnvConfig {
+                off: false,
+                tables: Some(vec!["requests".to_string(), "errors".to_string()]),
+                sampling: vec![("requests".to_string(), 0.1)],
+                level: Some(Level::Debug),
+            }
+        );
+        assert_eq!(parse("of").unwrap_err(), "Unknown directive `of`");
+        assert_eq!(parse("table=a").unwrap_err(), "Unknown key `table`");
+        assert!(parse("sample=a:2").is_err());
+        assert!(parse("sample=a").is_err());
+        assert!(parse("level=loud").is_err());
+    }
+}
@@ -0,0 +1,314 @@
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    fmt, io,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::{stats, telemetry};
+
+pub type ErrorHandler = Arc<dyn Fn(&CsvLoggerError) + Send + Sync>;
+
+/// How many of the most recent errors are kept
+const RECENT_ERRORS: usize = 64;
+
+static RECENT: Mutex<VecDeque<ErrorEntry>> = Mutex::new(VecDeque::new());
+
+pub(crate) fn default_error_handler() -> ErrorHandler {
+    Arc::new(|e| eprintln!("csv_logger: {e}"))
+}
+
+/// Records the error before handing it to `handler`, which may panic
+pub(crate) fn report(handler: &ErrorHandler, error: CsvLoggerError) {
+    report_at(handler, SystemTime::now(), error);
+}
+
+/// Like [`report`], recording the error at `at` of the reporting logger's clock
+pub(crate) fn report_at(handler: &ErrorHandler, at: SystemTime, error: CsvLoggerError) {
+    telemetry::error(error.kind());
+    stats::count_error();
+    record(&error, at);
+    handler(&error);
+}
+
+/// An error reported to an error handler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorEntry {
+    pub at: SystemTime,
+    /// See [`CsvLoggerError::kind`]
+    pub kind: &'static str,
+    pub message: String,
+}
+
+fn record(error: &CsvLoggerError, at: SystemTime) {
+    let entry = ErrorEntry {
+        at,
+        kind: error.kind(),
+        message: error.to_string(),
+    };
+    let mut recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    if recent.len() == RECENT_ERRORS {
+        recent.pop_front();
+    }
+    recent.push_back(entry);
+}
+
+/// Up to the 64 most recent errors reported by any logger, oldest first
+pub fn recent_errors() -> Vec<ErrorEntry> {
+    let recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    recent.iter().cloned().collect()
+}
+
+pub fn last_error() -> Option<ErrorEntry> {
+    let recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    recent.back().cloned()
+}
+
+#[derive(Debug)]
+pub enum CsvLoggerError {
+    RotatedFile {
+        table: Cow<'static, str>,
+        epoch: usize,
+        source: io::Error,
+    },
+    Sink {
+        source: io::Error,
+    },
+    IgnoredOption {
+        option: &'static str,
+    },
+    Repair {
+        table: Cow<'static, str>,
+        epoch: usize,
+        source: io::Error,
+    },
+    SchemaMismatch {
+        table: Cow<'static, str>,
+        expected: Vec<String>,
+        got: Vec<String>,
+    },
+    InvalidTableName {
+        table: String,
+    },
+    MissingColumns {
+        table: Cow<'static, str>,
+        columns: Vec<String>,
+    },
+    RateLimited {
+        table: Cow<'static, str>,
+        suppressed: u64,
+    },
+    TableLocked {
+        table: Cow<'static, str>,
+    },
+    FlusherPriority {
+        nice: i32,
+        source: io::Error,
+    },
+    FlushStalled {
+        since: Duration,
+        respawned: bool,
+    },
+    PrimaryUnavailable {
+        table: Cow<'static, str>,
+        source: io::Error,
+    },
+    Spool {
+        table: Cow<'static, str>,
+        source: io::Error,
+    },
+    FreeSpace {
+        source: io::Error,
+    },
+    RawHeader {
+        table: Cow<'static, str>,
+        expected: Vec<String>,
+        got: Vec<String>,
+    },
+    RawFieldCount {
+        table: Cow<'static, str>,
+        expected: usize,
+        got: usize,
+    },
+    /// A record has a field named like the column of `include_epoch_column`
+    EpochColumnCollision {
+        table: Cow<'static, str>,
+    },
+    /// Rows not forwarded to the [`crate::FailoverTee`] because its queue was full
+    TeeQueueFull {
+        dropped: u64,
+    },
+    /// A record that failed to serialize, e.g. one with a sequence while flattening
+    Serialize {
+        table: Cow<'static, str>,
+        message: String,
+    },
+    /// Failed to write a file of a table, e.g. a new log file
+    TableFile {
+        table: Cow<'static, str>,
+        /// What failed, e.g. `create a log file`
+        action: &'static str,
+        source: io::Error,
+    },
+}
+impl CsvLoggerError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CsvLoggerError::RotatedFile { .. } => "rotated_file",
+            CsvLoggerError::Sink { .. } => "sink",
+            CsvLoggerError::IgnoredOption { .. } => "ignored_option",
+            CsvLoggerError::Repair { .. } => "repair",
+            CsvLoggerError::SchemaMismatch { .. } => "schema_mismatch",
+            CsvLoggerError::InvalidTableName { .. } => "invalid_table_name",
+            CsvLoggerError::MissingColumns { .. } => "missing_columns",
+            CsvLoggerError::RateLimited { .. } => "rate_limited",
+            CsvLoggerError::TableLocked { .. } => "table_locked",
+            CsvLoggerError::FlusherPriority { .. } => "flusher_priority",
+            CsvLoggerError::FlushStalled { .. } => "flush_stalled",
+            CsvLoggerError::PrimaryUnavailable { .. } => "primary_unavailable",
+            CsvLoggerError::Spool { .. } => "spool",
+            CsvLoggerError::FreeSpace { .. } => "free_space",
+            CsvLoggerError::RawHeader { .. } => "raw_header",
+            CsvLoggerError::RawFieldCount { .. } => "raw_field_count",
+            CsvLoggerError::EpochColumnCollision { .. } => "epoch_column_collision",
+            CsvLoggerError::TeeQueueFull { .. } => "tee_queue_full",
+            CsvLoggerError::Serialize { .. } => "serialize",
+            CsvLoggerError::TableFile { .. } => "table_file",
+        }
+    }
+}
+impl fmt::Display for CsvLoggerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvLoggerError::RotatedFile {
+                table,
+                epoch,
+                source,
+            } => write!(
+                f,
+                "Failed to handle rotated file of table `{table}` at epoch {epoch}: {source}"
+            ),
+            CsvLoggerError::Sink { source } => write!(f, "Failed to write to the sink: {source}"),
+            CsvLoggerError::IgnoredOption { option } => {
+                write!(f, "`{option}` is ignored by the output target")
+            }
+            CsvLoggerError::Repair {
+                table,
+                epoch,
+                source,
+            } => write!(
+                f,
+                "Failed to repair the log file of table `{table}` at epoch {epoch}: {source}"
+            ),
+            CsvLoggerError::SchemaMismatch {
+                table,
+                expected,
+                got,
+            } => write!(
+                f,
+                "Dropped a row of table `{table}` with columns {got:?} instead of {expected:?}"
+            ),
+            CsvLoggerError::InvalidTableName { table } => {
+                write!(f, "Dropped a row of table `{table}`: invalid table name")
+            }
+            CsvLoggerError::MissingColumns { table, columns } => write!(
+                f,
+                "A row of table `{table}` lacks the ordered columns {columns:?}"
+            ),
+            CsvLoggerError::RateLimited { table, suppressed } => write!(
+                f,
+                "{suppressed} records of table `{table}` suppressed by the rate limit"
+            ),
+            CsvLoggerError::TableLocked { table } => {
+                write!(
+                    f,
+                    "Dropped a row of table `{table}`: locked by another logger"
+                )
+            }
+            CsvLoggerError::FlusherPriority { nice, source } => write!(
+                f,
+                "Failed to set the nice value of the flushing thread to {nice}: {source}"
+            ),
+            CsvLoggerError::FlushStalled { since, respawned } => {
+                write!(f, "The flushing thread has not flushed in {since:?}")?;
+                if *respawned {
+                    write!(f, "; respawned it")?;
+                }
+                Ok(())
+            }
+            CsvLoggerError::PrimaryUnavailable { table, source } => write!(
+                f,
+                "Spooling rows of table `{table}` until its log file is writable again: {source}"
+            ),
+            CsvLoggerError::Spool { table, source } => {
+                write!(f, "Failed to spool rows of table `{table}`: {source}")
+            }
+            CsvLoggerError::FreeSpace { source } => {
+                write!(
+                    f,
+                    "Failed to check the free space of the output directory: {source}"
+                )
+            }
+            CsvLoggerError::RawHeader {
+                table,
+                expected,
+                got,
+            } => write!(
+                f,
+                "Raw header {got:?} of table `{table}` does not match its columns {expected:?}"
+            ),
+            CsvLoggerError::RawFieldCount {
+                table,
+                expected,
+                got,
+            } => write!(
+                f,
+                "Dropped a raw row of table `{table}` with {got} fields instead of {expected}"
+            ),
+            CsvLoggerError::EpochColumnCollision { table } => write!(
+                f,
+                "Dropped a row of table `{table}` with a field named `epoch` while include_epoch_column is on"
+            ),
+            CsvLoggerError::TeeQueueFull { dropped } => write!(
+                f,
+                "Dropped {dropped} rows not forwarded to the tee: its queue was full"
+            ),
+            CsvLoggerError::Serialize { table, message } => write!(
+                f,
+                "Dropped a record of table `{table}` that failed to serialize: {message}"
+            ),
+            CsvLoggerError::TableFile {
+                table,
+                action,
+                source,
+            } => write!(f, "Failed to {action} of table `{table}`: {source}"),
+        }
+    }
+}
+impl std::error::Error for CsvLoggerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CsvLoggerError::RotatedFile { source, .. } => Some(source),
+            CsvLoggerError::Sink { source } => Some(source),
+            CsvLoggerError::IgnoredOption { .. } => None,
+            CsvLoggerError::Repair { source, .. } => Some(source),
+            CsvLoggerError::SchemaMismatch { .. } => None,
+            CsvLoggerError::InvalidTableName { .. } => None,
+            CsvLoggerError::MissingColumns { .. } => None,
+            CsvLoggerError::RateLimited { .. } => None,
+            CsvLoggerError::TableLocked { .. } => None,
+            CsvLoggerError::FlusherPriority { source, .. } => Some(source),
+            CsvLoggerError::FlushStalled { .. } => None,
+            CsvLoggerError::PrimaryUnavailable { source, .. } => Some(source),
+            CsvLoggerError::Spool { source, .. } => Some(source),
+            CsvLoggerError::FreeSpace { source } => Some(source),
+            CsvLoggerError::RawHeader { .. } => None,
+            CsvLoggerError::RawFieldCount { .. } => None,
+            CsvLoggerError::EpochColumnCollision { .. } => None,
+            CsvLoggerError::TeeQueueFull { .. } => None,
+            CsvLoggerError::Serialize { .. } => None,
+            CsvLoggerError::TableFile { source, .. } => Some(source),
+        }
+    }
+}
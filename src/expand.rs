@@ -0,0 +1,111 @@
+//! Expansion of `~` and environment variables in the output directory as configured
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Expands a leading `~` to the home directory and `$VAR` or `${VAR}` to the variable's value
+///
+/// A `$` not followed by a variable name is kept. Paths that are not UTF-8 are left alone.
+pub(crate) fn expand(path: &Path) -> io::Result<PathBuf> {
+    let Some(s) = path.to_str() else {
+        return Ok(path.to_path_buf());
+    };
+    expand_with(s, |name| std::env::var(name).ok())
+        .map(PathBuf::from)
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Cannot expand `{s}`: {e}"),
+            )
+        })
+}
+
+fn expand_with(path: &str, var: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = path;
+    if let Some(after) = path.strip_prefix('~') {
+        if after.is_empty() || after.starts_with(std::path::is_separator) {
+            let home = HOME_VARS
+                .iter()
+                .find_map(|&name| var(name))
+                .ok_or("the home directory is unknown")?;
+            expanded.push_str(&home);
+            rest = after;
+        }
+    }
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, next) = match after.strip_prefix('{') {
+            Some(braced) => {
+                let end = braced.find('}').ok_or("unclosed `${`")?;
+                (&braced[..end], &braced[end + 1..])
+            }
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if name.is_empty() {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+        let value = var(name).ok_or_else(|| format!("`${name}` is not set"))?;
+        expanded.push_str(&value);
+        rest = next;
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(not(windows))]
+const HOME_VARS: &[&str] = &["HOME"];
+#[cfg(windows)]
+const HOME_VARS: &[&str] = &["USERPROFILE", "HOME"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_with() {
+        let var = |name: &str| match name {
+            "HOME" | "USERPROFILE" => Some("/home/me".to_string()),
+            "STATE" => Some("/state".to_string()),
+            "APP_1" => Some("app".to_string()),
+            _ => None,
+        };
+        let expand = |path| expand_with(path, var);
+        assert_eq!(expand("~").unwrap(), "/home/me");
+        assert_eq!(expand("~/logs/app").unwrap(), "/home/me/logs/app");
+        assert_eq!(expand("~other/logs").unwrap(), "~other/logs");
+        assert_eq!(expand("logs/~").unwrap(), "logs/~");
+        assert_eq!(expand("$STATE/$APP_1").unwrap(), "/state/app");
+        assert_eq!(expand("${STATE}/${APP_1}-logs").unwrap(), "/state/app-logs");
+        assert_eq!(expand("$APP_1.d").unwrap(), "app.d");
+        assert_eq!(expand("/logs/$/a$").unwrap(), "/logs/$/a$");
+        assert_eq!(expand("/logs").unwrap(), "/logs");
+        assert_eq!(expand("$UNSET/logs").unwrap_err(), "`$UNSET` is not set");
+        assert_eq!(expand("${STATE/logs").unwrap_err(), "unclosed `${`");
+    }
+
+    #[test]
+    fn test_expand() {
+        std::env::set_var("CSV_LOGGER_TEST_EXPAND", "/var/lib/app");
+        assert_eq!(
+            expand(Path::new("$CSV_LOGGER_TEST_EXPAND/logs")).unwrap(),
+            Path::new("/var/lib/app/logs")
+        );
+        let e = expand(Path::new("${CSV_LOGGER_TEST_UNSET}/logs")).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            e.to_string(),
+            "Cannot expand `${CSV_LOGGER_TEST_UNSET}/logs`: `$CSV_LOGGER_TEST_UNSET` is not set"
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+#[cfg(feature = "zip")]
+use std::io::Seek;
+
+use csv::StringRecord;
+
+use crate::{
+    context::EPOCH_COLUMN,
+    reader::{epoch_files, open_epoch},
+    table_dir::TableFiles,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeStats {
+    pub epochs: usize,
+    pub rows: u64,
+    pub bytes: u64,
+}
+
+/// Concatenates the epoch files of a table in order into one CSV with a single header
+///
+/// The header comes from the first epoch. If `include_epoch`, an `epoch` column is prepended
+/// unless the rows carry one, as written by
+/// [`crate::CsvLoggerBuilder::include_epoch_column`].
+pub fn merge_table(
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+    dest: impl Write,
+    mut include_epoch: bool,
+) -> Result<MergeStats, csv::Error> {
+    let mut dest = Counted {
+        inner: dest,
+        bytes: 0,
+    };
+    let mut stats = MergeStats::default();
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(&mut dest);
+    let mut header_written = false;
+    let mut record = StringRecord::new();
+    let output_dir = output_dir.as_ref();
+    for file in epoch_files(&TableFiles::of(output_dir), output_dir, table_name)? {
+        let Some(mut reader) = open_epoch(&file.path)? else {
+            continue;
+        };
+        let headers = reader.headers()?;
+        if !header_written && !headers.is_empty() {
+            include_epoch &= !headers.iter().any(|column| column == EPOCH_COLUMN);
+            write_row(&mut writer, include_epoch.then_some(EPOCH_COLUMN), headers)?;
+            header_written = true;
+        }
+        let epoch = file.epoch.to_string();
+        let epoch = include_epoch.then_some(epoch.as_str());
+        while reader.read_record(&mut record)? {
+            write_row(&mut writer, epoch, &record)?;
+            stats.rows += 1;
+        }
+        stats.epochs += 1;
+    }
+    writer.flush()?;
+    drop(writer);
+    stats.bytes = dest.bytes;
+    Ok(stats)
+}
+
+fn write_row(
+    writer: &mut csv::Writer<impl Write>,
+    first: Option<&str>,
+    record: &StringRecord,
+) -> Result<(), csv::Error> {
+    writer.write_record(first.into_iter().chain(record.iter()))
+}
+
+struct Counted<W> {
+    inner: W,
+    bytes: u64,
+}
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchiveStats {
+    pub tables: usize,
+    pub files: usize,
+    /// Uncompressed bytes
+    pub bytes: u64,
+}
+
+/// Snapshots every table under the output directory into a zip archive
+///
+/// The registered logger is flushed first. Files are stored as `<table>/<file>`, also if the
+/// layout is flat, or as `<table>/<YYYY>/<MM>/<DD>/<file>` if in date buckets; a trailing line of
+/// an epoch file that has not been completely written is left out.
+#[cfg(feature = "zip")]
+pub fn archive(
+    output_dir: impl AsRef<Path>,
+    dest: impl Write + io::Seek,
+) -> io::Result<ArchiveStats> {
+    use std::{collections::BTreeMap, fs::File, path::PathBuf};
+
+    use crate::{
+        layout::{self, Layout},
+        network_fs,
+        reader::{complete_len, Compression},
+        table_dir,
+    };
+
+    table_log::flush();
+    let output_dir = output_dir.as_ref();
+    // Files by table directory, or by the prefixes of a flat output directory
+    let mut tables = BTreeMap::<String, Vec<(String, PathBuf)>>::new();
+    let flat = layout::of(output_dir) == Layout::Flat;
+    let buckets = layout::of(output_dir) == Layout::DateBuckets;
+    let is_table_file = |name: &str| !name.ends_with(".tmp") && name != table_dir::MAPPING_FILE;
+    for entry in std::fs::read_dir(output_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !flat && entry.file_type()?.is_dir() && name != network_fs::TRASH_DIR {
+            let files = tables.entry(name).or_default();
+            let mut dirs = vec![(String::new(), entry.path())];
+            while let Some((prefix, dir)) = dirs.pop() {
+                for entry in std::fs::read_dir(dir)? {
+                    let entry = entry?;
+                    let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+                    if entry.file_type()?.is_file() && is_table_file(&name) {
+                        files.push((name, entry.path()));
+                    } else if buckets && entry.file_type()?.is_dir() {
+                        dirs.push((format!("{name}/"), entry.path()));
+                    }
+                }
+            }
+        } else if flat && entry.file_type()?.is_file() && is_table_file(&name) {
+            if let Some((table, file)) = table_dir::split_flat(&name) {
+                let file = file.to_string();
+                tables.entry(table).or_default().push((file, entry.path()));
+            }
+        }
+    }
+
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    let mut zip = zip::ZipWriter::new(dest);
+    let mut stats = ArchiveStats::default();
+    for (table_name, mut files) in tables {
+        files.sort_unstable();
+        for (name, path) in files {
+            let mut file = match File::open(&path) {
+                Ok(file) => file,
+                // Removed by retention meanwhile
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let file_name = name.rsplit('/').next().unwrap_or(&name);
+            let len = match Compression::parse(file_name) {
+                Some((_, Compression::None)) => complete_len(&mut file)?,
+                _ => file.metadata()?.len(),
+            };
+            file.seek(io::SeekFrom::Start(0))?;
+            zip.start_file(format!("{table_name}/{name}"), options)?;
+            stats.bytes += io::copy(&mut io::Read::take(file, len), &mut zip)?;
+            stats.files += 1;
+        }
+        stats.tables += 1;
+    }
+    zip.finish()?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    #[cfg(feature = "zip")]
+    use serial_test::serial;
+    use table_log::Logger;
+
+    use crate::{CsvLogger, RotationPolicy};
+
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct TestRecord<'caller> {
+        pub s: &'caller str,
+        pub n: usize,
+    }
+    impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+        fn table_name(&self) -> &'static str {
+            "test"
+        }
+    }
+
+    #[test]
+    fn test_merge_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLogger::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 2,
+            },
+        );
+        for (n, s) in ["a", "b", "c", "d", "e"].into_iter().enumerate() {
+            logger.log(&TestRecord { s, n });
+        }
+        logger.flush();
+
+        let mut merged = vec![];
+        let stats = merge_table(dir.path(), "test", &mut merged, false).unwrap();
+        let expected = "s,n\nc,2\nd,3\ne,4\n";
+        assert_eq!(String::from_utf8(merged).unwrap(), expected);
+        assert_eq!(
+            stats,
+            MergeStats {
+                epochs: 2,
+                rows: 3,
+                bytes: expected.len() as u64,
+            }
+        );
+
+        let mut merged = vec![];
+        merge_table(dir.path(), "test", &mut merged, true).unwrap();
+        assert_eq!(
+            String::from_utf8(merged).unwrap(),
+            "epoch,s,n\n1,c,2\n1,d,3\n2,e,4\n"
+        );
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    #[serial]
+    fn test_archive() {
+        use std::io::{Read, Seek};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLogger::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 2,
+            },
+        );
+        for (n, s) in ["a", "b", "c", "d", "e"].into_iter().enumerate() {
+            logger.log(&TestRecord { s, n });
+        }
+        logger.flush();
+
+        let mut zip = io::Cursor::new(vec![]);
+        let stats = archive(dir.path(), &mut zip).unwrap();
+        assert_eq!(stats.tables, 1);
+        assert_eq!(stats.files, 4);
+
+        zip.rewind().unwrap();
+        let mut zip = zip::ZipArchive::new(zip).unwrap();
+        assert_eq!(zip.len(), 4);
+        for name in ["epoch", "manifest.json", "1.csv", "2.csv"] {
+            let mut archived = vec![];
+            zip.by_name(&format!("test/{name}"))
+                .unwrap()
+                .read_to_end(&mut archived)
+                .unwrap();
+            let source = std::fs::read(dir.path().join("test").join(name)).unwrap();
+            assert_eq!(archived, source);
+        }
+    }
+}
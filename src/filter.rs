@@ -0,0 +1,111 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        RwLock,
+    },
+};
+
+use crate::{row::Row, shared};
+
+/// Table names and `prefix*` patterns
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TableSet {
+    names: HashSet<String>,
+    prefixes: Vec<String>,
+}
+impl TableSet {
+    pub fn new(patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let mut set = Self::default();
+        for pattern in patterns {
+            let pattern = pattern.into();
+            match pattern.strip_suffix('*') {
+                Some(prefix) => set.prefixes.push(prefix.to_string()),
+                None => {
+                    set.names.insert(pattern);
+                }
+            }
+        }
+        set
+    }
+
+    pub fn contains(&self, table: &str) -> bool {
+        self.names.contains(table) || self.prefixes.iter().any(|p| table.starts_with(p.as_str()))
+    }
+}
+
+/// Tells from a serialized row whether to keep it
+pub type RowFilter = Box<dyn Fn(&Row) -> bool + Send + Sync>;
+
+/// Filters of one table or, without a table, of every table; a row is kept if all agree
+#[derive(Default)]
+pub(crate) struct RowFilters {
+    filters: Vec<(Option<String>, RowFilter)>,
+}
+impl RowFilters {
+    pub fn push(&mut self, table: Option<&str>, filter: RowFilter) {
+        self.filters.push((table.map(String::from), filter));
+    }
+
+    /// Replaces the filters registered for exactly `table`
+    pub fn set(&mut self, table: Option<&str>, filter: Option<RowFilter>) {
+        self.filters.retain(|(t, _)| t.as_deref() != table);
+        if let Some(filter) = filter {
+            self.push(table, filter);
+        }
+    }
+
+    /// Whether any filter may see rows of `table`
+    pub fn applies_to(&self, table: &str) -> bool {
+        self.filters
+            .iter()
+            .any(|(filtered, _)| filtered.as_deref().is_none_or(|filtered| filtered == table))
+    }
+
+    pub fn keep(&self, row: &Row) -> bool {
+        self.filters
+            .iter()
+            .filter(|(table, _)| table.as_deref().is_none_or(|table| table == row.table))
+            .all(|(_, filter)| filter(row))
+    }
+}
+
+/// Replaces the filters of `table`, or those of every table if `None`, in the registered logger
+///
+/// Filters of a single table are kept when replacing those of every table and vice versa. `None`
+/// as the filter removes them.
+pub fn set_filter(table: Option<&str>, filter: Option<RowFilter>) {
+    shared::with_registered(|logger| logger.filters.set(table, filter));
+}
+
+static DISABLED_TABLES: RwLock<Option<HashSet<String>>> = RwLock::new(None);
+/// Lets [`table_enabled`] skip the lock while every table is enabled
+static ANY_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Stops or restarts logging to `table` at runtime
+///
+/// Disabling flushes and closes the table's file in the registered logger right away. A
+/// re-enabled table starts a new epoch or appends to the last one per [`crate::ResumePolicy`].
+pub fn set_table_enabled(table: &str, enabled: bool) {
+    {
+        let mut disabled = DISABLED_TABLES.write().unwrap();
+        let set = disabled.get_or_insert_with(HashSet::new);
+        if enabled {
+            set.remove(table);
+        } else {
+            set.insert(table.to_string());
+        }
+        ANY_DISABLED.store(!set.is_empty(), Ordering::Relaxed);
+    }
+    if !enabled {
+        shared::with_registered(|logger| logger.close_table(table));
+    }
+}
+
+pub(crate) fn table_enabled(table: &str) -> bool {
+    if !ANY_DISABLED.load(Ordering::Relaxed) {
+        return true;
+    }
+    let disabled = DISABLED_TABLES.read().unwrap();
+    !disabled.as_ref().is_some_and(|set| set.contains(table))
+}
@@ -0,0 +1,429 @@
+use std::{borrow::Cow, fmt};
+
+use crate::{row::Row, ser::BytesEncoding};
+use serde::{
+    ser::{self, Impossible},
+    Serialize,
+};
+
+/// Serializes a record with nested structs and maps flattened into `outer.inner` columns
+///
+/// Nested values deeper than `max_depth` levels and sequences are errors. Bytes are encoded with
+/// `bytes`, or converted to UTF-8 lossily without.
+pub(crate) fn flatten(
+    table: Cow<'static, str>,
+    record: &(impl Serialize + ?Sized),
+    max_depth: usize,
+    bytes: Option<BytesEncoding>,
+) -> Result<Row, FlattenError> {
+    let mut columns = Columns {
+        header: vec![],
+        fields: vec![],
+        max_depth,
+        bytes,
+    };
+    record.serialize(ValueSerializer {
+        columns: &mut columns,
+        name: String::new(),
+        depth: 0,
+    })?;
+    Ok(Row {
+        table,
+        header: columns.header.into(),
+        fields: columns.fields,
+    })
+}
+
+#[derive(Debug)]
+pub struct FlattenError(String);
+impl fmt::Display for FlattenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl std::error::Error for FlattenError {}
+impl ser::Error for FlattenError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+struct Columns {
+    header: Vec<String>,
+    fields: Vec<String>,
+    max_depth: usize,
+    bytes: Option<BytesEncoding>,
+}
+
+/// Serializes one value named `name`; `depth` is `0` for the record itself
+struct ValueSerializer<'a> {
+    columns: &'a mut Columns,
+    name: String,
+    depth: usize,
+}
+impl<'a> ValueSerializer<'a> {
+    fn push(self, value: String) -> Result<(), FlattenError> {
+        if self.depth == 0 {
+            return Err(FlattenError(
+                "Only structs and maps can be flattened into rows".to_string(),
+            ));
+        }
+        self.columns.header.push(self.name);
+        self.columns.fields.push(value);
+        Ok(())
+    }
+
+    fn nest(self) -> Result<Nested<'a>, FlattenError> {
+        // The record itself is at depth 0 and a struct among its fields at depth 1
+        if self.columns.max_depth < self.depth {
+            return Err(FlattenError(format!(
+                "`{}` is nested deeper than {} levels",
+                self.name, self.columns.max_depth
+            )));
+        }
+        Ok(Nested {
+            columns: self.columns,
+            name: self.name,
+            depth: self.depth,
+            key: None,
+        })
+    }
+
+    fn sequence<T>(self) -> Result<T, FlattenError> {
+        Err(FlattenError(format!(
+            "`{}` is a sequence, which cannot be flattened",
+            self.name
+        )))
+    }
+}
+
+macro_rules! serialize_display {
+    ($($method:ident: $ty:ty,)*) => {
+        $(
+            fn $method(self, v: $ty) -> Result<(), FlattenError> {
+                self.push(v.to_string())
+            }
+        )*
+    };
+}
+
+impl<'a> ser::Serializer for ValueSerializer<'a> {
+    type Ok = ();
+    type Error = FlattenError;
+    type SerializeSeq = Impossible<(), FlattenError>;
+    type SerializeTuple = Impossible<(), FlattenError>;
+    type SerializeTupleStruct = Impossible<(), FlattenError>;
+    type SerializeTupleVariant = Impossible<(), FlattenError>;
+    type SerializeMap = Nested<'a>;
+    type SerializeStruct = Nested<'a>;
+    type SerializeStructVariant = Impossible<(), FlattenError>;
+
+    serialize_display! {
+        serialize_bool: bool,
+        serialize_i8: i8,
+        serialize_i16: i16,
+        serialize_i32: i32,
+        serialize_i64: i64,
+        serialize_i128: i128,
+        serialize_u8: u8,
+        serialize_u16: u16,
+        serialize_u32: u32,
+        serialize_u64: u64,
+        serialize_u128: u128,
+        serialize_char: char,
+        serialize_str: &str,
+    }
+
+    // Same formatting as `csv`
+    fn serialize_f32(self, v: f32) -> Result<(), FlattenError> {
+        self.push(ryu::Buffer::new().format(v).to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), FlattenError> {
+        self.push(ryu::Buffer::new().format(v).to_string())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), FlattenError> {
+        let value = match self.columns.bytes {
+            Some(encoding) => encoding.encode(v),
+            None => String::from_utf8_lossy(v).into_owned(),
+        };
+        self.push(value)
+    }
+
+    fn serialize_none(self) -> Result<(), FlattenError> {
+        self.push(String::new())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), FlattenError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), FlattenError> {
+        self.push(String::new())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), FlattenError> {
+        self.push(String::new())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), FlattenError> {
+        self.push(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), FlattenError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), FlattenError> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, FlattenError> {
+        self.sequence()
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, FlattenError> {
+        self.sequence()
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, FlattenError> {
+        self.sequence()
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, FlattenError> {
+        self.sequence()
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, FlattenError> {
+        self.nest()
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, FlattenError> {
+        self.nest()
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, FlattenError> {
+        Err(FlattenError(format!(
+            "`{}` is the struct variant `{variant}`, which cannot be flattened",
+            self.name
+        )))
+    }
+}
+
+/// Fields of a struct or map named `name`
+struct Nested<'a> {
+    columns: &'a mut Columns,
+    name: String,
+    depth: usize,
+    key: Option<String>,
+}
+impl Nested<'_> {
+    fn field(&mut self, key: &str) -> ValueSerializer<'_> {
+        let name = if self.name.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{key}", self.name)
+        };
+        ValueSerializer {
+            columns: &mut *self.columns,
+            name,
+            depth: self.depth + 1,
+        }
+    }
+}
+impl ser::SerializeStruct for Nested<'_> {
+    type Ok = ();
+    type Error = FlattenError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), FlattenError> {
+        value.serialize(self.field(key))
+    }
+
+    fn end(self) -> Result<(), FlattenError> {
+        Ok(())
+    }
+}
+impl ser::SerializeMap for Nested<'_> {
+    type Ok = ();
+    type Error = FlattenError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), FlattenError> {
+        // A key has to come out as a single plain value
+        let mut columns = Columns {
+            header: vec![],
+            fields: vec![],
+            max_depth: 0,
+            bytes: self.columns.bytes,
+        };
+        key.serialize(ValueSerializer {
+            columns: &mut columns,
+            name: String::new(),
+            depth: 1,
+        })?;
+        let [key] = <[String; 1]>::try_from(columns.fields)
+            .map_err(|_| FlattenError(format!("A key of `{}` is not a plain value", self.name)))?;
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), FlattenError> {
+        let key = self.key.take().unwrap_or_default();
+        value.serialize(self.field(&key))
+    }
+
+    fn end(self) -> Result<(), FlattenError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroUsize,
+        sync::{Arc, Mutex},
+    };
+
+    use table_log::Logger;
+
+    use crate::{log_file_path, CsvLoggerBuilder, RotationPolicy};
+
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct Addr {
+        host: &'static str,
+        port: u16,
+    }
+
+    #[derive(serde::Serialize)]
+    struct Conn {
+        addr: Addr,
+        secure: bool,
+    }
+
+    #[derive(serde::Serialize)]
+    struct Request {
+        id: u64,
+        conn: Conn,
+        latency: f64,
+    }
+    impl table_log::LogRecord<'_> for Request {
+        fn table_name(&self) -> &'static str {
+            "request"
+        }
+    }
+
+    #[derive(serde::Serialize)]
+    struct Batch {
+        ids: Vec<u64>,
+    }
+
+    fn request() -> Request {
+        Request {
+            id: 7,
+            conn: Conn {
+                addr: Addr {
+                    host: "example.com",
+                    port: 443,
+                },
+                secure: true,
+            },
+            latency: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_flatten_nested() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .flatten_nested(true)
+        .build();
+        logger.log(&request());
+        logger.flush();
+        assert_eq!(
+            std::fs::read_to_string(log_file_path(dir.path(), "request", 0)).unwrap(),
+            "id,conn.addr.host,conn.addr.port,conn.secure,latency\n7,example.com,443,true,1.0\n"
+        );
+    }
+
+    #[test]
+    fn test_report_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let errors = Arc::new(Mutex::new(vec![]));
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .flatten_nested(true)
+        .error_handler({
+            let errors = errors.clone();
+            move |e| errors.lock().unwrap().push(e.kind())
+        })
+        .build();
+        logger.log_to("batch", &Batch { ids: vec![1, 2] });
+        logger.log(&request());
+        logger.flush();
+        assert_eq!(*errors.lock().unwrap(), ["serialize"]);
+        assert_eq!(logger.stats().dropped.get("batch"), Some(&1));
+        assert!(!log_file_path(dir.path(), "batch", 0).exists());
+        assert!(log_file_path(dir.path(), "request", 0).exists());
+    }
+
+    #[test]
+    fn test_flatten_errors() {
+        assert!(flatten("request".into(), &request(), 2, None).is_ok());
+        let e = flatten("request".into(), &request(), 1, None).unwrap_err();
+        assert!(e.to_string().contains("conn.addr"));
+        let e = flatten("batch".into(), &Batch { ids: vec![1] }, 3, None).unwrap_err();
+        assert!(e.to_string().contains("ids"));
+    }
+}
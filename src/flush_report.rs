@@ -0,0 +1,29 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    time::Duration,
+};
+
+/// Called after every flush of the logger, periodic or explicit
+pub type FlushCallback = Box<dyn Fn(FlushReport) + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlushReport {
+    /// Tables that had anything to flush, by name
+    pub tables: Vec<TableFlush>,
+    /// Wall-clock time the flush took
+    pub duration: Duration,
+}
+
+/// What a table flushed, including what it wrote out on its own since the last report, e.g. when
+/// rotating
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableFlush {
+    pub table: String,
+    pub records: u64,
+    pub bytes: u64,
+}
+
+/// A panicking callback does not take the logger down
+pub(crate) fn notify(callback: &FlushCallback, report: FlushReport) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| callback(report)));
+}
@@ -0,0 +1,236 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    error::{self, default_error_handler, CsvLoggerError, ErrorHandler},
+    shared, CsvLoggerHandle,
+};
+
+/// How the registered logger was last scheduled to be flushed, if at all
+static SCHEDULE: Mutex<Option<(Schedule, FlusherThread)>> = Mutex::new(None);
+/// Of the thread last spawned per [`SCHEDULE`]
+static SCHEDULED_STATUS: Mutex<Option<Arc<FlusherStatus>>> = Mutex::new(None);
+
+/// How the flushing thread is spawned
+#[derive(Clone)]
+pub(crate) struct FlusherThread {
+    pub name: String,
+    /// Applied from within the thread
+    pub nice: Option<i32>,
+    pub error_handler: ErrorHandler,
+    /// Tells when to flush
+    pub clock: Arc<dyn Clock>,
+}
+impl Default for FlusherThread {
+    fn default() -> Self {
+        Self {
+            name: "CsvLogger::flush()".to_string(),
+            nice: None,
+            error_handler: default_error_handler(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+impl FlusherThread {
+    /// Keeps going at the default priority if the nice value cannot be set
+    fn prioritize(&self) {
+        let Some(nice) = self.nice else {
+            return;
+        };
+        if let Err(source) = set_nice(nice) {
+            let error = CsvLoggerError::FlusherPriority { nice, source };
+            error::report(&self.error_handler, error);
+        }
+    }
+}
+
+/// Only Linux applies the nice value of a thread ID to that thread alone
+#[cfg(all(target_os = "linux", feature = "thread-priority"))]
+fn set_nice(nice: i32) -> io::Result<()> {
+    let tid = unsafe { libc::gettid() };
+    let ret = unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice) };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(all(target_os = "linux", feature = "thread-priority")))]
+fn set_nice(_nice: i32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "thread priorities need Linux and the `thread-priority` feature",
+    ))
+}
+
+/// How the registered logger is flushed in the background
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Schedule {
+    /// Everything every interval
+    Global(Duration),
+    /// Tables whose flush interval has passed, checked every tick
+    PerTable(Duration),
+}
+impl Schedule {
+    pub fn spawn(self, thread: FlusherThread) -> io::Result<FlusherHandle> {
+        *SCHEDULE.lock().unwrap() = Some((self, thread.clone()));
+        let handle = match self {
+            Schedule::Global(interval) => FlusherHandle::spawn(thread, interval, table_log::flush)?,
+            Schedule::PerTable(tick) => FlusherHandle::spawn(thread, tick, || {
+                shared::with_registered(|logger| logger.flush_due());
+            })?,
+        };
+        *SCHEDULED_STATUS.lock().unwrap() = Some(handle.status.clone());
+        Ok(handle)
+    }
+
+    /// Spawns the flushing thread again, e.g. in a child process where it did not survive `fork`
+    pub fn respawn() -> io::Result<Option<FlusherHandle>> {
+        let schedule = SCHEDULE.lock().unwrap().clone();
+        schedule
+            .map(|(schedule, thread)| schedule.spawn(thread))
+            .transpose()
+    }
+}
+
+/// Whether the flushing thread is alive and when it last flushed
+#[derive(Debug)]
+pub(crate) struct FlusherStatus {
+    running: AtomicBool,
+    spawned_at: Instant,
+    last_run: Mutex<Option<(Instant, SystemTime)>>,
+}
+impl FlusherStatus {
+    pub fn running(&self) -> bool {
+        self.running.load(Ordering::Acquire)
+    }
+
+    pub fn last_run(&self) -> Option<SystemTime> {
+        let last_run = self.last_run.lock().unwrap_or_else(|e| e.into_inner());
+        last_run.map(|(_, at)| at)
+    }
+
+    /// Time until `now` since the thread last flushed, or since it was spawned if it never did
+    pub fn since_heartbeat(&self, now: Instant) -> Duration {
+        let last_run = self.last_run.lock().unwrap_or_else(|e| e.into_inner());
+        let heartbeat = last_run.map_or(self.spawned_at, |(at, _)| at);
+        now.saturating_duration_since(heartbeat)
+    }
+}
+
+/// Marks the thread stopped however it exits, panics included
+struct Running(Arc<FlusherStatus>);
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.running.store(false, Ordering::Release);
+    }
+}
+
+/// Status of the thread flushing the registered logger, if one was ever scheduled
+pub(crate) fn scheduled_status() -> Option<Arc<FlusherStatus>> {
+    let status = SCHEDULED_STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    status.clone()
+}
+
+/// Handle to the background thread that flushes the logger periodically
+///
+/// Dropping the handle leaves the thread running.
+pub struct FlusherHandle {
+    shutdown: Arc<AtomicBool>,
+    status: Arc<FlusherStatus>,
+    thread: JoinHandle<()>,
+}
+impl FlusherHandle {
+    pub(crate) fn spawn(
+        config: FlusherThread,
+        interval: Duration,
+        mut flush: impl FnMut() + Send + 'static,
+    ) -> io::Result<Self> {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let status = Arc::new(FlusherStatus {
+            running: AtomicBool::new(true),
+            spawned_at: config.clock.instant(),
+            last_run: Mutex::new(None),
+        });
+        let thread = std::thread::Builder::new()
+            .name(config.name.clone())
+            .spawn({
+                let shutdown = shutdown.clone();
+                let status = status.clone();
+                move || {
+                    let running = Running(status);
+                    config.prioritize();
+                    let clock = config.clock.clone();
+                    loop {
+                        let deadline = clock.instant() + interval;
+                        loop {
+                            if shutdown.load(Ordering::Acquire) {
+                                return;
+                            }
+                            let now = clock.instant();
+                            if deadline <= now {
+                                break;
+                            }
+                            clock.park_timeout(deadline - now);
+                        }
+                        flush();
+                        let heartbeat = (clock.instant(), clock.now());
+                        *running.0.last_run.lock().unwrap() = Some(heartbeat);
+                    }
+                }
+            })?;
+        Ok(Self {
+            shutdown,
+            status,
+            thread,
+        })
+    }
+
+    pub fn thread(&self) -> &std::thread::Thread {
+        self.thread.thread()
+    }
+
+    /// For diagnostics, e.g. [`JoinHandle::is_finished`]
+    pub fn join_handle(&self) -> &JoinHandle<()> {
+        &self.thread
+    }
+
+    /// Stops the thread and waits for it to exit
+    ///
+    /// The caller is responsible for the final flush.
+    pub fn shutdown(self) {
+        self.shutdown.store(true, Ordering::Release);
+        self.thread.thread().unpark();
+        let _ = self.thread.join();
+    }
+}
+
+/// Stops the flushing thread of a [`CsvLoggerHandle`] and flushes one last time when dropped
+pub struct FlusherGuard {
+    flusher: Option<FlusherHandle>,
+    handle: CsvLoggerHandle,
+}
+impl FlusherGuard {
+    pub(crate) fn new(flusher: FlusherHandle, handle: CsvLoggerHandle) -> Self {
+        Self {
+            flusher: Some(flusher),
+            handle,
+        }
+    }
+}
+impl Drop for FlusherGuard {
+    fn drop(&mut self) {
+        if let Some(flusher) = self.flusher.take() {
+            flusher.shutdown();
+        }
+        self.handle.flush();
+    }
+}
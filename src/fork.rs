@@ -0,0 +1,26 @@
+use std::io;
+
+use crate::{
+    flusher::{FlusherHandle, Schedule},
+    shared,
+};
+
+/// Makes the registered logger safe to use in a child process right after `fork`
+///
+/// Call it in the child before it logs anything. It is not called from a `pthread_atfork`
+/// handler since it locks, allocates and spawns a thread, none of which is safe there.
+///
+/// The child forgets the files it inherited without flushing them, so rows the parent buffered
+/// are written by the parent alone, and logs under the `pid-<child pid>` subdirectory of the
+/// output directory from then on. Forwarders such as the failover tee are dropped in the child.
+/// The flushing thread does not survive `fork` either; it is spawned again and returned if the
+/// logger was initialized with `auto_flush`, failing if it cannot be. The parent is unaffected.
+///
+/// Another thread of the parent holding the logger at the time of `fork` deadlocks the child.
+pub fn after_fork_in_child() -> io::Result<Option<FlusherHandle>> {
+    let subdir = format!("pid-{}", std::process::id());
+    if shared::with_registered(|logger| logger.forget_files(&subdir)).is_none() {
+        return Ok(None);
+    }
+    Schedule::respawn()
+}
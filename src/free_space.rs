@@ -0,0 +1,178 @@
+//! Keeping the filesystem of the output directory from filling up, checked when flushing
+
+use std::{
+    io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// Tells how many bytes the process may still write to the filesystem holding a path
+pub trait SpaceProvider: Send {
+    fn available(&self, path: &Path) -> io::Result<u64>;
+}
+impl<F> SpaceProvider for F
+where
+    F: Fn(&Path) -> io::Result<u64> + Send,
+{
+    fn available(&self, path: &Path) -> io::Result<u64> {
+        self(path)
+    }
+}
+
+/// Asks the operating system: `statvfs` on Unix, which needs the `free-space` feature, and
+/// `GetDiskFreeSpaceExW` on Windows
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsSpace;
+impl SpaceProvider for OsSpace {
+    fn available(&self, path: &Path) -> io::Result<u64> {
+        platform::available(path)
+    }
+}
+
+/// When to make room on the filesystem of the output directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreeSpacePolicy {
+    /// Below this, epochs other than the current ones of the tables in the output directory are
+    /// deleted, the furthest behind their tables' current epochs first, until there is enough
+    /// again
+    pub min_free_bytes: u64,
+    /// Below this even after deleting, logging is paused if `pause`
+    pub critical_free_bytes: u64,
+    /// Whether to drop the records of this logger once space is critical, until there is
+    /// `min_free_bytes` again
+    ///
+    /// This pause is apart from [`crate::pause`], which it neither sets nor lifts.
+    pub pause: bool,
+    /// Time between checks, which are made by flushes
+    pub check_interval: Duration,
+}
+
+pub(crate) struct FreeSpace {
+    pub policy: FreeSpacePolicy,
+    provider: Box<dyn SpaceProvider>,
+    checked_at: Option<Instant>,
+    /// Whether the logger drops records for want of space
+    pub paused: bool,
+}
+impl FreeSpace {
+    pub fn new(policy: FreeSpacePolicy, provider: Box<dyn SpaceProvider>) -> Self {
+        Self {
+            policy,
+            provider,
+            checked_at: None,
+            paused: false,
+        }
+    }
+
+    /// Whether a check is due at `now`, which then counts as checked
+    pub fn due(&mut self, now: Instant) -> bool {
+        if self
+            .checked_at
+            .is_some_and(|at| now.duration_since(at) < self.policy.check_interval)
+        {
+            return false;
+        }
+        self.checked_at = Some(now);
+        true
+    }
+
+    pub fn available(&self, output_dir: &Path) -> io::Result<u64> {
+        // The output directory may not have been created yet
+        let dir = output_dir
+            .ancestors()
+            .find(|dir| dir.exists())
+            .unwrap_or(output_dir);
+        self.provider.available(dir)
+    }
+}
+
+#[cfg(all(unix, feature = "free-space"))]
+mod platform {
+    use std::{ffi::CString, io, os::unix::ffi::OsStrExt, path::Path};
+
+    pub fn available(path: &Path) -> io::Result<u64> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let mut stat = unsafe { std::mem::zeroed::<libc::statvfs>() };
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        #[allow(clippy::unnecessary_cast)]
+        Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::{io, os::windows::ffi::OsStrExt, path::Path};
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            free_to_caller: *mut u64,
+            total: *mut u64,
+            total_free: *mut u64,
+        ) -> i32;
+    }
+
+    pub fn available(path: &Path) -> io::Result<u64> {
+        let path = path
+            .as_os_str()
+            .encode_wide()
+            .chain([0])
+            .collect::<Vec<_>>();
+        let mut free = 0;
+        let ok = unsafe {
+            GetDiskFreeSpaceExW(
+                path.as_ptr(),
+                &mut free,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(free)
+    }
+}
+
+#[cfg(not(any(all(unix, feature = "free-space"), windows)))]
+mod platform {
+    use std::{io, path::Path};
+
+    pub fn available(_path: &Path) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "checking free space needs Windows, or Unix and the `free-space` feature",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_due() {
+        let policy = FreeSpacePolicy {
+            min_free_bytes: 0,
+            critical_free_bytes: 0,
+            pause: false,
+            check_interval: Duration::from_secs(10),
+        };
+        let mut free_space =
+            FreeSpace::new(policy, Box::new(|_: &Path| -> io::Result<u64> { Ok(0) }));
+        let now = Instant::now();
+        assert!(free_space.due(now));
+        assert!(!free_space.due(now + Duration::from_secs(9)));
+        assert!(free_space.due(now + Duration::from_secs(10)));
+    }
+
+    #[cfg(all(unix, feature = "free-space"))]
+    #[test]
+    fn test_os_space() {
+        let dir = tempfile::tempdir().unwrap();
+        OsSpace.available(dir.path()).unwrap();
+    }
+}
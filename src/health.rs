@@ -0,0 +1,75 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use chrono::SecondsFormat;
+
+use crate::{error::ErrorHandler, stats::Totals};
+
+/// The table heartbeats are written to
+pub const HEALTH_TABLE: &str = "csv_logger_health";
+
+/// A row of [`HEALTH_TABLE`]; counts are since the previous heartbeat and across loggers
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct Heartbeat {
+    timestamp: String,
+    tables_open: usize,
+    records: u64,
+    bytes: u64,
+    dropped: u64,
+    /// Empty if no error was reported
+    last_error: String,
+}
+
+/// Writes a heartbeat every interval when the logger flushes
+pub(crate) struct Health {
+    interval: Duration,
+    beat_at: Instant,
+    totals: Totals,
+    /// Only ever set by the error handler, which therefore never logs anything itself
+    last_error: Arc<Mutex<Option<String>>>,
+}
+impl Health {
+    /// Returns the error handler to use instead of `error_handler` so that heartbeats see the
+    /// errors
+    pub fn new(
+        interval: Duration,
+        now: Instant,
+        error_handler: ErrorHandler,
+    ) -> (Self, ErrorHandler) {
+        let last_error = Arc::new(Mutex::new(None));
+        let health = Self {
+            interval,
+            beat_at: now,
+            totals: Totals::now(),
+            last_error: last_error.clone(),
+        };
+        let error_handler: ErrorHandler = Arc::new(move |e| {
+            *last_error.lock().unwrap() = Some(e.to_string());
+            error_handler(e);
+        });
+        (health, error_handler)
+    }
+
+    /// The heartbeat if the interval has passed since the last one
+    pub fn beat(&mut self, now: Instant, at: SystemTime, tables_open: usize) -> Option<Heartbeat> {
+        if now < self.beat_at + self.interval {
+            return None;
+        }
+        self.beat_at = now;
+        let totals = Totals::now();
+        let since = totals.since(&self.totals);
+        self.totals = totals;
+        let timestamp =
+            chrono::DateTime::<chrono::Utc>::from(at).to_rfc3339_opts(SecondsFormat::Millis, true);
+        Some(Heartbeat {
+            timestamp,
+            tables_open,
+            records: since.records,
+            bytes: since.bytes,
+            dropped: since.dropped,
+            last_error: self.last_error.lock().unwrap().take().unwrap_or_default(),
+        })
+    }
+}
@@ -0,0 +1,32 @@
+use std::{fs::File, io, path::Path};
+
+use crate::rotated::{RotatedFileDisposition, RotatedFileHandler};
+
+/// POSTs each rotated epoch file to `url` and deletes it once the upload succeeds
+///
+/// The table name and the epoch are sent in the `X-Table` and `X-Epoch` headers.
+pub struct HttpUploader {
+    url: String,
+    agent: ureq::Agent,
+}
+impl HttpUploader {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            agent: ureq::Agent::new(),
+        }
+    }
+}
+impl RotatedFileHandler for HttpUploader {
+    fn handle(&self, table: &str, epoch: usize, path: &Path) -> io::Result<RotatedFileDisposition> {
+        let file = File::open(path)?;
+        self.agent
+            .post(&self.url)
+            .set("Content-Type", "text/csv")
+            .set("X-Table", table)
+            .set("X-Epoch", &epoch.to_string())
+            .send(file)
+            .map_err(io::Error::other)?;
+        Ok(RotatedFileDisposition::Delete)
+    }
+}
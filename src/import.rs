@@ -0,0 +1,308 @@
+//! Importing CSV files written elsewhere into a table as epochs of their own
+//!
+//! An imported file goes in front of the table's epochs or after them, with the epoch file and the
+//! manifest updated so that readers and retention treat it like any other epoch.
+
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    checksum, epoch_file_path, is_valid_table_name, lock, manifest,
+    metadata::SkipMetadata,
+    reader::{self, EpochFile},
+    shared::{self, CsvLoggerHandle},
+    storage::{Backend, RealFs, Storage},
+    table_dir::{self, TableFiles},
+};
+
+/// Where an imported file goes among the epochs of its table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportPosition {
+    /// Before the oldest epoch, numbering the existing epochs one higher if it is epoch 0
+    BeforeExisting,
+    /// After the newest epoch, which the logger then starts its next epoch after
+    AfterExisting,
+}
+
+/// Copies the CSV file `src` into `table` of `namespace` as a new epoch and returns the epoch
+///
+/// The header of `src` must be that of the epoch it is placed next to. If the registered logger
+/// writes to the directory, the import goes through it, closing the table first; the table is
+/// reopened by its next record. Fails with [`io::ErrorKind::WouldBlock`] if another logger of this
+/// process has the table open or another process holds its lock. Loggers hold the lock of their
+/// open tables whenever it is free, see [`crate::CsvLoggerBuilder::conflict_policy`].
+pub fn import_file(
+    output_dir: impl AsRef<Path>,
+    namespace: Option<&str>,
+    table: &str,
+    src: &Path,
+    position: ImportPosition,
+) -> io::Result<usize> {
+    let output_dir = table_dir::namespace_dir(output_dir, namespace)?;
+    if let Some(inner) = shared::registered() {
+        let handle = CsvLoggerHandle { inner };
+        let canonical = lock::canonical(&output_dir);
+        if let Some(imported) = handle.with(|logger| {
+            (lock::canonical(&logger.output_dir) == canonical)
+                .then(|| logger.import_file(table, src, position))
+        }) {
+            return imported;
+        }
+    }
+    import_locked(
+        &TableFiles::of(&output_dir),
+        &output_dir,
+        table,
+        src,
+        position,
+    )
+}
+
+/// Imports while no logger of this process has the table open
+pub(crate) fn import_locked(
+    files: &TableFiles,
+    output_dir: &Path,
+    table: &str,
+    src: &Path,
+    position: ImportPosition,
+) -> io::Result<usize> {
+    if !is_valid_table_name(table) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid table name `{table}`"),
+        ));
+    }
+    if lock::is_open(output_dir, table) {
+        return Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            format!("Table `{table}` is open in a logger of this process"),
+        ));
+    }
+    let Some(_lock) = lock::lock(&Backend::Real, files, output_dir, table, false)? else {
+        return Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            format!("Table `{table}` is locked by another logger"),
+        ));
+    };
+    let (header, records) = read_src(src)?;
+    let epochs = reader::epoch_files(files, output_dir, table)?;
+    let neighbor = match position {
+        ImportPosition::BeforeExisting => epochs.first(),
+        ImportPosition::AfterExisting => epochs.last(),
+    };
+    if let Some(expected) = neighbor.map(header_of).transpose()?.flatten() {
+        if expected != header {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Header {header:?} of the imported file is not {expected:?}"),
+            ));
+        }
+    }
+
+    let manifest = manifest::load(&RealFs, files, output_dir, table);
+    let (epoch, shifted) = match (epochs.first(), epochs.last()) {
+        (Some(first), Some(last)) => match position {
+            ImportPosition::BeforeExisting if first.epoch == 0 => {
+                shift(files, output_dir, table, &epochs)?;
+                (0, true)
+            }
+            ImportPosition::BeforeExisting => (first.epoch - 1, false),
+            ImportPosition::AfterExisting => (last.epoch + 1, false),
+        },
+        _ => (0, false),
+    };
+    let dest = files.epoch_file(&RealFs, output_dir, table, epoch, true);
+    std::fs::create_dir_all(dest.parent().unwrap())?;
+    let tmp = dest.with_extension("csv.tmp");
+    let bytes = std::fs::copy(src, &tmp)?;
+    std::fs::rename(&tmp, &dest)?;
+    table_dir::record(&RealFs, output_dir, table)?;
+
+    let recorded = reader::epoch_files(files, output_dir, table)?
+        .last()
+        .map_or(epoch, |file| file.epoch);
+    RealFs.replace(
+        &epoch_file_path(files, output_dir, table),
+        recorded.to_string().as_bytes(),
+    )?;
+    match manifest {
+        Some(mut manifest) => {
+            if shifted {
+                manifest.shift();
+            }
+            manifest.close(epoch, records, bytes);
+            // Written elsewhere, without newlines escaped
+            manifest.set_single_line_fields(epoch, false);
+            manifest::store(&RealFs, files, output_dir, table, &manifest)?;
+        }
+        None => manifest::update(&RealFs, files, output_dir, table, |manifest| {
+            manifest.set_single_line_fields(epoch, false);
+        })?,
+    }
+    Ok(epoch)
+}
+
+/// The header and the number of rows of the file to import
+fn read_src(src: &Path) -> io::Result<(Vec<String>, u64)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(SkipMetadata::new(File::open(src)?));
+    let header = reader.headers()?.iter().map(String::from).collect();
+    let mut records = 0;
+    for record in reader.records() {
+        record?;
+        records += 1;
+    }
+    Ok((header, records))
+}
+
+fn header_of(file: &EpochFile) -> io::Result<Option<Vec<String>>> {
+    let Some(mut reader) = reader::open_epoch(&file.path)? else {
+        return Ok(None);
+    };
+    let header = reader.headers()?;
+    Ok((!header.is_empty()).then(|| header.iter().map(String::from).collect()))
+}
+
+/// Renames the epoch files and their checksums one epoch higher, newest first
+///
+/// Renames back what it renamed if one of the renames fails.
+fn shift(
+    files: &TableFiles,
+    output_dir: &Path,
+    table: &str,
+    epochs: &[EpochFile],
+) -> io::Result<()> {
+    let mut renamed = vec![];
+    let result = (|| {
+        for file in epochs.iter().rev() {
+            let to = shifted_path(files, table, file);
+            let sidecar = checksum::sidecar_path(&file.path);
+            std::fs::rename(&file.path, &to)?;
+            renamed.push((file.path.clone(), to.clone()));
+            if sidecar.exists() {
+                let sidecar_to = checksum::sidecar_path(&to);
+                std::fs::rename(&sidecar, &sidecar_to)?;
+                renamed.push((sidecar, sidecar_to));
+            }
+        }
+        Ok(())
+    })();
+    if result.is_err() {
+        for (from, to) in renamed.iter().rev() {
+            let _ = std::fs::rename(to, from);
+        }
+    }
+    for file in epochs {
+        files.forget_bucket(output_dir, table, file.epoch);
+        files.forget_bucket(output_dir, table, file.epoch + 1);
+    }
+    result
+}
+
+/// The path of an epoch file numbered one higher, keeping its prefix and extension
+fn shifted_path(files: &TableFiles, table: &str, file: &EpochFile) -> PathBuf {
+    let name = file.path.file_name().unwrap().to_str().unwrap();
+    let own = files.file_of(table, name).unwrap();
+    let prefix = &name[..name.len() - own.len()];
+    let extension = own.trim_start_matches(|c: char| c.is_ascii_digit());
+    file.path
+        .with_file_name(format!("{prefix}{}{extension}", file.epoch + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use crate::{log_file_path, reader::TableReader, CsvLogger, RotationPolicy};
+
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct TestRecord {
+        pub s: &'static str,
+    }
+
+    #[test]
+    fn test_import_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLogger::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        );
+        for s in ["a", "b", "c"] {
+            logger.log_to("test", &TestRecord { s });
+        }
+        let src = tempfile::tempdir().unwrap();
+        let write_src = |name: &str, contents: &str| {
+            let path = src.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            path
+        };
+
+        let old = write_src("old.csv", "s\nold\n");
+        let epoch = logger
+            .import_file("test", &old, ImportPosition::BeforeExisting)
+            .unwrap();
+        assert_eq!(epoch, 0);
+        let new = write_src("new.csv", "s\nnew\n");
+        let epoch = logger
+            .import_file("test", &new, ImportPosition::AfterExisting)
+            .unwrap();
+        assert_eq!(epoch, 3);
+        let mismatched = write_src("mismatched.csv", "n\n1\n");
+        let error = logger
+            .import_file("test", &mismatched, ImportPosition::AfterExisting)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // The logger goes on after the imported epoch
+        logger.log_to("test", &TestRecord { s: "d" });
+        logger.flush();
+        assert!(log_file_path(dir.path(), "test", 4).exists());
+
+        let rows = TableReader::open(dir.path(), None, "test")
+            .unwrap()
+            .records()
+            .map(|record| record.unwrap()[0].to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(rows, ["old", "a", "b", "c", "new", "d"]);
+        let manifest = manifest::read_manifest(dir.path(), "test").unwrap();
+        let epochs = manifest.epochs.iter().map(|e| e.epoch).collect::<Vec<_>>();
+        assert_eq!(epochs, [0, 1, 2, 3, 4]);
+        assert_eq!(manifest.get(0).unwrap().records, 1);
+        assert_eq!(manifest.get(2).unwrap().file, "2.csv");
+    }
+
+    #[test]
+    fn test_import_into_open_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLogger::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        );
+        logger.log_to("test", &TestRecord { s: "a" });
+        let src_dir = tempfile::tempdir().unwrap();
+        let src = src_dir.path().join("new.csv");
+        std::fs::write(&src, "s\nnew\n").unwrap();
+
+        // Through a path spelled differently
+        let spelled = dir.path().join(".");
+        let error =
+            import_file(&spelled, None, "test", &src, ImportPosition::AfterExisting).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+
+        logger.close_table("test");
+        let epoch = import_file(&spelled, None, "test", &src, ImportPosition::AfterExisting);
+        assert_eq!(epoch.unwrap(), 1);
+    }
+}
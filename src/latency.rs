@@ -0,0 +1,116 @@
+//! How long logging a record takes, in a histogram of fixed buckets updated without locks
+//!
+//! Bucket `i < 16` holds exactly `i` nanoseconds; above that every power of two is split into 8
+//! buckets, so a bucket is at most 12.5% wide.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+const BUCKETS: usize = bucket_of(u64::MAX) + 1;
+
+static COUNTS: [AtomicU64; BUCKETS] = [const { AtomicU64::new(0) }; BUCKETS];
+static MAX: AtomicU64 = AtomicU64::new(0);
+
+/// Latencies of every [`crate::CsvLogger`] logging a record since the process started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySnapshot {
+    pub count: u64,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// Percentiles are the upper bounds of their buckets, capped at the max
+pub fn latency_snapshot() -> LatencySnapshot {
+    let counts = COUNTS
+        .iter()
+        .map(|count| count.load(Ordering::Relaxed))
+        .collect::<Vec<_>>();
+    let count: u64 = counts.iter().sum();
+    let max = MAX.load(Ordering::Relaxed);
+    let percentile = |p: u64| {
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = (count * p).div_ceil(100).max(1);
+        let mut seen = 0;
+        let bucket = counts
+            .iter()
+            .position(|&n| {
+                seen += n;
+                rank <= seen
+            })
+            .unwrap_or(BUCKETS - 1);
+        Duration::from_nanos(upper_bound(bucket).min(max))
+    };
+    LatencySnapshot {
+        count,
+        p50: percentile(50),
+        p95: percentile(95),
+        p99: percentile(99),
+        max: Duration::from_nanos(max),
+    }
+}
+
+/// Records the time from its start until it is dropped
+pub(crate) struct Timer {
+    start: Instant,
+}
+impl Timer {
+    pub fn start() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+impl Drop for Timer {
+    fn drop(&mut self) {
+        record(self.start.elapsed());
+    }
+}
+
+fn record(latency: Duration) {
+    let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+    COUNTS[bucket_of(nanos)].fetch_add(1, Ordering::Relaxed);
+    MAX.fetch_max(nanos, Ordering::Relaxed);
+}
+
+const fn bucket_of(nanos: u64) -> usize {
+    if nanos < 2 * SUB_BUCKETS {
+        return nanos as usize;
+    }
+    let shift = 63 - nanos.leading_zeros() - SUB_BUCKET_BITS;
+    (shift as u64 * SUB_BUCKETS + (nanos >> shift)) as usize
+}
+
+fn upper_bound(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < 2 * SUB_BUCKETS {
+        return bucket;
+    }
+    let shift = bucket / SUB_BUCKETS - 1;
+    let mantissa = bucket % SUB_BUCKETS + SUB_BUCKETS;
+    (mantissa << shift) + ((1 << shift) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets() {
+        for nanos in [0, 1, 15, 16, 17, 31, 32, 1_000, 123_456_789, u64::MAX] {
+            let bucket = bucket_of(nanos);
+            assert!(nanos <= upper_bound(bucket), "{nanos}");
+            if bucket > 0 {
+                assert!(upper_bound(bucket - 1) < nanos, "{nanos}");
+            }
+        }
+        assert_eq!(upper_bound(BUCKETS - 1), u64::MAX);
+    }
+}
@@ -0,0 +1,72 @@
+//! Whether tables get directories of their own or share the output directory
+//!
+//! A flat logger leaves a `layout` marker in each directory it writes tables to, as does one with
+//! date buckets, for readers that only see the directory.
+
+use std::{io, path::Path};
+
+use crate::storage::{self, Storage};
+
+const MARKER: &str = "layout";
+const FLAT: &str = "flat";
+const DATE_BUCKETS: &str = "date-buckets";
+
+/// How the files of tables are laid out in the output directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// `<output_dir>/<table>/<epoch>.csv`
+    #[default]
+    PerTableDir,
+    /// `<output_dir>/<table>.<epoch>.csv`, for collectors that cannot recurse into directories
+    ///
+    /// The other files of a table are prefixed the same way, e.g. `<table>.epoch`. Dots and `%`
+    /// in table names are percent-encoded, so a file belongs to the table named before its first
+    /// dot.
+    Flat,
+    /// `<output_dir>/<table>/<YYYY>/<MM>/<DD>/<epoch>.csv`, by the UTC date each epoch was created
+    ///
+    /// The other files of a table stay in its directory and epochs count up across dates.
+    /// Retention removes date directories it empties.
+    DateBuckets,
+}
+
+/// Marks the layout of `output_dir` for readers, removing the marker of an earlier logger if laid
+/// out per table directory
+pub(crate) fn mark(storage: &impl Storage, output_dir: &Path, layout: Layout) -> io::Result<()> {
+    let path = output_dir.join(MARKER);
+    let marker = match layout {
+        Layout::PerTableDir => return storage::remove_if_exists(storage, &path).map(|_| ()),
+        Layout::Flat => FLAT,
+        Layout::DateBuckets => DATE_BUCKETS,
+    };
+    storage.replace(&path, marker.as_bytes())
+}
+
+/// The layout of `output_dir` per its marker
+pub(crate) fn of(output_dir: &Path) -> Layout {
+    let marker = std::fs::read_to_string(output_dir.join(MARKER)).unwrap_or_default();
+    match marker.trim() {
+        FLAT => Layout::Flat,
+        DATE_BUCKETS => Layout::DateBuckets,
+        _ => Layout::PerTableDir,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::RealFs;
+
+    #[test]
+    fn test_of() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(of(dir.path()), Layout::PerTableDir);
+        mark(&RealFs, dir.path(), Layout::Flat).unwrap();
+        assert_eq!(of(dir.path()), Layout::Flat);
+        mark(&RealFs, dir.path(), Layout::DateBuckets).unwrap();
+        assert_eq!(of(dir.path()), Layout::DateBuckets);
+        mark(&RealFs, dir.path(), Layout::PerTableDir).unwrap();
+        assert_eq!(of(dir.path()), Layout::PerTableDir);
+        assert!(!dir.path().join(MARKER).exists());
+    }
+}
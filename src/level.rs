@@ -0,0 +1,114 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        RwLock,
+    },
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Trace,
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+impl Level {
+    fn from_u8(n: u8) -> Self {
+        match n {
+            0 => Level::Trace,
+            1 => Level::Debug,
+            2 => Level::Info,
+            3 => Level::Warn,
+            _ => Level::Error,
+        }
+    }
+
+    /// Parses a level name regardless of case
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        Some(match s.to_ascii_lowercase().as_str() {
+            "trace" => Level::Trace,
+            "debug" => Level::Debug,
+            "info" => Level::Info,
+            "warn" => Level::Warn,
+            "error" => Level::Error,
+            _ => return None,
+        })
+    }
+}
+
+/// Severity of a record; records that do not override it are [`Level::Info`]
+pub trait Leveled {
+    fn level(&self) -> Level {
+        Level::Info
+    }
+}
+
+static MIN_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+/// Lets [`enabled`] skip the lock while no table overrides the minimum level
+static HAS_TABLE_LEVELS: AtomicBool = AtomicBool::new(false);
+static TABLE_LEVELS: RwLock<Option<HashMap<String, Level>>> = RwLock::new(None);
+
+/// Drops records below `level` from now on unless their table overrides it
+pub fn set_min_level(level: Level) {
+    MIN_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn min_level() -> Level {
+    Level::from_u8(MIN_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Overrides the minimum level of one table; `None` falls back to [`set_min_level`]
+pub fn set_table_level(table: &str, level: Option<Level>) {
+    let mut levels = TABLE_LEVELS.write().unwrap();
+    let map = levels.get_or_insert_with(HashMap::new);
+    match level {
+        Some(level) => map.insert(table.to_string(), level),
+        None => map.remove(table),
+    };
+    HAS_TABLE_LEVELS.store(!map.is_empty(), Ordering::Relaxed);
+}
+
+/// Whether a record of `table` at `level` would be logged
+pub fn enabled(table: &str, level: Level) -> bool {
+    if HAS_TABLE_LEVELS.load(Ordering::Relaxed) {
+        let levels = TABLE_LEVELS.read().unwrap();
+        if let Some(min) = levels.as_ref().and_then(|map| map.get(table)) {
+            return *min <= level;
+        }
+    }
+    min_level() <= level
+}
+
+thread_local! {
+    /// The level of the record [`log_leveled`] is logging on this thread
+    static RECORD_LEVEL: Cell<Option<Level>> = const { Cell::new(None) };
+}
+
+/// The level of the record being logged, if it was logged by [`log_leveled`]
+pub(crate) fn record_level() -> Option<Level> {
+    RECORD_LEVEL.get()
+}
+
+/// Whether the record being logged is at or above the minimum level of `table`, for loggers
+/// without a [`crate::FilterChain`]
+pub(crate) fn record_enabled(table: &str) -> bool {
+    record_level().is_none_or(|level| enabled(table, level))
+}
+
+/// Logs `record` to the registered logger, whose [`crate::LevelFilter`] drops it if its level is
+/// below the minimum of its table
+pub fn log_leveled<'caller, R: table_log::LogRecord<'caller> + Leveled>(record: &R) {
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            RECORD_LEVEL.set(None);
+        }
+    }
+    RECORD_LEVEL.set(Some(record.level()));
+    let _reset = Reset;
+    table_log::log!(record);
+}
@@ -1,278 +1,4684 @@
 use std::{
-    collections::HashMap,
-    io::{Read, Write},
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    io,
     num::NonZeroUsize,
     path::{Path, PathBuf},
-    time::Duration,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 
-use table::Table;
+use batch::BatchWorker;
+use cap::Cap;
+use error::ErrorHandler;
+use filter::{RowFilters, TableSet};
+use free_space::FreeSpace;
+use health::Health;
+use import::ImportPosition;
+use lock::TableLock;
+use metadata::Metadata;
+use observer::Events;
+use rotated::RotatedFileWorker;
+use row::{RowFormat, SerializeError};
+use spool::{Holding, Spool};
+use storage::{Backend, OpenMode, RealFs, Storage};
+use table::{IdleTable, LogWriter, Table};
+use table_dir::TableFiles;
+use table_log::SerWrap;
+use tee::TeeWorker;
+use telemetry::MeteredWriter;
+use watchdog::Watchdog;
 
+#[cfg(feature = "tokio")]
+pub use async_flush::{flush_async, init_async};
+pub use backoff::Backoff;
+pub use batch::{BatchForwarder, BatchPolicy, BatchSink, BoxError};
+pub use builder::{CsvLoggerBuilder, OutputTarget};
+pub use cap::reset_table_cap;
+pub use chain::{FilterChain, LevelFilter, RateLimitFilter, RecordFilter, SampleFilter, Verdict};
+pub use channel::{init_channel, Backpressure};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use config::{ConfigError, ConfigProblem};
+pub use context::set_context;
+#[cfg(feature = "derive")]
+pub use csv_logger_derive::CsvRecord;
+pub use debug::debug_dump;
+pub use default_dir::init_default;
+#[cfg(feature = "encryption")]
+pub use encryption::{set_decryption_keys, EncryptionKey, KeyProvider, RotatedCompression};
+pub use error::{last_error, recent_errors, CsvLoggerError, ErrorEntry};
+pub use filter::{set_filter, set_table_enabled, RowFilter};
+pub use flush_report::{FlushCallback, FlushReport, TableFlush};
+pub use flusher::{FlusherGuard, FlusherHandle};
+pub use fork::after_fork_in_child;
+pub use free_space::{FreeSpacePolicy, OsSpace, SpaceProvider};
+pub use health::HEALTH_TABLE;
+#[cfg(feature = "http-sink")]
+pub use http::HttpUploader;
+#[cfg(feature = "latency-metrics")]
+pub use latency::{latency_snapshot, LatencySnapshot};
+pub use layout::Layout;
+pub use level::{enabled, log_leveled, min_level, set_min_level, set_table_level, Level, Leveled};
+pub use lock::ConflictPolicy;
+#[cfg(feature = "test-util")]
+pub use observer::RecordingObserver;
+pub use observer::{LoggerEvent, LoggerObserver};
+pub use pause::{dropped_while_paused, is_paused, pause, resume};
+pub use raw::raw_table;
+pub use redact::{Mask, Redactor};
+pub use rename::HeaderCase;
+pub use rotated::{RotatedFileDisposition, RotatedFileHandler};
+pub use row::{Header, Row};
+pub use ser::BytesEncoding;
+pub use shared::{log_durable, log_to, output_dir, spawn_flusher, CsvLoggerHandle};
+#[cfg(feature = "syslog")]
+pub use sink::syslog::{Facility, SyslogTransport};
+pub use sink::{RecordSink, SinkFormat};
+pub use stats::{dropped_records, error_count, stats, LoggerStats, TableStats};
+pub use table_dir::namespace_dir;
+pub use tee::{FailoverTee, TeeLag, TeeLagHandle};
+pub use timestamp::{TimestampConfig, TimestampFormat, TimestampZone};
+pub use transaction::{transaction, Transaction};
+pub use watchdog::WatchdogPolicy;
+
+#[cfg(feature = "tokio")]
+mod async_flush;
+mod backoff;
+mod batch;
+mod builder;
+mod cap;
+mod chain;
+mod channel;
+mod checksum;
+mod clock;
+mod config;
+mod context;
+mod debug;
+mod dedup;
+mod default_dir;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod env;
+mod error;
+mod expand;
+pub mod export;
+mod filter;
+mod flatten;
+mod flush_report;
+mod flusher;
+mod fork;
+mod free_space;
+mod health;
+#[cfg(feature = "http-sink")]
+mod http;
+pub mod import;
+#[cfg(feature = "latency-metrics")]
+mod latency;
+mod layout;
+mod level;
+mod lock;
+mod long_path;
+pub mod manifest;
+mod map;
+mod metadata;
+mod network_fs;
+pub mod nonblocking;
+mod observer;
+mod panic_flush;
+mod pause;
+mod private_dirs;
+mod rate_limit;
+pub mod raw;
+pub mod reader;
+mod recreate;
+mod redact;
+mod rename;
+mod rotated;
+mod row;
+mod sampling;
+mod schema;
+mod sequence;
+pub mod ser;
+mod shared;
+mod sink;
+mod spool;
+mod stats;
+pub mod storage;
 mod table;
+mod table_dir;
+mod tee;
+mod telemetry;
+mod timestamp;
+mod transaction;
+pub mod verify;
+mod watchdog;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+const TRUNCATION_MARKER: &str = "…[truncated {n} bytes]";
+
+pub fn init(output_dir: PathBuf, rotation: RotationPolicy) {
+    CsvLoggerBuilder::new(output_dir, rotation)
+        .init()
+        .expect("Failed to initialize the logger");
+}
+
+pub struct CsvLogger {
+    output_dir: PathBuf,
+    storage: Backend,
+    /// Where the files of the tables are under the output directory
+    files: TableFiles,
+    tables: HashMap<Cow<'static, str>, Table>,
+    /// Tables closed by [`CsvLoggerBuilder::idle_close`] or
+    /// [`CsvLoggerBuilder::max_open_tables`] until they are logged to again
+    idle_tables: HashMap<Cow<'static, str>, IdleTable>,
+    rotation: RotationPolicy,
+    rotated_files: Option<RotatedFileWorker>,
+    tee: Option<TeeWorker>,
+    batch: Option<BatchWorker>,
+    resume: ResumePolicy,
+    repair_on_resume: bool,
+    timestamp: Option<TimestampConfig>,
+    sequence: bool,
+    context: Vec<(&'static str, String)>,
+    thread_info: bool,
+    epoch_column: bool,
+    schema_policy: SchemaPolicy,
+    flatten_depth: Option<usize>,
+    column_orders: HashMap<String, Vec<String>>,
+    redactions: HashMap<String, Vec<String>>,
+    redactor: Arc<dyn Redactor>,
+    column_renames: HashMap<String, HashMap<String, String>>,
+    header_case: HeaderCase,
+    /// The columns of the tables written through [`raw::TableHandle`]
+    raw_headers: HashMap<String, Vec<String>>,
+    metadata: Option<Metadata>,
+    max_field_bytes: Option<usize>,
+    truncation_marker: String,
+    single_line_fields: bool,
+    bytes_encoding: Option<BytesEncoding>,
+    allowed_tables: Option<TableSet>,
+    denied_tables: TableSet,
+    filter_chain: FilterChain,
+    dedup_tables: HashSet<String>,
+    filters: RowFilters,
+    caps: HashMap<String, Cap>,
+    /// Records dropped per table
+    dropped: HashMap<String, u64>,
+    flush_interval: Duration,
+    flush_intervals: HashMap<String, Duration>,
+    idle_close: Option<Duration>,
+    max_open_tables: Option<NonZeroUsize>,
+    flushed_at: Instant,
+    clock: Arc<dyn Clock>,
+    sync_durable: bool,
+    durable_rotation: bool,
+    spool: Option<Spool>,
+    free_space: Option<FreeSpace>,
+    conflict_policy: Option<ConflictPolicy>,
+    health: Option<Health>,
+    on_flush: Option<FlushCallback>,
+    events: Events,
+    watchdog: Option<Watchdog>,
+    error_handler: ErrorHandler,
+}
+impl CsvLogger {
+    pub fn new(output_dir: PathBuf, rotation: RotationPolicy) -> Self {
+        Self {
+            files: TableFiles::of(&output_dir),
+            output_dir,
+            storage: Backend::default(),
+            tables: HashMap::new(),
+            idle_tables: HashMap::new(),
+            rotation,
+            rotated_files: None,
+            tee: None,
+            batch: None,
+            resume: ResumePolicy::default(),
+            repair_on_resume: false,
+            timestamp: None,
+            sequence: false,
+            context: vec![],
+            thread_info: false,
+            epoch_column: false,
+            schema_policy: SchemaPolicy::default(),
+            flatten_depth: None,
+            column_orders: HashMap::new(),
+            redactions: HashMap::new(),
+            redactor: Arc::new(Mask),
+            column_renames: HashMap::new(),
+            header_case: HeaderCase::AsIs,
+            raw_headers: HashMap::new(),
+            metadata: None,
+            max_field_bytes: None,
+            truncation_marker: TRUNCATION_MARKER.to_string(),
+            single_line_fields: false,
+            bytes_encoding: None,
+            allowed_tables: None,
+            denied_tables: TableSet::default(),
+            filter_chain: FilterChain::new().push(LevelFilter),
+            dedup_tables: HashSet::new(),
+            filters: RowFilters::default(),
+            caps: HashMap::new(),
+            dropped: HashMap::new(),
+            flush_interval: FLUSH_INTERVAL,
+            flush_intervals: HashMap::new(),
+            idle_close: None,
+            max_open_tables: None,
+            flushed_at: Instant::now(),
+            clock: Arc::new(SystemClock),
+            sync_durable: false,
+            durable_rotation: false,
+            spool: None,
+            free_space: None,
+            conflict_policy: None,
+            health: None,
+            on_flush: None,
+            events: Events::default(),
+            watchdog: None,
+            error_handler: error::default_error_handler(),
+        }
+    }
+}
+impl CsvLogger {
+    /// Logs `record` to the table it names, without going through the global registry
+    pub fn log_record(&mut self, record: &dyn table_log::LogRecord) {
+        self.log_as(Cow::Borrowed(record.table_name()), &SerWrap(record));
+    }
+
+    pub fn flush(&mut self) {
+        let start = self.clock.instant();
+        self.heartbeat(start);
+        let tables = self.tables.keys().cloned().collect::<Vec<_>>();
+        let mut flushed = vec![];
+        for table_name in tables {
+            flushed.extend(self.flush_table(&table_name));
+        }
+        self.close_idle(start);
+        self.flushed_at = self.clock.instant();
+        self.flush_forwarders();
+        self.check_free_space(start);
+        self.storage.purge_trash();
+        self.report_flush(flushed, start);
+    }
+
+    /// Flushes the tables one by one for up to `budget` without panicking, e.g. from a panic hook
+    ///
+    /// Rows held back for their repeats stay held and spooled rows stay spooled. Errors are
+    /// ignored.
+    pub(crate) fn flush_before(&mut self, budget: Duration) {
+        let deadline = self.clock.instant() + budget;
+        for table in self.tables.values_mut() {
+            if deadline <= self.clock.instant() {
+                return;
+            }
+            let _ = table.flush();
+        }
+    }
+
+    /// Logs `record` to a table named at runtime
+    ///
+    /// Names that are empty, `.`, `..` or contain path separators are reported as
+    /// [`CsvLoggerError::InvalidTableName`].
+    pub fn log_to(&mut self, table: &str, record: &impl serde::Serialize) {
+        if self.check_table_name(table) {
+            self.log_as(Cow::Owned(table.to_string()), record);
+        }
+    }
+
+    /// Reports and drops the record of a table named at runtime unless the name is valid
+    fn check_table_name(&mut self, table: &str) -> bool {
+        if is_valid_table_name(table) {
+            return true;
+        }
+        let error = CsvLoggerError::InvalidTableName {
+            table: table.to_string(),
+        };
+        error::report_at(&self.error_handler, self.clock.now(), error);
+        self.drop_record(table);
+        false
+    }
+
+    /// Logs `record` and flushes its table, bypassing the flush interval
+    ///
+    /// The rows are also synced to disk if [`CsvLoggerBuilder::sync_durable`] is on.
+    pub fn log_durable(&mut self, record: &dyn table_log::LogRecord) {
+        let table_name = record.table_name();
+        // Also known when the record is the first since the table was opened
+        let rotations = match (
+            self.tables.get(table_name),
+            self.idle_tables.get(table_name),
+        ) {
+            (Some(table), _) => table.stats().rotations,
+            (None, Some(idle)) => idle.rotations,
+            (None, None) => 0,
+        };
+        self.log_record(record);
+        self.flush_table(table_name);
+        if !self.sync_durable {
+            return;
+        }
+        let Some(table) = self.tables.get(table_name) else {
+            return;
+        };
+        // The row went to the outgoing epoch if the table rotated
+        if table.stats().rotations != rotations {
+            let path = self.files.epoch_file(
+                &self.storage,
+                table.output_dir(),
+                table_name,
+                table.epoch() - 1,
+                false,
+            );
+            let synced = self
+                .storage
+                .open(&path, OpenMode::Append)
+                .and_then(|file| file.sync_data());
+            if let Err(source) = synced {
+                self.report_table_file(table_name, "sync the log file", source);
+            }
+        }
+        if let Err(source) = table.sync() {
+            self.report_table_file(table_name, "sync the log file", source);
+        }
+    }
+
+    fn log_as(&mut self, table_name: Cow<'static, str>, record: &(impl serde::Serialize + ?Sized)) {
+        #[cfg(feature = "latency-metrics")]
+        let _timer = latency::Timer::start();
+        let clock = self.clock.as_ref();
+        if let Some(error) = self.watchdog.as_mut().and_then(|w| w.check(clock)) {
+            error::report_at(&self.error_handler, self.clock.now(), error);
+        }
+        if !self.admit(&table_name) {
+            return;
+        }
+        match self.row_format().serialize(table_name, record) {
+            Ok((row, is_map)) => self.log_serialized(row, is_map),
+            Err(e) => self.drop_unserialized(e),
+        }
+    }
+
+    /// Logs a row serialized beforehand with [`CsvLogger::row_format`], e.g. on another thread
+    pub(crate) fn log_row(&mut self, row: Row, is_map: bool) {
+        if !self.admit(&row.table) {
+            return;
+        }
+        self.log_serialized(row, is_map);
+    }
+
+    /// Whether to log records of a table that may be named at runtime, see [`CsvLogger::admit`]
+    pub(crate) fn admit_checked(&mut self, table_name: &str) -> bool {
+        self.check_table_name(table_name) && self.admit(table_name)
+    }
+
+    /// The first of the records of `tables`, logged one after another, that
+    /// [`CsvLogger::admit_checked`] would not admit
+    ///
+    /// Nothing is admitted or reported, so no rate limit token or sample is taken, but filters of
+    /// the chain that cannot tell beforehand keep the records, see
+    /// [`RecordFilter::peek_tables`].
+    pub(crate) fn first_rejected(&mut self, tables: &[&str]) -> Option<usize> {
+        if pause::is_paused() || self.free_space.as_ref().is_some_and(|f| f.paused) {
+            return (!tables.is_empty()).then_some(0);
+        }
+        let rejected = tables.iter().position(|table| {
+            !is_valid_table_name(table)
+                || !self.table_allowed(table)
+                || !filter::table_enabled(table)
+        });
+        if rejected.is_some() {
+            return rejected;
+        }
+        let level = level::record_level();
+        (1..=tables.len())
+            .find(|&n| !self.filter_chain.keep_tables(&tables[..n], level))
+            .map(|n| n - 1)
+    }
+
+    /// Logs a row of a table admitted by [`CsvLogger::admit_checked`]
+    pub(crate) fn log_admitted(&mut self, row: Row, is_map: bool) {
+        self.log_serialized(row, is_map);
+    }
+
+    /// Starts a new epoch of a table now, writing out the row it holds back for its repeats
+    ///
+    /// An epoch without rows is started over instead, so rotating an idle table leaves no empty
+    /// epoch files behind. The file of the new epoch is still created right away rather than by
+    /// its first row: the `epoch` file and the manifest name it from the rotation on, readers
+    /// tailing the table wait for it, and a missing file of the current epoch reads as deleted by a
+    /// cleanup job.
+    pub fn rotate_table(&mut self, table_name: &str) {
+        let table_name = Cow::Owned(table_name.to_string());
+        if self.idle_tables.contains_key(table_name.as_ref()) {
+            self.make_room();
+            self.wake_table(&table_name);
+        }
+        self.flush_table(&table_name);
+        let Some(table) = self.tables.get_mut(table_name.as_ref()) else {
+            return;
+        };
+        let rotated = rotate(
+            &self.storage,
+            &self.files,
+            self.durable_rotation,
+            self.rotation.max_epochs,
+            self.rotated_files.as_ref(),
+            &mut self.events,
+            &table_name,
+            table,
+        );
+        if let Err(source) = rotated {
+            self.report_table_file(&table_name, "rotate the log file", source);
+        }
+    }
+
+    /// Like [`import::import_file`] into the output directory of this logger, closing the table
+    /// first
+    pub fn import_file(
+        &mut self,
+        table: &str,
+        src: &Path,
+        position: ImportPosition,
+    ) -> io::Result<usize> {
+        self.close_table(table);
+        import::import_locked(&self.files, &self.output_dir, table, src, position)
+    }
+
+    /// Takes `header` as the columns of the raw rows of `table`
+    ///
+    /// Fails if the table was registered with other columns or its last record had other columns.
+    pub(crate) fn register_raw(
+        &mut self,
+        table: &str,
+        header: &[&str],
+    ) -> Result<(), CsvLoggerError> {
+        if !is_valid_table_name(table) {
+            return Err(CsvLoggerError::InvalidTableName {
+                table: table.to_string(),
+            });
+        }
+        let header: Vec<String> = header.iter().map(|column| column.to_string()).collect();
+        let expected = match self.raw_headers.get(table) {
+            Some(registered) => Some(registered.as_slice()),
+            None => self.tables.get(table).and_then(Table::record_header),
+        };
+        if let Some(expected) = expected.filter(|expected| *expected != header) {
+            return Err(CsvLoggerError::RawHeader {
+                table: Cow::Owned(table.to_string()),
+                expected: expected.to_vec(),
+                got: header,
+            });
+        }
+        self.raw_headers.insert(table.to_string(), header);
+        Ok(())
+    }
+
+    /// Logs the fields of a table registered by [`CsvLogger::register_raw`] as a row
+    pub(crate) fn log_raw(
+        &mut self,
+        table: &Cow<'static, str>,
+        header: &[String],
+        fields: Vec<String>,
+    ) {
+        if !self.admit_raw(table, header, fields.len()) {
+            return;
+        }
+        let row = Row {
+            table: table.clone(),
+            header: header.to_vec().into(),
+            fields,
+        };
+        self.log_serialized(row, false);
+    }
+
+    /// Like [`CsvLogger::log_raw`] for fields that are already strings, writing them to the file
+    /// as they are unless a step of [`CsvLogger::log_serialized`] would see the row
+    pub(crate) fn log_raw_str(
+        &mut self,
+        table: &Cow<'static, str>,
+        header: &[String],
+        fields: &[&str],
+    ) {
+        if !self.admit_raw(table, header, fields.len()) {
+            return;
+        }
+        if self.passes_raw(table, header) {
+            let res = self
+                .tables
+                .get_mut(table.as_ref())
+                .unwrap()
+                .write_fields(table, fields);
+            match res {
+                Ok(()) => self.rotate_if_full(table),
+                Err(e) => {
+                    self.report_table_file(table, "write a row", e.into());
+                    self.drop_record(table);
+                }
+            }
+            return;
+        }
+        let row = Row {
+            table: table.clone(),
+            header: header.to_vec().into(),
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+        };
+        self.log_serialized(row, false);
+    }
+
+    /// Whether to log a raw row of `fields` fields
+    fn admit_raw(&mut self, table: &Cow<'static, str>, header: &[String], fields: usize) -> bool {
+        if fields != header.len() {
+            let error = CsvLoggerError::RawFieldCount {
+                table: table.clone(),
+                expected: header.len(),
+                got: fields,
+            };
+            error::report_at(&self.error_handler, self.clock.now(), error);
+            self.drop_record(table);
+            return false;
+        }
+        self.admit(table)
+    }
+
+    /// Whether a raw row of `header` would reach the file of `table` unchanged, so it can be
+    /// written without being built
+    ///
+    /// The table must be open on an epoch of these columns with rows written since the last
+    /// flush, and no column, transformation, filter, cap or forwarder may apply to its rows.
+    fn passes_raw(&self, table_name: &str, header: &[String]) -> bool {
+        let Some(table) = self.tables.get(table_name) else {
+            return false;
+        };
+        table.header() == Some(header)
+            && table.dirty()
+            && !table.outage()
+            && !spooling(&self.spool, table_name)
+            && self.timestamp.is_none()
+            && !self.sequence
+            && self.context.is_empty()
+            && !self.thread_info
+            && !self.epoch_column
+            && self.max_field_bytes.is_none()
+            && !self.single_line_fields
+            && self.header_case == HeaderCase::AsIs
+            && !self.dedup_tables.contains(table_name)
+            && !self.redactions.contains_key(table_name)
+            && !self.column_orders.contains_key(table_name)
+            && !self.column_renames.contains_key(table_name)
+            && !self.caps.contains_key(table_name)
+            && !self.filters.applies_to(table_name)
+            && !self.filter_chain.filters_rows()
+            && self.tee.is_none()
+            && self.batch.is_none()
+    }
+
+    pub(crate) fn row_format(&self) -> RowFormat {
+        RowFormat {
+            flatten_depth: self.flatten_depth,
+            bytes_encoding: self.bytes_encoding,
+        }
+    }
+
+    /// Whether to log records of a table at all
+    fn admit(&mut self, table_name: &str) -> bool {
+        if pause::drop_if_paused() || self.free_space.as_ref().is_some_and(|f| f.paused) {
+            self.drop_record(table_name);
+            return false;
+        }
+        if !self.table_allowed(table_name) {
+            return false;
+        }
+        if !filter::table_enabled(table_name) {
+            self.close_table(table_name);
+            return false;
+        }
+        if !self
+            .filter_chain
+            .keep_table(table_name, level::record_level(), &self.error_handler)
+        {
+            self.drop_record(table_name);
+            return false;
+        }
+        true
+    }
+
+    fn log_serialized(&mut self, mut row: Row, is_map: bool) {
+        let table_name = row.table.clone();
+        // The epoch column would come out twice
+        if self.epoch_column
+            && row
+                .header
+                .iter()
+                .any(|column| column == context::EPOCH_COLUMN)
+        {
+            let error = CsvLoggerError::EpochColumnCollision {
+                table: table_name.clone(),
+            };
+            error::report_at(&self.error_handler, self.clock.now(), error);
+            self.drop_record(&table_name);
+            return;
+        }
+        if !self
+            .filter_chain
+            .keep(&table_name, &row, &self.error_handler)
+        {
+            self.drop_record(&table_name);
+            return;
+        }
+        if !self.filters.keep(&row) {
+            return;
+        }
+        // Checked again once the table is open if where it goes is not known yet
+        let cap_dir = match self.caps.contains_key(table_name.as_ref()) {
+            true => self.table_output_dir(&table_name).map(Path::to_path_buf),
+            false => None,
+        };
+        if let (Some(cap), Some(output_dir)) = (self.caps.get_mut(table_name.as_ref()), cap_dir) {
+            if cap.exhausted(&self.storage, &self.files, &output_dir, &table_name) {
+                self.drop_record(&table_name);
+                return;
+            }
+        }
+        if !self.tables.contains_key(table_name.as_ref()) {
+            self.make_room();
+        }
+        self.wake_table(&table_name);
+        let new = !self.tables.contains_key(table_name.as_ref());
+        if new {
+            let table = match self.open_table(&table_name) {
+                Ok(Some(table)) => table,
+                Ok(None) => {
+                    self.drop_record(&table_name);
+                    return;
+                }
+                Err(source) => {
+                    self.report_table_file(&table_name, "open a log file", source);
+                    self.drop_record(&table_name);
+                    return;
+                }
+            };
+            let epoch = table.epoch();
+            let output_dir = table.output_dir().to_path_buf();
+            self.tables.insert(table_name.clone(), table);
+            self.adopt_spool(&table_name);
+            self.events.emit(|| LoggerEvent::TableCreated {
+                table: table_name.to_string(),
+                epoch,
+            });
+            let deleted = delete_old_log_file(
+                &self.storage,
+                &self.files,
+                &mut self.events,
+                epoch,
+                self.rotation.max_epochs,
+                &output_dir,
+                &table_name,
+            )
+            .unwrap_or_else(|source| {
+                self.report_table_file(&table_name, "delete an old epoch", source);
+                None
+            });
+            let opened = self.tables[table_name.as_ref()].stats();
+            let (storage, files) = (&self.storage, &self.files);
+            let res = self.record_epoch(&output_dir, &table_name, epoch, |manifest| {
+                manifest.close_before(storage, files, &output_dir, &table_name, epoch);
+                manifest.open(epoch, opened.epoch_records as u64, opened.epoch_bytes);
+                if let Some(deleted) = deleted {
+                    manifest.remove(deleted);
+                }
+            });
+            if let Err(source) = res {
+                self.report_table_file(&table_name, "record the epoch", source);
+            }
+        }
+        let mut table = self.tables.get_mut(table_name.as_ref()).unwrap();
+        table.note_record_header(&row.header);
+        let record_header = row.header.clone();
+        let dedup = self.dedup_tables.contains(table_name.as_ref());
+        let record = if dedup {
+            if table.repeat(&row) {
+                return;
+            }
+            if let Some(held) = table.take_held() {
+                self.commit(&table_name, held);
+                table = self.tables.get_mut(table_name.as_ref()).unwrap();
+            }
+            Some(row.clone())
+        } else {
+            None
+        };
+        // Rows of the same columns as one that fit come out the same, so they skip the checks
+        let fitting_header = match table.fits(&record_header) {
+            true => table.shared_header().cloned(),
+            false => None,
+        };
+        if let (None, Some(expected)) = (&fitting_header, self.raw_headers.get(table_name.as_ref()))
+        {
+            if *expected != *record_header {
+                let error = CsvLoggerError::SchemaMismatch {
+                    table: table_name.clone(),
+                    expected: expected.clone(),
+                    got: record_header.to_vec(),
+                };
+                error::report_at(&self.error_handler, self.clock.now(), error);
+                self.drop_record(&table_name);
+                return;
+            }
+        }
+        if let Some(timestamp) = &self.timestamp {
+            timestamp.prepend(&mut row, self.clock.now());
+        }
+        table.number(&mut row);
+        context::append(&mut row, &self.context);
+        if self.thread_info {
+            context::append_thread_info(&mut row);
+        }
+        if let Some(columns) = self.redactions.get(table_name.as_ref()) {
+            redact::apply(&mut row, columns, self.redactor.as_ref());
+        }
+        if let Some(max_bytes) = self.max_field_bytes {
+            let truncated = row.truncate_fields(max_bytes, &self.truncation_marker);
+            if truncated != 0 {
+                telemetry::truncated(truncated);
+            }
+        }
+        if self.single_line_fields {
+            row.fields.iter_mut().for_each(schema::escape);
+        }
+        if let Some(order) = self.column_orders.get(table_name.as_ref()) {
+            let missing = row.reorder(order);
+            if !missing.is_empty() {
+                let error = CsvLoggerError::MissingColumns {
+                    table: table_name.clone(),
+                    columns: missing,
+                };
+                error::report_at(&self.error_handler, self.clock.now(), error);
+            }
+        }
+        if self.epoch_column {
+            context::append_epoch(&mut row, table.epoch());
+        }
+        if dedup {
+            dedup::append_count(&mut row);
+        }
+        let renames = self.column_renames.get(table_name.as_ref());
+        if fitting_header.is_none() && (renames.is_some() || self.header_case != HeaderCase::AsIs) {
+            let (case, epoch_column) = (self.header_case, self.epoch_column);
+            table.rename(&mut row, |row| {
+                rename::apply(row, renames, case, epoch_column)
+            });
+        }
+        if let (true, Some(header)) = (is_map, table.shared_header()) {
+            map::align(&mut row, header, self.schema_policy == SchemaPolicy::Ignore);
+        }
+        if let Some(header) = fitting_header {
+            row.header = header;
+        } else if !table.header_changed(&row) {
+            table.fit(record_header);
+        } else {
+            match schema_policy(self.schema_policy, table) {
+                // Replaying the spool rotates instead
+                SchemaPolicy::RotateOnChange if spooling(&self.spool, &table_name) => (),
+                SchemaPolicy::RotateOnChange => {
+                    telemetry::schema_changed(&table_name);
+                    self.events.emit(|| LoggerEvent::SchemaChanged {
+                        table: table_name.to_string(),
+                        old_header: table.header().unwrap_or_default().to_vec(),
+                        new_header: row.header.to_vec(),
+                    });
+                    let rotated = rotate(
+                        &self.storage,
+                        &self.files,
+                        self.durable_rotation,
+                        self.rotation.max_epochs,
+                        self.rotated_files.as_ref(),
+                        &mut self.events,
+                        &table_name,
+                        table,
+                    );
+                    if let Err(source) = rotated {
+                        self.report_table_file(&table_name, "rotate the log file", source);
+                        self.drop_record(&table_name);
+                        return;
+                    }
+                }
+                SchemaPolicy::RejectMismatched => {
+                    let error = CsvLoggerError::SchemaMismatch {
+                        table: table_name.clone(),
+                        expected: table.header().unwrap_or_default().to_vec(),
+                        got: row.header.to_vec(),
+                    };
+                    error::report_at(&self.error_handler, self.clock.now(), error);
+                    self.drop_record(&table_name);
+                    return;
+                }
+                SchemaPolicy::Ignore => (),
+            }
+        }
+        match record {
+            Some(record) => table.hold(dedup::Held::new(record, row)),
+            None => self.commit(&table_name, row),
+        }
+    }
+
+    /// Writes a row that fits its table and rotates the table once full
+    ///
+    /// Spools the row instead while the table's file does not take rows.
+    fn commit(&mut self, table_name: &Cow<'static, str>, mut row: Row) {
+        if self.tables[table_name.as_ref()].outage() {
+            self.start_spooling(table_name);
+        }
+        if spooling(&self.spool, table_name) {
+            self.spool_row(table_name, &row);
+            return;
+        }
+        let mut terminal = false;
+        if let Some(cap) = self.caps.get_mut(table_name.as_ref()) {
+            let output_dir = self.tables[table_name.as_ref()].output_dir();
+            if cap.exhausted(&self.storage, &self.files, output_dir, table_name) {
+                self.drop_record(table_name);
+                return;
+            }
+            if let Some(terminal_row) =
+                cap.admit(&self.storage, &self.files, output_dir, table_name, &row)
+            {
+                self.drop_record(table_name);
+                row = terminal_row;
+                terminal = true;
+            }
+        }
+        if !self.tables[table_name.as_ref()].dirty() {
+            self.recreate_if_deleted(table_name);
+        }
+        let table = self.tables.get_mut(table_name.as_ref()).unwrap();
+        if let (Some(metadata), None) = (&self.metadata, table.header()) {
+            if let Err(source) = table.write_preamble(&metadata.render(table.epoch())) {
+                self.report_table_file(&table_name, "write the metadata", source);
+            }
+        }
+        let table = self.tables.get_mut(table_name.as_ref()).unwrap();
+        if self.epoch_column && !terminal {
+            // The table may have rotated since the row was formed
+            let dedup = self.dedup_tables.contains(table_name.as_ref());
+            context::set_epoch(&mut row, table.epoch(), dedup);
+        }
+        if let Err(e) = table.write_row(&row) {
+            self.report_table_file(table_name, "write a row", e.into());
+            self.drop_record(table_name);
+            return;
+        }
+        if let Some(batch) = &self.batch {
+            batch.send(row.clone());
+        }
+        if let Some(tee) = &self.tee {
+            tee.send(row);
+        }
+        self.rotate_if_full(table_name);
+    }
+
+    /// Rotates the log file of a table once full, or once the spool is replayed if the file
+    /// stopped taking rows
+    fn rotate_if_full(&mut self, table_name: &Cow<'static, str>) {
+        let table = self.tables.get_mut(table_name.as_ref()).unwrap();
+        if table.outage() {
+            self.start_spooling(table_name);
+        } else if self.rotation.max_records.get() <= table.records_written() {
+            let rotated = rotate(
+                &self.storage,
+                &self.files,
+                self.durable_rotation,
+                self.rotation.max_epochs,
+                self.rotated_files.as_ref(),
+                &mut self.events,
+                table_name,
+                table,
+            );
+            if let Err(source) = rotated {
+                self.report_table_file(table_name, "rotate the log file", source);
+            }
+            self.save_cap(table_name);
+        }
+    }
+
+    /// Writes the row count of a capped table to its count file if it changed
+    fn save_cap(&mut self, table_name: &str) {
+        let Some(cap) = self.caps.get_mut(table_name) else {
+            return;
+        };
+        if let Err(source) = cap.save(&self.storage, &self.files, table_name) {
+            self.report_table_file(table_name, "write the count file", source);
+        }
+    }
+
+    /// Starts a new epoch of a table whose file is gone, e.g. with the output directory wiped by a
+    /// cleanup job, recreating the directories
+    ///
+    /// Backs off between recreations of the table, leaving it writing to the deleted file
+    /// meanwhile.
+    fn recreate_if_deleted(&mut self, table_name: &str) {
+        let table = self.tables.get_mut(table_name).unwrap();
+        let output_dir = table.output_dir().to_path_buf();
+        let path =
+            self.files
+                .epoch_file(&self.storage, &output_dir, table_name, table.epoch(), false);
+        if self.storage.exists(&path) || !table.allow_recreation() {
+            return;
+        }
+        let new_path = self.files.epoch_file(
+            &self.storage,
+            &output_dir,
+            table_name,
+            table.epoch() + 1,
+            true,
+        );
+        let dir = new_path.parent().expect("Log files are in a directory");
+        if let Err(source) = self.storage.create_dir_all(dir) {
+            self.report_table_file(table_name, "recreate the table directory", source);
+            return;
+        }
+        let writer = match open_log_writer(&self.storage, &new_path, OpenMode::Truncate) {
+            Ok(writer) => writer,
+            Err(source) => {
+                self.report_table_file(table_name, "recreate the log file", source);
+                return;
+            }
+        };
+        table.replace(writer);
+        let epoch = table.epoch();
+        manifest::forget(&self.files, &output_dir, table_name);
+        self.events.emit(|| LoggerEvent::TableRecreated {
+            table: table_name.to_string(),
+            epoch,
+            path: new_path,
+        });
+        let res = self.record_epoch(&output_dir, table_name, epoch, |manifest| {
+            manifest.open(epoch, 0, 0);
+        });
+        if let Err(source) = res {
+            self.report_table_file(table_name, "record the epoch", source);
+        }
+    }
+
+    /// Writes down a new epoch of a table in its epoch, schema and manifest files and the
+    /// directory mapping; `f` opens the epoch in the manifest
+    fn record_epoch(
+        &self,
+        output_dir: &Path,
+        table_name: &str,
+        epoch: usize,
+        f: impl FnOnce(&mut manifest::Manifest),
+    ) -> io::Result<()> {
+        write_epoch(&self.storage, &self.files, output_dir, table_name, epoch)?;
+        schema::write_schema(
+            &self.storage,
+            &self.files,
+            output_dir,
+            table_name,
+            self.single_line_fields,
+        )?;
+        table_dir::record(&self.storage, output_dir, table_name)?;
+        let single_line = self.single_line_fields;
+        manifest::update(
+            &self.storage,
+            &self.files,
+            output_dir,
+            table_name,
+            |manifest| {
+                f(manifest);
+                manifest.set_single_line_fields(epoch, single_line);
+            },
+        )
+    }
+
+    fn report_table_file(&self, table_name: &str, action: &'static str, source: io::Error) {
+        let error = CsvLoggerError::TableFile {
+            table: Cow::Owned(table_name.to_string()),
+            action,
+            source,
+        };
+        error::report_at(&self.error_handler, self.clock.now(), error);
+    }
+
+    /// Flushes a table after writing the row it holds back for its repeats
+    ///
+    /// Returns what the table wrote out, if anything.
+    fn flush_table(&mut self, table_name: &str) -> Option<TableFlush> {
+        if let Some(row) = self.tables.get_mut(table_name).and_then(Table::take_held) {
+            self.commit(&row.table.clone(), row);
+        }
+        let table = self.tables.get_mut(table_name)?;
+        if let Err(source) = table.flush() {
+            self.report_table_file(table_name, "flush the log file", source);
+        }
+        let table = self.tables.get_mut(table_name)?;
+        if table.outage() {
+            self.start_spooling(table_name);
+        } else if spooling(&self.spool, table_name) {
+            self.replay_spool(table_name);
+        }
+        let table = self.tables.get_mut(table_name)?;
+        if let Some(next) = table.next_sequence() {
+            let written = sequence::write_sequence(
+                &self.storage,
+                &self.files,
+                table.output_dir(),
+                table_name,
+                next,
+            );
+            if let Err(source) = written {
+                self.report_table_file(table_name, "write the sequence file", source);
+            }
+        }
+        self.save_cap(table_name);
+        let table = self.tables.get_mut(table_name)?;
+        let (records, bytes) = table.take_flushed();
+        (records != 0 || bytes != 0).then(|| TableFlush {
+            table: table_name.to_string(),
+            records,
+            bytes,
+        })
+    }
+
+    /// Sends the rows of a table to the spool from now on, reporting why once per outage
+    fn start_spooling(&mut self, table_name: &str) {
+        let Some(spool) = &mut self.spool else {
+            return;
+        };
+        spool.start(table_name);
+        let table = &self.tables[table_name];
+        if let Some(source) = table.take_failure() {
+            let error = CsvLoggerError::PrimaryUnavailable {
+                table: Cow::Owned(table_name.to_string()),
+                source,
+            };
+            error::report_at(&self.error_handler, self.clock.now(), error);
+        }
+    }
+
+    fn spool_row(&mut self, table_name: &str, row: &Row) {
+        let spool = self.spool.as_mut().unwrap();
+        match spool.push(table_name, row) {
+            Ok(dropped) => (0..dropped).for_each(|_| self.drop_record(table_name)),
+            Err(source) => {
+                let error = CsvLoggerError::Spool {
+                    table: Cow::Owned(table_name.to_string()),
+                    source,
+                };
+                error::report_at(&self.error_handler, self.clock.now(), error);
+                self.drop_record(table_name);
+            }
+        }
+    }
+
+    /// Moves the rows held back from the file of a table in an outage to the front of its spool
+    fn spool_held(&mut self, table_name: &Cow<'static, str>) {
+        let mut rows = self
+            .tables
+            .get_mut(table_name)
+            .unwrap()
+            .take_held_rows(table_name);
+        if rows.is_empty() {
+            return;
+        }
+        match self.spool.as_mut().unwrap().take(table_name) {
+            Ok(spooled) => rows.extend(spooled),
+            Err(source) => {
+                let error = CsvLoggerError::Spool {
+                    table: table_name.clone(),
+                    source,
+                };
+                error::report_at(&self.error_handler, self.clock.now(), error);
+            }
+        }
+        for row in rows {
+            self.spool_row(table_name, &row);
+        }
+    }
+
+    /// Writes the spooled rows of a table, oldest first, once its file takes rows again
+    fn replay_spool(&mut self, table_name: &str) {
+        let table = self.tables.get_mut(table_name).unwrap();
+        // The rotation put off by the outage
+        if self.rotation.max_records.get() <= table.records_written() {
+            let rotated = rotate(
+                &self.storage,
+                &self.files,
+                self.durable_rotation,
+                self.rotation.max_epochs,
+                self.rotated_files.as_ref(),
+                &mut self.events,
+                &Cow::Owned(table_name.to_string()),
+                table,
+            );
+            if let Err(source) = rotated {
+                self.report_table_file(table_name, "rotate the log file", source);
+            }
+        }
+        let rows = match self.spool.as_mut().unwrap().take(table_name) {
+            Ok(rows) => rows,
+            Err(source) => {
+                let error = CsvLoggerError::Spool {
+                    table: Cow::Owned(table_name.to_string()),
+                    source,
+                };
+                error::report_at(&self.error_handler, self.clock.now(), error);
+                return;
+            }
+        };
+        for row in rows {
+            let table = self.tables.get_mut(table_name).unwrap();
+            if schema_policy(self.schema_policy, table) == SchemaPolicy::RotateOnChange
+                && table.header_changed(&row)
+            {
+                telemetry::schema_changed(&row.table);
+                self.events.emit(|| LoggerEvent::SchemaChanged {
+                    table: table_name.to_string(),
+                    old_header: table.header().unwrap_or_default().to_vec(),
+                    new_header: row.header.to_vec(),
+                });
+                let rotated = rotate(
+                    &self.storage,
+                    &self.files,
+                    self.durable_rotation,
+                    self.rotation.max_epochs,
+                    self.rotated_files.as_ref(),
+                    &mut self.events,
+                    &row.table,
+                    table,
+                );
+                if let Err(source) = rotated {
+                    self.report_table_file(table_name, "rotate the log file", source);
+                    self.drop_record(table_name);
+                    continue;
+                }
+            }
+            self.commit(&row.table.clone(), row);
+        }
+        let table = self.tables.get_mut(table_name).unwrap();
+        if let Err(source) = table.flush() {
+            self.report_table_file(table_name, "flush the log file", source);
+        }
+        if self.tables[table_name].outage() {
+            self.start_spooling(table_name);
+        }
+    }
+
+    fn report_flush(&mut self, mut tables: Vec<TableFlush>, start: Instant) {
+        tables.sort_by(|a, b| a.table.cmp(&b.table));
+        if !tables.is_empty() {
+            self.events.emit(|| LoggerEvent::Flushed {
+                tables: tables.clone(),
+            });
+        }
+        if let Some(callback) = &self.on_flush {
+            let report = FlushReport {
+                tables,
+                duration: start.elapsed(),
+            };
+            flush_report::notify(callback, report);
+        }
+    }
+
+    /// Flushes the tables whose flush interval has passed since they were last flushed
+    ///
+    /// Everything else is flushed at the logger's flush interval.
+    pub(crate) fn flush_due(&mut self) {
+        let now = self.clock.instant();
+        self.heartbeat(now);
+        let due = self
+            .tables
+            .iter()
+            .filter(|(name, table)| {
+                let interval = self.flush_intervals.get(name.as_ref());
+                table.flushed_at() + interval.copied().unwrap_or(self.flush_interval) <= now
+                    || self.idle(name, table, now)
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        let mut flushed = vec![];
+        for table_name in &due {
+            flushed.extend(self.flush_table(table_name));
+        }
+        self.close_idle(now);
+        if self.flushed_at + self.flush_interval <= now {
+            self.flushed_at = now;
+            self.flush_forwarders();
+        }
+        if !due.is_empty() {
+            self.report_flush(flushed, now);
+        }
+    }
+
+    /// Whether the table went unwritten for [`CsvLoggerBuilder::idle_close`] and can be closed
+    fn idle(&self, table_name: &str, table: &Table, now: Instant) -> bool {
+        self.idle_close
+            .is_some_and(|idle_close| table.written_at() + idle_close <= now)
+            && !table.outage()
+            && !spooling(&self.spool, table_name)
+    }
+
+    /// Closes the flushed tables that went idle, remembering their epochs to append to
+    fn close_idle(&mut self, now: Instant) {
+        let idle = self
+            .tables
+            .iter()
+            .filter(|(name, table)| !table.dirty() && self.idle(name, table, now))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for table_name in idle {
+            self.idle_table(table_name);
+        }
+    }
+
+    /// Closes the least recently written tables until fewer than
+    /// [`CsvLoggerBuilder::max_open_tables`] are open
+    ///
+    /// Tables that cannot be flushed stay open.
+    fn make_room(&mut self) {
+        let Some(max_open_tables) = self.max_open_tables else {
+            return;
+        };
+        while max_open_tables.get() <= self.tables.len() {
+            let Some(table_name) = self
+                .tables
+                .iter()
+                .filter(|(name, table)| !table.outage() && !spooling(&self.spool, name))
+                .min_by_key(|(_, table)| table.written_at())
+                .map(|(name, _)| name.clone())
+            else {
+                return;
+            };
+            self.flush_table(&table_name);
+            if self.tables[&table_name].dirty() {
+                return;
+            }
+            self.idle_table(table_name);
+        }
+    }
+
+    /// Reopens a table closed for going idle, if it was
+    fn wake_table(&mut self, table_name: &Cow<'static, str>) {
+        let Some(idle) = self.idle_tables.remove(table_name.as_ref()) else {
+            return;
+        };
+        if let Some(table) = self.reopen_table(table_name, idle) {
+            self.tables.insert(table_name.clone(), table);
+            self.adopt_spool(table_name);
+        }
+    }
+
+    /// Picks up the spool file of a table just opened, unless the table is spooling already
+    fn adopt_spool(&mut self, table_name: &Cow<'static, str>) {
+        let Some(spool) = &mut self.spool else {
+            return;
+        };
+        if spool.is_spooling(table_name) {
+            return;
+        }
+        if let Err(source) = spool.adopt(table_name) {
+            let error = CsvLoggerError::Spool {
+                table: table_name.clone(),
+                source,
+            };
+            error::report_at(&self.error_handler, self.clock.now(), error);
+        }
+    }
+
+    /// Closes a flushed table, remembering its epoch to append to
+    fn idle_table(&mut self, table_name: Cow<'static, str>) {
+        let table = self.tables.remove(&table_name).unwrap();
+        let idle = table.into_idle();
+        let updated = manifest::update(
+            &self.storage,
+            &self.files,
+            &idle.output_dir,
+            &table_name,
+            |manifest| {
+                manifest.open(idle.epoch, idle.records_written as u64, idle.bytes);
+            },
+        );
+        if let Err(source) = updated {
+            self.report_table_file(&table_name, "write the manifest", source);
+        }
+        self.idle_tables.insert(table_name, idle);
+        self.forget_idle();
+    }
+
+    /// Forgets the least recently written idle tables while more than
+    /// [`CsvLoggerBuilder::max_open_tables`] are idle, closing their epochs in their manifests
+    ///
+    /// Their next records open them afresh per the [`ResumePolicy`].
+    fn forget_idle(&mut self) {
+        let Some(max_open_tables) = self.max_open_tables else {
+            return;
+        };
+        while max_open_tables.get() < self.idle_tables.len() {
+            let Some(table_name) = self
+                .idle_tables
+                .iter()
+                .min_by_key(|(_, idle)| idle.written_at)
+                .map(|(name, _)| name.clone())
+            else {
+                return;
+            };
+            let idle = self.idle_tables.remove(&table_name).unwrap();
+            self.close_epoch(&table_name, &idle);
+            self.dropped.remove(table_name.as_ref());
+        }
+    }
+
+    /// Logs a heartbeat to [`HEALTH_TABLE`] if one is due
+    fn heartbeat(&mut self, now: Instant) {
+        let tables_open = self.tables.len() + self.idle_tables.len();
+        let Some(heartbeat) = self
+            .health
+            .as_mut()
+            .and_then(|health| health.beat(now, self.clock.now(), tables_open))
+        else {
+            return;
+        };
+        self.log_as(Cow::Borrowed(HEALTH_TABLE), &heartbeat);
+    }
+
+    /// Deletes closed epochs while space is short and pauses logging while it is critical, per
+    /// the [`FreeSpacePolicy`]
+    fn check_free_space(&mut self, now: Instant) {
+        let Some(free_space) = self.free_space.as_mut() else {
+            return;
+        };
+        if !free_space.due(now) {
+            return;
+        }
+        let policy = free_space.policy;
+        let mut available = match free_space.available(&self.output_dir) {
+            Ok(available) => available,
+            Err(source) => {
+                error::report_at(
+                    &self.error_handler,
+                    self.clock.now(),
+                    CsvLoggerError::FreeSpace { source },
+                );
+                return;
+            }
+        };
+        let short = available < policy.min_free_bytes;
+        let mut deleted = vec![];
+        if short {
+            // The current epochs of the open tables and of those left by earlier runs
+            let mut current = BTreeMap::new();
+            for (table_name, table) in &self.tables {
+                let key = (table.output_dir().to_path_buf(), table_name.to_string());
+                current.insert(key, table.epoch());
+            }
+            for (table_name, idle) in &self.idle_tables {
+                current.insert(
+                    (idle.output_dir.clone(), table_name.to_string()),
+                    idle.epoch,
+                );
+            }
+            let listed = reader::list_tables(&self.output_dir, None, false).unwrap_or_default();
+            for table in listed {
+                if let Some(epoch) = table.current_epoch {
+                    let key = (self.output_dir.clone(), table.name);
+                    current.entry(key).or_insert(epoch);
+                }
+            }
+            // Epochs other than the current ones by how far behind the current epochs of their
+            // tables, closed or not as recorded by a run that did not close them
+            let mut epochs = vec![];
+            for ((output_dir, table_name), current) in current {
+                let manifest = manifest::load(&self.storage, &self.files, &output_dir, &table_name);
+                let behind = manifest.into_iter().flat_map(|m| m.epochs);
+                epochs.extend(behind.filter(|e| e.epoch < current).map(|e| {
+                    (
+                        current - e.epoch,
+                        table_name.clone(),
+                        output_dir.clone(),
+                        e.epoch,
+                    )
+                }));
+            }
+            epochs.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+            for (_, table_name, output_dir, epoch) in epochs {
+                if policy.min_free_bytes <= available {
+                    break;
+                }
+                let path =
+                    self.files
+                        .epoch_file(&self.storage, &output_dir, &table_name, epoch, false);
+                let removed = delete_epoch(
+                    &self.storage,
+                    &self.files,
+                    &mut self.events,
+                    &output_dir,
+                    &table_name,
+                    epoch,
+                )
+                .and_then(|()| {
+                    manifest::update(
+                        &self.storage,
+                        &self.files,
+                        &output_dir,
+                        &table_name,
+                        |manifest| {
+                            manifest.remove(epoch);
+                        },
+                    )
+                });
+                if let Err(source) = removed {
+                    let error = CsvLoggerError::TableFile {
+                        table: Cow::Owned(table_name),
+                        action: "delete an old epoch",
+                        source,
+                    };
+                    error::report_at(&self.error_handler, self.clock.now(), error);
+                    continue;
+                }
+                deleted.push(path);
+                available = match free_space.available(&self.output_dir) {
+                    Ok(available) => available,
+                    Err(source) => {
+                        error::report_at(
+                            &self.error_handler,
+                            self.clock.now(),
+                            CsvLoggerError::FreeSpace { source },
+                        );
+                        return;
+                    }
+                };
+            }
+        }
+        if available < policy.critical_free_bytes && policy.pause {
+            free_space.paused = true;
+        } else if policy.min_free_bytes <= available {
+            free_space.paused = false;
+        }
+        if short {
+            let paused = free_space.paused;
+            self.events.emit(|| LoggerEvent::LowFreeSpace {
+                available,
+                deleted,
+                paused,
+            });
+        }
+    }
+
+    /// Flushes the filter chain and the tee and batch forwarders
+    fn flush_forwarders(&mut self) {
+        self.filter_chain.flush(&self.error_handler);
+        if let Some(tee) = &self.tee {
+            tee.flush();
+        }
+        if let Some(batch) = &self.batch {
+            batch.flush();
+        }
+    }
+
+    /// Reports a record that failed to serialize and counts it as dropped
+    pub(crate) fn drop_unserialized(&mut self, e: SerializeError) {
+        self.drop_record(&e.table);
+        error::report_at(&self.error_handler, self.clock.now(), e.into());
+    }
+
+    /// Counts a record of `table` as dropped
+    pub(crate) fn drop_record(&mut self, table: &str) {
+        stats::count_dropped(1);
+        match self.dropped.get_mut(table) {
+            Some(dropped) => *dropped += 1,
+            None => {
+                self.forget_closed_drops();
+                self.dropped.insert(table.to_string(), 1);
+            }
+        }
+    }
+
+    /// Forgets the drops of a table neither open nor idle once as many tables have drops as may
+    /// be open and idle under [`CsvLoggerBuilder::max_open_tables`]
+    fn forget_closed_drops(&mut self) {
+        let Some(max_open_tables) = self.max_open_tables else {
+            return;
+        };
+        if self.dropped.len() < 2 * max_open_tables.get() {
+            return;
+        }
+        let closed = self
+            .dropped
+            .keys()
+            .find(|table| {
+                !self.tables.contains_key(table.as_str())
+                    && !self.idle_tables.contains_key(table.as_str())
+            })
+            .cloned();
+        if let Some(table) = closed {
+            self.dropped.remove(&table);
+        }
+    }
+
+    /// Rows dropped by the filter chain so far by reason
+    pub fn filter_drops(&self) -> &HashMap<&'static str, u64> {
+        self.filter_chain.drops()
+    }
+
+    pub fn stats(&self) -> LoggerStats {
+        let idle = self
+            .idle_tables
+            .iter()
+            .map(|(name, idle)| (name, idle.stats()));
+        let tables = self
+            .tables
+            .iter()
+            .map(|(name, table)| (name, table.stats()))
+            .chain(idle)
+            .map(|(name, stats)| (name.to_string(), stats))
+            .collect();
+        let dropped = self
+            .dropped
+            .iter()
+            .map(|(table, &dropped)| (table.clone(), dropped))
+            .collect();
+        LoggerStats { tables, dropped }
+    }
+
+    fn table_allowed(&self, table_name: &str) -> bool {
+        let allowed = self
+            .allowed_tables
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(table_name));
+        allowed && !self.denied_tables.contains(table_name)
+    }
+
+    /// Flushes and closes the file of a table until it is logged to again
+    fn close_table(&mut self, table_name: &str) {
+        let closed = match self.idle_tables.remove(table_name) {
+            Some(idle) => idle,
+            None => {
+                self.flush_table(table_name);
+                let Some(table) = self.tables.remove(table_name) else {
+                    return;
+                };
+                table.into_idle()
+            }
+        };
+        self.close_epoch(table_name, &closed);
+    }
+
+    /// Records the epoch of a closed table as closed in its manifest
+    fn close_epoch(&self, table_name: &str, closed: &IdleTable) {
+        let updated = manifest::update(
+            &self.storage,
+            &self.files,
+            &closed.output_dir,
+            table_name,
+            |manifest| {
+                manifest.close(closed.epoch, closed.records_written as u64, closed.bytes);
+            },
+        );
+        if let Err(source) = updated {
+            self.report_table_file(table_name, "write the manifest", source);
+        }
+    }
+
+    /// Forgets the files and forwarder threads inherited by a child process without flushing them
+    /// and logs under `subdir` from then on
+    pub(crate) fn forget_files(&mut self, subdir: &str) {
+        for (_, table) in self.tables.drain() {
+            std::mem::forget(table);
+        }
+        lock::forget_open(&self.output_dir);
+        self.idle_tables.clear();
+        std::mem::forget(self.rotated_files.take());
+        std::mem::forget(self.tee.take());
+        std::mem::forget(self.batch.take());
+        self.output_dir = self.output_dir.join(subdir);
+    }
+
+    pub(crate) fn reset_cap(&mut self, table_name: &str) {
+        let output_dir = self
+            .table_output_dir(table_name)
+            .unwrap_or(self.output_dir.as_path())
+            .to_path_buf();
+        if let Some(cap) = self.caps.get_mut(table_name) {
+            cap.reset(&output_dir);
+        }
+        if let Err(source) = cap::remove_count(&self.storage, &self.files, &output_dir, table_name)
+        {
+            self.report_table_file(table_name, "remove the count file", source);
+        }
+    }
+
+    /// The directory a table is open or idle in, else the output directory unless the table may
+    /// go under `pid-<pid>` once it is locked
+    fn table_output_dir(&self, table_name: &str) -> Option<&Path> {
+        if let Some(table) = self.tables.get(table_name) {
+            return Some(table.output_dir());
+        }
+        if let Some(idle) = self.idle_tables.get(table_name) {
+            return Some(&idle.output_dir);
+        }
+        match self.conflict_policy {
+            Some(ConflictPolicy::PidSubdir) => None,
+            _ => Some(&self.output_dir),
+        }
+    }
+
+    /// Where to log a table and the logger's hold on it; `None` if another logger holds it or it
+    /// cannot be locked, and its records are dropped
+    ///
+    /// Without a conflict policy the lock is taken if it is free, which keeps
+    /// [`import::import_file`] off the table, and the table is logged to either way.
+    fn lock_table(&self, table_name: &Cow<'static, str>) -> Option<(PathBuf, TableLock)> {
+        let lock = |output_dir: &Path, wait| {
+            // Before the lock file creates it otherwise
+            let table_dir = self.files.table_dir(output_dir, table_name);
+            self.storage.create_dir_all(&table_dir)?;
+            // Also for readers of directories below the output directory, e.g. `pid-<pid>`
+            if self.files.layout() != Layout::PerTableDir {
+                layout::mark(&self.storage, output_dir, self.files.layout())?;
+            }
+            lock::lock(&self.storage, &self.files, output_dir, table_name, wait)
+        };
+        let hold = |output_dir: PathBuf, file| {
+            let hold = TableLock::new(&output_dir, table_name, file);
+            Some((output_dir, hold))
+        };
+        let Some(policy) = self.conflict_policy else {
+            let file = lock(&self.output_dir, false).ok().flatten();
+            return hold(self.output_dir.clone(), file);
+        };
+        let wait = policy == ConflictPolicy::Wait;
+        let held = match lock(&self.output_dir, wait) {
+            Ok(held) => held,
+            Err(source) => {
+                self.report_table_file(table_name, "take the lock", source);
+                return None;
+            }
+        };
+        if held.is_some() {
+            return hold(self.output_dir.clone(), held);
+        }
+        if policy == ConflictPolicy::PidSubdir {
+            let output_dir = self.output_dir.join(format!("pid-{}", std::process::id()));
+            return match lock(&output_dir, true) {
+                Ok(held) => hold(output_dir, held),
+                Err(source) => {
+                    self.report_table_file(table_name, "take the lock", source);
+                    None
+                }
+            };
+        }
+        let error = CsvLoggerError::TableLocked {
+            table: table_name.clone(),
+        };
+        error::report_at(&self.error_handler, self.clock.now(), error);
+        None
+    }
+
+    /// Appends to the epoch a table was closed in for going idle; `None` if its file is gone or
+    /// another logger holds the table
+    fn reopen_table(&self, table_name: &Cow<'static, str>, idle: IdleTable) -> Option<Table> {
+        let path = self.files.epoch_file(
+            &self.storage,
+            &idle.output_dir,
+            table_name,
+            idle.epoch,
+            false,
+        );
+        if !self.storage.exists(&path) {
+            return None;
+        }
+        let (output_dir, lock) = self.lock_table(table_name)?;
+        if output_dir != idle.output_dir {
+            return None;
+        }
+        let writer = match open_log_writer(&self.storage, &path, OpenMode::Append) {
+            Ok(writer) => writer,
+            Err(source) => {
+                self.report_table_file(table_name, "reopen the log file", source);
+                return None;
+            }
+        };
+        let mut table = Table::resume(
+            idle.output_dir.clone(),
+            writer,
+            idle.epoch,
+            idle.records_written,
+            idle.header.clone(),
+            self.clock.clone(),
+        )
+        .with_resumed_bytes(idle.bytes)
+        .with_idle_stats(&idle);
+        if let Some(next) = idle.next_sequence {
+            table = table.with_sequence(next);
+        }
+        table.set_hold(self.spool.is_some());
+        Some(table.with_lock(lock))
+    }
+
+    /// Whether the manifest has an epoch encoded as this logger encodes fields, or does not say
+    fn encoded_alike(&self, output_dir: &Path, table_name: &str, epoch: usize) -> bool {
+        manifest::load(&self.storage, &self.files, output_dir, table_name)
+            .and_then(|manifest| manifest.get(epoch)?.single_line_fields)
+            .is_none_or(|single_line| single_line == self.single_line_fields)
+    }
+
+    /// `Ok(None)` if another logger holds the table
+    fn open_table(&self, table_name: &Cow<'static, str>) -> io::Result<Option<Table>> {
+        let Some((output_dir, lock)) = self.lock_table(table_name) else {
+            return Ok(None);
+        };
+        let cur = cur_epoch(&self.storage, &self.files, &output_dir, table_name)?;
+        let resumed = match (self.resume, cur) {
+            // Not mixing rows with newlines escaped and rows without in one epoch
+            (ResumePolicy::AppendToLast, Some(epoch))
+                if self.encoded_alike(&output_dir, table_name, epoch) =>
+            {
+                let path =
+                    self.files
+                        .epoch_file(&self.storage, &output_dir, table_name, epoch, false);
+                if self.repair_on_resume && self.storage.on_disk() {
+                    if let Err(source) = verify::repair_epoch(epoch, &path, verify::RepairMode::Fix)
+                    {
+                        error::report_at(
+                            &self.error_handler,
+                            self.clock.now(),
+                            CsvLoggerError::Repair {
+                                table: table_name.clone(),
+                                epoch,
+                                source,
+                            },
+                        );
+                    }
+                }
+                open_appending_log_writer(&self.storage, &path)?.map(
+                    |(writer, rows, header, bytes)| {
+                        Table::resume(
+                            output_dir.clone(),
+                            writer,
+                            epoch,
+                            rows,
+                            header.map(Header::from),
+                            self.clock.clone(),
+                        )
+                        .with_resumed_bytes(bytes)
+                        .with_inherited_header()
+                    },
+                )
+            }
+            _ => None,
+        };
+        let mut table = match resumed {
+            Some(table) => table,
+            None => {
+                let mut epoch = cur.map(|e| e + 1).unwrap_or_default();
+                // Epoch files of an earlier run may outlive a lost or stale `epoch` file
+                let writer = loop {
+                    let path =
+                        self.files
+                            .epoch_file(&self.storage, &output_dir, table_name, epoch, true);
+                    match open_log_writer(&self.storage, &path, OpenMode::CreateNew) {
+                        Ok(writer) => break writer,
+                        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => epoch += 1,
+                        Err(e) => return Err(e),
+                    }
+                };
+                Table::new(output_dir.clone(), writer, epoch, self.clock.clone())
+            }
+        };
+        if self.sequence {
+            let next = sequence::next_sequence(&self.storage, &self.files, &output_dir, table_name);
+            table = table.with_sequence(next);
+        }
+        table.set_hold(self.spool.is_some());
+        Ok(Some(table.with_lock(lock)))
+    }
+}
+impl table_log::Logger for CsvLogger {
+    fn log(&mut self, record: &dyn table_log::LogRecord) {
+        self.log_record(record);
+    }
+
+    fn flush(&mut self) {
+        CsvLogger::flush(self);
+    }
+}
+impl Drop for CsvLogger {
+    /// Writes out the rows held back for their repeats and spools the rows the log files have not
+    /// taken yet
+    fn drop(&mut self) {
+        let tables = self.tables.keys().cloned().collect::<Vec<_>>();
+        for table_name in &tables {
+            if let Some(row) = self.tables.get_mut(table_name).and_then(Table::take_held) {
+                self.commit(table_name, row);
+            }
+        }
+        if self.spool.is_some() {
+            for table_name in tables {
+                self.spool_held(&table_name);
+            }
+        }
+        for (table_name, cap) in &mut self.caps {
+            let _ = cap.save(&self.storage, &self.files, table_name);
+        }
+    }
+}
+pub struct RotationPolicy {
+    pub max_records: NonZeroUsize,
+    pub max_epochs: usize,
+}
+
+/// What to do with a row whose columns differ from the header of its table's epoch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaPolicy {
+    /// Start a new epoch with the new header
+    #[default]
+    RotateOnChange,
+    /// Drop the row and report [`CsvLoggerError::SchemaMismatch`]
+    RejectMismatched,
+    /// Write the row under the old header anyway
+    ///
+    /// Keys of map records missing from the old header are dropped.
+    Ignore,
+}
+
+/// What to do with a table's last epoch when the logger starts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResumePolicy {
+    /// Start a fresh epoch after the last one
+    #[default]
+    NewEpoch,
+    /// Keep appending to the last epoch
+    ///
+    /// A first row with other columns than the epoch's header starts a new epoch instead,
+    /// whatever the [`SchemaPolicy`].
+    AppendToLast,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn rotate(
+    storage: &impl Storage,
+    files: &TableFiles,
+    durable: bool,
+    max_epochs: usize,
+    rotated_files: Option<&RotatedFileWorker>,
+    events: &mut Events,
+    table_name: &Cow<'static, str>,
+    table: &mut Table,
+) -> io::Result<()> {
+    let output_dir = table.output_dir().to_path_buf();
+    // Complete the outgoing epoch before the next one appears for tailing readers
+    table.flush()?;
+    // Rotated once the rows held back by an outage are out
+    if table.outage() {
+        return Ok(());
+    }
+    let outgoing = files.epoch_file(storage, &output_dir, table_name, table.epoch(), false);
+    // An epoch without rows starts over instead of being left behind holding at most a header.
+    // An empty file is left alone; truncating it would trash it on network filesystems.
+    if table.records_written() == 0 {
+        if table.bytes_written() != 0 {
+            table.restart(|| open_log_writer(storage, &outgoing, OpenMode::Truncate))?;
+        }
+        return Ok(());
+    }
+    let closed = table.stats();
+    // The table directory, or the date buckets of the epochs
+    let sync_dir =
+        |path: &Path| storage.sync_dir(path.parent().expect("Log files are in a directory"));
+    if durable {
+        table.sync_all()?;
+        sync_dir(&outgoing)?;
+    }
+    let new_path = files.epoch_file(storage, &output_dir, table_name, table.epoch() + 1, true);
+    let new_writer = open_log_writer(storage, &new_path, OpenMode::Truncate)?;
+    if durable {
+        sync_dir(&new_path)?;
+    }
+    table.replace(new_writer);
+    telemetry::rotated();
+
+    let epoch = table.epoch();
+    let old_path = files.epoch_file(storage, &output_dir, table_name, epoch - 1, false);
+    events.emit(|| LoggerEvent::Rotated {
+        table: table_name.to_string(),
+        old_path: old_path.clone(),
+        new_epoch: epoch,
+    });
+    if let Some(rotated_files) = rotated_files {
+        rotated_files.send(table_name.clone(), epoch - 1, old_path);
+    }
+    write_epoch(storage, files, &output_dir, table_name, epoch)?;
+    let deleted = delete_old_log_file(
+        storage,
+        files,
+        events,
+        epoch,
+        max_epochs,
+        &output_dir,
+        table_name,
+    )?;
+    manifest::update(storage, files, &output_dir, table_name, |manifest| {
+        manifest.close(
+            closed.epoch,
+            closed.epoch_records as u64,
+            closed.epoch_bytes,
+        );
+        manifest.open(epoch, 0, 0);
+        if let Some(deleted) = deleted {
+            manifest.remove(deleted);
+        }
+    })
+}
+
+/// The epoch resumed from an earlier run only takes rows under its own header
+fn schema_policy(policy: SchemaPolicy, table: &Table) -> SchemaPolicy {
+    match table.header_inherited() {
+        true => SchemaPolicy::RotateOnChange,
+        false => policy,
+    }
+}
+
+fn spooling(spool: &Option<Spool>, table_name: &str) -> bool {
+    spool
+        .as_ref()
+        .is_some_and(|spool| spool.is_spooling(table_name))
+}
+
+fn delete_old_log_file(
+    storage: &impl Storage,
+    files: &TableFiles,
+    events: &mut Events,
+    epoch: usize,
+    max_epochs: usize,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> io::Result<Option<usize>> {
+    let del_epoch = epoch.checked_sub(max_epochs);
+    if let Some(del_epoch) = del_epoch {
+        delete_epoch(storage, files, events, output_dir, table_name, del_epoch)?;
+    }
+    Ok(del_epoch)
+}
+
+/// Removes the log file of an epoch and its checksum, if any, and the date buckets it empties
+fn delete_epoch(
+    storage: &impl Storage,
+    files: &TableFiles,
+    events: &mut Events,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+    epoch: usize,
+) -> io::Result<()> {
+    let output_dir = output_dir.as_ref();
+    let path = files.epoch_file(storage, output_dir, table_name, epoch, false);
+    let sidecar = checksum::sidecar_path(&path);
+    let deleted = storage::remove_if_exists(storage, &path)?;
+    // Encrypted since it was rotated
+    #[cfg(feature = "encryption")]
+    let (path, deleted) = match deleted {
+        true => (path, true),
+        false => {
+            let mut sealed = (path.clone(), false);
+            for encrypted in encryption::sealed_paths(&path) {
+                let deleted = storage::remove_if_exists(storage, &encrypted)?;
+                storage::remove_if_exists(storage, &checksum::sidecar_path(&encrypted))?;
+                if deleted {
+                    sealed = (encrypted, true);
+                }
+            }
+            sealed
+        }
+    };
+    storage::remove_if_exists(storage, &sidecar)?;
+    if files.layout() == Layout::DateBuckets {
+        files.forget_bucket(output_dir, table_name, epoch);
+        // Day, month and year
+        for dir in path.ancestors().skip(1).take(3) {
+            if !storage::remove_dir_if_empty(storage, dir) {
+                break;
+            }
+        }
+    }
+    if deleted {
+        events.emit(|| LoggerEvent::EpochDeleted {
+            table: table_name.to_string(),
+            epoch,
+            path,
+        });
+    }
+    Ok(())
+}
+
+/// An appending writer continues the header of the epoch it resumes
+fn open_log_writer(storage: &impl Storage, path: &Path, mode: OpenMode) -> io::Result<LogWriter> {
+    let file = MeteredWriter::new(storage.open(path, mode)?);
+    Ok(csv::Writer::from_writer(Holding::new(file)))
+}
+
+/// Returns `None` if the log file is gone; the header is `None` if the file is empty
+///
+/// A file without a complete line, i.e. with a partial header, is emptied so the header is
+/// written anew. Also returns the rows and the size of the file.
+fn open_appending_log_writer(
+    storage: &impl Storage,
+    path: &Path,
+) -> io::Result<Option<(LogWriter, usize, Option<Vec<String>>, u64)>> {
+    let contents = match storage.read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !contents.is_empty() && !contents.contains(&b'\n') {
+        let writer = open_log_writer(storage, path, OpenMode::Truncate)?;
+        return Ok(Some((writer, 0, None, 0)));
+    }
+    let bytes = contents.len() as u64;
+    let mut reader = reader::epoch_reader(contents);
+    let header = reader
+        .headers()?
+        .iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let rows = reader.records().count();
+    let writer = open_log_writer(storage, path, OpenMode::Append)?;
+    Ok(Some((writer, rows, (bytes != 0).then_some(header), bytes)))
+}
+
+fn write_epoch(
+    storage: &impl Storage,
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+    epoch: usize,
+) -> io::Result<()> {
+    let path = epoch_file_path(files, output_dir, table_name);
+    storage.replace(&path, epoch.to_string().as_bytes())
+}
+
+fn cur_epoch(
+    storage: &impl Storage,
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> io::Result<Option<usize>> {
+    if let Some(epoch) = manifest::load(storage, files, &output_dir, table_name)
+        .and_then(|manifest| manifest.current_epoch())
+    {
+        return Ok(Some(epoch));
+    }
+    let path = epoch_file_path(files, output_dir, table_name);
+    let epoch = match storage.read(&path) {
+        Ok(epoch) => epoch,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let epoch: usize = match std::str::from_utf8(&epoch)
+        .ok()
+        .and_then(|e| e.parse().ok())
+    {
+        Some(epoch) => epoch,
+        None => {
+            storage.remove(&path)?;
+            return Ok(None);
+        }
+    };
+    Ok(Some(epoch))
+}
+
+fn is_valid_table_name(table_name: &str) -> bool {
+    !matches!(table_name, "" | "." | "..")
+        && !table_name.contains(|c| matches!(c, '/' | '\\' | '\0'))
+}
+
+fn epoch_file_path(files: &TableFiles, output_dir: impl AsRef<Path>, table_name: &str) -> PathBuf {
+    files.table_file(output_dir, table_name, "epoch")
+}
+
+/// The log file of an epoch as laid out per the `layout` marker of `output_dir`
+#[cfg(test)]
+fn log_file_path(output_dir: impl AsRef<Path>, table_name: &str, epoch: usize) -> PathBuf {
+    let output_dir = output_dir.as_ref();
+    TableFiles::of(output_dir).epoch_file(&RealFs, output_dir, table_name, epoch, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use serial_test::serial;
+
+    use super::*;
+    use crate::storage::MemStorage;
+
+    #[derive(serde::Serialize)]
+    struct TestRecord<'caller> {
+        pub s: &'caller str,
+        pub n: usize,
+    }
+    impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+        fn table_name(&self) -> &'static str {
+            "test"
+        }
+    }
+
+    fn remove_logger() {
+        let mut log = table_log::GLOBAL_LOG.lock().unwrap();
+        log.remove_logger();
+    }
+
+    fn write_fields(path: &Path, mode: OpenMode, fields: &[&str]) -> io::Result<()> {
+        let mut writer = open_log_writer(&Backend::Real, path, mode)?;
+        writer.write_record(fields)?;
+        writer.flush()
+    }
+
+    #[test]
+    fn test_open_log_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test").join("0.csv");
+
+        // Every mode creates a missing file and its directories
+        write_fields(&path, OpenMode::CreateNew, &["a"]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\n");
+        let other = dir.path().join("other").join("0.csv");
+        write_fields(&other, OpenMode::Append, &["a"]).unwrap();
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "a\n");
+        let other = dir.path().join("another").join("0.csv");
+        write_fields(&other, OpenMode::Truncate, &["a"]).unwrap();
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "a\n");
+
+        // Existing content
+        let err = write_fields(&path, OpenMode::CreateNew, &["b"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\n");
+        write_fields(&path, OpenMode::Append, &["b"]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb\n");
+        write_fields(&path, OpenMode::Truncate, &["c"]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "c\n");
+    }
+
+    #[test]
+    #[serial]
+    fn test_stale_epoch_not_clobbered() {
+        let dir = tempfile::tempdir().unwrap();
+        // Left behind by a run whose `epoch` file is lost
+        let stale = log_file_path(dir.path(), "test", 0);
+        std::fs::create_dir_all(stale.parent().unwrap()).unwrap();
+        std::fs::write(&stale, "s,n\nold,0\n").unwrap();
+
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .build();
+        logger.log_record(&TestRecord { s: "new", n: 1 });
+        logger.flush();
+
+        assert_eq!(std::fs::read_to_string(&stale).unwrap(), "s,n\nold,0\n");
+        let path = log_file_path(dir.path(), "test", 1);
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "s,n\nnew,1\n");
+        assert_eq!(
+            cur_epoch(
+                &Backend::Real,
+                &TableFiles::of(dir.path()),
+                dir.path(),
+                "test"
+            )
+            .unwrap(),
+            Some(1)
+        );
+    }
+
+    /// The backends the global logger tests run against
+    enum TestBackend {
+        Real(tempfile::TempDir),
+        Memory(MemStorage),
+    }
+    impl TestBackend {
+        fn all() -> [Self; 2] {
+            [
+                Self::Real(tempfile::tempdir().unwrap()),
+                Self::Memory(MemStorage::new()),
+            ]
+        }
+
+        fn output_dir(&self) -> PathBuf {
+            match self {
+                Self::Real(dir) => dir.path().to_owned(),
+                Self::Memory(_) => PathBuf::from("/logs"),
+            }
+        }
+
+        fn init(&self, rotation: RotationPolicy) {
+            let builder = CsvLoggerBuilder::new(self.output_dir(), rotation);
+            let builder = match self {
+                Self::Real(_) => builder,
+                Self::Memory(storage) => builder.storage(storage.clone()),
+            };
+            builder.init().expect("Failed to initialize the logger");
+        }
+
+        fn read(&self, epoch: usize) -> Option<String> {
+            let path = log_file_path(self.output_dir(), "test", epoch);
+            match self {
+                Self::Real(_) => std::fs::read_to_string(path).ok(),
+                Self::Memory(storage) => storage.read_to_string(path),
+            }
+        }
+
+        fn exists(&self, epoch: usize) -> bool {
+            self.read(epoch).is_some()
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_logger() {
+        for backend in TestBackend::all() {
+            backend.init(RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 2,
+            });
+            table_log::log!(&TestRecord { s: "a", n: 0 });
+            table_log::log!(&TestRecord { s: "b", n: 1 });
+            table_log::flush();
+            assert_eq!(
+                backend.read(0).unwrap(),
+                r#"s,n
+a,0
+b,1
+"#
+            );
+
+            remove_logger();
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_rotation() {
+        for backend in TestBackend::all() {
+            backend.init(RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 2,
+            });
+
+            table_log::log!(&TestRecord { s: "a", n: 0 });
+            table_log::flush();
+            assert!(backend.exists(0));
+            assert!(!backend.exists(1));
+
+            table_log::log!(&TestRecord { s: "b", n: 1 });
+            assert!(backend.exists(0));
+            assert!(backend.exists(1));
+            assert!(!backend.exists(2));
+
+            table_log::log!(&TestRecord { s: "c", n: 2 });
+            table_log::flush();
+            assert!(backend.exists(0));
+            assert!(backend.exists(1));
+            assert!(!backend.exists(2));
+
+            table_log::log!(&TestRecord { s: "d", n: 3 });
+            assert!(!backend.exists(0));
+            assert!(backend.exists(1));
+            assert!(backend.exists(2));
+            assert!(!backend.exists(3));
+
+            remove_logger();
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn flusher_threads() -> usize {
+        std::fs::read_dir("/proc/self/task")
+            .unwrap()
+            .filter_map(|task| std::fs::read_to_string(task.unwrap().path().join("comm")).ok())
+            .filter(|comm| comm.starts_with("CsvLogger::flus"))
+            .count()
+    }
+
+    fn wait_until(mut cond: impl FnMut() -> bool) {
+        let start = std::time::Instant::now();
+        while !cond() {
+            assert!(start.elapsed() < Duration::from_secs(5), "Timed out");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    struct RecordingHandler {
+        calls: std::sync::Arc<std::sync::Mutex<Vec<(String, usize)>>>,
+        failures: std::sync::atomic::AtomicUsize,
+        disposition: RotatedFileDisposition,
+    }
+    impl RotatedFileHandler for RecordingHandler {
+        fn handle(
+            &self,
+            table: &str,
+            epoch: usize,
+            path: &Path,
+        ) -> std::io::Result<RotatedFileDisposition> {
+            assert_eq!(
+                path,
+                log_file_path(path.parent().unwrap().parent().unwrap(), table, epoch)
+            );
+            if 0 < self.failures.load(std::sync::atomic::Ordering::SeqCst) {
+                self.failures
+                    .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                return Err(std::io::Error::other("upload failed"));
+            }
+            self.calls.lock().unwrap().push((table.to_string(), epoch));
+            Ok(self.disposition)
+        }
+    }
+
+    fn init_with_handler(
+        dir: &Path,
+        disposition: RotatedFileDisposition,
+        failures: usize,
+    ) -> std::sync::Arc<std::sync::Mutex<Vec<(String, usize)>>> {
+        let calls = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let handler = RecordingHandler {
+            calls: calls.clone(),
+            failures: std::sync::atomic::AtomicUsize::new(failures),
+            disposition,
+        };
+        CsvLoggerBuilder::new(
+            dir.to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(1).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .rotated_file_handler(handler)
+        .rotated_file_backoff(Backoff {
+            max_attempts: NonZeroUsize::new(3).unwrap(),
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(1),
+        })
+        .init()
+        .unwrap();
+        calls
+    }
+
+    #[test]
+    #[serial]
+    fn test_rotated_file_handler_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let calls = init_with_handler(dir.path(), RotatedFileDisposition::Delete, 1);
+
+        table_log::log!(&TestRecord { s: "a", n: 0 });
+        table_log::log!(&TestRecord { s: "b", n: 1 });
+        wait_until(|| calls.lock().unwrap().len() == 2);
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [("test".to_string(), 0), ("test".to_string(), 1)]
+        );
+        wait_until(|| {
+            !log_file_path(dir.path(), "test", 0).exists()
+                && !log_file_path(dir.path(), "test", 1).exists()
+        });
+        assert!(log_file_path(dir.path(), "test", 2).exists());
+
+        remove_logger();
+    }
+
+    #[test]
+    #[serial]
+    fn test_rotated_file_handler_keep() {
+        let dir = tempfile::tempdir().unwrap();
+        let calls = init_with_handler(dir.path(), RotatedFileDisposition::Keep, 0);
+
+        table_log::log!(&TestRecord { s: "a", n: 0 });
+        wait_until(|| calls.lock().unwrap().len() == 1);
+        remove_logger();
+        let mut csv = String::new();
+        std::fs::File::open(log_file_path(dir.path(), "test", 0))
+            .unwrap()
+            .read_to_string(&mut csv)
+            .unwrap();
+        assert_eq!(csv, "s,n\na,0\n");
+    }
+
+    #[test]
+    #[serial]
+    fn test_tcp_sink() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(1).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .output_target(OutputTarget::Tcp(listener.local_addr().unwrap()))
+        .init()
+        .unwrap();
+        table_log::log!(&TestRecord { s: "a", n: 0 });
+        table_log::log!(&TestRecord { s: "b", n: 1 });
+        // Rows to tables named at runtime go through the sink too
+        log_to("tenant-1", &TestRecord { s: "c", n: 2 });
+        transaction(|tx| tx.log_to("tenant-2", &TestRecord { s: "d", n: 3 }));
+        table_log::flush();
+        let (mut stream, _) = listener.accept().unwrap();
+        remove_logger();
+
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        assert_eq!(
+            received,
+            "test,s,n\ntest,a,0\ntest,b,1\n\
+             tenant-1,s,n\ntenant-1,c,2\ntenant-2,s,n\ntenant-2,d,3\n"
+        );
+        assert!(!dir.path().join("test").exists());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    #[serial]
+    fn test_metrics() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let dir = tempfile::tempdir().unwrap();
+        metrics::with_local_recorder(&recorder, || {
+            init(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(2).unwrap(),
+                    max_epochs: 2,
+                },
+            );
+            table_log::log!(&TestRecord { s: "a", n: 0 });
+            table_log::log!(&TestRecord { s: "b", n: 1 });
+            table_log::log!(&TestRecord { s: "c", n: 2 });
+            table_log::flush();
+        });
+        remove_logger();
+
+        let counters: HashMap<String, u64> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .filter_map(|(key, _, _, value)| match value {
+                DebugValue::Counter(v) => Some((key.key().name().to_string(), v)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(counters["csv_logger_records_total"], 3);
+        assert_eq!(counters["csv_logger_rotations_total"], 1);
+        let bytes = "s,n\na,0\nb,1\n".len() + "s,n\nc,2\n".len();
+        assert_eq!(counters["csv_logger_bytes_written_total"], bytes as u64);
+        assert!(!counters.contains_key("csv_logger_errors_total"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn test_unix_socket_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("collector.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(1).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .output_target(OutputTarget::UnixSocket(socket))
+        .sink_format(SinkFormat::JsonLines)
+        .init()
+        .unwrap();
+        table_log::log!(&TestRecord { s: "a\"", n: 0 });
+        table_log::log!(&TestRecord { s: "b", n: 1 });
+        table_log::flush();
+        let (mut stream, _) = listener.accept().unwrap();
+        remove_logger();
+
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        assert_eq!(
+            received,
+            r#"{"table":"test","s":"a\"","n":"0"}
+{"table":"test","s":"b","n":"1"}
+"#
+        );
+    }
+
+    #[cfg(feature = "http-sink")]
+    #[test]
+    #[serial]
+    fn test_http_uploader() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/upload", server.server_addr().to_ip().unwrap());
+        let dir = tempfile::tempdir().unwrap();
+        CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .rotated_file_handler(HttpUploader::new(url))
+        .init()
+        .unwrap();
+        table_log::log!(&TestRecord { s: "a", n: 0 });
+        table_log::log!(&TestRecord { s: "b", n: 1 });
+
+        let mut request = server
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap()
+            .unwrap();
+        let header = |name: &str| {
+            request
+                .headers()
+                .iter()
+                .find(|h| h.field.equiv(name))
+                .map(|h| h.value.as_str().to_string())
+        };
+        assert_eq!(header("X-Table").as_deref(), Some("test"));
+        assert_eq!(header("X-Epoch").as_deref(), Some("0"));
+        let mut body = String::new();
+        request.as_reader().read_to_string(&mut body).unwrap();
+        assert_eq!(body, "s,n\na,0\nb,1\n");
+        request.respond(tiny_http::Response::empty(200)).unwrap();
+
+        wait_until(|| !log_file_path(dir.path(), "test", 0).exists());
+        remove_logger();
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    #[serial]
+    async fn test_init_async() {
+        #[cfg(target_os = "linux")]
+        let before = flusher_threads();
+
+        let dir = tempfile::tempdir().unwrap();
+        let flusher = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .flush_interval(Duration::from_millis(50))
+        .init_async()
+        .unwrap();
+        table_log::log!(&TestRecord { s: "a", n: 0 });
+
+        let path = log_file_path(dir.path(), "test", 0);
+        let start = std::time::Instant::now();
+        while std::fs::read_to_string(&path).unwrap() != "s,n\na,0\n" {
+            assert!(start.elapsed() < Duration::from_secs(5), "Timed out");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        #[cfg(target_os = "linux")]
+        assert_eq!(flusher_threads(), before);
+
+        table_log::log!(&TestRecord { s: "b", n: 1 });
+        flush_async().await;
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "s,n\na,0\nb,1\n");
+
+        flusher.abort();
+        remove_logger();
+    }
+
+    #[test]
+    #[serial]
+    fn test_channel() {
+        let rx = init_channel(16, Backpressure::Block);
+        table_log::log!(&TestRecord { s: "a", n: 0 });
+        table_log::log!(&TestRecord { s: "b", n: 1 });
+        table_log::flush();
+        remove_logger();
+
+        let rows = rx.iter().collect::<Vec<_>>();
+        let row = |s: &str, n: &str| Row {
+            table: "test".into(),
+            header: vec!["s".to_string(), "n".to_string()].into(),
+            fields: vec![s.to_string(), n.to_string()],
+        };
+        assert_eq!(rows, [row("a", "0"), row("b", "1")]);
+    }
+
+    struct FlakyRemote {
+        received: std::sync::Arc<std::sync::Mutex<Vec<Row>>>,
+        failing: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    }
+    impl RecordSink for FlakyRemote {
+        fn write_row(&mut self, row: &Row) -> std::io::Result<()> {
+            if self.failing.load(std::sync::atomic::Ordering::SeqCst) {
+                return Err(std::io::Error::other("remote down"));
+            }
+            self.received.lock().unwrap().push(row.clone());
+            Ok(())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_failover_tee() {
+        let dir = tempfile::tempdir().unwrap();
+        let received = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let failing = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let tee = FailoverTee::new(
+            FlakyRemote {
+                received: received.clone(),
+                failing: failing.clone(),
+            },
+            dir.path().join("spill.csv"),
+        )
+        .retry_interval(Duration::from_millis(10));
+        let lag = tee.lag();
+        CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(100).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .failover_tee(tee)
+        .error_handler(|_| ())
+        .init()
+        .unwrap();
+
+        table_log::log!(&TestRecord { s: "a", n: 0 });
+        table_log::log!(&TestRecord { s: "a", n: 1 });
+        wait_until(|| received.lock().unwrap().len() == 2);
+
+        failing.store(true, std::sync::atomic::Ordering::SeqCst);
+        for n in 2..5 {
+            table_log::log!(&TestRecord { s: "a", n });
+        }
+        wait_until(|| lag.get().spilled == 3);
+
+        failing.store(false, std::sync::atomic::Ordering::SeqCst);
+        table_log::log!(&TestRecord { s: "a", n: 5 });
+        wait_until(|| received.lock().unwrap().len() == 6);
+        wait_until(|| lag.get().spilled == 0);
+        let ns = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|row| row.fields[1].parse::<usize>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ns, (0..6).collect::<Vec<_>>());
+        assert!(!dir.path().join("spill.csv").exists());
+
+        table_log::flush();
+        remove_logger();
+        let local = std::fs::read_to_string(log_file_path(dir.path(), "test", 0)).unwrap();
+        assert_eq!(local.lines().count(), 1 + 6);
+    }
+
+    #[test]
+    #[serial]
+    fn test_no_auto_flush() {
+        #[cfg(target_os = "linux")]
+        let before = flusher_threads();
+        let dir = tempfile::tempdir().unwrap();
+        let flusher = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .auto_flush(false)
+        .init()
+        .unwrap();
+        assert!(flusher.is_none());
+        #[cfg(target_os = "linux")]
+        assert_eq!(flusher_threads(), before);
+
+        table_log::log!(&TestRecord { s: "a", n: 0 });
+        let path = log_file_path(dir.path(), "test", 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        table_log::flush();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "s,n\na,0\n");
+
+        remove_logger();
+    }
+
+    #[test]
+    #[serial]
+    fn test_flusher_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let flusher = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .init()
+        .unwrap()
+        .unwrap();
+        assert_eq!(flusher.thread().name(), Some("CsvLogger::flush()"));
+        flusher.shutdown();
+
+        remove_logger();
+    }
+
+    struct FlakyBatchSink {
+        pushes: std::sync::Arc<std::sync::Mutex<Vec<Vec<Vec<String>>>>>,
+        failures: usize,
+    }
+    impl BatchSink for FlakyBatchSink {
+        fn push(&mut self, table: &str, rows: &[Vec<String>]) -> Result<(), BoxError> {
+            assert_eq!(table, "test");
+            if 0 < self.failures {
+                self.failures -= 1;
+                return Err("push failed".into());
+            }
+            self.pushes.lock().unwrap().push(rows.to_vec());
+            Ok(())
+        }
+    }
+
+    fn init_with_batch_sink(
+        dir: &Path,
+        failures: usize,
+    ) -> (
+        std::sync::Arc<std::sync::Mutex<Vec<Vec<Vec<String>>>>>,
+        std::sync::Arc<std::sync::atomic::AtomicU64>,
+    ) {
+        let pushes = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let forwarder = BatchForwarder::new(
+            FlakyBatchSink {
+                pushes: pushes.clone(),
+                failures,
+            },
+            BatchPolicy {
+                max_rows: NonZeroUsize::new(3).unwrap(),
+                max_age: Duration::from_millis(100),
+                backoff: Backoff {
+                    max_attempts: NonZeroUsize::new(2).unwrap(),
+                    initial: Duration::from_millis(1),
+                    max: Duration::from_millis(1),
+                },
+                ..Default::default()
+            },
+        );
+        let dropped = forwarder.dropped_rows();
+        CsvLoggerBuilder::new(
+            dir.to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(100).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .batch_forwarder(forwarder)
+        .error_handler(|_| ())
+        .init()
+        .unwrap();
+        (pushes, dropped)
+    }
+
+    #[test]
+    #[serial]
+    fn test_batch_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let (pushes, dropped) = init_with_batch_sink(dir.path(), 1);
+        for n in 0..7 {
+            table_log::log!(&TestRecord { s: "a", n });
+        }
+        wait_until(|| pushes.lock().unwrap().len() == 3);
+        let ns = pushes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|batch| batch.iter().map(|row| row[1].clone()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(ns, [vec!["0", "1", "2"], vec!["3", "4", "5"], vec!["6"]]);
+        assert_eq!(dropped.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        remove_logger();
+    }
+
+    #[test]
+    #[serial]
+    fn test_batch_sink_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let (pushes, dropped) = init_with_batch_sink(dir.path(), 2);
+        for n in 0..4 {
+            table_log::log!(&TestRecord { s: "a", n });
+        }
+        wait_until(|| pushes.lock().unwrap().len() == 1);
+        assert_eq!(dropped.load(std::sync::atomic::Ordering::SeqCst), 3);
+        assert_eq!(
+            pushes.lock().unwrap()[0],
+            [vec!["a".to_string(), "3".to_string()]]
+        );
+
+        table_log::flush();
+        remove_logger();
+        let local = std::fs::read_to_string(log_file_path(dir.path(), "test", 0)).unwrap();
+        assert_eq!(local.lines().count(), 1 + 4);
+    }
+
+    #[test]
+    fn test_resume_append_to_last() {
+        use table_log::Logger;
+
+        let dir = tempfile::tempdir().unwrap();
+        let rotation = || RotationPolicy {
+            max_records: NonZeroUsize::new(3).unwrap(),
+            max_epochs: 2,
+        };
+        let build = || {
+            CsvLoggerBuilder::new(dir.path().to_owned(), rotation())
+                .resume_policy(ResumePolicy::AppendToLast)
+                .repair_on_resume(true)
+                .build()
+        };
+        let mut logger = build();
+        logger.log(&TestRecord { s: "a", n: 0 });
+        logger.log(&TestRecord { s: "b", n: 1 });
+        logger.flush();
+        drop(logger);
+
+        // Half-written row left by a crash
+        let path = log_file_path(dir.path(), "test", 0);
+        let mut file = std::fs::File::options().append(true).open(&path).unwrap();
+        file.write_all(b"c,").unwrap();
+        drop(file);
+
+        let mut logger = build();
+        logger.log(&TestRecord { s: "c", n: 2 });
+        logger.flush();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(csv, "s,n\na,0\nb,1\nc,2\n");
+        assert!(path.with_extension("repaired").exists());
+        // The resumed rows count towards rotation
+        assert!(log_file_path(dir.path(), "test", 1).exists());
+    }
+
+    #[test]
+    fn test_resume_header_mismatch() {
+        use std::sync::Mutex;
+
+        use table_log::Logger;
+
+        #[derive(serde::Serialize)]
+        struct RenamedRecord {
+            pub name: &'static str,
+            pub n: usize,
+        }
+        impl table_log::LogRecord<'_> for RenamedRecord {
+            fn table_name(&self) -> &'static str {
+                "test"
+            }
+        }
+
+        #[derive(Clone, Default)]
+        struct SchemaChanges(Arc<Mutex<Vec<LoggerEvent>>>);
+        impl LoggerObserver for SchemaChanges {
+            fn on_event(&self, event: LoggerEvent) {
+                if matches!(event, LoggerEvent::SchemaChanged { .. }) {
+                    self.0.lock().unwrap().push(event);
+                }
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let changes = SchemaChanges::default();
+        let build = || {
+            CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(10).unwrap(),
+                    max_epochs: 10,
+                },
+            )
+            .resume_policy(ResumePolicy::AppendToLast)
+            // Would append misaligned rows to the resumed epoch
+            .schema_policy(SchemaPolicy::Ignore)
+            .observer(changes.clone())
+            .build()
+        };
+        let mut logger = build();
+        logger.log(&TestRecord { s: "a", n: 0 });
+        logger.flush();
+        drop(logger);
+
+        let mut logger = build();
+        logger.log(&RenamedRecord { name: "b", n: 1 });
+        logger.log(&TestRecord { s: "c", n: 2 });
+        logger.flush();
+        let read = |epoch| std::fs::read_to_string(log_file_path(dir.path(), "test", epoch));
+        assert_eq!(read(0).unwrap(), "s,n\na,0\n");
+        // Only the resumed epoch is protected; the policy applies from then on
+        assert_eq!(read(1).unwrap(), "name,n\nb,1\nc,2\n");
+        assert_eq!(
+            *changes.0.lock().unwrap(),
+            [LoggerEvent::SchemaChanged {
+                table: "test".to_string(),
+                old_header: vec!["s".to_string(), "n".to_string()],
+                new_header: vec!["name".to_string(), "n".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_resume_partial_header() {
+        use table_log::Logger;
+
+        let resume = |contents: &str| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = log_file_path(dir.path(), "test", 0);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, contents).unwrap();
+            std::fs::write(
+                epoch_file_path(&TableFiles::of(dir.path()), dir.path(), "test"),
+                "0",
+            )
+            .unwrap();
+            let mut logger = CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(10).unwrap(),
+                    max_epochs: 10,
+                },
+            )
+            .resume_policy(ResumePolicy::AppendToLast)
+            .build();
+            logger.log(&TestRecord { s: "a", n: 0 });
+            logger.log(&TestRecord { s: "b", n: 1 });
+            logger.flush();
+            assert!(!log_file_path(dir.path(), "test", 1).exists());
+            assert!(verify::check_table(dir.path(), "test").unwrap().is_ok());
+            std::fs::read_to_string(&path).unwrap()
+        };
+        assert_eq!(resume(""), "s,n\na,0\nb,1\n");
+        // Cut short by a crash before the first flush completed
+        assert_eq!(resume("s,"), "s,n\na,0\nb,1\n");
+
+        // Headerless rows are appended to a headerless epoch
+        let dir = tempfile::tempdir().unwrap();
+        let path = log_file_path(dir.path(), "test", 0);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "a,0\n").unwrap();
+        std::fs::write(
+            epoch_file_path(&TableFiles::of(dir.path()), dir.path(), "test"),
+            "0",
+        )
+        .unwrap();
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .resume_policy(ResumePolicy::AppendToLast)
+        .build();
+        logger.log_to("test", &("b", 1));
+        logger.log_to("test", &("c", 2));
+        logger.flush();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a,0\nb,1\nc,2\n");
+        assert!(!log_file_path(dir.path(), "test", 1).exists());
+    }
+
+    fn log_two_shapes(policy: SchemaPolicy) -> (tempfile::TempDir, Vec<String>) {
+        use table_log::Logger;
+
+        #[derive(serde::Serialize)]
+        struct WiderRecord<'caller> {
+            pub s: &'caller str,
+            pub n: usize,
+            pub extra: bool,
+        }
+        impl<'caller> table_log::LogRecord<'caller> for WiderRecord<'caller> {
+            fn table_name(&self) -> &'static str {
+                "test"
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let errors = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .schema_policy(policy)
+        .error_handler({
+            let errors = errors.clone();
+            move |e| errors.lock().unwrap().push(e.to_string())
+        })
+        .build();
+        logger.log(&TestRecord { s: "a", n: 0 });
+        logger.log(&WiderRecord {
+            s: "b",
+            n: 1,
+            extra: true,
+        });
+        logger.log(&WiderRecord {
+            s: "c",
+            n: 2,
+            extra: false,
+        });
+        logger.flush();
+        let errors = errors.lock().unwrap().clone();
+        (dir, errors)
+    }
+
+    fn read_epoch(dir: &Path, epoch: usize) -> String {
+        std::fs::read_to_string(log_file_path(dir, "test", epoch)).unwrap()
+    }
+
+    #[test]
+    fn test_schema_rotate_on_change() {
+        let (dir, errors) = log_two_shapes(SchemaPolicy::RotateOnChange);
+        assert!(errors.is_empty());
+        assert_eq!(read_epoch(dir.path(), 0), "s,n\na,0\n");
+        assert_eq!(
+            read_epoch(dir.path(), 1),
+            "s,n,extra\nb,1,true\nc,2,false\n"
+        );
+        assert!(!log_file_path(dir.path(), "test", 2).exists());
+    }
+
+    #[test]
+    fn test_schema_reject_mismatched() {
+        let (dir, errors) = log_two_shapes(SchemaPolicy::RejectMismatched);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("extra"));
+        assert_eq!(read_epoch(dir.path(), 0), "s,n\na,0\n");
+        assert!(!log_file_path(dir.path(), "test", 1).exists());
+    }
+
+    #[test]
+    fn test_schema_ignore() {
+        let (dir, errors) = log_two_shapes(SchemaPolicy::Ignore);
+        assert!(errors.is_empty());
+        assert_eq!(read_epoch(dir.path(), 0), "s,n\na,0\nb,1,true\nc,2,false\n");
+    }
+
+    #[test]
+    fn test_reserved_table_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLogger::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 10,
+            },
+        );
+        logger.log_to("con", &TestRecord { s: "a", n: 0 });
+        logger.log_to("_con_", &TestRecord { s: "b", n: 1 });
+        logger.flush();
+        assert!(dir.path().join("_con_").join("0.csv").exists());
+        assert!(dir.path().join("__con__").join("0.csv").exists());
+
+        let tables = reader::list_tables(dir.path(), None, false).unwrap();
+        let names = tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["_con_", "con"]);
+        let rows = reader::TableReader::open(dir.path(), None, "con")
+            .unwrap()
+            .records()
+            .count();
+        assert_eq!(rows, 1);
+    }
+
+    #[test]
+    fn test_rotate_idle_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .network_fs_mode(true)
+        .build();
+        logger.log_record(&TestRecord { s: "a", n: 0 });
+        logger.rotate_table("test");
+        logger.rotate_table("test");
+        logger.rotate_table("test");
+        // The empty epoch was not truncated over and over; flushing would purge the trash
+        let trash = dir.path().join(network_fs::TRASH_DIR);
+        assert!(std::fs::read_dir(trash).map_or(true, |mut trash| trash.next().is_none()));
+        logger.log_record(&TestRecord { s: "b", n: 1 });
+        logger.rotate_table("test");
+        logger.flush();
+
+        let epochs = reader::epochs(dir.path(), None, "test").unwrap();
+        let epochs = epochs.iter().map(|e| e.epoch).collect::<Vec<_>>();
+        assert_eq!(epochs, [0, 1, 2]);
+        assert_eq!(read_epoch(dir.path(), 0), "s,n\na,0\n");
+        assert_eq!(read_epoch(dir.path(), 1), "s,n\nb,1\n");
+        // Created by the rotation, see `rotate_table`
+        assert_eq!(read_epoch(dir.path(), 2), "");
+    }
+
+    #[test]
+    fn test_idle_close() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .clock(clock.clone())
+        .idle_close(Some(Duration::from_secs(60)))
+        .build();
+        logger.log_record(&TestRecord { s: "a", n: 0 });
+        logger.log_to("bare", &("a", 0));
+        logger.flush();
+        assert!(logger.stats().tables.contains_key("test"));
+
+        let open = logger.stats().tables["test"].clone();
+
+        clock.advance(Duration::from_secs(60));
+        logger.flush();
+        assert!(!logger.tables.contains_key("test"));
+        // Closed tables keep their counters
+        let closed = logger.stats().tables["test"].clone();
+        assert_eq!(closed.records_written, 1);
+        assert_eq!(closed.bytes_written, open.bytes_written);
+        assert_eq!(closed.epoch_bytes, "s,n\na,0\n".len() as u64);
+        assert_eq!(read_epoch(dir.path(), 0), "s,n\na,0\n");
+        let manifest = manifest::read_manifest(dir.path(), "test").unwrap();
+        assert!(!manifest.get(0).unwrap().closed);
+
+        // Appended to the same epoch, counting the rows from before
+        logger.log_record(&TestRecord { s: "b", n: 1 });
+        logger.flush();
+        assert_eq!(read_epoch(dir.path(), 0), "s,n\na,0\nb,1\n");
+        let reopened = logger.stats().tables["test"].clone();
+        assert_eq!(reopened.epoch_records, 2);
+        assert_eq!(reopened.records_written, 2);
+        assert_eq!(reopened.bytes_written, "s,n\na,0\nb,1\n".len() as u64);
+        assert_eq!(reopened.epoch_bytes, reopened.bytes_written);
+        assert!(!log_file_path(dir.path(), "test", 1).exists());
+
+        // A headerless epoch stays headerless
+        logger.log_to("bare", &("b", 1));
+        logger.log_to("bare", &("c", 2));
+        logger.flush();
+        let bare = std::fs::read_to_string(log_file_path(dir.path(), "bare", 0)).unwrap();
+        assert_eq!(bare, "a,0\nb,1\nc,2\n");
+        assert!(!log_file_path(dir.path(), "bare", 1).exists());
+    }
+
+    #[test]
+    fn test_max_open_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .clock(clock.clone())
+        .max_open_tables(NonZeroUsize::new(2))
+        .build();
+        for (i, tenant) in ["a", "b", "a", "c", "b"].into_iter().enumerate() {
+            logger.log_to(&format!("tenant-{tenant}"), &TestRecord { s: tenant, n: i });
+            clock.advance(Duration::from_secs(1));
+            assert!(logger.tables.len() <= 2);
+        }
+        logger.flush();
+        // `b` was the least recently written when `c` came, and `a` when `b` came back
+        let mut open = logger
+            .tables
+            .keys()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        open.sort();
+        assert_eq!(open, ["tenant-b", "tenant-c"]);
+        let read = |tenant: &str| {
+            std::fs::read_to_string(log_file_path(dir.path(), &format!("tenant-{tenant}"), 0))
+                .unwrap()
+        };
+        assert_eq!(read("a"), "s,n\na,0\na,2\n");
+        assert_eq!(read("b"), "s,n\nb,1\nb,4\n");
+        assert_eq!(read("c"), "s,n\nc,3\n");
+    }
+
+    #[test]
+    fn test_max_open_tables_bounds_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .clock(clock.clone())
+        .max_open_tables(NonZeroUsize::new(2))
+        .error_handler(|_| ())
+        .build();
+        for n in 0..10 {
+            logger.log_to(&format!("tenant-{n}"), &TestRecord { s: "a", n });
+            // Dropped for its invalid name
+            logger.log_to(&format!("../tenant-{n}"), &TestRecord { s: "a", n });
+            clock.advance(Duration::from_secs(1));
+            assert!(logger.tables.len() <= 2);
+            assert!(logger.idle_tables.len() <= 2);
+            assert!(logger.dropped.len() <= 4);
+        }
+        assert!(!logger.idle_tables.contains_key("tenant-0"));
+        let manifest = manifest::read_manifest(dir.path(), "tenant-0").unwrap();
+        assert!(manifest.get(0).unwrap().closed);
+
+        // A forgotten table opens afresh
+        logger.log_to("tenant-0", &TestRecord { s: "b", n: 10 });
+        logger.flush();
+        let read =
+            |epoch| std::fs::read_to_string(log_file_path(dir.path(), "tenant-0", epoch)).unwrap();
+        assert_eq!(read(0), "s,n\na,0\n");
+        assert_eq!(read(1), "s,n\nb,10\n");
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let tenant = |namespace: &str| {
+            let logger = CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(10).unwrap(),
+                    max_epochs: 10,
+                },
+            )
+            .namespace(Some(namespace.to_string()))
+            .build();
+            CsvLoggerHandle::new(logger)
+        };
+        let (a, b) = (tenant("a"), tenant("b"));
+        a.log_to("test", &TestRecord { s: "a", n: 0 });
+        b.log_to("test", &TestRecord { s: "b", n: 1 });
+        b.log_to("test", &TestRecord { s: "b", n: 2 });
+        a.flush();
+        b.flush();
+
+        let mut entries = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(entries, ["a", "b"]);
+        assert_eq!(
+            std::fs::read_to_string(log_file_path(dir.path().join("a"), "test", 0)).unwrap(),
+            "s,n\na,0\n"
+        );
+        assert!(reader::list_tables(dir.path(), None, false)
+            .unwrap()
+            .is_empty());
+        let tables = reader::list_tables(dir.path(), Some("b"), false).unwrap();
+        assert_eq!(tables.len(), 1);
+        let rows = reader::TableReader::open(dir.path(), Some("b"), "test")
+            .unwrap()
+            .records()
+            .map(|record| record.unwrap()[0].to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(rows, ["b", "b"]);
+        assert_eq!(
+            reader::epochs(dir.path(), Some("a"), "test").unwrap().len(),
+            1
+        );
+        let error = reader::epochs(dir.path(), Some(".."), "test").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(namespace_dir(dir.path(), Some("a/b")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_private_dirs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = dir.path().join("logs");
+        let mut logger = CsvLoggerBuilder::new(
+            output_dir.clone(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(1).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .private_dirs(true)
+        .conflict_policy(ConflictPolicy::Wait)
+        .spool_dir(Some(dir.path().join("spool")))
+        .build();
+        logger.log_record(&TestRecord { s: "a", n: 0 });
+        logger.flush();
+        let row = Row {
+            table: Cow::Borrowed("test"),
+            header: vec!["s".to_string()].into(),
+            fields: vec!["b".to_string()],
+        };
+        logger.spool.as_mut().unwrap().push("test", &row).unwrap();
+
+        let mode = |path: PathBuf| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(output_dir.clone()), 0o700);
+        assert_eq!(mode(output_dir.join("test")), 0o700);
+        assert_eq!(mode(log_file_path(&output_dir, "test", 0)), 0o600);
+        assert_eq!(mode(log_file_path(&output_dir, "test", 1)), 0o600);
+        assert_eq!(
+            mode(epoch_file_path(
+                &TableFiles::of(&output_dir),
+                &output_dir,
+                "test"
+            )),
+            0o600
+        );
+        assert_eq!(mode(output_dir.join("test").join("manifest.json")), 0o600);
+        assert_eq!(mode(dir.path().join("spool")), 0o700);
+        assert_eq!(mode(dir.path().join("spool").join("test.csv")), 0o600);
+    }
+
+    #[test]
+    fn test_date_buckets() {
+        let dir = tempfile::tempdir().unwrap();
+        let day = 24 * 60 * 60;
+        // 2022-01-31T12:00:00Z
+        let clock =
+            ManualClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(19_023 * day + day / 2));
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(1).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .layout(Layout::DateBuckets)
+        .clock(clock.clone())
+        .timestamp(TimestampConfig::default())
+        .build();
+        let table_dir = dir.path().join("test");
+        logger.log_record(&TestRecord { s: "a", n: 0 });
+        logger.flush();
+        assert!(table_dir.join("2022/01/31/0.csv").exists());
+        assert!(table_dir.join("2022/01/31/1.csv").exists());
+        assert!(table_dir.join("epoch").exists());
+
+        clock.advance(Duration::from_secs(day));
+        logger.log_record(&TestRecord { s: "b", n: 1 });
+        logger.flush();
+        let csv = std::fs::read_to_string(table_dir.join("2022/01/31/1.csv")).unwrap();
+        assert_eq!(csv, "ts,s,n\n2022-02-01T12:00:00.000Z,b,1\n");
+        assert!(!table_dir.join("2022/01/31/0.csv").exists());
+        assert!(table_dir.join("2022/02/01/2.csv").exists());
+        assert_eq!(
+            log_file_path(dir.path(), "test", 2),
+            table_dir.join("2022/02/01/2.csv")
+        );
+
+        // Retention takes the emptied day and month along
+        logger.log_record(&TestRecord { s: "c", n: 2 });
+        logger.flush();
+        assert!(!table_dir.join("2022/01").exists());
+        assert!(table_dir.join("2022/02/01/3.csv").exists());
+        let epochs = reader::epoch_files(&TableFiles::of(dir.path()), dir.path(), "test").unwrap();
+        let epochs = epochs.iter().map(|f| f.epoch).collect::<Vec<_>>();
+        assert_eq!(epochs, [2, 3]);
+    }
+
+    #[test]
+    #[serial]
+    fn test_log_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let errors = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .error_handler({
+            let errors = errors.clone();
+            move |e| errors.lock().unwrap().push(e.to_string())
+        })
+        .init()
+        .unwrap();
+        for (tenant, rows) in [(0, 3), (1, 1)] {
+            let table = format!("tenant_{tenant}");
+            for n in 0..rows {
+                log_to(&table, &TestRecord { s: "a", n });
+            }
+        }
+        log_to("../escape", &TestRecord { s: "a", n: 0 });
+        table_log::flush();
+
+        let read = |table: &str, epoch| {
+            std::fs::read_to_string(log_file_path(dir.path(), table, epoch)).unwrap()
+        };
+        assert_eq!(read("tenant_0", 0), "s,n\na,0\na,1\n");
+        assert_eq!(read("tenant_0", 1), "s,n\na,2\n");
+        assert_eq!(read("tenant_1", 0), "s,n\na,0\n");
+        assert!(!log_file_path(dir.path(), "tenant_1", 1).exists());
+        assert_eq!(errors.lock().unwrap().len(), 1);
+        assert!(!dir.path().parent().unwrap().join("escape").exists());
+
+        remove_logger();
+    }
+
+    #[test]
+    fn test_rotate_error() {
+        let dir = tempfile::tempdir().unwrap();
+        // The next epoch cannot be created
+        std::fs::create_dir_all(log_file_path(dir.path(), "test", 1)).unwrap();
+        let errors = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(1).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .error_handler({
+            let errors = errors.clone();
+            move |e| errors.lock().unwrap().push(e.to_string())
+        })
+        .build();
+        logger.log_record(&TestRecord { s: "a", n: 0 });
+        logger.flush();
+
+        let path = log_file_path(dir.path(), "test", 0);
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "s,n\na,0\n");
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("rotate the log file"), "{}", errors[0]);
+    }
+
+    #[test]
+    #[serial]
+    fn test_log_durable() {
+        let dir = tempfile::tempdir().unwrap();
+        CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .auto_flush(false)
+        .sync_durable(true)
+        .init()
+        .unwrap();
+        let read = |epoch| std::fs::read_to_string(log_file_path(dir.path(), "test", epoch));
+
+        table_log::log!(&TestRecord { s: "a", n: 0 });
+        assert_eq!(read(0).unwrap(), "");
+        log_durable(&TestRecord { s: "b", n: 1 });
+        assert_eq!(read(0).unwrap(), "s,n\na,0\nb,1\n");
+        log_durable(&TestRecord { s: "c", n: 2 });
+        assert_eq!(read(1).unwrap(), "s,n\nc,2\n");
+
+        remove_logger();
+    }
+
+    #[test]
+    fn test_column_order() {
+        use table_log::Logger;
+
+        #[derive(serde::Serialize)]
+        struct Event {
+            pub kind: &'static str,
+            pub id: u64,
+            pub at: u64,
+        }
+        impl table_log::LogRecord<'_> for Event {
+            fn table_name(&self) -> &'static str {
+                "event"
+            }
+        }
+
+        let log = |order: &[&str]| {
+            let dir = tempfile::tempdir().unwrap();
+            let errors = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+            let mut logger = CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(10).unwrap(),
+                    max_epochs: 2,
+                },
+            )
+            .column_order("event", order.iter().map(|c| c.to_string()).collect())
+            .error_handler({
+                let errors = errors.clone();
+                move |e| errors.lock().unwrap().push(e.to_string())
+            })
+            .build();
+            for id in 0..2 {
+                logger.log(&Event {
+                    kind: "open",
+                    id,
+                    at: 100 + id,
+                });
+            }
+            logger.flush();
+            let csv = std::fs::read_to_string(log_file_path(dir.path(), "event", 0)).unwrap();
+            let errors = errors.lock().unwrap().len();
+            (csv, errors)
+        };
+
+        let (csv, errors) = log(&["at", "id"]);
+        assert_eq!(csv, "at,id,kind\n100,0,open\n101,1,open\n");
+        assert_eq!(errors, 0);
+
+        let (csv, errors) = log(&["at", "missing"]);
+        assert_eq!(csv, "at,kind,id\n100,open,0\n101,open,1\n");
+        assert_eq!(errors, 2);
+    }
+
+    #[test]
+    fn test_column_renames() {
+        use table_log::Logger;
+
+        #[derive(serde::Serialize)]
+        struct Login {
+            pub user_id: u64,
+            pub remote_addr: &'static str,
+        }
+        impl table_log::LogRecord<'_> for Login {
+            fn table_name(&self) -> &'static str {
+                "login"
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let renames = HashMap::from([("remote_addr".to_string(), "Remote Address".to_string())]);
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .column_renames("login", renames.clone())
+        .header_case(HeaderCase::Camel)
+        .sequence(true)
+        .build();
+        for user_id in 0..2 {
+            logger.log(&Login {
+                user_id,
+                remote_addr: "user_id",
+            });
+        }
+        logger.flush();
+        let csv = std::fs::read_to_string(log_file_path(dir.path(), "login", 0)).unwrap();
+        assert_eq!(csv, "seq,userId,Remote Address\n0,0,user_id\n1,1,user_id\n");
+
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct LoginRow {
+            user_id: u64,
+            remote_addr: String,
+        }
+        let reader = reader::TableReader::open(dir.path(), None, "login").unwrap();
+        assert!(reader.deserialize::<LoginRow>().all(|row| row.is_err()));
+        let rows = reader
+            .original_names(renames, HeaderCase::Camel)
+            .deserialize::<LoginRow>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            rows[1],
+            LoginRow {
+                user_id: 1,
+                remote_addr: "user_id".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_epoch_column() {
+        #[derive(serde::Serialize)]
+        struct Event {
+            pub n: u64,
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 3,
+            },
+        )
+        .include_epoch_column(true)
+        .dedup_consecutive("dup", true)
+        .table_cap("capped", 1)
+        .build();
+        for n in [0, 1, 2] {
+            logger.log_to("event", &Event { n });
+            logger.log_to("capped", &Event { n });
+        }
+        // Written once a different record comes, after the table rotated
+        for n in [0, 1, 1, 2, 3] {
+            logger.log_to("dup", &Event { n });
+        }
+        logger.flush();
+        let read = |table: &str, epoch: usize| {
+            std::fs::read_to_string(log_file_path(dir.path(), table, epoch)).unwrap()
+        };
+        assert_eq!(read("event", 0), "n,epoch\n0,0\n1,0\n");
+        assert_eq!(read("event", 1), "n,epoch\n2,1\n");
+        assert_eq!(read("dup", 0), "n,epoch,repeat_count\n0,0,1\n1,0,2\n");
+        assert_eq!(read("dup", 1), "n,epoch,repeat_count\n2,1,1\n3,1,1\n");
+        // The terminal row carries no epoch
+        assert_eq!(
+            read("capped", 0),
+            "n,epoch\n0,0\n[cap of 1 records reached],\n"
+        );
+
+        let mut merged = vec![];
+        export::merge_table(dir.path(), "event", &mut merged, true).unwrap();
+        assert_eq!(
+            String::from_utf8(merged).unwrap(),
+            "n,epoch\n0,0\n1,0\n2,1\n"
+        );
+
+        // A field of its own named `epoch` is not mistaken for the column
+        #[derive(serde::Serialize)]
+        struct Epoch {
+            pub epoch: u64,
+        }
+        logger.log_to("event", &Epoch { epoch: 7 });
+        assert_eq!(logger.stats().dropped.get("event"), Some(&1));
+        assert!(error::recent_errors()
+            .iter()
+            .any(|e| e.kind == "epoch_column_collision"));
+    }
+
+    #[test]
+    fn test_metadata_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let build = || {
+            CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(2).unwrap(),
+                    max_epochs: 10,
+                },
+            )
+            .metadata_header(true)
+            .app_info("auditor\napp", "1.2.3")
+            .resume_policy(ResumePolicy::AppendToLast)
+            .build()
+        };
+        let mut logger = build();
+        for n in 0..3 {
+            logger.log_record(&TestRecord { s: "#a", n });
+        }
+        logger.flush();
+        drop(logger);
+        // Resuming the second epoch does not repeat its block
+        let mut logger = build();
+        logger.log_record(&TestRecord { s: "#a", n: 3 });
+        logger.flush();
+
+        let epochs = reader::epochs(dir.path(), None, "test").unwrap();
+        assert_eq!(epochs.len(), 2);
+        for info in &epochs {
+            let metadata = info.metadata().unwrap();
+            let value = |key: &str| {
+                metadata
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.clone())
+            };
+            let file_epoch = info.path.file_stem().unwrap().to_str().unwrap();
+            assert_eq!(value("epoch").as_deref(), Some(file_epoch));
+            assert_eq!(value("app_name").as_deref(), Some("auditor\napp"));
+            assert_eq!(value("max_records").as_deref(), Some("2"));
+            assert_eq!(
+                value("csv_logger").as_deref(),
+                Some(env!("CARGO_PKG_VERSION"))
+            );
+        }
+        let csv = std::fs::read_to_string(&epochs[1].path).unwrap();
+        assert_eq!(csv.matches("# epoch: 1\n").count(), 1);
+        assert!(csv.ends_with("\ns,n\n#a,2\n#a,3\n"));
+
+        let reader = reader::TableReader::open(dir.path(), None, "test").unwrap();
+        let header = reader.header().unwrap().unwrap();
+        assert_eq!(header.iter().collect::<Vec<_>>(), ["s", "n"]);
+        let rows = reader.records().map(|r| r.unwrap()[1].to_string());
+        assert_eq!(rows.collect::<Vec<_>>(), ["0", "1", "2", "3"]);
+        assert!(verify::check_table(dir.path(), "test").unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_map_records() {
+        let log = |policy| {
+            let dir = tempfile::tempdir().unwrap();
+            let mut logger = CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(10).unwrap(),
+                    max_epochs: 10,
+                },
+            )
+            .schema_policy(policy)
+            .build();
+            let maps: [&[(&str, &str)]; 3] = [
+                &[("b", "1"), ("a", "2")],
+                &[("a", "3")],
+                &[("a", "4"), ("c", "5")],
+            ];
+            for map in maps {
+                let map = map
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect::<HashMap<_, _>>();
+                logger.log_to("kv", &map);
+            }
+            table_log::Logger::flush(&mut logger);
+            dir
+        };
+        let read =
+            |dir: &Path, epoch| std::fs::read_to_string(log_file_path(dir, "kv", epoch)).unwrap();
+
+        let dir = log(SchemaPolicy::RotateOnChange);
+        assert_eq!(read(dir.path(), 0), "a,b\n2,1\n3,\n");
+        assert_eq!(read(dir.path(), 1), "a,c\n4,5\n");
+
+        let dir = log(SchemaPolicy::Ignore);
+        assert_eq!(read(dir.path(), 0), "a,b\n2,1\n3,\n4,\n");
+        assert!(!log_file_path(dir.path(), "kv", 1).exists());
+    }
+
+    #[test]
+    fn test_redaction() {
+        use table_log::Logger;
+
+        #[derive(serde::Serialize)]
+        struct Login {
+            pub user: &'static str,
+            pub email: &'static str,
+            pub token: &'static str,
+        }
+        impl table_log::LogRecord<'_> for Login {
+            fn table_name(&self) -> &'static str {
+                "login"
+            }
+        }
+
+        struct Domain;
+        impl Redactor for Domain {
+            fn redact(&self, column: &str, value: &str) -> String {
+                match (column, value.split_once('@')) {
+                    ("email", Some((_, domain))) => format!("***@{domain}"),
+                    _ => "***".to_string(),
+                }
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .redact("login", vec!["email".to_string(), "token".to_string()])
+        .redactor(Domain)
+        .build();
+        logger.log(&Login {
+            user: "alice",
+            email: "alice@example.com",
+            token: "secret",
+        });
+        logger.flush();
+        assert_eq!(
+            std::fs::read_to_string(log_file_path(dir.path(), "login", 0)).unwrap(),
+            "user,email,token\nalice,***@example.com,***\n"
+        );
+    }
+
+    #[test]
+    fn test_max_field_bytes() {
+        use table_log::Logger;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .max_field_bytes(10)
+        .build();
+        // 'é' takes 2 bytes so the 10th byte is not a character boundary
+        let s = "aaaaaaaaaé".repeat(100);
+        logger.log(&TestRecord { s: &s, n: 0 });
+        logger.flush();
+
+        let csv = std::fs::read(log_file_path(dir.path(), "test", 0)).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let cut = s.len() - 9;
+        assert_eq!(csv, format!("s,n\naaaaaaaaa…[truncated {cut} bytes],0\n"));
+    }
+
+    #[test]
+    fn test_single_line_fields() {
+        use table_log::Logger;
+
+        let dir = tempfile::tempdir().unwrap();
+        let build = |single_line| {
+            CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(10).unwrap(),
+                    max_epochs: 3,
+                },
+            )
+            .single_line_fields(single_line)
+            .resume_policy(ResumePolicy::AppendToLast)
+            .build()
+        };
+        let mut logger = build(true);
+        let s = "panicked at\r\n  src\\main.rs:1\n";
+        logger.log(&TestRecord { s, n: 0 });
+        logger.flush();
+
+        let csv = std::fs::read_to_string(log_file_path(dir.path(), "test", 0)).unwrap();
+        assert_eq!(csv.lines().count(), 2);
+        assert_eq!(csv, "s,n\npanicked at\\r\\n  src\\\\main.rs:1\\n,0\n");
+        let records = reader::TableReader::open(dir.path(), None, "test")
+            .unwrap()
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(&records[0][0], s);
+        drop(logger);
+
+        // Turned off, the logger starts an epoch of its own and readers decode each epoch as it
+        // was written
+        let mut logger = build(false);
+        logger.log(&TestRecord { s, n: 1 });
+        logger.flush();
+        assert!(log_file_path(dir.path(), "test", 1).exists());
+        let records = reader::TableReader::open(dir.path(), None, "test")
+            .unwrap()
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let fields = records.iter().map(|r| &r[0]).collect::<Vec<_>>();
+        assert_eq!(fields, [s, s]);
+        let last = reader::last_n(dir.path(), None, "test", 2).unwrap();
+        assert_eq!(&last[0][0], s);
+        assert_eq!(&last[1][0], s);
+    }
+
+    #[test]
+    fn test_sampling() {
+        use table_log::Logger;
+
+        #[derive(serde::Serialize)]
+        struct OtherRecord {
+            pub x: usize,
+        }
+        impl table_log::LogRecord<'_> for OtherRecord {
+            fn table_name(&self) -> &'static str {
+                "other"
+            }
+        }
+
+        let log = |seed| {
+            let dir = tempfile::tempdir().unwrap();
+            let mut logger = CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(100_000).unwrap(),
+                    max_epochs: 2,
+                },
+            )
+            .table_sampling("test", 0.5)
+            .sample_seed(seed)
+            .build();
+            for n in 0..10_000 {
+                logger.log(&TestRecord { s: "a", n });
+                logger.log(&OtherRecord { x: n });
+            }
+            logger.flush();
+            let read =
+                |table| std::fs::read_to_string(log_file_path(dir.path(), table, 0)).unwrap();
+            (read("test"), read("other"))
+        };
+
+        let (sampled, other) = log(1);
+        let rows = sampled.lines().count() - 1;
+        assert!((4_500..5_500).contains(&rows), "{rows}");
+        assert_eq!(other.lines().count() - 1, 10_000);
+        assert_eq!(log(1).0, sampled);
+        assert_ne!(log(2).0, sampled);
+    }
+
+    #[test]
+    fn test_denied_tables() {
+        use table_log::Logger;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .denied_tables(["debug_*"])
+        .build();
+        logger.log(&TestRecord { s: "a", n: 0 });
+        logger.log_to("debug_cache", &TestRecord { s: "b", n: 1 });
+        logger.flush();
+        assert!(dir.path().join("test").exists());
+        assert!(!dir.path().join("debug_cache").exists());
+    }
+
+    #[test]
+    fn test_allowed_tables() {
+        use table_log::Logger;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .allowed_tables(["tenant_*"])
+        .denied_tables(["tenant_2"])
+        .build();
+        for table in ["tenant_1", "tenant_2", "other"] {
+            logger.log_to(table, &TestRecord { s: "a", n: 0 });
+        }
+        logger.flush();
+        assert!(dir.path().join("tenant_1").exists());
+        assert!(!dir.path().join("tenant_2").exists());
+        assert!(!dir.path().join("other").exists());
+    }
 
-const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+    #[test]
+    fn test_rate_limit() {
+        use table_log::Logger;
 
-pub fn init(output_dir: PathBuf, rotation: RotationPolicy) {
-    let logger = CsvLogger::new(output_dir, rotation);
-    let mut log = table_log::GLOBAL_LOG.lock().unwrap();
-    if log.has_logger() {
-        panic!("Only one logger can be registered at a time");
-    }
-    log.register(Box::new(logger));
-    drop(log);
-    std::thread::Builder::new()
-        .name("CsvLogger::flush()".to_string())
-        .spawn(|| loop {
-            std::thread::sleep(FLUSH_INTERVAL);
-            let mut log = table_log::GLOBAL_LOG.lock().unwrap();
-            log.flush();
+        let dir = tempfile::tempdir().unwrap();
+        let errors = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(100).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .table_rate_limit("test", 2.0)
+        .error_handler({
+            let errors = errors.clone();
+            move |e| errors.lock().unwrap().push(e.to_string())
         })
-        .expect("Failed to spawn the flushing worker thread");
-}
+        .build();
+        for n in 0..10 {
+            logger.log(&TestRecord { s: "a", n });
+        }
+        logger.flush();
 
-pub struct CsvLogger {
-    output_dir: PathBuf,
-    tables: HashMap<&'static str, Table>,
-    rotation: RotationPolicy,
-}
-impl CsvLogger {
-    pub fn new(output_dir: PathBuf, rotation: RotationPolicy) -> Self {
-        Self {
-            output_dir,
-            tables: HashMap::new(),
-            rotation,
+        let csv = std::fs::read_to_string(log_file_path(dir.path(), "test", 0)).unwrap();
+        assert_eq!(csv, "s,n\na,0\na,1\n");
+        assert_eq!(
+            *errors.lock().unwrap(),
+            ["8 records of table `test` suppressed by the rate limit"]
+        );
+    }
+
+    #[test]
+    fn test_dedup_consecutive() {
+        use table_log::Logger;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(100).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .dedup_consecutive("test", true)
+        .build();
+        for s in ["a", "a", "a", "b"] {
+            logger.log(&TestRecord { s, n: 0 });
         }
+        let path = log_file_path(dir.path(), "test", 0);
+        logger.flush();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "s,n,repeat_count\na,0,3\nb,0,1\n"
+        );
+
+        logger.log(&TestRecord { s: "b", n: 0 });
+        logger.log(&TestRecord { s: "b", n: 1 });
+        logger.flush();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "s,n,repeat_count\na,0,3\nb,0,1\nb,0,1\nb,1,1\n"
+        );
+
+        // Written out when the logger is dropped
+        logger.log(&TestRecord { s: "c", n: 0 });
+        logger.log(&TestRecord { s: "c", n: 0 });
+        drop(logger);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "s,n,repeat_count\na,0,3\nb,0,1\nb,0,1\nb,1,1\nc,0,2\n"
+        );
     }
-}
-impl table_log::Logger for CsvLogger {
-    fn log(&mut self, record: &dyn table_log::LogRecord) {
-        let entry = self.tables.entry(record.table_name());
-        let (table, new) = match entry {
-            std::collections::hash_map::Entry::Occupied(entry) => (entry.into_mut(), false),
-            std::collections::hash_map::Entry::Vacant(entry) => {
-                let epoch = cur_epoch(&self.output_dir, record.table_name())
-                    .map(|e| e + 1)
-                    .unwrap_or_default();
-                let path = log_file_path(&self.output_dir, record.table_name(), epoch);
-                let writer = create_clean_log_writer(path);
-                let table = entry.insert(Table::new(writer, epoch));
-                (table, true)
-            }
-        };
-        if new {
-            let epoch = table.epoch();
-            write_epoch(&self.output_dir, record.table_name(), epoch);
-            delete_old_log_file(
-                epoch,
-                self.rotation.max_epochs,
-                &self.output_dir,
-                record.table_name(),
-            );
+
+    #[test]
+    fn test_filter() {
+        use table_log::Logger;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(100).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .filter(
+            Some("test"),
+            Box::new(|row| {
+                let i = row.header.iter().position(|c| c == "n").unwrap();
+                row.fields[i].parse::<usize>().unwrap() < 2
+            }),
+        )
+        .filter(None, Box::new(|row| row.fields[0] != "b"))
+        .build();
+        for n in 0..4 {
+            logger.log(&TestRecord { s: "a", n });
+            logger.log(&TestRecord { s: "b", n });
         }
-        table.serialize(record).expect("Failed to serialize");
+        logger.flush();
+        let path = log_file_path(dir.path(), "test", 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "s,n\na,0\na,1\n");
 
-        // Rotate log file
-        if self.rotation.max_records.get() <= table.records_written() {
-            let new_path = log_file_path(&self.output_dir, record.table_name(), table.epoch() + 1);
-            let new_writer = create_clean_log_writer(new_path);
-            table.replace(new_writer);
+        logger.filters.set(Some("test"), None);
+        logger.log(&TestRecord { s: "a", n: 2 });
+        logger.log(&TestRecord { s: "b", n: 2 });
+        logger.flush();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "s,n\na,0\na,1\na,2\n"
+        );
+    }
 
-            let epoch = table.epoch();
-            write_epoch(&self.output_dir, record.table_name(), epoch);
-            delete_old_log_file(
-                epoch,
-                self.rotation.max_epochs,
-                &self.output_dir,
-                record.table_name(),
-            );
+    #[test]
+    fn test_table_cap() {
+        use table_log::Logger;
+
+        let dir = tempfile::tempdir().unwrap();
+        let build = || {
+            CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(2).unwrap(),
+                    max_epochs: 10,
+                },
+            )
+            .table_cap("test", 3)
+            .build()
+        };
+        let mut logger = build();
+        for n in 0..3 {
+            logger.log(&TestRecord { s: "a", n });
+        }
+        // The count is written on flush and rotation, not for every row
+        let count = std::fs::read_to_string(dir.path().join("test").join("count")).unwrap();
+        assert_eq!(count, "2");
+        for n in 3..5 {
+            logger.log(&TestRecord { s: "a", n });
         }
+        logger.flush();
+        // The records past the cap are dropped, the terminal row is a row of its own
+        assert_eq!(logger.stats().dropped.get("test"), Some(&2));
+        drop(logger);
+        let read = |epoch| std::fs::read_to_string(log_file_path(dir.path(), "test", epoch));
+        assert_eq!(read(0).unwrap(), "s,n\na,0\na,1\n");
+        assert_eq!(read(1).unwrap(), "s,n\na,2\n[cap of 3 records reached],\n");
+        assert_eq!(read(2).unwrap(), "");
+
+        let mut logger = build();
+        logger.log(&TestRecord { s: "b", n: 5 });
+        logger.flush();
+        // Nothing opened after the restart
+        assert!(read(3).is_err());
+
+        logger.reset_cap("test");
+        logger.log(&TestRecord { s: "c", n: 6 });
+        logger.flush();
+        assert_eq!(read(3).unwrap(), "s,n\nc,6\n");
     }
 
-    fn flush(&mut self) {
-        self.tables.iter_mut().for_each(|(_, t)| {
-            t.flush().expect("Failed to flush");
-        });
+    #[test]
+    fn test_filter_chain() {
+        use table_log::Logger;
+
+        let log = |chain: FilterChain| {
+            let dir = tempfile::tempdir().unwrap();
+            let mut logger = CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(100).unwrap(),
+                    max_epochs: 2,
+                },
+            )
+            .filter_chain(chain)
+            .error_handler(|_| ())
+            .build();
+            for n in 0..10 {
+                logger.log(&TestRecord { s: "a", n });
+            }
+            logger.flush();
+            let csv = std::fs::read_to_string(log_file_path(dir.path(), "test", 0));
+            (csv.ok(), logger.filter_drops().clone())
+        };
+        let sample = || SampleFilter::new([("test".to_string(), 0.5)], Some(1));
+        let rate_limit = || RateLimitFilter::new([("test".to_string(), 2.0)]);
+
+        // The sampler keeps rows 3, 4 and 8 of which the rate limiter lets the first two through
+        let (csv, drops) = log(FilterChain::new().push(sample()).push(rate_limit()));
+        assert_eq!(csv.unwrap(), "s,n\na,3\na,4\n");
+        assert_eq!(drops, HashMap::from([("sampled", 7), ("rate_limited", 1)]));
+
+        // The rate limiter lets rows 0 and 1 through of which the sampler keeps none
+        let (csv, drops) = log(FilterChain::new().push(rate_limit()).push(sample()));
+        assert!(csv.is_none());
+        assert_eq!(drops, HashMap::from([("rate_limited", 8), ("sampled", 2)]));
     }
-}
-pub struct RotationPolicy {
-    pub max_records: NonZeroUsize,
-    pub max_epochs: usize,
-}
 
-fn delete_old_log_file(
-    epoch: usize,
-    max_epochs: usize,
-    output_dir: impl AsRef<Path>,
-    table_name: &str,
-) {
-    let del_epoch = epoch.checked_sub(max_epochs);
-    if let Some(del_epoch) = del_epoch {
-        let del_path = log_file_path(output_dir, table_name, del_epoch);
-        if del_path.exists() {
-            std::fs::remove_file(del_path).expect("Failed to remove outdated log file");
+    #[test]
+    fn test_filter_chain_after_builder_filters() {
+        use table_log::Logger;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(100).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .table_sampling("test", 0.5)
+        .sample_seed(1)
+        .filter_chain(FilterChain::new().push(RateLimitFilter::new([("test".to_string(), 2.0)])))
+        .error_handler(|_| ())
+        .build();
+        for n in 0..10 {
+            logger.log(&TestRecord { s: "a", n });
         }
+        logger.flush();
+        // Sampled by the builder's filter before the chain's rate limiter sees the rows
+        let csv = std::fs::read_to_string(log_file_path(dir.path(), "test", 0)).unwrap();
+        assert_eq!(csv, "s,n\na,3\na,4\n");
+        assert_eq!(
+            logger.filter_drops(),
+            &HashMap::from([("sampled", 7), ("rate_limited", 1)])
+        );
     }
-}
-
-fn create_clean_log_writer(path: impl AsRef<Path>) -> csv::Writer<std::fs::File> {
-    std::fs::create_dir_all(path.as_ref().parent().unwrap()).expect("Failed to create directories");
-    let file = std::fs::File::options()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(path)
-        .expect("Cannot create a log file");
-    csv::Writer::from_writer(file)
-}
 
-fn write_epoch(output_dir: impl AsRef<Path>, table_name: &str, epoch: usize) {
-    let path = epoch_file_path(output_dir, table_name);
-    std::fs::create_dir_all(path.parent().unwrap()).expect("Failed to create directories");
-    let mut file = std::fs::File::options()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(path)
-        .expect("Failed to create an epoch file");
-    file.write_all(epoch.to_string().as_bytes())
-        .expect("Failed to write epoch to the file");
-}
+    #[test]
+    fn test_standalone_instances() {
+        let dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let handles = dirs.each_ref().map(|dir| {
+            CsvLoggerHandle::new(CsvLogger::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(1000).unwrap(),
+                    max_epochs: 2,
+                },
+            ))
+        });
+        let flushers = handles
+            .iter()
+            .map(|handle| spawn_flusher(handle.clone(), Duration::from_millis(10)).unwrap())
+            .collect::<Vec<_>>();
+        std::thread::scope(|s| {
+            for (i, handle) in handles.iter().enumerate() {
+                for t in 0..2 {
+                    let handle = handle.clone();
+                    s.spawn(move || {
+                        let s = ["a", "b"][i];
+                        for n in 0..100 {
+                            handle.log(&TestRecord { s, n: t * 100 + n });
+                        }
+                    });
+                }
+            }
+        });
+        // Flushes one last time
+        drop(flushers);
 
-fn cur_epoch(output_dir: impl AsRef<Path>, table_name: &str) -> Option<usize> {
-    let path = epoch_file_path(output_dir, table_name);
-    if !path.exists() {
-        return None;
-    }
-    let mut file = std::fs::File::options()
-        .read(true)
-        .open(&path)
-        .expect("Failed to open the epoch file");
-    let mut epoch = String::new();
-    file.read_to_string(&mut epoch)
-        .expect("Failed to read the epoch file");
-    let epoch: usize = match epoch.parse() {
-        Ok(epoch) => epoch,
-        Err(_) => {
-            std::fs::remove_file(&path).expect("Failed to delete old epoch file");
-            return None;
+        for (dir, s) in dirs.iter().zip(["a", "b"]) {
+            let csv = std::fs::read_to_string(log_file_path(dir.path(), "test", 0)).unwrap();
+            let mut lines = csv.lines();
+            assert_eq!(lines.next(), Some("s,n"));
+            let mut ns = lines
+                .map(|line| {
+                    let (row_s, n) = line.split_once(',').unwrap();
+                    assert_eq!(row_s, s);
+                    n.parse::<usize>().unwrap()
+                })
+                .collect::<Vec<_>>();
+            ns.sort_unstable();
+            assert_eq!(ns, (0..200).collect::<Vec<_>>());
         }
-    };
-    Some(epoch)
-}
+    }
 
-fn epoch_file_path(output_dir: impl AsRef<Path>, table_name: &str) -> PathBuf {
-    output_dir.as_ref().join(table_name).join("epoch")
-}
+    #[test]
+    fn test_conflict_policy() {
+        use table_log::Logger;
 
-fn log_file_path(output_dir: impl AsRef<Path>, table_name: &str, epoch: usize) -> PathBuf {
-    let mut path = output_dir.as_ref().join(table_name).join(epoch.to_string());
-    path.set_extension("csv");
-    path
-}
+        let dir = tempfile::tempdir().unwrap();
+        let errors = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let build = |policy| {
+            let errors = errors.clone();
+            CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(100).unwrap(),
+                    max_epochs: 10,
+                },
+            )
+            .conflict_policy(policy)
+            .error_handler(move |e| errors.lock().unwrap().push(e.to_string()))
+            .build()
+        };
+        let mut first = build(ConflictPolicy::Error);
+        first.log(&TestRecord { s: "a", n: 0 });
 
-#[cfg(test)]
-mod tests {
-    use std::io::Read;
+        // Stands in for a second process on the same directory
+        let mut second = build(ConflictPolicy::Error);
+        second.log(&TestRecord { s: "b", n: 0 });
+        second.flush();
+        assert!(!log_file_path(dir.path(), "test", 1).exists());
+        assert_eq!(
+            *errors.lock().unwrap(),
+            ["Dropped a row of table `test`: locked by another logger"]
+        );
 
-    use serial_test::serial;
+        let mut third = build(ConflictPolicy::PidSubdir);
+        third.log(&TestRecord { s: "c", n: 0 });
+        third.flush();
+        let pid_dir = dir.path().join(format!("pid-{}", std::process::id()));
+        assert_eq!(
+            std::fs::read_to_string(log_file_path(&pid_dir, "test", 0)).unwrap(),
+            "s,n\nc,0\n"
+        );
 
-    use super::*;
+        // Released on drop
+        first.flush();
+        drop(first);
+        second.log(&TestRecord { s: "b", n: 1 });
+        second.flush();
+        assert_eq!(
+            std::fs::read_to_string(log_file_path(dir.path(), "test", 1)).unwrap(),
+            "s,n\nb,1\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(log_file_path(dir.path(), "test", 0)).unwrap(),
+            "s,n\na,0\n"
+        );
+    }
 
-    #[derive(serde::Serialize)]
-    struct TestRecord<'caller> {
-        pub s: &'caller str,
-        pub n: usize,
+    #[test]
+    fn test_pid_subdir_cap() {
+        use table_log::Logger;
+
+        let dir = tempfile::tempdir().unwrap();
+        let build = || {
+            CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(100).unwrap(),
+                    max_epochs: 10,
+                },
+            )
+            .conflict_policy(ConflictPolicy::PidSubdir)
+            .table_cap("test", 1)
+            .build()
+        };
+        let mut first = build();
+        first.log(&TestRecord { s: "a", n: 0 });
+        first.flush();
+
+        // Counted on its own under `pid-<pid>`
+        let mut second = build();
+        second.log(&TestRecord { s: "b", n: 0 });
+        second.flush();
+        let pid_dir = dir.path().join(format!("pid-{}", std::process::id()));
+        assert_eq!(
+            std::fs::read_to_string(log_file_path(&pid_dir, "test", 0)).unwrap(),
+            "s,n\nb,0\n"
+        );
+        let count = |dir: &Path| std::fs::read_to_string(dir.join("test").join("count")).unwrap();
+        assert_eq!(count(dir.path()), "1");
+        assert_eq!(count(&pid_dir), "1");
     }
-    impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
-        fn table_name(&self) -> &'static str {
-            "test"
+
+    #[test]
+    fn test_thread_info() {
+        let dir = tempfile::tempdir().unwrap();
+        let handle = CsvLoggerHandle::new(
+            CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(100).unwrap(),
+                    max_epochs: 2,
+                },
+            )
+            .with_thread_info()
+            .build(),
+        );
+        for name in ["first", "second"] {
+            let handle = handle.clone();
+            std::thread::Builder::new()
+                .name(name.to_string())
+                .spawn(move || handle.log(&TestRecord { s: "a", n: 0 }))
+                .unwrap()
+                .join()
+                .unwrap();
         }
+        handle.flush();
+
+        let csv = std::fs::read_to_string(log_file_path(dir.path(), "test", 0)).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("s,n,thread_name,thread_id"));
+        let rows = lines
+            .map(|line| line.split(',').map(String::from).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][2], "first");
+        assert_eq!(rows[1][2], "second");
+        assert!(rows.iter().all(|row| row[3].parse::<u64>().is_ok()));
+        assert_ne!(rows[0][3], rows[1][3]);
     }
 
-    fn remove_logger() {
-        let mut log = table_log::GLOBAL_LOG.lock().unwrap();
-        log.remove_logger();
+    #[test]
+    #[serial]
+    fn test_flusher_thread_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let flusher = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(100).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .flusher_thread_name("csv-flusher")
+        .init()
+        .unwrap()
+        .unwrap();
+        assert_eq!(flusher.thread().name(), Some("csv-flusher"));
+        assert_eq!(flusher.join_handle().thread().id(), flusher.thread().id());
+        assert!(!flusher.join_handle().is_finished());
+
+        flusher.shutdown();
+        remove_logger();
     }
 
     #[test]
     #[serial]
-    fn test_logger() {
+    fn test_debug_dump() {
         let dir = tempfile::tempdir().unwrap();
-        init(
+        let flusher = CsvLoggerBuilder::new(
             dir.path().to_owned(),
             RotationPolicy {
                 max_records: NonZeroUsize::new(2).unwrap(),
-                max_epochs: 2,
+                max_epochs: 10,
             },
-        );
+        )
+        .flush_interval(Duration::from_millis(10))
+        .error_handler(|_| ())
+        .init()
+        .unwrap()
+        .unwrap();
+
         table_log::log!(&TestRecord { s: "a", n: 0 });
         table_log::log!(&TestRecord { s: "b", n: 1 });
-        table_log::flush();
-        let path = log_file_path(dir.path(), "test", 0);
-        assert!(path.exists());
-        let mut file = std::fs::File::options().read(true).open(path).unwrap();
-        let mut csv = String::new();
-        file.read_to_string(&mut csv).unwrap();
-        assert_eq!(
-            csv,
-            r#"s,n
-a,0
-b,1
-"#
-        );
+        table_log::log!(&TestRecord { s: "c", n: 2 });
+        log_to("..", &TestRecord { s: "d", n: 3 });
+        std::thread::sleep(Duration::from_millis(50));
+
+        let dump = debug_dump();
+        let output_dir = format!("output dir: {}\n", dir.path().display());
+        assert!(dump.contains(&output_dir), "{dump}");
+        assert!(dump.contains("rotation: 2 records per epoch, 10 epochs kept\n"));
+        assert!(dump.contains("  test: epoch 1, 1 records, "), "{dump}");
+        assert!(dump.contains("  ..: 1 records dropped\n"), "{dump}");
+        assert!(dump.contains("flusher: running, last ran 20"), "{dump}");
+        assert!(dump.contains("] [invalid_table_name] "), "{dump}");
 
+        flusher.shutdown();
+        assert!(debug_dump().contains("flusher: stopped, last ran 20"));
         remove_logger();
+        assert!(debug_dump().contains("file logger: none registered\n"));
     }
 
     #[test]
     #[serial]
-    fn test_rotation() {
+    fn test_flush_watchdog() {
         let dir = tempfile::tempdir().unwrap();
-        init(
+        let errors = Arc::new(std::sync::Mutex::new(vec![]));
+        let flusher = CsvLoggerBuilder::new(
             dir.path().to_owned(),
             RotationPolicy {
-                max_records: NonZeroUsize::new(2).unwrap(),
+                max_records: NonZeroUsize::new(1000).unwrap(),
                 max_epochs: 2,
             },
-        );
+        )
+        .flush_interval(Duration::from_millis(50))
+        .flush_watchdog(Some(WatchdogPolicy {
+            missed_intervals: 2,
+            respawn: false,
+        }))
+        .error_handler({
+            let errors = errors.clone();
+            move |e| errors.lock().unwrap().push(e.kind())
+        })
+        .init()
+        .unwrap()
+        .unwrap();
+        let log = |records| {
+            for n in 0..records {
+                table_log::log!(&TestRecord { s: "a", n });
+            }
+        };
+
+        std::thread::sleep(Duration::from_millis(80));
+        log(64);
+        assert!(errors.lock().unwrap().is_empty());
+
+        flusher.shutdown();
+        std::thread::sleep(Duration::from_millis(150));
+        log(63);
+        assert!(errors.lock().unwrap().is_empty());
+        log(1);
+        assert_eq!(*errors.lock().unwrap(), ["flush_stalled"]);
+        log(64 * 3);
+        assert_eq!(*errors.lock().unwrap(), ["flush_stalled"]);
+
+        remove_logger();
+    }
+
+    #[test]
+    #[serial]
+    fn test_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .auto_flush(false)
+        .init()
+        .unwrap();
+        assert!(stats().tables.is_empty());
 
         table_log::log!(&TestRecord { s: "a", n: 0 });
-        table_log::flush();
-        let path = log_file_path(dir.path(), "test", 0);
-        assert!(path.exists());
-        let path = log_file_path(dir.path(), "test", 1);
-        assert!(!path.exists());
+        let table = &stats().tables["test"];
+        assert_eq!(table.records_written, 1);
+        assert_eq!(table.epoch_records, 1);
+        assert_eq!((table.epoch, table.rotations), (0, 0));
+        assert_eq!(table.last_flush, None);
 
         table_log::log!(&TestRecord { s: "b", n: 1 });
-        let path = log_file_path(dir.path(), "test", 0);
-        assert!(path.exists());
-        let path = log_file_path(dir.path(), "test", 1);
-        assert!(path.exists());
-        let path = log_file_path(dir.path(), "test", 2);
-        assert!(!path.exists());
-
         table_log::log!(&TestRecord { s: "c", n: 2 });
+        let before_flush = SystemTime::now();
         table_log::flush();
-        let path = log_file_path(dir.path(), "test", 0);
-        assert!(path.exists());
-        let path = log_file_path(dir.path(), "test", 1);
-        assert!(path.exists());
-        let path = log_file_path(dir.path(), "test", 2);
-        assert!(!path.exists());
+        let table = &stats().tables["test"];
+        assert_eq!(table.records_written, 3);
+        assert_eq!(table.epoch_records, 1);
+        assert_eq!((table.epoch, table.rotations), (1, 1));
+        assert!(table.last_flush.unwrap() >= before_flush);
+        let size = |epoch| {
+            let path = log_file_path(dir.path(), "test", epoch);
+            std::fs::metadata(path).unwrap().len()
+        };
+        assert_eq!(table.epoch_bytes, size(1));
+        assert_eq!(table.bytes_written, size(0) + size(1));
 
-        table_log::log!(&TestRecord { s: "d", n: 3 });
-        let path = log_file_path(dir.path(), "test", 0);
-        assert!(!path.exists());
-        let path = log_file_path(dir.path(), "test", 1);
-        assert!(path.exists());
-        let path = log_file_path(dir.path(), "test", 2);
-        assert!(path.exists());
-        let path = log_file_path(dir.path(), "test", 3);
-        assert!(!path.exists());
+        remove_logger();
+    }
+
+    #[test]
+    #[serial]
+    fn test_health_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let flusher = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(100).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .flush_interval(Duration::from_millis(10))
+        .health_interval(Some(Duration::from_millis(10)))
+        .init()
+        .unwrap()
+        .unwrap();
+        table_log::log!(&TestRecord { s: "a", n: 0 });
+        table_log::log!(&TestRecord { s: "b", n: 1 });
+
+        let path = log_file_path(dir.path(), HEALTH_TABLE, 0);
+        let read_rows = || {
+            let csv = std::fs::read_to_string(&path).unwrap_or_default();
+            let mut lines = csv.lines().map(String::from).collect::<Vec<_>>();
+            if !lines.is_empty() {
+                assert_eq!(
+                    lines.remove(0),
+                    "timestamp,tables_open,records,bytes,dropped,last_error"
+                );
+            }
+            lines
+        };
+        wait_until(|| 2 <= read_rows().len());
+        flusher.shutdown();
+        remove_logger();
+
+        let rows = read_rows();
+        let counts = rows
+            .iter()
+            .map(|row| {
+                let fields = row.split(',').collect::<Vec<_>>();
+                assert_eq!(fields.len(), 6);
+                assert!(timestamp::parse(fields[0]).is_some());
+                fields[1..5]
+                    .iter()
+                    .map(|n| n.parse::<u64>().unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        // Tables open, records and bytes
+        assert!(counts.iter().any(|c| 1 <= c[0] && 2 <= c[1] && 0 < c[2]));
+    }
+
+    #[test]
+    #[serial]
+    fn test_on_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let reports = Arc::new(std::sync::Mutex::new(vec![]));
+        CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(100).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .auto_flush(false)
+        .on_flush(Box::new({
+            let reports = reports.clone();
+            move |report| reports.lock().unwrap().push(report)
+        }))
+        .init()
+        .unwrap();
+
+        table_log::log!(&TestRecord { s: "a", n: 0 });
+        table_log::log!(&TestRecord { s: "b", n: 1 });
+        table_log::flush();
+        table_log::flush();
 
-        // println!("{:?}", dir.path());
-        // std::thread::sleep(std::time::Duration::from_secs(u64::MAX));
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 2);
+        let expected = vec![TableFlush {
+            table: "test".to_string(),
+            records: 2,
+            bytes: "s,n\na,0\nb,1\n".len() as u64,
+        }];
+        assert_eq!(reports[0].tables, expected);
+        assert!(reports[1].tables.is_empty());
+        drop(reports);
 
         remove_logger();
     }
+
+    #[test]
+    fn test_on_flush_panic() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(100).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .on_flush(Box::new(|_| panic!("callback failed")))
+        .build();
+        logger.log_record(&TestRecord { s: "a", n: 0 });
+        logger.flush();
+        logger.log_record(&TestRecord { s: "b", n: 1 });
+        logger.flush();
+
+        let csv = std::fs::read_to_string(log_file_path(dir.path(), "test", 0)).unwrap();
+        assert_eq!(csv, "s,n\na,0\nb,1\n");
+    }
 }
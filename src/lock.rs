@@ -0,0 +1,87 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    storage::{Backend, LockFile},
+    table_dir::TableFiles,
+};
+
+/// What to do when another process holds the lock of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Block until the lock is released
+    Wait,
+    /// Drop the records of the table and report [`crate::CsvLoggerError::TableLocked`]
+    Error,
+    /// Log the table under the `pid-<pid>` subdirectory of the output directory instead
+    PidSubdir,
+}
+
+/// The tables open in the loggers of this process, by canonical output directory
+static OPEN: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+
+/// `dir` with relative components and links resolved, or as it is if it does not exist
+pub(crate) fn canonical(dir: &Path) -> PathBuf {
+    std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
+}
+
+/// A logger's hold on one of its tables: the table's lock, if taken, and its entry among the
+/// tables open in this process
+#[derive(Debug)]
+pub(crate) struct TableLock {
+    _file: Option<LockFile>,
+    key: (PathBuf, String),
+}
+impl TableLock {
+    pub fn new(output_dir: &Path, table_name: &str, file: Option<LockFile>) -> Self {
+        let key = (canonical(output_dir), table_name.to_string());
+        OPEN.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(key.clone());
+        Self { _file: file, key }
+    }
+}
+impl Drop for TableLock {
+    fn drop(&mut self) {
+        let mut open = OPEN.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(i) = open.iter().position(|key| *key == self.key) {
+            open.swap_remove(i);
+        }
+    }
+}
+
+/// Whether a logger of this process has the table open
+pub(crate) fn is_open(output_dir: &Path, table_name: &str) -> bool {
+    let key = (canonical(output_dir), table_name.to_string());
+    OPEN.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .contains(&key)
+}
+
+/// Forgets the tables open under `output_dir`, e.g. those a child process inherited
+pub(crate) fn forget_open(output_dir: &Path) {
+    let output_dir = canonical(output_dir);
+    OPEN.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .retain(|(dir, _)| *dir != output_dir);
+}
+
+fn lock_file_path(files: &TableFiles, output_dir: impl AsRef<Path>, table_name: &str) -> PathBuf {
+    files.table_file(output_dir, table_name, ".lock")
+}
+
+/// Takes the advisory lock of a table, released once the file is dropped
+///
+/// Returns `None` if another holder has it, unless `wait`.
+pub(crate) fn lock(
+    storage: &Backend,
+    files: &TableFiles,
+    output_dir: &Path,
+    table_name: &str,
+    wait: bool,
+) -> io::Result<Option<LockFile>> {
+    storage.lock(&lock_file_path(files, output_dir, table_name), wait)
+}
@@ -0,0 +1,86 @@
+//! Paths past the Windows `MAX_PATH` limit, which only verbatim `\\?\` paths escape
+
+use std::path::PathBuf;
+
+const MAX_PATH: usize = 260;
+/// Left for the names of the files in a table directory, e.g. `manifest.json.tmp`
+const FILE_NAME_ROOM: usize = 32;
+
+/// Makes a table directory verbatim on Windows once files in it could exceed `MAX_PATH`
+///
+/// Unchanged on other platforms, or if the path cannot be made absolute.
+pub(crate) fn long_dir(dir: PathBuf) -> PathBuf {
+    #[cfg(windows)]
+    if too_long(dir.as_os_str().len()) {
+        let verbatim = std::path::absolute(&dir)
+            .ok()
+            .and_then(|dir| verbatim(dir.to_str()?));
+        if let Some(verbatim) = verbatim {
+            return PathBuf::from(verbatim);
+        }
+    }
+    dir
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+fn too_long(dir_len: usize) -> bool {
+    MAX_PATH <= dir_len + FILE_NAME_ROOM
+}
+
+/// The `\\?\` form of an absolute Windows path
+///
+/// Verbatim paths skip normalization, so separators are made backslashes; `path` must not have
+/// `.` or `..` components, as [`std::path::absolute`] ensures on Windows.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn verbatim(path: &str) -> Option<String> {
+    if path.starts_with(r"\\?\") {
+        return Some(path.to_string());
+    }
+    let path = path.replace('/', r"\");
+    if let Some(unc) = path.strip_prefix(r"\\") {
+        return Some(format!(r"\\?\UNC\{unc}"));
+    }
+    let drive = path.as_bytes();
+    let absolute =
+        3 <= drive.len() && drive[0].is_ascii_alphabetic() && drive[1] == b':' && drive[2] == b'\\';
+    absolute.then(|| format!(r"\\?\{path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbatim() {
+        assert_eq!(
+            verbatim(r"C:\logs\test").as_deref(),
+            Some(r"\\?\C:\logs\test")
+        );
+        assert_eq!(
+            verbatim("d:/logs/test").as_deref(),
+            Some(r"\\?\d:\logs\test")
+        );
+        assert_eq!(
+            verbatim(r"\\server\share\logs").as_deref(),
+            Some(r"\\?\UNC\server\share\logs")
+        );
+        assert_eq!(verbatim(r"\\?\C:\logs").as_deref(), Some(r"\\?\C:\logs"));
+        assert_eq!(verbatim(r"logs\test"), None);
+        assert_eq!(verbatim(r"C:logs"), None);
+        assert_eq!(verbatim(r"\logs"), None);
+    }
+
+    #[test]
+    fn test_too_long() {
+        assert!(!too_long(10));
+        assert!(!too_long(MAX_PATH - FILE_NAME_ROOM - 1));
+        assert!(too_long(MAX_PATH - FILE_NAME_ROOM));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_long_dir_elsewhere() {
+        let dir = PathBuf::from("/").join("a".repeat(MAX_PATH));
+        assert_eq!(long_dir(dir.clone()), dir);
+    }
+}
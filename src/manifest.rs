@@ -0,0 +1,453 @@
+//! `manifest.json` in each table directory, listing the table's epochs
+//!
+//! The manifest carries a CRC-32 of its epochs. A missing or corrupt manifest is rebuilt by
+//! scanning the table directory the next time the logger opens the table, which also migrates
+//! tables written before manifests existed.
+
+use std::{
+    collections::hash_map::Entry,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    metadata::SkipMetadata,
+    reader::{self, Compression},
+    storage::{OpenMode, RealFs, Storage},
+    table_dir::TableFiles,
+};
+
+const VERSION: u64 = 1;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// In epoch order; the last one is the current epoch
+    pub epochs: Vec<ManifestEpoch>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEpoch {
+    pub epoch: usize,
+    /// Name of the file as written, before any compression of rotated files and without the
+    /// table's prefix if flat
+    pub file: String,
+    /// Rows as of the last manifest update, i.e. final once closed
+    ///
+    /// Compressed files found by scanning count no rows unless readable per the enabled features.
+    pub records: u64,
+    pub bytes: u64,
+    /// Whether the logger rotated or closed the epoch cleanly
+    pub closed: bool,
+    /// Whether newlines in fields are escaped, see
+    /// [`crate::CsvLoggerBuilder::single_line_fields`]; `None` if found by scanning
+    pub single_line_fields: Option<bool>,
+}
+
+impl Manifest {
+    pub fn current_epoch(&self) -> Option<usize> {
+        self.epochs.last().map(|e| e.epoch)
+    }
+
+    pub fn get(&self, epoch: usize) -> Option<&ManifestEpoch> {
+        self.epochs.iter().find(|e| e.epoch == epoch)
+    }
+
+    /// Marks `epoch` open with its counts so far
+    pub(crate) fn open(&mut self, epoch: usize, records: u64, bytes: u64) {
+        self.upsert(epoch, records, bytes, false);
+    }
+
+    pub(crate) fn close(&mut self, epoch: usize, records: u64, bytes: u64) {
+        self.upsert(epoch, records, bytes, true);
+    }
+
+    /// Closes the epochs before `epoch` that a run stopped without closing, recounting their files
+    pub(crate) fn close_before(
+        &mut self,
+        storage: &impl Storage,
+        files: &TableFiles,
+        output_dir: &Path,
+        table_name: &str,
+        epoch: usize,
+    ) {
+        for entry in self
+            .epochs
+            .iter_mut()
+            .filter(|e| e.epoch < epoch && !e.closed)
+        {
+            let path = files.epoch_file(storage, output_dir, table_name, entry.epoch, false);
+            if let Ok(contents) = storage.read(&path) {
+                entry.records = count_records(&contents);
+                entry.bytes = contents.len() as u64;
+            }
+            entry.closed = true;
+        }
+    }
+
+    /// Keeps whether the epoch is closed, e.g. after repairing its file
+    pub(crate) fn recount(&mut self, epoch: usize, records: u64, bytes: u64) {
+        if let Some(entry) = self.epochs.iter_mut().find(|e| e.epoch == epoch) {
+            entry.records = records;
+            entry.bytes = bytes;
+        }
+    }
+
+    /// Records how the fields of `epoch` are encoded
+    pub(crate) fn set_single_line_fields(&mut self, epoch: usize, single_line: bool) {
+        if let Some(entry) = self.epochs.iter_mut().find(|e| e.epoch == epoch) {
+            entry.single_line_fields = Some(single_line);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, epoch: usize) {
+        self.epochs.retain(|e| e.epoch != epoch);
+    }
+
+    /// Numbers every epoch one higher, e.g. after an epoch is imported in front of them
+    pub(crate) fn shift(&mut self) {
+        for entry in &mut self.epochs {
+            entry.epoch += 1;
+            entry.file = format!("{}.csv", entry.epoch);
+        }
+    }
+
+    /// A new epoch is encoded like the one before it, as written by the same logger
+    fn upsert(&mut self, epoch: usize, records: u64, bytes: u64, closed: bool) {
+        let mut entry = ManifestEpoch {
+            epoch,
+            file: format!("{epoch}.csv"),
+            records,
+            bytes,
+            closed,
+            single_line_fields: None,
+        };
+        match self.epochs.binary_search_by_key(&epoch, |e| e.epoch) {
+            Ok(i) => {
+                entry.single_line_fields = self.epochs[i].single_line_fields;
+                self.epochs[i] = entry;
+            }
+            Err(i) => {
+                entry.single_line_fields = i
+                    .checked_sub(1)
+                    .and_then(|prev| self.epochs[prev].single_line_fields);
+                self.epochs.insert(i, entry);
+            }
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let epochs = Value::Array(
+            self.epochs
+                .iter()
+                .map(|e| {
+                    let mut entry = json!({
+                        "epoch": e.epoch,
+                        "file": e.file,
+                        "records": e.records,
+                        "bytes": e.bytes,
+                        "closed": e.closed,
+                    });
+                    if let Some(single_line) = e.single_line_fields {
+                        entry["single_line_fields"] = Value::Bool(single_line);
+                    }
+                    entry
+                })
+                .collect(),
+        );
+        let crc = crc32(epochs.to_string().as_bytes());
+        json!({ "version": VERSION, "epochs": epochs, "crc": crc }).to_string()
+    }
+
+    /// `None` if malformed or if the CRC does not match
+    fn parse(json: &[u8]) -> Option<Self> {
+        let manifest: Value = serde_json::from_slice(json).ok()?;
+        if manifest.get("version")?.as_u64()? != VERSION {
+            return None;
+        }
+        let crc = manifest.get("crc")?.as_u64()?;
+        let epochs = manifest.get("epochs")?;
+        if u64::from(crc32(epochs.to_string().as_bytes())) != crc {
+            return None;
+        }
+        let epochs = epochs
+            .as_array()?
+            .iter()
+            .map(|e| {
+                Some(ManifestEpoch {
+                    epoch: usize::try_from(e.get("epoch")?.as_u64()?).ok()?,
+                    file: e.get("file")?.as_str()?.to_string(),
+                    records: e.get("records")?.as_u64()?,
+                    bytes: e.get("bytes")?.as_u64()?,
+                    closed: e.get("closed")?.as_bool()?,
+                    single_line_fields: e.get("single_line_fields").and_then(Value::as_bool),
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { epochs })
+    }
+
+    /// Rebuilds the manifest from the epoch files in the table directory
+    ///
+    /// Every epoch but the last counts as closed.
+    fn scan(
+        storage: &impl Storage,
+        files: &TableFiles,
+        output_dir: impl AsRef<Path>,
+        table_name: &str,
+    ) -> io::Result<Self> {
+        let mut paths = vec![];
+        for dir in files.epoch_dirs(storage, output_dir, table_name) {
+            match storage.read_dir(&dir) {
+                Ok(entries) => paths.extend(entries),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e),
+            }
+        }
+        let mut epochs = paths
+            .iter()
+            .filter_map(|path| {
+                let file = path.file_name()?.to_str()?;
+                let file = files.file_of(table_name, file)?;
+                let (epoch, compression) = Compression::parse(file)?;
+                Some((epoch, compression, path))
+            })
+            .collect::<Vec<_>>();
+        epochs.sort_unstable_by_key(|&(epoch, compression, _)| (epoch, compression));
+        epochs.dedup_by_key(|&mut (epoch, ..)| epoch);
+
+        let mut manifest = Self::default();
+        let last = epochs.last().map(|&(epoch, ..)| epoch);
+        for (epoch, compression, path) in epochs {
+            let Ok(contents) = storage.read(path) else {
+                continue;
+            };
+            let records = match compression {
+                Compression::None => count_records(&contents),
+                // Only rotated files on the real filesystem are compressed
+                _ => reader::open_epoch(path)
+                    .ok()
+                    .flatten()
+                    .map_or(0, |mut reader| {
+                        reader.records().map_while(Result::ok).count() as u64
+                    }),
+            };
+            let closed = Some(epoch) != last;
+            manifest.upsert(epoch, records, contents.len() as u64, closed);
+        }
+        Ok(manifest)
+    }
+}
+
+pub(crate) fn manifest_path(
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> PathBuf {
+    files.table_file(output_dir, table_name, "manifest.json")
+}
+
+/// The manifest of a table if it is intact
+pub fn read_manifest(output_dir: impl AsRef<Path>, table_name: &str) -> Option<Manifest> {
+    let output_dir = output_dir.as_ref();
+    load(&RealFs, &TableFiles::of(output_dir), output_dir, table_name)
+}
+
+pub(crate) fn load(
+    storage: &impl Storage,
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> Option<Manifest> {
+    let path = manifest_path(files, output_dir, table_name);
+    if let Some(manifest) = files.manifests().get(&path) {
+        return Some(manifest.clone());
+    }
+    Manifest::parse(&storage.read(&path).ok()?)
+}
+
+/// Applies `f` to the manifest of a table, rebuilt from the directory if missing or corrupt, and
+/// replaces the manifest file atomically
+///
+/// The manifest is read once and then kept with `files`, so later updates only write it.
+pub(crate) fn update(
+    storage: &impl Storage,
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+    f: impl FnOnce(&mut Manifest),
+) -> io::Result<()> {
+    let output_dir = output_dir.as_ref();
+    let path = manifest_path(files, output_dir, table_name);
+    let mut manifests = files.manifests();
+    let manifest = match manifests.entry(path.clone()) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let stored = storage.read(&path).ok();
+            let manifest = match stored.and_then(|json| Manifest::parse(&json)) {
+                Some(manifest) => manifest,
+                None => Manifest::scan(storage, files, output_dir, table_name)?,
+            };
+            entry.insert(manifest)
+        }
+    };
+    f(manifest);
+    write(storage, &path, manifest)
+}
+
+/// Drops the manifest kept by [`update`], e.g. once the files of the table were deleted behind the
+/// logger's back
+pub(crate) fn forget(files: &TableFiles, output_dir: impl AsRef<Path>, table_name: &str) {
+    files
+        .manifests()
+        .remove(&manifest_path(files, output_dir, table_name));
+}
+
+/// Replaces the manifest file atomically
+pub(crate) fn store(
+    storage: &impl Storage,
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+    manifest: &Manifest,
+) -> io::Result<()> {
+    let path = manifest_path(files, output_dir, table_name);
+    files.manifests().insert(path.clone(), manifest.clone());
+    write(storage, &path, manifest)
+}
+
+fn write(storage: &impl Storage, path: &Path, manifest: &Manifest) -> io::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    let mut file = storage.open(&tmp, OpenMode::Truncate)?;
+    file.write_all(manifest.to_json().as_bytes())?;
+    drop(file);
+    storage.rename(&tmp, path)
+}
+
+/// Rows after the header
+fn count_records(contents: &[u8]) -> u64 {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(SkipMetadata::new(contents));
+    reader.records().map_while(Result::ok).count() as u64
+}
+
+/// CRC-32 (IEEE)
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use crate::{storage::Backend, CsvLogger, RotationPolicy};
+
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct TestRecord {
+        n: usize,
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_manifest_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLogger::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        );
+        for n in 0..3 {
+            logger.log_to("test", &TestRecord { n });
+        }
+        drop(logger);
+
+        let manifest = read_manifest(dir.path(), "test").unwrap();
+        assert_eq!(manifest.current_epoch(), Some(1));
+        let first = ManifestEpoch {
+            epoch: 0,
+            file: "0.csv".to_string(),
+            records: 2,
+            bytes: "n\n0\n1\n".len() as u64,
+            closed: true,
+            single_line_fields: Some(false),
+        };
+        assert_eq!(manifest.epochs[0], first);
+        assert!(!manifest.epochs[1].closed);
+
+        let files = TableFiles::of(dir.path());
+        let path = manifest_path(&files, dir.path(), "test");
+        let corrupt = std::fs::read_to_string(&path)
+            .unwrap()
+            .replace("\"records\":2", "\"records\":3");
+        std::fs::write(&path, corrupt).unwrap();
+        assert_eq!(read_manifest(dir.path(), "test"), None);
+        assert_eq!(
+            crate::cur_epoch(&Backend::Real, &files, dir.path(), "test").unwrap(),
+            Some(1)
+        );
+
+        // Scanning cannot tell how the fields are encoded
+        let scanned = Manifest::scan(&Backend::Real, &files, dir.path(), "test").unwrap();
+        let first = ManifestEpoch {
+            single_line_fields: None,
+            ..first
+        };
+        assert_eq!(scanned.epochs[0], first);
+        let second = ManifestEpoch {
+            epoch: 1,
+            file: "1.csv".to_string(),
+            records: 1,
+            bytes: "n\n2\n".len() as u64,
+            closed: false,
+            single_line_fields: None,
+        };
+        assert_eq!(scanned.epochs[1], second);
+
+        update(&Backend::Real, &files, dir.path(), "test", |_| ()).unwrap();
+        assert_eq!(read_manifest(dir.path(), "test"), Some(scanned));
+    }
+
+    #[test]
+    fn test_close_on_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = || {
+            CsvLogger::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(10).unwrap(),
+                    max_epochs: 10,
+                },
+            )
+        };
+        let mut first = logger();
+        first.log_to("test", &TestRecord { n: 0 });
+        first.flush();
+        // Left open as if the process died
+        std::mem::forget(first);
+
+        let mut second = logger();
+        second.log_to("test", &TestRecord { n: 1 });
+        let manifest = read_manifest(dir.path(), "test").unwrap();
+        assert_eq!(manifest.current_epoch(), Some(1));
+        assert!(manifest.epochs[0].closed);
+        assert_eq!(manifest.epochs[0].records, 1);
+        assert!(!manifest.epochs[1].closed);
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt;
+
+use serde::{
+    ser::{self, Impossible},
+    Serialize,
+};
+
+use crate::row::{Header, Row};
+
+/// Whether `record` serializes as a map rather than a struct
+pub(crate) fn is_map(record: &(impl Serialize + ?Sized)) -> bool {
+    matches!(record.serialize(Probe), Err(ProbeError { map: true }))
+}
+
+/// Sorts the columns of a map record by key
+pub(crate) fn sort(row: &mut Row) {
+    let mut columns = std::mem::take(&mut *row.header)
+        .into_iter()
+        .zip(std::mem::take(&mut row.fields))
+        .collect::<Vec<_>>();
+    columns.sort_by(|a, b| a.0.cmp(&b.0));
+    let (header, fields): (Vec<_>, Vec<_>) = columns.into_iter().unzip();
+    row.header = header.into();
+    row.fields = fields;
+}
+
+/// Rearranges a map record to `header`, leaving cells of missing keys empty
+///
+/// A row with keys outside of `header` is left as it is unless `drop_new_keys`.
+pub(crate) fn align(row: &mut Row, header: &Header, drop_new_keys: bool) {
+    if !drop_new_keys && row.header.iter().any(|column| !header.contains(column)) {
+        return;
+    }
+    let fields = header
+        .iter()
+        .map(|column| match row.header.iter().position(|c| c == column) {
+            Some(i) => std::mem::take(&mut row.fields[i]),
+            None => String::new(),
+        })
+        .collect();
+    row.header = header.clone();
+    row.fields = fields;
+}
+
+/// Stops at the first call, telling whether it was for a map
+struct Probe;
+
+#[derive(Debug)]
+struct ProbeError {
+    map: bool,
+}
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "probed")
+    }
+}
+impl std::error::Error for ProbeError {}
+impl ser::Error for ProbeError {
+    fn custom<T: fmt::Display>(_msg: T) -> Self {
+        Self { map: false }
+    }
+}
+
+macro_rules! not_a_map {
+    ($($method:ident: $ty:ty,)*) => {
+        $(
+            fn $method(self, _v: $ty) -> Result<(), ProbeError> {
+                Err(ProbeError { map: false })
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for Probe {
+    type Ok = ();
+    type Error = ProbeError;
+    type SerializeSeq = Impossible<(), ProbeError>;
+    type SerializeTuple = Impossible<(), ProbeError>;
+    type SerializeTupleStruct = Impossible<(), ProbeError>;
+    type SerializeTupleVariant = Impossible<(), ProbeError>;
+    type SerializeMap = Impossible<(), ProbeError>;
+    type SerializeStruct = Impossible<(), ProbeError>;
+    type SerializeStructVariant = Impossible<(), ProbeError>;
+
+    not_a_map! {
+        serialize_bool: bool,
+        serialize_i8: i8,
+        serialize_i16: i16,
+        serialize_i32: i32,
+        serialize_i64: i64,
+        serialize_u8: u8,
+        serialize_u16: u16,
+        serialize_u32: u32,
+        serialize_u64: u64,
+        serialize_f32: f32,
+        serialize_f64: f64,
+        serialize_char: char,
+        serialize_str: &str,
+        serialize_bytes: &[u8],
+        serialize_unit_struct: &'static str,
+    }
+
+    fn serialize_none(self) -> Result<(), ProbeError> {
+        Err(ProbeError { map: false })
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<(), ProbeError> {
+        Err(ProbeError { map: false })
+    }
+
+    fn serialize_unit(self) -> Result<(), ProbeError> {
+        Err(ProbeError { map: false })
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), ProbeError> {
+        Err(ProbeError { map: false })
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), ProbeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), ProbeError> {
+        Err(ProbeError { map: false })
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, ProbeError> {
+        Err(ProbeError { map: false })
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, ProbeError> {
+        Err(ProbeError { map: false })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, ProbeError> {
+        Err(ProbeError { map: false })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, ProbeError> {
+        Err(ProbeError { map: false })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, ProbeError> {
+        Err(ProbeError { map: true })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, ProbeError> {
+        Err(ProbeError { map: false })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, ProbeError> {
+        Err(ProbeError { map: false })
+    }
+}
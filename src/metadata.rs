@@ -0,0 +1,229 @@
+//! A block of `# key: value` comment lines above the header of each epoch file
+//!
+//! Values have `\`, newlines and carriage returns escaped as in [`crate::schema`]. The block starts
+//! with the [`MARKER`] line, by which every reader of epoch files tells it from a header or row
+//! that starts with `#` and skips it.
+
+use std::{
+    io::{self, BufRead, Read},
+    sync::OnceLock,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::{schema, RotationPolicy};
+
+/// The start of the first line of a block, followed by the crate version
+pub(crate) const MARKER: &str = "# csv_logger: ";
+
+/// When the first logger of the process was built
+static PROCESS_START: OnceLock<SystemTime> = OnceLock::new();
+
+/// The lines of the block shared by every epoch file of a logger
+#[derive(Debug, Clone)]
+pub(crate) struct Metadata {
+    entries: Vec<(&'static str, String)>,
+}
+impl Metadata {
+    pub fn new(app: Option<(String, String)>, rotation: &RotationPolicy) -> Self {
+        let start = *PROCESS_START.get_or_init(SystemTime::now);
+        // Rendered as the marker
+        let mut entries = vec![("csv_logger", env!("CARGO_PKG_VERSION").to_string())];
+        if let Some((name, version)) = app {
+            entries.push(("app_name", name));
+            entries.push(("app_version", version));
+        }
+        entries.extend([
+            (
+                "host",
+                gethostname::gethostname().to_string_lossy().into_owned(),
+            ),
+            (
+                "process_start",
+                DateTime::<Utc>::from(start).to_rfc3339_opts(SecondsFormat::Millis, true),
+            ),
+            ("max_records", rotation.max_records.to_string()),
+            ("max_epochs", rotation.max_epochs.to_string()),
+        ]);
+        Self { entries }
+    }
+
+    /// The block of the file of `epoch`
+    pub fn render(&self, epoch: usize) -> String {
+        let mut block = String::new();
+        let epoch = ("epoch", epoch.to_string());
+        for (key, value) in self.entries.iter().chain([&epoch]) {
+            let mut value = value.clone();
+            schema::escape(&mut value);
+            block.push_str(&format!("# {key}: {value}\n"));
+        }
+        block
+    }
+}
+
+/// Parses the block at the start of `read`, if any
+pub(crate) fn parse(read: impl Read) -> io::Result<Vec<(String, String)>> {
+    let mut entries = vec![];
+    for (i, line) in io::BufReader::new(read).lines().enumerate() {
+        let line = line?;
+        if i == 0 && !line.starts_with(MARKER) {
+            break;
+        }
+        let Some(comment) = line.strip_prefix('#') else {
+            break;
+        };
+        if let Some((key, value)) = comment.trim_start().split_once(": ") {
+            entries.push((key.to_string(), schema::unescape(value)));
+        }
+    }
+    Ok(entries)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Nothing read yet
+    Start,
+    LineStart,
+    Comment,
+    Body,
+}
+
+/// Reads past the leading `#` lines if the first of them is the [`MARKER`] line
+pub(crate) struct SkipMetadata<R> {
+    inner: R,
+    state: State,
+    /// Read while looking for the marker, but not part of a block
+    pending: Vec<u8>,
+    skipped: u64,
+    skipped_lines: u64,
+}
+impl<R: Read> SkipMetadata<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            state: State::Start,
+            pending: vec![],
+            skipped: 0,
+            skipped_lines: 0,
+        }
+    }
+
+    /// Bytes of the block read past so far
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Lines of the block read past so far
+    pub fn skipped_lines(&self) -> u64 {
+        self.skipped_lines
+    }
+}
+impl<R: Read> Read for SkipMetadata<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.state == State::Start {
+            let mut head = vec![0; MARKER.len()];
+            let mut len = 0;
+            while len < head.len() {
+                match self.inner.read(&mut head[len..])? {
+                    0 => break,
+                    n => len += n,
+                }
+            }
+            head.truncate(len);
+            if head == MARKER.as_bytes() {
+                self.state = State::Comment;
+                self.skipped = len as u64;
+            } else {
+                self.state = State::Body;
+                self.pending = head;
+            }
+        }
+        if !self.pending.is_empty() {
+            let n = buf.len().min(self.pending.len());
+            buf[..n].copy_from_slice(&self.pending[..n]);
+            self.pending.drain(..n);
+            return Ok(n);
+        }
+        loop {
+            let n = self.inner.read(buf)?;
+            if n == 0 || self.state == State::Body {
+                return Ok(n);
+            }
+            let mut start = 0;
+            while start < n && self.state != State::Body {
+                match (self.state, buf[start]) {
+                    (State::LineStart, b'#') => self.state = State::Comment,
+                    (State::LineStart, _) => {
+                        self.state = State::Body;
+                        continue;
+                    }
+                    (State::Comment, b'\n') => {
+                        self.state = State::LineStart;
+                        self.skipped_lines += 1;
+                    }
+                    _ => (),
+                }
+                start += 1;
+            }
+            self.skipped += start as u64;
+            if start < n {
+                buf.copy_within(start..n, 0);
+                return Ok(n - start);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let rotation = RotationPolicy {
+            max_records: NonZeroUsize::new(10).unwrap(),
+            max_epochs: 2,
+        };
+        let metadata = Metadata::new(
+            Some(("app\nname".to_string(), "1.0".to_string())),
+            &rotation,
+        );
+        let block = metadata.render(3);
+        assert!(block.contains("# app_name: app\\nname\n"));
+        let file = format!("{block}a,b\n#1,2\n");
+
+        let entries = parse(file.as_bytes()).unwrap();
+        assert!(entries.contains(&("app_name".to_string(), "app\nname".to_string())));
+        assert_eq!(
+            entries.last(),
+            Some(&("epoch".to_string(), "3".to_string()))
+        );
+
+        // A byte at a time to split lines across reads
+        let mut skip = SkipMetadata::new(file.as_bytes());
+        let mut rest = vec![];
+        let mut byte = [0];
+        while skip.read(&mut byte).unwrap() != 0 {
+            rest.push(byte[0]);
+        }
+        assert_eq!(rest, b"a,b\n#1,2\n");
+        assert_eq!(skip.skipped(), block.len() as u64);
+        assert_eq!(skip.skipped_lines(), block.lines().count() as u64);
+    }
+
+    #[test]
+    fn test_without_block() {
+        // A header or headerless row starting with `#` is not a block
+        for file in ["#a,b\n1,2\n", "# a: b\n", "#", ""] {
+            let mut skip = SkipMetadata::new(file.as_bytes());
+            let mut rest = String::new();
+            skip.read_to_string(&mut rest).unwrap();
+            assert_eq!(rest, file);
+            assert_eq!(skip.skipped(), 0);
+            assert!(parse(file.as_bytes()).unwrap().is_empty());
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Replacing and deleting files by renames, for output directories on network filesystems
+//!
+//! CIFS and NFS may fail to remove or truncate a file another host has open, or leave a ghost of
+//! it behind. So files are replaced by renaming a complete temporary file over them, and deleted
+//! by renaming them into `.trash` in the output directory, which flushes empty later.
+
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::storage::{self, Backend, OpenMode, Storage, StorageFile};
+
+pub(crate) const TRASH_DIR: &str = ".trash";
+/// Files the trash holds before deletions fall back to removing in place
+const MAX_TRASH: usize = 64;
+
+/// Tells trashed files of the same name apart
+static TRASHED: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone)]
+pub(crate) struct NetworkFs {
+    inner: Box<Backend>,
+    trash: PathBuf,
+    /// Whether the trash may hold files; an earlier run may have left some
+    pending: Arc<AtomicBool>,
+}
+impl NetworkFs {
+    pub fn new(inner: Backend, output_dir: &Path) -> Self {
+        Self {
+            inner: Box::new(inner),
+            trash: output_dir.join(TRASH_DIR),
+            pending: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    pub fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        self.inner.create_dir_all(dir)
+    }
+
+    pub fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+
+    /// Tries to delete the files in the trash; those still open elsewhere stay for the next try
+    pub fn purge_trash(&self) {
+        if !self.pending.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        let left = self.purge();
+        if left != 0 {
+            self.pending.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Returns the number of files left in the trash
+    fn purge(&self) -> usize {
+        let Ok(paths) = self.inner.read_dir(&self.trash) else {
+            return 0;
+        };
+        paths
+            .iter()
+            .filter(|path| storage::remove_if_exists(&*self.inner, path).is_err())
+            .count()
+    }
+
+    fn trashed_path(&self, path: &Path) -> PathBuf {
+        let n = TRASHED.fetch_add(1, Ordering::Relaxed);
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.trash
+            .join(format!("{}.{n}.{file_name}", std::process::id()))
+    }
+}
+impl Storage for NetworkFs {
+    /// Moves a file being truncated out of the way first, in case it is open elsewhere
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<StorageFile> {
+        if mode == OpenMode::Truncate {
+            storage::remove_if_exists(self, path)?;
+        }
+        self.inner.open(path, mode)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.inner.read(path)
+    }
+
+    /// Moves `path` into the trash, unless the trash is full even after purging it
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let full = self
+            .inner
+            .read_dir(&self.trash)
+            .is_ok_and(|paths| paths.len() >= MAX_TRASH);
+        if full && self.purge() >= MAX_TRASH {
+            return self.inner.remove(path);
+        }
+        let trashed = self.trashed_path(path);
+        match self.inner.rename(path, &trashed) {
+            // The trash may not exist yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.inner.create_dir_all(&self.trash)?;
+                self.inner.rename(path, &trashed)?;
+            }
+            res => res?,
+        }
+        self.pending.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        self.inner.read_dir(dir)
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        self.inner.sync_dir(dir)
+    }
+
+    fn replace(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut file = self.inner.open(&tmp, OpenMode::Truncate)?;
+        file.write_all(contents)?;
+        drop(file);
+        self.inner.rename(&tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemStorage;
+
+    use super::*;
+
+    #[test]
+    fn test_trash() {
+        let storage = MemStorage::new();
+        let network = NetworkFs::new(Backend::Memory(storage.clone()), Path::new("/logs"));
+        let write = |path: &str| {
+            network
+                .open(Path::new(path), OpenMode::Truncate)
+                .unwrap()
+                .write_all(b"n\n")
+                .unwrap();
+        };
+        write("/logs/test/0.csv");
+        write("/logs/test/0.csv");
+        network.remove(Path::new("/logs/test/0.csv")).unwrap();
+        assert!(!storage.exists("/logs/test/0.csv"));
+        assert_eq!(network.inner.read_dir(&network.trash).unwrap().len(), 2);
+        network.purge_trash();
+        assert!(network.inner.read_dir(&network.trash).is_err());
+
+        for _ in 0..MAX_TRASH + 1 {
+            write("/logs/test/epoch");
+            network.remove(Path::new("/logs/test/epoch")).unwrap();
+        }
+        // Purged when full
+        assert_eq!(network.inner.read_dir(&network.trash).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,224 @@
+//! Logging from async code without waiting on files or the global logger lock
+//!
+//! Records are serialized on the calling thread and sent to a channel logger whose receiver a
+//! background thread writes to disk, started with
+//! [`CsvLoggerBuilder::init_nonblocking`](crate::CsvLoggerBuilder::init_nonblocking).
+
+use std::{
+    borrow::Cow,
+    io,
+    sync::{OnceLock, Weak},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use table_log::SerWrap;
+
+use crate::{
+    channel::{self, Backpressure, Queued},
+    error::{self, ErrorHandler},
+    row::{Row, RowFormat, SerializeError},
+    stats, CsvLogger,
+};
+
+static QUEUE: OnceLock<Queue> = OnceLock::new();
+
+enum Message {
+    Row { row: Row, is_map: bool },
+    Flush(Done),
+}
+impl Queued for Message {
+    fn from_record(
+        record: &dyn table_log::LogRecord,
+        format: RowFormat,
+    ) -> Result<Self, SerializeError> {
+        let table = Cow::Borrowed(record.table_name());
+        let (row, is_map) = format.serialize(table, &SerWrap(record))?;
+        Ok(Message::Row { row, is_map })
+    }
+
+    /// Blocks until the writer thread has flushed
+    fn flush(tx: &Sender<Self>) {
+        let (done, rx) = crossbeam_channel::bounded(1);
+        if tx.send(Message::Flush(Done::Blocking(done))).is_ok() {
+            let _ = rx.recv();
+        }
+    }
+}
+
+/// Signals that everything enqueued before a flush is written
+enum Done {
+    Blocking(Sender<()>),
+    #[cfg(feature = "tokio")]
+    Async(tokio::sync::oneshot::Sender<()>),
+}
+impl Done {
+    fn send(self) {
+        match self {
+            Done::Blocking(tx) => {
+                let _ = tx.send(());
+            }
+            #[cfg(feature = "tokio")]
+            Done::Async(tx) => {
+                let _ = tx.send(());
+            }
+        }
+    }
+}
+
+/// The channel of the registered logger, for logging without taking the global logger lock
+struct Queue {
+    tx: Weak<Sender<Message>>,
+    format: RowFormat,
+    error_handler: ErrorHandler,
+}
+
+/// Serializes `record` and hands it to the writer thread without ever blocking
+///
+/// Does nothing unless the writer is started. When the queue is full, the record is dropped
+/// whatever the writer's [`Backpressure`], which only applies to [`table_log::log!`].
+pub fn log<'caller>(record: &impl table_log::LogRecord<'caller>) {
+    let Some(queue) = QUEUE.get() else {
+        return;
+    };
+    let Some(tx) = queue.tx.upgrade() else {
+        return;
+    };
+    match Message::from_record(record, queue.format) {
+        Ok(message) => channel::send(&tx, message, Backpressure::Drop),
+        Err(e) => {
+            stats::count_dropped(1);
+            error::report(&queue.error_handler, e.into());
+        }
+    }
+}
+
+/// Resolves once the writer thread has written and flushed every record enqueued before the call
+#[cfg(feature = "tokio")]
+pub async fn flush() {
+    use crossbeam_channel::TrySendError;
+
+    let Some(tx) = QUEUE.get().and_then(|queue| queue.tx.upgrade()) else {
+        return;
+    };
+    let (done, rx) = tokio::sync::oneshot::channel();
+    let mut message = Message::Flush(Done::Async(done));
+    // Never dropped, and never blocking the runtime while the queue is full
+    loop {
+        match tx.try_send(message) {
+            Ok(()) => break,
+            Err(TrySendError::Full(m)) => {
+                message = m;
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            Err(TrySendError::Disconnected(_)) => return,
+        }
+    }
+    drop(tx);
+    let _ = rx.await;
+}
+
+/// Registers a channel logger of `capacity` messages and starts the thread writing what it
+/// receives with `logger`, which also flushes every `flush_interval`
+///
+/// Unregisters the channel logger again if the thread cannot be spawned.
+pub(crate) fn start(
+    mut logger: CsvLogger,
+    capacity: usize,
+    backpressure: Backpressure,
+    flush_interval: Duration,
+) -> io::Result<()> {
+    if QUEUE.get().is_some() {
+        panic!("Only one nonblocking writer can be started");
+    }
+    let format = logger.row_format();
+    let error_handler = logger.error_handler.clone();
+    let (tx, rx): (_, Receiver<Message>) =
+        channel::register(capacity, backpressure, format, error_handler.clone());
+    let _ = QUEUE.set(Queue {
+        tx,
+        format,
+        error_handler,
+    });
+    let spawned = std::thread::Builder::new()
+        .name("csv_logger::nonblocking".to_string())
+        .spawn(move || write(logger, rx, flush_interval));
+    if let Err(e) = spawned {
+        table_log::GLOBAL_LOG.lock().unwrap().remove_logger();
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Writes what `rx` receives with `logger` until it disconnects, flushing every `flush_interval`
+/// whether or not rows keep coming
+fn write(mut logger: CsvLogger, rx: Receiver<Message>, flush_interval: Duration) {
+    let mut next_flush = Instant::now() + flush_interval;
+    loop {
+        match rx.recv_deadline(next_flush) {
+            Ok(Message::Row { row, is_map }) => logger.log_row(row, is_map),
+            Ok(Message::Flush(done)) => {
+                logger.flush();
+                next_flush = Instant::now() + flush_interval;
+                done.send();
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => {
+                logger.flush();
+                return;
+            }
+        }
+        if next_flush <= Instant::now() {
+            logger.flush();
+            next_flush = Instant::now() + flush_interval;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use crate::{log_file_path, CsvLoggerBuilder, RotationPolicy};
+
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct TestRecord {
+        pub n: usize,
+    }
+
+    #[test]
+    fn test_flush_under_steady_traffic() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(1000).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .build();
+        let flush_interval = Duration::from_millis(200);
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let writer = std::thread::spawn(move || write(logger, rx, flush_interval));
+
+        let path = log_file_path(dir.path(), "test", 0);
+        let start = Instant::now();
+        // A row every few milliseconds never leaves the writer waiting out the interval
+        for n in 0.. {
+            let table = Cow::Borrowed("test");
+            let (row, is_map) = RowFormat::default()
+                .serialize(table, &TestRecord { n })
+                .unwrap();
+            tx.send(Message::Row { row, is_map }).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+            if std::fs::read_to_string(&path).is_ok_and(|csv| csv.starts_with("n\n0\n")) {
+                break;
+            }
+            assert!(start.elapsed() < 2 * flush_interval, "Not flushed");
+        }
+        drop(tx);
+        writer.join().unwrap();
+    }
+}
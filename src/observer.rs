@@ -0,0 +1,161 @@
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{error::ErrorHandler, TableFlush};
+
+/// Notified of what happens in a logger
+pub trait LoggerObserver: Send + Sync {
+    fn on_event(&self, event: LoggerEvent);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoggerEvent {
+    /// A table was opened at `epoch`
+    TableCreated { table: String, epoch: usize },
+    Rotated {
+        table: String,
+        old_path: PathBuf,
+        new_epoch: usize,
+    },
+    /// A row came with other columns than the epoch of its table, which starts a new epoch
+    ///
+    /// Always the case for the epoch resumed from an earlier run, whatever the
+    /// [`crate::SchemaPolicy`].
+    SchemaChanged {
+        table: String,
+        old_header: Vec<String>,
+        new_header: Vec<String>,
+    },
+    /// Tables that had anything to flush, by name
+    Flushed { tables: Vec<TableFlush> },
+    /// The log file of an epoch beyond the kept ones was deleted
+    EpochDeleted {
+        table: String,
+        epoch: usize,
+        path: PathBuf,
+    },
+    /// A check found less than [`crate::FreeSpacePolicy::min_free_bytes`] left; `available` is
+    /// after deleting the log files of `deleted`
+    LowFreeSpace {
+        available: u64,
+        deleted: Vec<PathBuf>,
+        /// Whether logging is paused for lack of space
+        paused: bool,
+    },
+    /// The file of a table was deleted from under the logger, which went on in a new epoch
+    /// created at `path`
+    TableRecreated {
+        table: String,
+        epoch: usize,
+        path: PathBuf,
+    },
+    /// An error was reported, see [`crate::CsvLoggerError::kind`]
+    Error { kind: &'static str, message: String },
+}
+
+/// Hands events to the observers of a logger
+#[derive(Default)]
+pub(crate) struct Events {
+    observers: Vec<Arc<dyn LoggerObserver>>,
+    /// Events waiting for [`Events::take`] if deferred
+    deferred: Option<Vec<LoggerEvent>>,
+}
+impl Events {
+    pub fn new(observers: Vec<Arc<dyn LoggerObserver>>) -> Self {
+        Self {
+            observers,
+            deferred: None,
+        }
+    }
+
+    /// Holds events back until taken instead of notifying right away, e.g. to notify once the
+    /// logger is unlocked
+    pub fn defer(&mut self) {
+        self.deferred.get_or_insert_with(Vec::new);
+    }
+
+    /// Only builds the event if observed
+    pub fn emit(&mut self, event: impl FnOnce() -> LoggerEvent) {
+        if self.observers.is_empty() {
+            return;
+        }
+        let event = event();
+        match &mut self.deferred {
+            Some(deferred) => deferred.push(event),
+            None => notify(&self.observers, event),
+        }
+    }
+
+    pub fn take(&mut self) -> Deferred {
+        let events = self
+            .deferred
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default();
+        let observers = match events.is_empty() {
+            true => vec![],
+            false => self.observers.clone(),
+        };
+        Deferred { observers, events }
+    }
+}
+
+/// Events held back while the logger was locked
+#[must_use]
+pub(crate) struct Deferred {
+    observers: Vec<Arc<dyn LoggerObserver>>,
+    events: Vec<LoggerEvent>,
+}
+impl Deferred {
+    pub fn notify(self) {
+        for event in self.events {
+            notify(&self.observers, event);
+        }
+    }
+}
+
+fn notify(observers: &[Arc<dyn LoggerObserver>], event: LoggerEvent) {
+    for observer in observers {
+        observer.on_event(event.clone());
+    }
+}
+
+/// Notifies `observers` of every error before `error_handler` handles it
+///
+/// Errors come from background threads as well, so they are never deferred.
+pub(crate) fn observe_errors(
+    observers: Vec<Arc<dyn LoggerObserver>>,
+    error_handler: ErrorHandler,
+) -> ErrorHandler {
+    Arc::new(move |e| {
+        let event = LoggerEvent::Error {
+            kind: e.kind(),
+            message: e.to_string(),
+        };
+        notify(&observers, event);
+        error_handler(e);
+    })
+}
+
+/// Records every event for tests to assert on; clones share the events
+#[cfg(feature = "test-util")]
+#[derive(Debug, Clone, Default)]
+pub struct RecordingObserver {
+    events: Arc<std::sync::Mutex<Vec<LoggerEvent>>>,
+}
+#[cfg(feature = "test-util")]
+impl RecordingObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the events recorded so far
+    pub fn take_events(&self) -> Vec<LoggerEvent> {
+        std::mem::take(&mut self.events.lock().unwrap())
+    }
+}
+#[cfg(feature = "test-util")]
+impl LoggerObserver for RecordingObserver {
+    fn on_event(&self, event: LoggerEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
@@ -0,0 +1,38 @@
+use std::{
+    panic,
+    sync::{Once, TryLockError},
+    time::Duration,
+};
+
+use crate::shared;
+
+/// How long the hook spends flushing at most
+const DEADLINE: Duration = Duration::from_secs(2);
+
+/// Installs a panic hook, once, flushing the registered logger before calling the hook installed
+/// before it
+pub(crate) fn install() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            flush_registered();
+            previous(info);
+        }));
+    });
+}
+
+/// Best effort: a nested panic would abort the process, so nothing here may panic
+///
+/// Skips the logger while another thread, possibly the panicking one, holds it.
+fn flush_registered() {
+    let Some(logger) = shared::registered() else {
+        return;
+    };
+    let mut logger = match logger.try_lock() {
+        Ok(logger) => logger,
+        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(TryLockError::WouldBlock) => return,
+    };
+    logger.flush_before(DEADLINE);
+}
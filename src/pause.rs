@@ -0,0 +1,33 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Drops every record from now on until [`resume`]
+///
+/// Flushing still writes out what is buffered. Tables and epochs are kept as they are.
+pub fn pause() {
+    PAUSED.store(true, Ordering::Relaxed);
+}
+
+pub fn resume() {
+    PAUSED.store(false, Ordering::Relaxed);
+}
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Records dropped while paused since the process started
+pub fn dropped_while_paused() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// Counts the record as dropped if paused
+pub(crate) fn drop_if_paused() -> bool {
+    if !is_paused() {
+        return false;
+    }
+    DROPPED.fetch_add(1, Ordering::Relaxed);
+    true
+}
@@ -0,0 +1,160 @@
+//! Owner-only permissions for the output directory and what the logger creates in it
+//!
+//! Only Unix has the modes; elsewhere [`PrivateFs`] behaves like [`RealFs`].
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::storage::{self, OpenMode, RealFs, Storage, StorageFile};
+
+#[cfg(unix)]
+const DIR_MODE: u32 = 0o700;
+#[cfg(unix)]
+const FILE_MODE: u32 = 0o600;
+
+/// The real filesystem, creating the output directory and the directories in it `0700` and files
+/// `0600`
+///
+/// Ancestors of the output directory are created as [`RealFs`] would.
+#[derive(Debug, Clone)]
+pub(crate) struct PrivateFs {
+    output_dir: PathBuf,
+    /// Whether to also restrict directories that already exist, if they are ours
+    tighten_existing: bool,
+}
+impl PrivateFs {
+    pub fn new(output_dir: PathBuf, tighten_existing: bool) -> Self {
+        Self {
+            output_dir,
+            tighten_existing,
+        }
+    }
+
+    pub fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        if !dir.starts_with(&self.output_dir) {
+            return std::fs::create_dir_all(dir);
+        }
+        match dir.parent() {
+            Some(parent) if dir != self.output_dir => self.create_dir_all(parent)?,
+            Some(parent) => std::fs::create_dir_all(parent)?,
+            None => (),
+        }
+        match create_dir(dir) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && dir.is_dir() => {
+                match self.tighten_existing {
+                    true => tighten(dir),
+                    false => Ok(()),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+impl Storage for PrivateFs {
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<StorageFile> {
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)?;
+        }
+        let mut options = storage::open_options(mode);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, FILE_MODE);
+        Ok(StorageFile::real(options.open(path)?))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        RealFs.read(path)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        RealFs.remove(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        RealFs.rename(from, to)
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        RealFs.read_dir(dir)
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        RealFs.sync_dir(dir)
+    }
+}
+
+fn create_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, DIR_MODE);
+    builder.create(dir)
+}
+
+/// Takes the group's and others' permissions off `dir`, unless it belongs to another user
+#[cfg(unix)]
+fn tighten(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = std::fs::metadata(dir)?.permissions().mode();
+    if mode & 0o077 == 0 {
+        return Ok(());
+    }
+    let permissions = std::fs::Permissions::from_mode(mode & DIR_MODE);
+    match std::fs::set_permissions(dir, permissions) {
+        // Only the owner may change the mode
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Ok(()),
+        res => res,
+    }
+}
+#[cfg(not(unix))]
+fn tighten(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{io::Write, os::unix::fs::PermissionsExt};
+
+    use super::*;
+
+    fn mode(path: &Path) -> u32 {
+        std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn test_private_fs() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = dir.path().join("parent").join("logs");
+        let storage = PrivateFs::new(output_dir.clone(), true);
+        let path = output_dir.join("pid-1").join("test").join("0.csv");
+        storage
+            .open(&path, OpenMode::Truncate)
+            .unwrap()
+            .write_all(b"n\n")
+            .unwrap();
+        assert_eq!(mode(&output_dir), 0o700);
+        assert_eq!(mode(&output_dir.join("pid-1")), 0o700);
+        assert_eq!(mode(&output_dir.join("pid-1").join("test")), 0o700);
+        assert_eq!(mode(&path), 0o600);
+    }
+
+    #[test]
+    fn test_tighten_existing() {
+        let dir = tempfile::tempdir().unwrap();
+        let table_dir = dir.path().join("test");
+        std::fs::create_dir(&table_dir).unwrap();
+        std::fs::set_permissions(&table_dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let path = table_dir.join("0.csv");
+        PrivateFs::new(dir.path().to_owned(), false)
+            .open(&path, OpenMode::Append)
+            .unwrap();
+        assert_eq!(mode(&table_dir), 0o755);
+        PrivateFs::new(dir.path().to_owned(), true)
+            .open(&path, OpenMode::Append)
+            .unwrap();
+        assert_eq!(mode(&table_dir), 0o700);
+    }
+}
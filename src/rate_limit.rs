@@ -0,0 +1,45 @@
+use std::time::Instant;
+
+/// Token bucket holding up to one second worth of records
+pub(crate) struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    /// `None` until the first record
+    last: Option<Instant>,
+    /// Records dropped since the last [`RateLimiter::take_suppressed`]
+    suppressed: u64,
+}
+impl RateLimiter {
+    pub fn new(max_records_per_sec: f64) -> Self {
+        Self {
+            rate: max_records_per_sec,
+            tokens: max_records_per_sec.max(1.0),
+            last: None,
+            suppressed: 0,
+        }
+    }
+
+    /// Records that may be logged at `now`, without taking any
+    pub fn available(&self, now: Instant) -> f64 {
+        let elapsed = self.last.map_or(0.0, |last| {
+            now.saturating_duration_since(last).as_secs_f64()
+        });
+        (self.tokens + elapsed * self.rate).min(self.rate.max(1.0))
+    }
+
+    /// Whether a record may be logged now
+    pub fn acquire(&mut self, now: Instant) -> bool {
+        self.tokens = self.available(now);
+        self.last = Some(now);
+        if 1.0 <= self.tokens {
+            self.tokens -= 1.0;
+            return true;
+        }
+        self.suppressed += 1;
+        false
+    }
+
+    pub fn take_suppressed(&mut self) -> u64 {
+        std::mem::take(&mut self.suppressed)
+    }
+}
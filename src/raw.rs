@@ -0,0 +1,164 @@
+//! Writing fields formatted by the caller to a table, without serializing a record
+//!
+//! The fields then go through the same steps as the rows of serialized records, so a table written
+//! both ways holds the same lines for the same values.
+
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    sync::{Mutex, Weak},
+};
+
+use crate::{
+    shared::{self, CsvLoggerHandle},
+    CsvLogger, CsvLoggerError,
+};
+
+/// Writes rows of the columns registered by [`crate::raw_table`]
+///
+/// Does nothing once its logger is dropped.
+#[derive(Debug, Clone)]
+pub struct TableHandle {
+    logger: Weak<Mutex<CsvLogger>>,
+    table: Cow<'static, str>,
+    header: Vec<String>,
+}
+impl TableHandle {
+    pub(crate) fn new(logger: Weak<Mutex<CsvLogger>>, table: &str, header: &[&str]) -> Self {
+        Self {
+            logger,
+            table: Cow::Owned(table.to_string()),
+            header: header.iter().map(|column| column.to_string()).collect(),
+        }
+    }
+
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    pub fn header(&self) -> &[String] {
+        &self.header
+    }
+
+    /// Logs a row of `fields` in the order of the header
+    pub fn write(&self, fields: &[&dyn Display]) {
+        self.log(|| fields.iter().map(|field| field.to_string()).collect());
+    }
+
+    /// Like [`Self::write`] for fields that are already strings
+    ///
+    /// The fields go to the file as they are, without being copied, while no step of the logger
+    /// changes the rows of the table.
+    pub fn write_str_fields(&self, fields: &[&str]) {
+        let Some(inner) = self.logger.upgrade() else {
+            return;
+        };
+        CsvLoggerHandle { inner }
+            .with(|logger| logger.log_raw_str(&self.table, &self.header, fields));
+    }
+
+    /// Formats the fields before locking the logger
+    fn log(&self, fields: impl FnOnce() -> Vec<String>) {
+        let Some(inner) = self.logger.upgrade() else {
+            return;
+        };
+        let fields = fields();
+        CsvLoggerHandle { inner }.with(|logger| logger.log_raw(&self.table, &self.header, fields));
+    }
+}
+
+/// Registers `header` as the columns of `table` of the registered file logger and returns a handle
+/// logging rows of them
+///
+/// Fails if the table name is invalid, or if the table was registered or logged to with other
+/// columns. Records of other columns logged to the table afterwards are dropped as
+/// [`CsvLoggerError::SchemaMismatch`]. The handle does nothing unless the registered logger writes
+/// to files.
+pub fn raw_table(table: &str, header: &[&str]) -> Result<TableHandle, CsvLoggerError> {
+    match shared::registered() {
+        Some(inner) => CsvLoggerHandle { inner }.raw_table(table, header),
+        None => Ok(TableHandle::new(Weak::new(), table, header)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, path::Path};
+
+    use crate::{CsvLoggerBuilder, CsvLoggerHandle, RotationPolicy};
+
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct Order {
+        pub id: u64,
+        pub item: &'static str,
+    }
+
+    #[derive(serde::Serialize)]
+    struct Refund {
+        pub id: u64,
+    }
+
+    fn handle(dir: &Path) -> CsvLoggerHandle {
+        let logger = CsvLoggerBuilder::new(
+            dir.to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .build();
+        CsvLoggerHandle::new(logger)
+    }
+
+    fn read(dir: &Path, table: &str) -> String {
+        std::fs::read_to_string(crate::log_file_path(dir, table, 0)).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_raw_table() {
+        let serde_dir = tempfile::tempdir().unwrap();
+        let serde_logger = handle(serde_dir.path());
+        serde_logger.log_to("order", &Order { id: 1, item: "a,b" });
+        serde_logger.log_to("order", &Order { id: 2, item: "c" });
+        serde_logger.flush();
+
+        let raw_dir = tempfile::tempdir().unwrap();
+        let raw_logger = handle(raw_dir.path());
+        let order = raw_logger.raw_table("order", &["id", "item"]).unwrap();
+        order.write(&[&1, &"a,b"]);
+        order.write_str_fields(&["2", "c"]);
+        raw_logger.flush();
+        assert_eq!(
+            read(raw_dir.path(), "order"),
+            read(serde_dir.path(), "order")
+        );
+
+        // Wrong number of fields
+        order.write_str_fields(&["3"]);
+        assert_eq!(raw_logger.stats().dropped.get("order"), Some(&1));
+        // Records of other columns
+        raw_logger.log_to("order", &Refund { id: 4 });
+        assert_eq!(raw_logger.stats().dropped.get("order"), Some(&2));
+        assert!(matches!(
+            raw_logger.raw_table("order", &["id"]),
+            Err(CsvLoggerError::RawHeader { .. })
+        ));
+
+        // Checked against the records logged before
+        assert!(matches!(
+            serde_logger.raw_table("order", &["item", "id"]),
+            Err(CsvLoggerError::RawHeader { .. })
+        ));
+        assert!(serde_logger.raw_table("order", &["id", "item"]).is_ok());
+        assert!(matches!(
+            serde_logger.raw_table("../order", &["id"]),
+            Err(CsvLoggerError::InvalidTableName { .. })
+        ));
+
+        // Nothing to write to once the logger is gone
+        drop(raw_logger);
+        order.write_str_fields(&["6", "e"]);
+    }
+}
@@ -0,0 +1,1193 @@
+//! Reading the tables of an output directory
+//!
+//! The entry points take the namespace the tables were written under, if any; see
+//! [`crate::CsvLoggerBuilder::namespace`].
+
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    ops::RangeBounds,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use csv::StringRecord;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    layout::Layout,
+    manifest,
+    metadata::{self, SkipMetadata},
+    network_fs,
+    rename::{self, HeaderCase},
+    schema::{self, SingleLine},
+    storage::{RealFs, Storage},
+    table_dir::{self, TableFiles},
+};
+
+#[derive(Debug, Clone)]
+pub(crate) struct EpochFile {
+    pub epoch: usize,
+    pub path: PathBuf,
+    pub compression: Compression,
+}
+
+/// Compression of an epoch file as told by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    /// Sealed by [`crate::encryption`], compressed beforehand per the rest of the extension
+    Encrypted,
+}
+impl Compression {
+    const EXTENSIONS: [(&'static str, Compression); 6] = [
+        (".csv", Compression::None),
+        (".csv.gz", Compression::Gzip),
+        (".csv.zst", Compression::Zstd),
+        (".csv.enc", Compression::Encrypted),
+        (".csv.gz.enc", Compression::Encrypted),
+        (".csv.zst.enc", Compression::Encrypted),
+    ];
+
+    /// Parses `<epoch>.csv`, `<epoch>.csv.gz` or `<epoch>.csv.zst`, each optionally followed by
+    /// `.enc`
+    pub fn parse(file_name: &str) -> Option<(usize, Self)> {
+        Self::EXTENSIONS
+            .iter()
+            .find_map(|(extension, compression)| {
+                let epoch = file_name.strip_suffix(extension)?.parse().ok()?;
+                Some((epoch, *compression))
+            })
+    }
+
+    /// Per the extension alone, so also of the prefixed files of a flat layout
+    pub fn of(path: &Path) -> Self {
+        let file_name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        Self::EXTENSIONS
+            .iter()
+            .find(|(extension, _)| file_name.ends_with(extension))
+            .map_or(Compression::None, |&(_, compression)| compression)
+    }
+}
+
+/// Lists the epoch files of a table in epoch order
+///
+/// An uncompressed file wins over a compressed one of the same epoch that is still being written.
+pub(crate) fn epoch_files(
+    table_files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> io::Result<Vec<EpochFile>> {
+    stored_epoch_files(&RealFs, table_files, output_dir, table_name)
+}
+
+/// Like [`epoch_files`] but lists them through `storage`
+pub(crate) fn stored_epoch_files(
+    storage: &impl Storage,
+    table_files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> io::Result<Vec<EpochFile>> {
+    let mut files = vec![];
+    for dir in table_files.epoch_dirs(storage, output_dir, table_name) {
+        for path in storage.read_dir(&dir)? {
+            let Some((epoch, compression)) = path
+                .file_name()
+                .and_then(|s| s.to_str())
+                .and_then(|s| table_files.file_of(table_name, s))
+                .and_then(Compression::parse)
+            else {
+                continue;
+            };
+            files.push(EpochFile {
+                epoch,
+                path,
+                compression,
+            });
+        }
+    }
+    files.sort_unstable_by_key(|f| (f.epoch, f.compression));
+    files.dedup_by_key(|f| f.epoch);
+    Ok(files)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableInfo {
+    pub name: String,
+    /// From the manifest or epoch file, or else the highest epoch file
+    pub current_epoch: Option<usize>,
+    pub epoch_files: usize,
+    pub total_bytes: u64,
+    /// Modification time of the most recently modified epoch file
+    pub modified: Option<SystemTime>,
+}
+
+/// Lists the tables under the output directory sorted by name
+///
+/// Tables whose directories are named differently, e.g. `_CON_` for `CON`, are listed under their
+/// own names.
+/// Directories without an epoch file or any epoch files are only listed if `include_empty`.
+/// In a [`crate::Layout::Flat`] output directory, tables are told by the prefixes of their files.
+pub fn list_tables(
+    output_dir: impl AsRef<Path>,
+    namespace: Option<&str>,
+    include_empty: bool,
+) -> io::Result<Vec<TableInfo>> {
+    let output_dir = &table_dir::namespace_dir(output_dir, namespace)?;
+    let table_name = table_dir::table_names(output_dir);
+    let table_files = TableFiles::of(output_dir);
+    let flat = table_files.layout() == Layout::Flat;
+    let mut names = BTreeSet::new();
+    for entry in std::fs::read_dir(output_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        match flat {
+            false if entry.file_type()?.is_dir() && file_name != network_fs::TRASH_DIR => {
+                names.insert(table_name(file_name));
+            }
+            true if entry.file_type()?.is_file() => {
+                // Skips `tables.json` and the like
+                let table_file = table_dir::split_flat(file_name).filter(|(_, file)| {
+                    matches!(*file, "epoch" | "manifest.json") || Compression::parse(file).is_some()
+                });
+                if let Some((dir_name, _)) = table_file {
+                    names.insert(table_name(&dir_name));
+                }
+            }
+            _ => (),
+        }
+    }
+    let mut tables = vec![];
+    for name in names {
+        let files = epoch_files(&table_files, output_dir, &name)?;
+        let recorded_epoch = recorded_epoch(&table_files, output_dir, &name);
+        if !include_empty && files.is_empty() && recorded_epoch.is_none() {
+            continue;
+        }
+        let mut total_bytes = 0;
+        let mut modified = None;
+        for file in &files {
+            let metadata = match std::fs::metadata(&file.path) {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            total_bytes += metadata.len();
+            modified = modified.max(metadata.modified().ok());
+        }
+        tables.push(TableInfo {
+            current_epoch: recorded_epoch.or(files.last().map(|f| f.epoch)),
+            epoch_files: files.len(),
+            total_bytes,
+            modified,
+            name,
+        });
+    }
+    tables.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    Ok(tables)
+}
+
+/// Per the manifest, or else the legacy epoch file
+fn recorded_epoch(files: &TableFiles, output_dir: &Path, table_name: &str) -> Option<usize> {
+    if let Some(epoch) = manifest::load(&RealFs, files, output_dir, table_name)
+        .and_then(|manifest| manifest.current_epoch())
+    {
+        return Some(epoch);
+    }
+    std::fs::read_to_string(crate::epoch_file_path(files, output_dir, table_name))
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpochInfo {
+    pub epoch: usize,
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// `None` where the platform does not record it
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+    /// Whether this is the epoch the logger is writing to per the manifest or epoch file
+    pub active: bool,
+    /// Rows per the manifest if the logger closed the epoch cleanly
+    pub closed_rows: Option<u64>,
+}
+impl EpochInfo {
+    /// The `# key: value` lines of [`crate::CsvLoggerBuilder::metadata_header`] at the top of
+    /// the file, unescaped; empty if there are none
+    pub fn metadata(&self) -> io::Result<Vec<(String, String)>> {
+        match open_decoded(&self.path)? {
+            Some(read) => metadata::parse(read),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Counts the complete rows, excluding the header, unless the manifest has them
+    pub fn rows(&self) -> io::Result<u64> {
+        if let Some(rows) = self.closed_rows {
+            return Ok(rows);
+        }
+        let Some(mut reader) = open_epoch(&self.path)? else {
+            return Ok(0);
+        };
+        let rows = reader
+            .records()
+            .try_fold(0, |rows, record| record.map(|_| rows + 1))?;
+        Ok(rows)
+    }
+}
+
+/// Lists the epoch files of a table in epoch order
+pub fn epochs(
+    output_dir: impl AsRef<Path>,
+    namespace: Option<&str>,
+    table_name: &str,
+) -> io::Result<Vec<EpochInfo>> {
+    let output_dir = &table_dir::namespace_dir(output_dir, namespace)?;
+    let files = TableFiles::of(output_dir);
+    let active = recorded_epoch(&files, output_dir, table_name);
+    let manifest = manifest::load(&RealFs, &files, output_dir, table_name).unwrap_or_default();
+    let mut epochs = vec![];
+    for file in epoch_files(&files, output_dir, table_name)? {
+        let metadata = match std::fs::metadata(&file.path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let closed_rows = manifest
+            .get(file.epoch)
+            .filter(|entry| entry.closed)
+            .map(|entry| entry.records);
+        epochs.push(EpochInfo {
+            active: active == Some(file.epoch),
+            closed_rows,
+            epoch: file.epoch,
+            path: file.path,
+            bytes: metadata.len(),
+            created: metadata.created().ok(),
+            modified: metadata.modified().ok(),
+        });
+    }
+    Ok(epochs)
+}
+
+/// Reads the rows of a table across all of its epoch files
+pub struct TableReader {
+    output_dir: PathBuf,
+    table_name: String,
+    epochs: Vec<EpochFile>,
+    timestamp_column: String,
+    skip_bad_timestamps: bool,
+    /// Epochs with newlines in fields escaped
+    single_line: SingleLine,
+    /// Original names by column name, and the case to reverse for the other columns
+    original_names: Option<(HashMap<String, String>, HeaderCase)>,
+}
+impl TableReader {
+    pub fn open(
+        output_dir: impl AsRef<Path>,
+        namespace: Option<&str>,
+        table_name: &str,
+    ) -> io::Result<Self> {
+        let output_dir = table_dir::namespace_dir(output_dir, namespace)?;
+        let epochs = epoch_files(&TableFiles::of(&output_dir), &output_dir, table_name)?;
+        Ok(Self {
+            output_dir: output_dir.clone(),
+            table_name: table_name.to_string(),
+            epochs,
+            timestamp_column: "ts".to_string(),
+            skip_bad_timestamps: false,
+            single_line: SingleLine::read(&output_dir, table_name),
+            original_names: None,
+        })
+    }
+
+    /// Column read by [`TableReader::between`]; `ts` by default
+    pub fn timestamp_column(mut self, column: impl Into<String>) -> Self {
+        self.timestamp_column = column.into();
+        self
+    }
+
+    /// Whether [`TableReader::between`] skips rows with unparseable timestamps instead of
+    /// yielding errors for them
+    pub fn skip_bad_timestamps(mut self, skip: bool) -> Self {
+        self.skip_bad_timestamps = skip;
+        self
+    }
+
+    /// Makes [`TableReader::deserialize`] map columns back to the field names they were renamed
+    /// from, given the logger's [`crate::CsvLoggerBuilder::column_renames`] and
+    /// [`crate::CsvLoggerBuilder::header_case`]
+    pub fn original_names(mut self, renames: HashMap<String, String>, case: HeaderCase) -> Self {
+        let renamed = renames
+            .into_iter()
+            .map(|(field, column)| (column, field))
+            .collect();
+        self.original_names = Some((renamed, case));
+        self
+    }
+
+    /// Epochs found when the reader was opened
+    pub fn epochs(&self) -> impl Iterator<Item = usize> + '_ {
+        self.epochs.iter().map(|f| f.epoch)
+    }
+
+    /// Keeps only the epochs within `range`
+    pub fn select_epochs(mut self, range: impl RangeBounds<usize>) -> Self {
+        self.epochs.retain(|f| range.contains(&f.epoch));
+        self
+    }
+
+    /// Header of the newest epoch that has one
+    pub fn header(&self) -> Result<Option<StringRecord>, csv::Error> {
+        for file in self.epochs.iter().rev() {
+            let Some(mut reader) = open_epoch(&file.path)? else {
+                continue;
+            };
+            let header = reader.headers()?;
+            if !header.is_empty() {
+                return Ok(Some(header.clone()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Yields the rows of every epoch in order, without their headers
+    ///
+    /// A trailing line that has not been completely written yet is skipped. `.csv.gz` and
+    /// `.csv.zst` epoch files are decompressed and `.enc` ones decrypted, see
+    /// [`crate::set_decryption_keys`]; a file that fails to open, decompress or decrypt yields an
+    /// error and the iteration moves on to the next epoch.
+    pub fn records(&self) -> impl Iterator<Item = Result<StringRecord, csv::Error>> + '_ {
+        self.epochs.iter().flat_map(|file| {
+            let single_line = self.single_line.of(file.epoch);
+            let records = match open_epoch(&file.path) {
+                Ok(Some(reader)) => Box::new(until_io_error(reader)) as Box<dyn Iterator<Item = _>>,
+                // Deleted by retention since the reader was opened
+                Ok(None) => Box::new(std::iter::empty()),
+                Err(e) => Box::new(std::iter::once(Err(e.into()))),
+            };
+            records.map(move |record| record.map(|record| schema::decode(single_line, record)))
+        })
+    }
+
+    /// Yields the rows from the first epoch on and then blocks for new rows as they are flushed
+    ///
+    /// Rotation is followed into the next epoch once the current one is exhausted.
+    pub fn tail(&self, poll_interval: Duration) -> Tail {
+        Tail {
+            files: TableFiles::of(&self.output_dir),
+            output_dir: self.output_dir.clone(),
+            table_name: self.table_name.clone(),
+            poll_interval,
+            epoch: self.epochs.first().map(|f| f.epoch),
+            offset: 0,
+            pending: VecDeque::new(),
+            single_line: self.single_line.clone(),
+        }
+    }
+
+    /// Yields the rows whose timestamp column falls in `start..end`
+    ///
+    /// Timestamps are RFC 3339 or Unix milliseconds. Assuming they are the times the rows were
+    /// logged, epoch files last modified before `start` and those following an epoch file last
+    /// modified at or after `end` are not read.
+    pub fn between(
+        &self,
+        start: SystemTime,
+        end: SystemTime,
+    ) -> impl Iterator<Item = Result<StringRecord, RecordError>> + '_ {
+        let mut files = vec![];
+        let mut prev_modified = None;
+        for file in &self.epochs {
+            let modified = std::fs::metadata(&file.path)
+                .and_then(|m| m.modified())
+                .ok();
+            let before = modified.is_some_and(|m| m < start);
+            let after = prev_modified.is_some_and(|m| end <= m);
+            prev_modified = modified;
+            if !before && !after {
+                files.push(file);
+            }
+        }
+        files
+            .into_iter()
+            .flat_map(move |file| self.between_epoch(file, start, end).into_iter().flatten())
+    }
+
+    fn between_epoch(
+        &self,
+        file: &EpochFile,
+        start: SystemTime,
+        end: SystemTime,
+    ) -> Option<Box<dyn Iterator<Item = Result<StringRecord, RecordError>>>> {
+        let epoch = file.epoch;
+        let error =
+            move |line: u64, record: Option<StringRecord>, source: csv::Error| RecordError {
+                epoch,
+                line,
+                record,
+                source,
+            };
+        let mut reader = match open_epoch(&file.path) {
+            Ok(Some(reader)) => reader,
+            Ok(None) => return None,
+            Err(e) => return Some(Box::new(std::iter::once(Err(error(0, None, e.into()))))),
+        };
+        let column = match reader.headers() {
+            Ok(headers) => headers.iter().position(|h| h == self.timestamp_column),
+            Err(e) => return Some(Box::new(std::iter::once(Err(error(0, None, e))))),
+        };
+        let Some(column) = column else {
+            let e = io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("No `{}` column", self.timestamp_column),
+            );
+            return Some(Box::new(std::iter::once(Err(error(1, None, e.into())))));
+        };
+        let skip_bad_timestamps = self.skip_bad_timestamps;
+        let single_line = self.single_line.of(file.epoch);
+        Some(Box::new(until_io_error(reader).filter_map(move |record| {
+            let record = match record {
+                Ok(record) => schema::decode(single_line, record),
+                Err(e) => {
+                    let line = e.position().map(|p| p.line()).unwrap_or_default();
+                    return Some(Err(error(line, None, e)));
+                }
+            };
+            let field = record.get(column).unwrap_or_default();
+            match crate::timestamp::parse(field) {
+                Some(time) => (start <= time && time < end).then_some(Ok(record)),
+                None if skip_bad_timestamps => None,
+                None => {
+                    let line = record.position().map(|p| p.line()).unwrap_or_default();
+                    let e = io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Unparseable timestamp `{field}`"),
+                    );
+                    Some(Err(error(line, Some(record), e.into())))
+                }
+            }
+        })))
+    }
+
+    /// Yields the rows of every epoch in order as `T`
+    ///
+    /// Columns are mapped by the header of each epoch file, so columns may be reordered between
+    /// epochs. A bad row is yielded as an error without stopping the iteration.
+    pub fn deserialize<T: DeserializeOwned + 'static>(
+        &self,
+    ) -> impl Iterator<Item = Result<T, RecordError>> + '_ {
+        self.epochs.iter().flat_map(|file| {
+            let single_line = self.single_line.of(file.epoch);
+            deserialize_epoch(file, single_line, self.original_names.as_ref())
+                .into_iter()
+                .flatten()
+        })
+    }
+}
+
+fn deserialize_epoch<T: DeserializeOwned + 'static>(
+    file: &EpochFile,
+    single_line: bool,
+    original_names: Option<&(HashMap<String, String>, HeaderCase)>,
+) -> Option<Box<dyn Iterator<Item = Result<T, RecordError>>>> {
+    let epoch = file.epoch;
+    let error = move |line: u64, record: Option<StringRecord>, source: csv::Error| RecordError {
+        epoch,
+        line,
+        record,
+        source,
+    };
+    let mut reader = match open_epoch(&file.path) {
+        Ok(Some(reader)) => reader,
+        Ok(None) => return None,
+        Err(e) => return Some(Box::new(std::iter::once(Err(error(0, None, e.into()))))),
+    };
+    let headers = match (reader.headers(), original_names) {
+        (Ok(headers), None) => headers.clone(),
+        (Ok(headers), Some((renamed, case))) => headers
+            .iter()
+            .map(|column| rename::reverse(column, renamed, *case))
+            .collect(),
+        (Err(e), _) => return Some(Box::new(std::iter::once(Err(error(0, None, e))))),
+    };
+    Some(Box::new(until_io_error(reader).map(move |record| {
+        let record = match record {
+            Ok(record) => schema::decode(single_line, record),
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or_default();
+                return Err(error(line, None, e));
+            }
+        };
+        match record.deserialize(Some(&headers)) {
+            Ok(row) => Ok(row),
+            Err(e) => {
+                let line = record.position().map(|p| p.line()).unwrap_or_default();
+                Err(error(line, Some(record), e))
+            }
+        }
+    })))
+}
+
+/// Returns the last `n` rows of a table in chronological order
+///
+/// Epoch files are read from the newest and only as many as needed.
+pub fn last_n(
+    output_dir: impl AsRef<Path>,
+    namespace: Option<&str>,
+    table_name: &str,
+    n: usize,
+) -> Result<Vec<StringRecord>, csv::Error> {
+    let output_dir = table_dir::namespace_dir(output_dir, namespace)?;
+    let single_line = SingleLine::read(&output_dir, table_name);
+    let mut epochs = vec![];
+    let mut count = 0;
+    for file in epoch_files(&TableFiles::of(&output_dir), &output_dir, table_name)?
+        .iter()
+        .rev()
+    {
+        if n <= count {
+            break;
+        }
+        let Some(reader) = open_epoch(&file.path)? else {
+            continue;
+        };
+        let records = reader.into_records().collect::<Result<Vec<_>, _>>()?;
+        count += records.len();
+        epochs.push((file.epoch, records));
+    }
+    let mut records = epochs
+        .into_iter()
+        .rev()
+        .flat_map(|(epoch, records)| {
+            let single_line = single_line.of(epoch);
+            records
+                .into_iter()
+                .map(move |record| schema::decode(single_line, record))
+        })
+        .collect::<Vec<_>>();
+    let skip = records.len().saturating_sub(n);
+    records.drain(..skip);
+    Ok(records)
+}
+
+pub struct Tail {
+    files: TableFiles,
+    output_dir: PathBuf,
+    table_name: String,
+    poll_interval: Duration,
+    epoch: Option<usize>,
+    /// Bytes of the current epoch file consumed so far
+    offset: u64,
+    pending: VecDeque<StringRecord>,
+    single_line: SingleLine,
+}
+impl Tail {
+    /// Returns whether any rows were read
+    fn poll(&mut self) -> Result<bool, csv::Error> {
+        let files = epoch_files(&self.files, &self.output_dir, &self.table_name)?;
+        let epoch = match self.epoch {
+            Some(epoch) => epoch,
+            None => match files.first() {
+                Some(file) => *self.epoch.insert(file.epoch),
+                None => return Ok(false),
+            },
+        };
+        // Checked before reading so that the current epoch is complete if a next one exists
+        let next = files.iter().find(|f| epoch < f.epoch).map(|f| f.epoch);
+        if self.read(epoch)? {
+            return Ok(true);
+        }
+        if let Some(next) = next {
+            self.epoch = Some(next);
+            self.offset = 0;
+            return self.read(next);
+        }
+        Ok(false)
+    }
+
+    fn read(&mut self, epoch: usize) -> Result<bool, csv::Error> {
+        let path = self
+            .files
+            .epoch_file(&RealFs, &self.output_dir, &self.table_name, epoch, false);
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut buf = vec![];
+        file.read_to_end(&mut buf)?;
+        let Some(end) = buf.iter().rposition(|&b| b == b'\n') else {
+            return Ok(false);
+        };
+        let complete = &buf[..end + 1];
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(SkipMetadata::new(complete));
+        let mut records = reader.records();
+        // The header, after any metadata
+        if self.offset == 0 && records.next().transpose()?.is_none() {
+            return Ok(false);
+        }
+        let before = self.pending.len();
+        let single_line = self.single_line.of(epoch);
+        for record in records {
+            self.pending.push_back(schema::decode(single_line, record?));
+        }
+        self.offset += complete.len() as u64;
+        Ok(before != self.pending.len())
+    }
+}
+impl Iterator for Tail {
+    type Item = Result<StringRecord, csv::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                return Some(Ok(record));
+            }
+            match self.poll() {
+                Ok(true) => (),
+                Ok(false) => std::thread::sleep(self.poll_interval),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// A row that could not be read or deserialized
+#[derive(Debug)]
+pub struct RecordError {
+    pub epoch: usize,
+    /// 1-based line number in the epoch file; `0` if unknown
+    pub line: u64,
+    /// The raw row if it could be read
+    pub record: Option<StringRecord>,
+    pub source: csv::Error,
+}
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Bad record at epoch {} line {}: {}",
+            self.epoch, self.line, self.source
+        )
+    }
+}
+impl std::error::Error for RecordError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+pub(crate) type EpochReader = csv::Reader<Box<dyn Read + Send>>;
+
+/// Opens an epoch file, decompressing it by its extension
+///
+/// A trailing line of an uncompressed file that has not been completely written is left out, and
+/// so are the leading lines of [`crate::CsvLoggerBuilder::metadata_header`].
+pub(crate) fn open_epoch(path: &Path) -> io::Result<Option<EpochReader>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let read: Box<dyn Read + Send> = match Compression::of(path) {
+        Compression::None => {
+            let len = complete_len(&mut file)?;
+            file.seek(SeekFrom::Start(0))?;
+            Box::new(file.take(len))
+        }
+        _ => decoder(file, path)?,
+    };
+    Ok(Some(csv::Reader::from_reader(Box::new(SkipMetadata::new(
+        read,
+    )))))
+}
+
+/// Like [`open_epoch`] but reads an uncompressed epoch through `storage`
+pub(crate) fn read_epoch(storage: &impl Storage, path: &Path) -> io::Result<Option<EpochReader>> {
+    if Compression::of(path) != Compression::None {
+        return open_epoch(path);
+    }
+    match storage.read(path) {
+        Ok(contents) => Ok(Some(epoch_reader(contents))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads the contents of an uncompressed epoch up to its last complete line
+pub(crate) fn epoch_reader(mut contents: Vec<u8>) -> EpochReader {
+    let complete = contents
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    contents.truncate(complete);
+    csv::Reader::from_reader(Box::new(SkipMetadata::new(io::Cursor::new(contents))))
+}
+
+/// Like [`open_epoch`] but returns the whole decompressed content
+pub(crate) fn open_decoded(path: &Path) -> io::Result<Option<Box<dyn Read + Send>>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    decoder(file, path).map(Some)
+}
+
+fn decoder(file: File, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    match Compression::of(path) {
+        #[cfg(feature = "encryption")]
+        Compression::Encrypted => {
+            let plaintext = crate::encryption::decrypt_file(path, file)?;
+            // Compressed before it was encrypted
+            let compression = Compression::of(&path.with_extension(""));
+            decode(io::Cursor::new(plaintext), compression)
+        }
+        compression => decode(file, compression),
+    }
+}
+
+fn decode(
+    file: impl Read + Send + 'static,
+    compression: Compression,
+) -> io::Result<Box<dyn Read + Send>> {
+    match compression {
+        Compression::None => Ok(Box::new(file)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Box::new(flate2::read::MultiGzDecoder::new(
+            io::BufReader::new(file),
+        ))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(zstd::stream::read::Decoder::new(file)?)),
+        #[allow(unreachable_patterns)]
+        compression => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Reading {compression:?} epoch files requires the matching feature"),
+        )),
+    }
+}
+
+/// Stops after an I/O error such as a corrupt compressed stream, which would otherwise repeat
+fn until_io_error<R: Read>(
+    reader: csv::Reader<R>,
+) -> impl Iterator<Item = Result<StringRecord, csv::Error>> {
+    let mut failed = false;
+    reader.into_records().map_while(move |record| {
+        if failed {
+            return None;
+        }
+        failed = record.as_ref().is_err_and(|e| e.is_io_error());
+        Some(record)
+    })
+}
+
+/// Length of the file up to and including its last newline
+pub(crate) fn complete_len(file: &mut File) -> io::Result<u64> {
+    const CHUNK: u64 = 4096;
+    let mut end = file.seek(SeekFrom::End(0))?;
+    let mut buf = vec![0; CHUNK as usize];
+    while end != 0 {
+        let start = end.saturating_sub(CHUNK);
+        let chunk = &mut buf[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        if let Some(i) = chunk.iter().rposition(|&b| b == b'\n') {
+            return Ok(start + i as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, num::NonZeroUsize};
+
+    use table_log::Logger;
+
+    use crate::{log_file_path, CsvLogger, RotationPolicy};
+
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct TestRecord<'caller> {
+        pub s: &'caller str,
+        pub n: usize,
+    }
+    impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+        fn table_name(&self) -> &'static str {
+            "test"
+        }
+    }
+
+    fn write_three_epochs(dir: &Path) {
+        let mut logger = CsvLogger::new(
+            dir.to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        );
+        for (n, s) in ["a", "b", "c", "d", "e"].into_iter().enumerate() {
+            logger.log(&TestRecord { s, n });
+        }
+        logger.flush();
+    }
+
+    fn read_all(dir: &Path) -> Vec<Vec<String>> {
+        TableReader::open(dir, None, "test")
+            .unwrap()
+            .records()
+            .map(|r| r.unwrap().iter().map(String::from).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_records() {
+        let dir = tempfile::tempdir().unwrap();
+        write_three_epochs(dir.path());
+        let reader = TableReader::open(dir.path(), None, "test").unwrap();
+        assert_eq!(reader.epochs().collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(
+            read_all(dir.path()),
+            [["a", "0"], ["b", "1"], ["c", "2"], ["d", "3"], ["e", "4"]]
+        );
+    }
+
+    #[derive(serde::Serialize)]
+    struct OtherRecord {
+        pub x: u8,
+    }
+    impl<'caller> table_log::LogRecord<'caller> for OtherRecord {
+        fn table_name(&self) -> &'static str {
+            "other"
+        }
+    }
+
+    #[test]
+    fn test_epochs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLogger::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 2,
+            },
+        );
+        for (n, s) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            logger.log(&TestRecord { s, n });
+        }
+        logger.flush();
+
+        let epochs = epochs(dir.path(), None, "test").unwrap();
+        assert_eq!(epochs.iter().map(|e| e.epoch).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(epochs[0].path, log_file_path(dir.path(), "test", 1));
+        assert_eq!(epochs[0].bytes, "s,n\nc,2\nd,3\n".len() as u64);
+        assert_eq!(epochs[0].rows().unwrap(), 2);
+        assert!(!epochs[0].active);
+        assert!(epochs[0].modified.is_some());
+        assert_eq!(epochs[1].bytes, 0);
+        assert_eq!(epochs[1].rows().unwrap(), 0);
+        assert!(epochs[1].active);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_compressed_epochs() {
+        let dir = tempfile::tempdir().unwrap();
+        write_three_epochs(dir.path());
+        let expected = read_all(dir.path());
+
+        let path = log_file_path(dir.path(), "test", 0);
+        let csv = std::fs::read(&path).unwrap();
+        let gz = std::fs::File::create(path.with_extension("csv.gz")).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(gz, flate2::Compression::default());
+        encoder.write_all(&csv).unwrap();
+        encoder.finish().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read_all(dir.path()), expected);
+        assert_eq!(last_n(dir.path(), None, "test", 5).unwrap().len(), 5);
+
+        // A corrupt epoch does not stop the others from being read
+        let path = log_file_path(dir.path(), "test", 1);
+        std::fs::write(path.with_extension("csv.gz"), b"not gzip").unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let records = TableReader::open(dir.path(), None, "test")
+            .unwrap()
+            .records()
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 2 + 1 + 1);
+        assert!(records[2].is_err());
+        assert_eq!(&records[3].as_ref().unwrap()[0], "e");
+    }
+
+    #[test]
+    fn test_between() {
+        #[derive(serde::Serialize)]
+        struct TimedRecord {
+            ts: String,
+            n: usize,
+        }
+        impl table_log::LogRecord<'_> for TimedRecord {
+            fn table_name(&self) -> &'static str {
+                "timed"
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLogger::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        );
+        let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let at = |secs: u64| base + Duration::from_secs(secs);
+        let millis = |secs: u64| at(secs).duration_since(SystemTime::UNIX_EPOCH).unwrap();
+        // Epochs hold rows at 0..2, 2..4 and 4..6 seconds
+        for n in 0..6 {
+            let ts = match n % 2 {
+                0 => millis(n as u64).as_millis().to_string(),
+                _ => chrono::DateTime::<chrono::Utc>::from(at(n as u64)).to_rfc3339(),
+            };
+            logger.log(&TimedRecord { ts, n });
+        }
+        logger.log(&TimedRecord {
+            ts: "yesterday".to_string(),
+            n: 6,
+        });
+        logger.flush();
+        for epoch in 0..3 {
+            let file = std::fs::File::options()
+                .append(true)
+                .open(log_file_path(dir.path(), "timed", epoch))
+                .unwrap();
+            file.set_modified(at(epoch as u64 * 2 + 1)).unwrap();
+        }
+        let file = std::fs::File::options()
+            .append(true)
+            .open(log_file_path(dir.path(), "timed", 3))
+            .unwrap();
+        file.set_modified(at(7)).unwrap();
+
+        let reader = TableReader::open(dir.path(), None, "timed").unwrap();
+        let ns = |reader: &TableReader, start, end| {
+            reader
+                .between(at(start), at(end))
+                .map(|r| r.map(|r| r[1].to_string()))
+                .collect::<Vec<_>>()
+        };
+        let rows = ns(&reader, 1, 6);
+        assert_eq!(rows.len(), 6);
+        assert_eq!(
+            rows[..5]
+                .iter()
+                .map(|r| r.as_ref().unwrap())
+                .collect::<Vec<_>>(),
+            ["1", "2", "3", "4", "5"]
+        );
+        assert_eq!(rows[5].as_ref().unwrap_err().epoch, 3);
+
+        // Epoch 0 is skipped by its modification time without being read
+        std::fs::write(log_file_path(dir.path(), "timed", 0), "garbage").unwrap();
+        let file = std::fs::File::options()
+            .append(true)
+            .open(log_file_path(dir.path(), "timed", 0))
+            .unwrap();
+        file.set_modified(at(1)).unwrap();
+        let reader = reader.skip_bad_timestamps(true);
+        let rows = ns(&reader, 2, 7)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(rows, ["2", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn test_list_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        write_three_epochs(dir.path());
+        let mut logger = CsvLogger::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        );
+        logger.log(&OtherRecord { x: 1 });
+        logger.flush();
+        std::fs::create_dir(dir.path().join("empty")).unwrap();
+
+        let tables = list_tables(dir.path(), None, false).unwrap();
+        assert_eq!(
+            tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(),
+            ["other", "test"]
+        );
+        assert_eq!(tables[0].current_epoch, Some(0));
+        assert_eq!(tables[0].epoch_files, 1);
+        assert_eq!(tables[0].total_bytes, "x\n1\n".len() as u64);
+        assert_eq!(tables[1].current_epoch, Some(2));
+        assert_eq!(tables[1].epoch_files, 3);
+        let bytes = "s,n\na,0\nb,1\n".len() * 2 + "s,n\ne,4\n".len();
+        assert_eq!(tables[1].total_bytes, bytes as u64);
+        assert!(tables[1].modified.is_some());
+
+        let tables = list_tables(dir.path(), None, true).unwrap();
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables[0].name, "empty");
+        assert_eq!(tables[0].current_epoch, None);
+        assert_eq!(tables[0].total_bytes, 0);
+    }
+
+    #[test]
+    fn test_flat_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = crate::CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .layout(Layout::Flat)
+        .build();
+        for (n, s) in ["a", "b", "c", "d", "e"].into_iter().enumerate() {
+            logger.log(&TestRecord { s, n });
+        }
+        logger.log(&OtherRecord { x: 1 });
+        logger.flush();
+        assert!(dir.path().join("test.2.csv").exists());
+
+        let tables = list_tables(dir.path(), None, true).unwrap();
+        assert_eq!(
+            tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(),
+            ["other", "test"]
+        );
+        assert_eq!(tables[1].current_epoch, Some(2));
+        assert_eq!(tables[1].epoch_files, 3);
+        assert_eq!(
+            read_all(dir.path()),
+            [["a", "0"], ["b", "1"], ["c", "2"], ["d", "3"], ["e", "4"]]
+        );
+    }
+
+    #[test]
+    fn test_last_n() {
+        let dir = tempfile::tempdir().unwrap();
+        write_three_epochs(dir.path());
+        let last = |n| {
+            last_n(dir.path(), None, "test", n).map(|records| {
+                records
+                    .iter()
+                    .map(|r| r.iter().map(String::from).collect::<Vec<_>>())
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(
+            last(4).unwrap(),
+            [["b", "1"], ["c", "2"], ["d", "3"], ["e", "4"]]
+        );
+        // Epochs older than needed are never read
+        std::fs::write(log_file_path(dir.path(), "test", 0), "s,n\nbad\n").unwrap();
+        assert_eq!(last(3).unwrap(), [["c", "2"], ["d", "3"], ["e", "4"]]);
+        assert!(last(4).is_err());
+    }
+
+    #[test]
+    fn test_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLogger::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(3).unwrap(),
+                max_epochs: 10,
+            },
+        );
+        let mut log = |s, n| {
+            logger.log(&TestRecord { s, n });
+            logger.flush();
+        };
+        log("a", 0);
+        let mut tail = TableReader::open(dir.path(), None, "test")
+            .unwrap()
+            .tail(Duration::from_millis(1));
+        let mut next = || {
+            tail.next()
+                .unwrap()
+                .unwrap()
+                .iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(next(), ["a", "0"]);
+        log("b", 1);
+        assert_eq!(next(), ["b", "1"]);
+        // Rotates into epoch 1
+        log("c", 2);
+        log("d", 3);
+        assert_eq!(next(), ["c", "2"]);
+        assert_eq!(next(), ["d", "3"]);
+        log("e", 4);
+        assert_eq!(next(), ["e", "4"]);
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct OwnedRecord {
+        s: String,
+        n: usize,
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let dir = tempfile::tempdir().unwrap();
+        write_three_epochs(dir.path());
+        // Columns reordered by a schema change
+        std::fs::write(log_file_path(dir.path(), "test", 1), "n,s\n2,c\nx,d\n").unwrap();
+        let reader = TableReader::open(dir.path(), None, "test").unwrap();
+        let rows = reader.deserialize::<OwnedRecord>().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 5);
+        let owned = |s: &str, n| OwnedRecord {
+            s: s.to_string(),
+            n,
+        };
+        assert_eq!(rows[0].as_ref().unwrap(), &owned("a", 0));
+        assert_eq!(rows[1].as_ref().unwrap(), &owned("b", 1));
+        assert_eq!(rows[2].as_ref().unwrap(), &owned("c", 2));
+        let e = rows[3].as_ref().unwrap_err();
+        assert_eq!((e.epoch, e.line), (1, 3));
+        assert_eq!(
+            e.record.as_ref().unwrap().iter().collect::<Vec<_>>(),
+            ["x", "d"]
+        );
+        assert_eq!(rows[4].as_ref().unwrap(), &owned("e", 4));
+    }
+
+    #[test]
+    fn test_missing_epoch_and_partial_line() {
+        let dir = tempfile::tempdir().unwrap();
+        write_three_epochs(dir.path());
+        std::fs::remove_file(log_file_path(dir.path(), "test", 1)).unwrap();
+        let mut last = File::options()
+            .append(true)
+            .open(log_file_path(dir.path(), "test", 2))
+            .unwrap();
+        last.write_all(b"f,").unwrap();
+        assert_eq!(read_all(dir.path()), [["a", "0"], ["b", "1"], ["e", "4"]]);
+    }
+}
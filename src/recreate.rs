@@ -0,0 +1,54 @@
+//! Recreating the files of tables deleted from under the logger, e.g. by a cleanup job
+//!
+//! Writes to a deleted file still succeed on Unix, so the logger looks for the file of a table
+//! before writing to it after a flush.
+
+use std::time::{Duration, Instant};
+
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// Spaces recreations out so as not to fight a cleaner, doubling the wait after each recreation
+/// that follows the previous one within [`MAX_DELAY`]
+#[derive(Debug, Default)]
+pub(crate) struct Recreation {
+    last: Option<Instant>,
+    delay: Duration,
+}
+impl Recreation {
+    /// Whether to recreate at `now`, which then counts as a recreation
+    pub fn allow(&mut self, now: Instant) -> bool {
+        match self.last.map(|last| now.duration_since(last)) {
+            Some(since) if since < self.delay => return false,
+            Some(since) if since < MAX_DELAY => {
+                self.delay = self.delay.saturating_mul(2).min(MAX_DELAY)
+            }
+            _ => self.delay = INITIAL_DELAY,
+        }
+        self.last = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allow() {
+        let mut recreation = Recreation::default();
+        let now = Instant::now();
+        assert!(recreation.allow(now));
+        assert!(!recreation.allow(now + Duration::from_millis(999)));
+        let now = now + INITIAL_DELAY;
+        assert!(recreation.allow(now));
+        assert!(!recreation.allow(now + INITIAL_DELAY));
+        let now = now + 2 * INITIAL_DELAY;
+        assert!(recreation.allow(now));
+
+        // Quiet for long enough to start over
+        let now = now + MAX_DELAY;
+        assert!(recreation.allow(now));
+        assert!(recreation.allow(now + INITIAL_DELAY));
+    }
+}
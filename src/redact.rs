@@ -0,0 +1,23 @@
+use crate::row::Row;
+
+/// Replaces the values of redacted columns before rows are written anywhere
+pub trait Redactor: Send + Sync {
+    fn redact(&self, column: &str, value: &str) -> String {
+        let _ = (column, value);
+        "***".to_string()
+    }
+}
+
+/// Replaces every redacted value with `***`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mask;
+impl Redactor for Mask {}
+
+/// Rows without field names are left as they are
+pub(crate) fn apply(row: &mut Row, columns: &[String], redactor: &dyn Redactor) {
+    for (column, value) in row.header.iter().zip(row.fields.iter_mut()) {
+        if columns.contains(column) {
+            *value = redactor.redact(column, value);
+        }
+    }
+}
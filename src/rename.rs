@@ -0,0 +1,153 @@
+//! Renaming the columns of the header without touching the field names of records
+
+use std::collections::HashMap;
+
+use crate::{context::EPOCH_COLUMN, row::Row, sequence::SEQUENCE_COLUMN};
+
+/// How the columns of every header are cased, assuming `snake_case` field names
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeaderCase {
+    /// Columns are written as named
+    #[default]
+    AsIs,
+    /// `user_id` becomes `userId`
+    Camel,
+    /// `user_id` becomes `UserId`
+    Pascal,
+    /// `user_id` becomes `USER_ID`
+    ScreamingSnake,
+}
+impl HeaderCase {
+    pub fn apply(self, column: &str) -> String {
+        let words = || column.split('_').filter(|word| !word.is_empty());
+        match self {
+            HeaderCase::AsIs => column.to_string(),
+            HeaderCase::Camel => words()
+                .enumerate()
+                .map(|(i, word)| match i {
+                    0 => word.to_string(),
+                    _ => capitalize(word),
+                })
+                .collect(),
+            HeaderCase::Pascal => words().map(capitalize).collect(),
+            HeaderCase::ScreamingSnake => column.to_uppercase(),
+        }
+    }
+
+    /// The `snake_case` column `column` was cased from
+    pub fn reverse(self, column: &str) -> String {
+        match self {
+            HeaderCase::AsIs => column.to_string(),
+            HeaderCase::Camel | HeaderCase::Pascal => {
+                let mut snake = String::with_capacity(column.len() + 4);
+                for (i, c) in column.char_indices() {
+                    if c.is_uppercase() && i != 0 {
+                        snake.push('_');
+                    }
+                    snake.extend(c.to_lowercase());
+                }
+                snake
+            }
+            HeaderCase::ScreamingSnake => column.to_lowercase(),
+        }
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Renames the columns of the row's header, by `renames` or else by `case`
+///
+/// The sequence column keeps its name since resuming looks it up, and so does the epoch column
+/// for merging if `epoch_column` says the logger added it; otherwise `epoch` is a field like any
+/// other.
+pub(crate) fn apply(
+    row: &mut Row,
+    renames: Option<&HashMap<String, String>>,
+    case: HeaderCase,
+    epoch_column: bool,
+) {
+    for column in row.header.iter_mut() {
+        if column == SEQUENCE_COLUMN || (epoch_column && column == EPOCH_COLUMN) {
+            continue;
+        }
+        match renames.and_then(|renames| renames.get(column.as_str())) {
+            Some(renamed) => *column = renamed.clone(),
+            None if case != HeaderCase::AsIs => *column = case.apply(column),
+            None => (),
+        }
+    }
+}
+
+/// Reverses [`apply`] on a header read back
+///
+/// The epoch column needs no exception: no case changes `epoch` back.
+pub(crate) fn reverse(column: &str, renamed: &HashMap<String, String>, case: HeaderCase) -> String {
+    match renamed.get(column) {
+        Some(original) => original.clone(),
+        None if column == SEQUENCE_COLUMN => column.to_string(),
+        None => case.reverse(column),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    #[test]
+    fn test_case() {
+        for (case, cased) in [
+            (HeaderCase::AsIs, "user_id"),
+            (HeaderCase::Camel, "userId"),
+            (HeaderCase::Pascal, "UserId"),
+            (HeaderCase::ScreamingSnake, "USER_ID"),
+        ] {
+            assert_eq!(case.apply("user_id"), cased);
+            assert_eq!(case.reverse(cased), "user_id");
+        }
+        assert_eq!(HeaderCase::Camel.apply("n"), "n");
+        assert_eq!(HeaderCase::Pascal.apply("n"), "N");
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut row = Row {
+            table: Cow::Borrowed("test"),
+            header: ["seq", "user_id", "ts"].map(String::from).to_vec().into(),
+            fields: ["0", "a_b", "1"].map(String::from).to_vec(),
+        };
+        let renames = HashMap::from([("ts".to_string(), "Time".to_string())]);
+        apply(&mut row, Some(&renames), HeaderCase::Camel, false);
+        assert_eq!(*row.header, ["seq", "userId", "Time"]);
+        assert_eq!(row.fields, ["0", "a_b", "1"]);
+
+        let renamed = HashMap::from([("Time".to_string(), "ts".to_string())]);
+        let original = row
+            .header
+            .iter()
+            .map(|column| reverse(column, &renamed, HeaderCase::Camel))
+            .collect::<Vec<_>>();
+        assert_eq!(original, ["seq", "user_id", "ts"]);
+
+        // Only the epoch column of the logger keeps its name
+        let epoch_row = |column: &str| Row {
+            table: Cow::Borrowed("test"),
+            header: vec![column.to_string()].into(),
+            fields: vec!["0".to_string()],
+        };
+        for (epoch_column, cased) in [(false, "EPOCH"), (true, "epoch")] {
+            let mut row = epoch_row("epoch");
+            apply(&mut row, None, HeaderCase::ScreamingSnake, epoch_column);
+            assert_eq!(*row.header, [cased]);
+            let original = reverse(cased, &HashMap::new(), HeaderCase::ScreamingSnake);
+            assert_eq!(original, "epoch");
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    borrow::Cow,
+    io,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+};
+
+use crate::{
+    backoff::Backoff,
+    checksum,
+    error::{self, CsvLoggerError, ErrorHandler},
+    storage::{self, Backend},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotatedFileDisposition {
+    Keep,
+    Delete,
+}
+
+/// Called from a background thread with each epoch file that has been rotated out
+pub trait RotatedFileHandler: Send + Sync {
+    fn handle(&self, table: &str, epoch: usize, path: &Path) -> io::Result<RotatedFileDisposition>;
+}
+
+struct RotatedFile {
+    table: Cow<'static, str>,
+    epoch: usize,
+    path: PathBuf,
+}
+
+pub(crate) struct RotatedFileWorker {
+    tx: mpsc::Sender<RotatedFile>,
+}
+impl RotatedFileWorker {
+    /// Handlers run in order until one of them asks for the file to be deleted from `storage`
+    pub fn spawn(
+        handlers: Vec<Arc<dyn RotatedFileHandler>>,
+        backoff: Backoff,
+        error_handler: ErrorHandler,
+        storage: Backend,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel::<RotatedFile>();
+        std::thread::Builder::new()
+            .name("CsvLogger::rotated_files()".to_string())
+            .spawn(move || {
+                for file in rx {
+                    handle_rotated_file(&handlers, &backoff, &error_handler, &storage, file);
+                }
+            })
+            .expect("Failed to spawn the rotated file worker thread");
+        Self { tx }
+    }
+
+    pub fn send(&self, table: Cow<'static, str>, epoch: usize, path: PathBuf) {
+        // The worker only stops after all senders are dropped
+        let _ = self.tx.send(RotatedFile { table, epoch, path });
+    }
+}
+
+fn handle_rotated_file(
+    handlers: &[Arc<dyn RotatedFileHandler>],
+    backoff: &Backoff,
+    error_handler: &ErrorHandler,
+    storage: &Backend,
+    file: RotatedFile,
+) {
+    let disposition = backoff.retry(|| {
+        // Retention might have deleted the file already
+        if !storage.exists(&file.path) {
+            return Ok(RotatedFileDisposition::Keep);
+        }
+        for handler in handlers {
+            if handler.handle(&file.table, file.epoch, &file.path)?
+                == RotatedFileDisposition::Delete
+            {
+                return Ok(RotatedFileDisposition::Delete);
+            }
+        }
+        Ok(RotatedFileDisposition::Keep)
+    });
+    let res = match disposition {
+        Ok(RotatedFileDisposition::Keep) => Ok(()),
+        Ok(RotatedFileDisposition::Delete) => storage::remove_if_exists(storage, &file.path)
+            .and_then(|_| storage::remove_if_exists(storage, &checksum::sidecar_path(&file.path)))
+            .map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = res {
+        error::report(
+            error_handler,
+            CsvLoggerError::RotatedFile {
+                table: file.table,
+                epoch: file.epoch,
+                source: e,
+            },
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+use csv::ByteRecord;
+use serde::Serialize;
+use table_log::SerWrap;
+
+use crate::{flatten, map, ser::BytesEncoding, CsvLoggerError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub table: Cow<'static, str>,
+    pub header: Header,
+    pub fields: Vec<String>,
+}
+
+/// The columns of a row
+///
+/// Rows serialized from records of the same type share their header, so that comparing them is
+/// mostly comparing pointers. Changing a shared header copies it first.
+#[derive(Clone, Default)]
+pub struct Header(Arc<Vec<String>>);
+impl Header {
+    /// Whether both are the same header rather than equal ones
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl From<Vec<String>> for Header {
+    fn from(columns: Vec<String>) -> Self {
+        Self(Arc::new(columns))
+    }
+}
+impl Deref for Header {
+    type Target = Vec<String>;
+
+    fn deref(&self) -> &Vec<String> {
+        &self.0
+    }
+}
+impl DerefMut for Header {
+    fn deref_mut(&mut self) -> &mut Vec<String> {
+        Arc::make_mut(&mut self.0)
+    }
+}
+impl PartialEq for Header {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || self.0 == other.0
+    }
+}
+impl Eq for Header {}
+impl fmt::Debug for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Bounds the header cache of threads logging to many tables named at runtime
+const MAX_CACHED_HEADERS: usize = 1024;
+
+thread_local! {
+    /// The header line last serialized per table, and the header parsed from it
+    static HEADERS: RefCell<HashMap<Cow<'static, str>, (ByteRecord, Header)>> =
+        RefCell::new(HashMap::new());
+}
+
+/// The header of `line`, parsed only if the table's last header line was another
+fn cached_header(table: &Cow<'static, str>, line: ByteRecord) -> Header {
+    HEADERS.with_borrow_mut(|headers| {
+        if let Some((cached, header)) = headers.get(table.as_ref()) {
+            if *cached == line {
+                return header.clone();
+            }
+        }
+        let header = Header::from(line.iter().map(utf8).collect::<Vec<_>>());
+        if MAX_CACHED_HEADERS <= headers.len() && !headers.contains_key(table.as_ref()) {
+            let evicted = headers.keys().next().cloned();
+            if let Some(evicted) = evicted {
+                headers.remove(&evicted);
+            }
+        }
+        headers.insert(table.clone(), (line, header.clone()));
+        header
+    })
+}
+
+/// Serialized fields are UTF-8 as written from Rust strings
+fn utf8(field: &[u8]) -> String {
+    String::from_utf8_lossy(field).into_owned()
+}
+impl Row {
+    pub(crate) fn serialize(record: &dyn table_log::LogRecord) -> Result<Self, csv::Error> {
+        Self::serialize_as(Cow::Borrowed(record.table_name()), &SerWrap(record))
+    }
+
+    pub(crate) fn serialize_as(
+        table: Cow<'static, str>,
+        record: &(impl Serialize + ?Sized),
+    ) -> Result<Self, csv::Error> {
+        let mut buf = vec![];
+        let mut writer = csv::Writer::from_writer(&mut buf);
+        writer.serialize(record)?;
+        writer.flush()?;
+        drop(writer);
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(buf.as_slice());
+        let mut first = ByteRecord::new();
+        let mut second = ByteRecord::new();
+        let lines = (
+            reader.read_byte_record(&mut first)?,
+            reader.read_byte_record(&mut second)?,
+        );
+        // Records without field names produce no header line
+        let (header, fields) = match lines {
+            (true, true) => (cached_header(&table, first), second),
+            (true, false) => (Header::default(), first),
+            _ => (Header::default(), ByteRecord::new()),
+        };
+        Ok(Self {
+            table,
+            header,
+            fields: fields.iter().map(utf8).collect(),
+        })
+    }
+
+    /// Moves the columns in `order` to the front; returns the listed columns the row lacks
+    ///
+    /// Rows without field names are left as they are.
+    pub(crate) fn reorder(&mut self, order: &[String]) -> Vec<String> {
+        if self.header.is_empty() {
+            return vec![];
+        }
+        let mut indices = Vec::with_capacity(self.header.len());
+        let mut missing = vec![];
+        for column in order {
+            match self.header.iter().position(|h| h == column) {
+                Some(i) if !indices.contains(&i) => indices.push(i),
+                Some(_) => (),
+                None => missing.push(column.clone()),
+            }
+        }
+        for i in 0..self.header.len() {
+            if !indices.contains(&i) {
+                indices.push(i);
+            }
+        }
+        self.header = Header::from(
+            indices
+                .iter()
+                .map(|&i| self.header[i].clone())
+                .collect::<Vec<_>>(),
+        );
+        self.fields = indices
+            .iter()
+            .filter_map(|&i| self.fields.get(i).cloned())
+            .collect();
+        missing
+    }
+
+    /// Cuts fields longer than `max_bytes` at a character boundary and appends `marker` with `{n}`
+    /// replaced by the number of bytes cut; returns the number of fields truncated
+    pub(crate) fn truncate_fields(&mut self, max_bytes: usize, marker: &str) -> usize {
+        let mut truncated = 0;
+        for field in &mut self.fields {
+            if field.len() <= max_bytes {
+                continue;
+            }
+            let mut end = max_bytes;
+            while !field.is_char_boundary(end) {
+                end -= 1;
+            }
+            let cut = field.len() - end;
+            field.truncate(end);
+            field.push_str(&marker.replace("{n}", &cut.to_string()));
+            truncated += 1;
+        }
+        truncated
+    }
+}
+
+/// How records are serialized into rows
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RowFormat {
+    pub flatten_depth: Option<usize>,
+    pub bytes_encoding: Option<BytesEncoding>,
+}
+impl RowFormat {
+    /// Returns the row and whether the record is a map, whose columns are sorted
+    pub fn serialize(
+        self,
+        table: Cow<'static, str>,
+        record: &(impl Serialize + ?Sized),
+    ) -> Result<(Row, bool), SerializeError> {
+        let is_map = map::is_map(record);
+        // `csv` can neither serialize maps nor encode bytes
+        let serialized = if self.flatten_depth.is_some() || is_map || self.bytes_encoding.is_some()
+        {
+            let max_depth = self.flatten_depth.unwrap_or_default();
+            flatten::flatten(table.clone(), record, max_depth, self.bytes_encoding)
+                .map_err(|e| e.to_string())
+        } else {
+            Row::serialize_as(table.clone(), record).map_err(|e| e.to_string())
+        };
+        let mut row = serialized.map_err(|message| SerializeError { table, message })?;
+        if is_map {
+            map::sort(&mut row);
+        }
+        Ok((row, is_map))
+    }
+}
+
+/// A record that failed to serialize, e.g. one with a sequence to flatten
+#[derive(Debug)]
+pub(crate) struct SerializeError {
+    pub table: Cow<'static, str>,
+    pub message: String,
+}
+impl From<SerializeError> for CsvLoggerError {
+    fn from(e: SerializeError) -> Self {
+        CsvLoggerError::Serialize {
+            table: e.table,
+            message: e.message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct TestRecord {
+        pub s: &'static str,
+        pub n: usize,
+    }
+
+    #[test]
+    fn test_shared_header() {
+        let serialize = |table: &'static str, n| {
+            Row::serialize_as(Cow::Borrowed(table), &TestRecord { s: "a", n }).unwrap()
+        };
+        let (a, b) = (serialize("test", 0), serialize("test", 1));
+        assert!(a.header.ptr_eq(&b.header));
+        assert_eq!(b.fields, ["a", "1"]);
+
+        // Equal but parsed on their own
+        let other = serialize("other", 2);
+        assert!(!other.header.ptr_eq(&a.header));
+        assert_eq!(other.header, a.header);
+
+        // Changed by copying
+        let mut changed = serialize("test", 3);
+        changed.header.push("seq".to_string());
+        assert_eq!(*a.header, ["s", "n"]);
+        assert_eq!(*changed.header, ["s", "n", "seq"]);
+    }
+
+    #[test]
+    fn test_header_cache_bounded() {
+        for n in 0..MAX_CACHED_HEADERS + 10 {
+            let table = Cow::Owned(format!("tenant-{n}"));
+            Row::serialize_as(table, &TestRecord { s: "a", n }).unwrap();
+        }
+        assert_eq!(HEADERS.with_borrow(HashMap::len), MAX_CACHED_HEADERS);
+    }
+}
@@ -0,0 +1,33 @@
+use std::time::SystemTime;
+
+/// SplitMix64; cheap and good enough for sampling decisions
+#[derive(Clone)]
+pub(crate) struct Sampler {
+    state: u64,
+}
+impl Sampler {
+    pub fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| {
+            let nanos = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as u64;
+            nanos ^ u64::from(std::process::id()).rotate_left(32)
+        });
+        Self { state: seed }
+    }
+
+    /// Whether to keep a record of a table sampled at `rate`
+    pub fn keep(&mut self, rate: f64) -> bool {
+        let x = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        x < rate
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
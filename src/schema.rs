@@ -0,0 +1,125 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use csv::StringRecord;
+
+use crate::{
+    manifest,
+    storage::{self, RealFs, Storage},
+    table_dir::TableFiles,
+};
+
+/// Sidecar in a table directory recording how the table's fields are encoded
+pub(crate) fn schema_path(
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> PathBuf {
+    files.table_file(output_dir, table_name, "schema")
+}
+
+pub(crate) fn write_schema(
+    storage: &impl Storage,
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+    single_line: bool,
+) -> io::Result<()> {
+    let path = schema_path(files, output_dir, table_name);
+    if single_line {
+        storage.replace(&path, b"single_line_fields=true\n")
+    } else {
+        storage::remove_if_exists(storage, &path).map(|_| ())
+    }
+}
+
+/// Whether the fields of a table have newlines escaped
+pub(crate) fn single_line_fields(
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> bool {
+    std::fs::read_to_string(schema_path(files, output_dir, table_name))
+        .is_ok_and(|schema| schema.lines().any(|line| line == "single_line_fields=true"))
+}
+
+/// Whether the fields of each epoch of a table have newlines escaped
+///
+/// As recorded per epoch in the manifest; epochs it says nothing about follow the schema file.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SingleLine {
+    table: bool,
+    epochs: HashMap<usize, bool>,
+}
+impl SingleLine {
+    pub fn read(output_dir: impl AsRef<Path>, table_name: &str) -> Self {
+        let output_dir = output_dir.as_ref();
+        let files = TableFiles::of(output_dir);
+        let epochs = manifest::load(&RealFs, &files, output_dir, table_name)
+            .map(|manifest| {
+                manifest
+                    .epochs
+                    .iter()
+                    .filter_map(|e| Some((e.epoch, e.single_line_fields?)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            table: single_line_fields(&files, output_dir, table_name),
+            epochs,
+        }
+    }
+
+    pub fn of(&self, epoch: usize) -> bool {
+        self.epochs.get(&epoch).copied().unwrap_or(self.table)
+    }
+}
+
+/// Replaces `\`, `\n` and `\r` with `\\`, `\n` and `\r` escape sequences
+pub(crate) fn escape(field: &mut String) {
+    if !field.contains(['\\', '\n', '\r']) {
+        return;
+    }
+    let mut escaped = String::with_capacity(field.len() + 2);
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    *field = escaped;
+}
+
+pub(crate) fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(c) => unescaped.push(c),
+            // Cut by truncation
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Reverses [`escape`] on every field if `single_line`
+pub(crate) fn decode(single_line: bool, record: StringRecord) -> StringRecord {
+    if !single_line || !record.iter().any(|field| field.contains('\\')) {
+        return record;
+    }
+    let mut decoded = record.iter().map(unescape).collect::<StringRecord>();
+    decoded.set_position(record.position().cloned());
+    decoded
+}
@@ -0,0 +1,144 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{reader, storage::Storage, table_dir::TableFiles};
+
+pub(crate) const SEQUENCE_COLUMN: &str = "seq";
+
+fn sequence_file_path(
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> PathBuf {
+    files.table_file(output_dir, table_name, "seq")
+}
+
+/// The sequence number to continue from after a restart
+///
+/// Rows that reached the disk after the last persisted value are taken into account so that the
+/// sequence never goes backwards.
+pub(crate) fn next_sequence(
+    storage: &impl Storage,
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> u64 {
+    let output_dir = output_dir.as_ref();
+    let persisted = storage
+        .read(&sequence_file_path(files, output_dir, table_name))
+        .ok()
+        .and_then(|seq| String::from_utf8(seq).ok()?.trim().parse().ok())
+        .unwrap_or_default();
+    let on_disk = last_sequence(storage, files, output_dir, table_name)
+        .map(|seq| seq + 1)
+        .unwrap_or_default();
+    persisted.max(on_disk)
+}
+
+fn last_sequence(
+    storage: &impl Storage,
+    files: &TableFiles,
+    output_dir: &Path,
+    table_name: &str,
+) -> Option<u64> {
+    for file in reader::stored_epoch_files(storage, files, output_dir, table_name)
+        .ok()?
+        .iter()
+        .rev()
+    {
+        let Ok(Some(mut reader)) = reader::read_epoch(storage, &file.path) else {
+            continue;
+        };
+        let Some(column) = reader
+            .headers()
+            .ok()
+            .and_then(|h| h.iter().position(|h| h == SEQUENCE_COLUMN))
+        else {
+            continue;
+        };
+        let last = reader.records().map_while(Result::ok).last();
+        if let Some(seq) = last.and_then(|r| r.get(column)?.parse().ok()) {
+            return Some(seq);
+        }
+    }
+    None
+}
+
+pub(crate) fn write_sequence(
+    storage: &impl Storage,
+    files: &TableFiles,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+    next: u64,
+) -> io::Result<()> {
+    let path = sequence_file_path(files, output_dir, table_name);
+    storage.replace(&path, next.to_string().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use table_log::Logger;
+
+    use crate::{reader::TableReader, CsvLoggerBuilder, RotationPolicy};
+
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct TestRecord<'caller> {
+        pub s: &'caller str,
+        pub n: usize,
+    }
+    impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+        fn table_name(&self) -> &'static str {
+            "test"
+        }
+    }
+
+    #[test]
+    fn test_sequence() {
+        let dir = tempfile::tempdir().unwrap();
+        let build = || {
+            CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(2).unwrap(),
+                    max_epochs: 10,
+                },
+            )
+            .sequence(true)
+            .build()
+        };
+        let mut logger = build();
+        for n in 0..5 {
+            logger.log(&TestRecord { s: "a", n });
+        }
+        logger.flush();
+        drop(logger);
+        // Dropped without a flush so the persisted value lags behind the rows on disk
+        let mut logger = build();
+        for n in 5..8 {
+            logger.log(&TestRecord { s: "b", n });
+        }
+        drop(logger);
+        let mut logger = build();
+        logger.log(&TestRecord { s: "c", n: 8 });
+        logger.flush();
+
+        let reader = TableReader::open(dir.path(), None, "test").unwrap();
+        assert_eq!(
+            reader.header().unwrap().unwrap().get(0),
+            Some(SEQUENCE_COLUMN)
+        );
+        let seqs = reader
+            .records()
+            .map(|r| r.unwrap()[0].parse::<u64>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(seqs.len(), 9);
+        assert!(seqs.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(seqs[..5], [0, 1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,320 @@
+//! Serde helpers for fields that would not fit in a single cell otherwise
+//!
+//! Use them with `#[serde(with = "csv_logger::ser::duration_millis")]` and the like. The
+//! deserializers read back what the serializers write, e.g. through
+//! [`TableReader::deserialize`](crate::reader::TableReader::deserialize).
+
+/// [`Duration`](std::time::Duration) as whole milliseconds
+pub mod duration_millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(u64::try_from(value.as_millis()).unwrap_or(u64::MAX))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+/// [`Duration`](std::time::Duration) as fractional seconds
+pub mod duration_secs_f64 {
+    use std::time::Duration;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(value.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(secs).map_err(D::Error::custom)
+    }
+}
+
+/// [`SystemTime`](std::time::SystemTime) as an RFC 3339 timestamp in UTC
+///
+/// Sub-second digits are written as needed, so the value reads back unchanged.
+pub mod systemtime_rfc3339 {
+    use std::time::SystemTime;
+
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        let time = DateTime::<Utc>::from(*value);
+        serializer.serialize_str(&time.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let time = DateTime::parse_from_rfc3339(&s).map_err(D::Error::custom)?;
+        Ok(time.with_timezone(&Utc).into())
+    }
+}
+
+/// [`SystemTime`](std::time::SystemTime) as milliseconds since the Unix epoch
+pub mod systemtime_unix_millis {
+    use std::time::SystemTime;
+
+    use chrono::{DateTime, Utc};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(DateTime::<Utc>::from(*value).timestamp_millis())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let millis = i64::deserialize(deserializer)?;
+        let time = DateTime::<Utc>::from_timestamp_millis(millis)
+            .ok_or_else(|| D::Error::custom("timestamp out of range"))?;
+        Ok(time.into())
+    }
+}
+
+/// How byte strings are written as text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BytesEncoding {
+    /// Lowercase hex
+    Hex,
+    /// Standard base64 with padding
+    Base64,
+}
+impl BytesEncoding {
+    pub(crate) fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            BytesEncoding::Hex => bytes.iter().map(|b| format!("{b:02x}")).collect(),
+            BytesEncoding::Base64 => {
+                use base64::Engine;
+
+                base64::engine::general_purpose::STANDARD.encode(bytes)
+            }
+        }
+    }
+
+    pub(crate) fn decode(self, s: &str) -> Result<Vec<u8>, String> {
+        match self {
+            BytesEncoding::Hex => {
+                if s.len() % 2 != 0 {
+                    return Err(format!("Odd number of hex digits in `{s}`"));
+                }
+                (0..s.len())
+                    .step_by(2)
+                    .map(|i| {
+                        s.get(i..i + 2)
+                            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                            .ok_or_else(|| format!("Invalid hex `{s}`"))
+                    })
+                    .collect()
+            }
+            BytesEncoding::Base64 => {
+                use base64::Engine;
+
+                base64::engine::general_purpose::STANDARD
+                    .decode(s)
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+macro_rules! bytes_module {
+    ($(#[$doc:meta])* $module:ident, $encoding:expr) => {
+        $(#[$doc])*
+        ///
+        /// Works with any `AsRef<[u8]>` such as `Vec<u8>` and `[u8; N]`.
+        pub mod $module {
+            use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+            use super::BytesEncoding;
+
+            pub fn serialize<T: AsRef<[u8]>, S: Serializer>(
+                value: &T,
+                serializer: S,
+            ) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&$encoding.encode(value.as_ref()))
+            }
+
+            pub fn deserialize<'de, T: TryFrom<Vec<u8>>, D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<T, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                let bytes = $encoding.decode(&s).map_err(D::Error::custom)?;
+                let len = bytes.len();
+                T::try_from(bytes).map_err(|_| D::Error::custom(format!("Unexpected {len} bytes")))
+            }
+        }
+    };
+}
+
+bytes_module! {
+    /// Bytes as lowercase hex
+    bytes_hex, BytesEncoding::Hex
+}
+
+bytes_module! {
+    /// Bytes as standard base64 with padding
+    bytes_base64, BytesEncoding::Base64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroUsize,
+        time::{Duration, SystemTime},
+    };
+
+    use table_log::Logger;
+
+    use crate::{log_file_path, reader::TableReader, CsvLogger, CsvLoggerBuilder, RotationPolicy};
+
+    use super::BytesEncoding;
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct TimingRecord {
+        #[serde(with = "super::duration_millis")]
+        elapsed_ms: Duration,
+        #[serde(with = "super::duration_secs_f64")]
+        elapsed_s: Duration,
+        #[serde(with = "super::systemtime_rfc3339")]
+        at: SystemTime,
+        #[serde(with = "super::systemtime_unix_millis")]
+        at_ms: SystemTime,
+    }
+    impl table_log::LogRecord<'_> for TimingRecord {
+        fn table_name(&self) -> &'static str {
+            "timing"
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLogger::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        );
+        let at = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let record = TimingRecord {
+            elapsed_ms: Duration::from_millis(1500),
+            elapsed_s: Duration::from_millis(1500),
+            at,
+            at_ms: at,
+        };
+        logger.log(&record);
+        logger.flush();
+
+        assert_eq!(
+            std::fs::read_to_string(log_file_path(dir.path(), "timing", 0)).unwrap(),
+            "elapsed_ms,elapsed_s,at,at_ms\n1500,1.5,2023-11-14T22:13:20.123Z,1700000000123\n"
+        );
+        let read = TableReader::open(dir.path(), None, "timing")
+            .unwrap()
+            .deserialize::<TimingRecord>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read, [record]);
+    }
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct DigestRecord {
+        #[serde(with = "super::bytes_hex")]
+        hex: [u8; 32],
+        #[serde(with = "super::bytes_base64")]
+        base64: [u8; 32],
+    }
+    impl table_log::LogRecord<'_> for DigestRecord {
+        fn table_name(&self) -> &'static str {
+            "digest"
+        }
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = CsvLogger::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        );
+        let digest = std::array::from_fn(|i| i as u8 * 8);
+        let record = DigestRecord {
+            hex: digest,
+            base64: digest,
+        };
+        logger.log(&record);
+        logger.flush();
+
+        let csv = std::fs::read_to_string(log_file_path(dir.path(), "digest", 0)).unwrap();
+        let row = csv.lines().nth(1).unwrap();
+        let (hex, base64) = row.split_once(',').unwrap();
+        assert!(hex.starts_with("0008101820"));
+        assert_eq!(hex.len(), 64);
+        assert!(base64.starts_with("AAgQGCAo"));
+        assert_eq!(base64.len(), 44);
+        let read = TableReader::open(dir.path(), None, "digest")
+            .unwrap()
+            .deserialize::<DigestRecord>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read, [record]);
+    }
+
+    #[test]
+    fn test_bytes_encoding() {
+        struct Raw(&'static [u8]);
+        impl serde::Serialize for Raw {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_bytes(self.0)
+            }
+        }
+
+        #[derive(serde::Serialize)]
+        struct RawRecord {
+            id: Raw,
+        }
+        impl table_log::LogRecord<'_> for RawRecord {
+            fn table_name(&self) -> &'static str {
+                "raw"
+            }
+        }
+
+        for (encoding, expected) in [
+            (BytesEncoding::Hex, "id\n00ff10\n"),
+            (BytesEncoding::Base64, "id\nAP8Q\n"),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut logger = CsvLoggerBuilder::new(
+                dir.path().to_owned(),
+                RotationPolicy {
+                    max_records: NonZeroUsize::new(10).unwrap(),
+                    max_epochs: 2,
+                },
+            )
+            .bytes_encoding(encoding)
+            .build();
+            logger.log(&RawRecord {
+                id: Raw(&[0x00, 0xff, 0x10]),
+            });
+            logger.flush();
+            assert_eq!(
+                std::fs::read_to_string(log_file_path(dir.path(), "raw", 0)).unwrap(),
+                expected
+            );
+            assert_eq!(
+                encoding
+                    .decode(&encoding.encode(&[0x00, 0xff, 0x10]))
+                    .unwrap(),
+                [0x00, 0xff, 0x10]
+            );
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    borrow::Cow,
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use crate::{
+    flusher::{FlusherGuard, FlusherHandle, FlusherThread},
+    raw::TableHandle,
+    row::RowFormat,
+    sink, stats,
+    transaction::Transaction,
+    CsvLogger, CsvLoggerError, LoggerStats,
+};
+
+/// The file logger registered globally, if any
+static REGISTERED: Mutex<Weak<Mutex<CsvLogger>>> = Mutex::new(Weak::new());
+
+/// Logs `record` to the table `table` of the registered logger
+///
+/// Unlike [`table_log::log!`], the table name is not bound to the record type. A registered logger
+/// writing to a [`crate::OutputTarget`] other than files gets the row through its sink.
+pub fn log_to(table: &str, record: &impl serde::Serialize) {
+    if with_registered(|logger| logger.log_to(table, record)).is_none() && sink::is_registered() {
+        match RowFormat::default().serialize(Cow::Owned(table.to_string()), record) {
+            Ok((row, _)) => {
+                sink::write_registered([row], false);
+            }
+            Err(e) => {
+                stats::count_dropped(1);
+                sink::report_registered(e.into());
+            }
+        }
+    }
+}
+
+/// Logs `record` and flushes its table before returning
+///
+/// Loggers that do not write to files are flushed as a whole. See
+/// [`CsvLogger::log_durable`].
+pub fn log_durable<'caller>(record: &impl table_log::LogRecord<'caller>) {
+    if with_registered(|logger| logger.log_durable(record)).is_none() {
+        table_log::log!(record);
+        table_log::flush();
+    }
+}
+
+/// The output directory of the registered logger, unless it does not write to files
+pub fn output_dir() -> Option<PathBuf> {
+    with_registered(|logger| logger.output_dir.clone())
+}
+
+/// The registered file logger, if any
+pub(crate) fn registered() -> Option<Arc<Mutex<CsvLogger>>> {
+    REGISTERED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .upgrade()
+}
+
+/// Runs `f` on the registered file logger, if any
+pub(crate) fn with_registered<T>(f: impl FnOnce(&mut CsvLogger) -> T) -> Option<T> {
+    let logger = REGISTERED.lock().unwrap().upgrade()?;
+    Some(CsvLoggerHandle { inner: logger }.with(f))
+}
+
+/// A [`CsvLogger`] shared between threads, usable without registering it globally
+///
+/// Clones log to the same files. Observers are notified once the logger is unlocked.
+#[derive(Clone)]
+pub struct CsvLoggerHandle {
+    pub(crate) inner: Arc<Mutex<CsvLogger>>,
+}
+impl CsvLoggerHandle {
+    pub fn new(mut logger: CsvLogger) -> Self {
+        logger.events.defer();
+        Self {
+            inner: Arc::new(Mutex::new(logger)),
+        }
+    }
+
+    pub fn log<'caller>(&self, record: &impl table_log::LogRecord<'caller>) {
+        self.with(|logger| logger.log_record(record));
+    }
+
+    /// See [`CsvLogger::log_to`]
+    pub fn log_to(&self, table: &str, record: &impl serde::Serialize) {
+        self.with(|logger| logger.log_to(table, record));
+    }
+
+    pub fn flush(&self) {
+        self.with(|logger| logger.flush());
+    }
+
+    /// See [`CsvLogger::rotate_table`]
+    pub fn rotate_table(&self, table: &str) {
+        self.with(|logger| logger.rotate_table(table));
+    }
+
+    /// Like [`crate::transaction`] but on this logger
+    pub fn transaction<T>(&self, f: impl FnOnce(&mut Transaction) -> T) -> T {
+        let format = self.inner.lock().unwrap().row_format();
+        let (out, tx) = Transaction::run(Some(format), f);
+        self.with(|logger| tx.commit(logger));
+        out
+    }
+
+    /// Like [`crate::raw_table`] but on this logger
+    pub fn raw_table(&self, table: &str, header: &[&str]) -> Result<TableHandle, CsvLoggerError> {
+        self.with(|logger| logger.register_raw(table, header))?;
+        Ok(TableHandle::new(Arc::downgrade(&self.inner), table, header))
+    }
+
+    pub fn stats(&self) -> LoggerStats {
+        self.inner.lock().unwrap().stats()
+    }
+
+    /// Runs `f` on the logger and then notifies its observers of the events of `f`
+    pub(crate) fn with<T>(&self, f: impl FnOnce(&mut CsvLogger) -> T) -> T {
+        let (out, events) = {
+            let mut logger = self.inner.lock().unwrap();
+            let out = f(&mut logger);
+            (out, logger.events.take())
+        };
+        events.notify();
+        out
+    }
+
+    /// Lets [`log_to`] and the other free functions reach this logger
+    pub(crate) fn register(&self) {
+        *REGISTERED.lock().unwrap() = Arc::downgrade(&self.inner);
+    }
+}
+impl table_log::Logger for CsvLoggerHandle {
+    fn log(&mut self, record: &dyn table_log::LogRecord) {
+        self.with(|logger| logger.log_record(record));
+    }
+
+    fn flush(&mut self) {
+        CsvLoggerHandle::flush(self);
+    }
+}
+
+/// Flushes `handle` every `interval` on a background thread until the guard is dropped, failing
+/// if the thread cannot be spawned
+pub fn spawn_flusher(handle: CsvLoggerHandle, interval: Duration) -> io::Result<FlusherGuard> {
+    let flusher = FlusherHandle::spawn(FlusherThread::default(), interval, {
+        let handle = handle.clone();
+        move || handle.flush()
+    })?;
+    Ok(FlusherGuard::new(flusher, handle))
+}
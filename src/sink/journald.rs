@@ -0,0 +1,132 @@
+use std::{io, path::Path};
+
+use crate::row::Row;
+
+use super::{write_line, RecordSink};
+
+pub(crate) const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const MAX_FIELD_NAME_LEN: usize = 64;
+
+/// Sends each row as a journal entry over the native journald protocol
+pub(crate) struct JournaldSink {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+}
+impl JournaldSink {
+    #[cfg(unix)]
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self { socket })
+    }
+
+    #[cfg(not(unix))]
+    pub fn new(_path: impl AsRef<Path>) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "journald is not supported on this platform",
+        ))
+    }
+}
+impl RecordSink for JournaldSink {
+    #[cfg(unix)]
+    fn write_row(&mut self, row: &Row) -> io::Result<()> {
+        self.socket.send(&encode(row)?)?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn write_row(&mut self, _row: &Row) -> io::Result<()> {
+        unreachable!()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn encode(row: &Row) -> io::Result<Vec<u8>> {
+    let mut message = vec![];
+    write_line(&mut message, &row.table, &row.fields)?;
+    message.pop();
+
+    let mut buf = vec![];
+    push_field(&mut buf, "MESSAGE", &message);
+    push_field(&mut buf, "PRIORITY", b"6");
+    push_field(&mut buf, "TABLE", row.table.as_bytes());
+    for (column, value) in row.header.iter().zip(&row.fields) {
+        push_field(&mut buf, &field_name(column), value.as_bytes());
+    }
+    Ok(buf)
+}
+
+fn push_field(buf: &mut Vec<u8>, name: &str, value: &[u8]) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value);
+    buf.push(b'\n');
+}
+
+/// Journal field names only contain `A-Z`, `0-9` and `_` and must not start with `_` or a digit
+fn field_name(column: &str) -> String {
+    let name = column
+        .chars()
+        .map(|c| match c.to_ascii_uppercase() {
+            c @ ('A'..='Z' | '0'..='9') => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    let name = name.trim_start_matches('_');
+    let mut name = match name.chars().next() {
+        Some(c) if !c.is_ascii_digit() => name.to_string(),
+        _ => format!("COL_{name}"),
+    };
+    name.truncate(MAX_FIELD_NAME_LEN);
+    name
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::os::unix::net::UnixDatagram;
+
+    use super::*;
+
+    #[test]
+    fn test_field_name() {
+        assert_eq!(field_name("conn.addr"), "CONN_ADDR");
+        assert_eq!(field_name("_private"), "PRIVATE");
+        assert_eq!(field_name("0th"), "COL_0TH");
+        assert_eq!(field_name(""), "COL_");
+        assert_eq!(field_name(&"a".repeat(100)).len(), MAX_FIELD_NAME_LEN);
+    }
+
+    #[test]
+    fn test_journald_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.sock");
+        let journal = UnixDatagram::bind(&path).unwrap();
+        let mut sink = JournaldSink::new(&path).unwrap();
+        sink.write_row(&Row {
+            table: "test".into(),
+            header: vec!["s".to_string(), "n".to_string()].into(),
+            fields: vec!["a\nb".to_string(), "0".to_string()],
+        })
+        .unwrap();
+
+        let mut buf = [0; 1024];
+        let n = journal.recv(&mut buf).unwrap();
+        let mut expected = vec![];
+        expected.extend_from_slice(b"MESSAGE\n");
+        expected.extend_from_slice(&12_u64.to_le_bytes());
+        expected.extend_from_slice(b"test,\"a\nb\",0\n");
+        expected.extend_from_slice(b"PRIORITY=6\nTABLE=test\nS\n");
+        expected.extend_from_slice(&3_u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\nN=0\n");
+        assert_eq!(&buf[..n], expected);
+    }
+}
@@ -0,0 +1,286 @@
+use std::{
+    borrow::Cow,
+    io,
+    sync::{Arc, Mutex, Weak},
+};
+
+use crate::{
+    error::{self, CsvLoggerError, ErrorHandler},
+    level,
+    row::Row,
+    stats,
+};
+
+#[cfg(feature = "journald")]
+pub(crate) mod journald;
+pub(crate) mod stream;
+#[cfg(feature = "syslog")]
+pub(crate) mod syslog;
+pub(crate) mod tcp;
+pub(crate) mod unix;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SinkFormat {
+    /// `table_name,<csv fields>`
+    #[default]
+    Csv,
+    /// `{"table":"table_name","column":"value",...}`
+    JsonLines,
+}
+
+pub trait RecordSink: Send {
+    fn write_row(&mut self, row: &Row) -> io::Result<()>;
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// The sink logger registered globally, if any
+static REGISTERED: Mutex<Option<Weak<Mutex<SinkState>>>> = Mutex::new(None);
+
+struct SinkState {
+    sink: Box<dyn RecordSink>,
+    error_handler: ErrorHandler,
+}
+impl SinkState {
+    fn write_row(&mut self, row: &Row) {
+        if let Err(e) = self.sink.write_row(row) {
+            error::report(&self.error_handler, CsvLoggerError::Sink { source: e });
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Err(e) = self.sink.flush() {
+            error::report(&self.error_handler, CsvLoggerError::Sink { source: e });
+        }
+    }
+}
+
+pub(crate) struct SinkLogger {
+    state: Arc<Mutex<SinkState>>,
+}
+impl SinkLogger {
+    pub fn new(sink: impl RecordSink + 'static, error_handler: ErrorHandler) -> Self {
+        let state = SinkState {
+            sink: Box::new(sink),
+            error_handler,
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Lets [`crate::log_to`] and [`crate::transaction`] reach the logger, to be registered
+    /// globally
+    pub fn register(self) -> Box<dyn table_log::Logger> {
+        *REGISTERED.lock().unwrap_or_else(|e| e.into_inner()) = Some(Arc::downgrade(&self.state));
+        Box::new(self)
+    }
+}
+impl table_log::Logger for SinkLogger {
+    fn log(&mut self, record: &dyn table_log::LogRecord) {
+        if !level::record_enabled(record.table_name()) {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        match Row::serialize(record) {
+            Ok(row) => state.write_row(&row),
+            Err(e) => {
+                stats::count_dropped(1);
+                let error = CsvLoggerError::Serialize {
+                    table: Cow::Borrowed(record.table_name()),
+                    message: e.to_string(),
+                };
+                error::report(&state.error_handler, error);
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        self.state.lock().unwrap().flush();
+    }
+}
+
+/// Writes `rows` to the sink of the registered sink logger, flushing it if `flush`; `false` if
+/// there is none
+pub(crate) fn write_registered(rows: impl IntoIterator<Item = Row>, flush: bool) -> bool {
+    let registered = REGISTERED.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let Some(state) = registered.and_then(|state| state.upgrade()) else {
+        return false;
+    };
+    let mut state = state.lock().unwrap();
+    for row in rows {
+        state.write_row(&row);
+    }
+    if flush {
+        state.flush();
+    }
+    true
+}
+
+/// Reports `error` to the error handler of the registered sink logger; `false` if there is none
+pub(crate) fn report_registered(error: CsvLoggerError) -> bool {
+    let registered = REGISTERED.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let Some(state) = registered.and_then(|state| state.upgrade()) else {
+        return false;
+    };
+    let handler = state.lock().unwrap().error_handler.clone();
+    error::report(&handler, error);
+    true
+}
+
+/// Whether a sink logger is registered globally
+pub(crate) fn is_registered() -> bool {
+    REGISTERED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .is_some_and(|state| state.strong_count() > 0)
+}
+
+pub(crate) fn write_line(
+    writer: &mut impl io::Write,
+    table: &str,
+    fields: &[String],
+) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(std::iter::once(table).chain(fields.iter().map(String::as_str)))?;
+    writer.flush()
+}
+
+pub(crate) fn write_json_line(writer: &mut impl io::Write, row: &Row) -> io::Result<()> {
+    let mut line = String::from("{\"table\":");
+    push_json_str(&mut line, &row.table);
+    for (i, field) in row.fields.iter().enumerate() {
+        line.push(',');
+        match row.header.get(i) {
+            Some(column) => push_json_str(&mut line, column),
+            None => push_json_str(&mut line, &i.to_string()),
+        }
+        line.push(':');
+        push_json_str(&mut line, field);
+    }
+    line.push_str("}\n");
+    writer.write_all(line.as_bytes())
+}
+
+fn push_json_str(buf: &mut String, s: &str) {
+    use std::fmt::Write;
+
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if c.is_control() => write!(buf, "\\u{:04x}", c as u32).unwrap(),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serial_test::serial;
+    use table_log::Logger;
+
+    use crate::{log_leveled, Level, Leveled};
+
+    use super::*;
+
+    /// Keeps the rows written to it
+    struct VecSink(Arc<Mutex<Vec<Row>>>);
+    impl RecordSink for VecSink {
+        fn write_row(&mut self, row: &Row) -> io::Result<()> {
+            self.0.lock().unwrap().push(row.clone());
+            Ok(())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(serde::Serialize)]
+    struct TaggedRecord {
+        tags: BTreeMap<&'static str, u32>,
+    }
+    impl table_log::LogRecord<'_> for TaggedRecord {
+        fn table_name(&self) -> &'static str {
+            "tagged"
+        }
+    }
+
+    #[derive(serde::Serialize)]
+    struct TestRecord {
+        n: usize,
+    }
+    impl table_log::LogRecord<'_> for TestRecord {
+        fn table_name(&self) -> &'static str {
+            "test"
+        }
+    }
+
+    impl Leveled for TestRecord {}
+
+    #[derive(serde::Serialize)]
+    struct DebugRecord {
+        n: usize,
+    }
+    impl table_log::LogRecord<'_> for DebugRecord {
+        fn table_name(&self) -> &'static str {
+            "test"
+        }
+    }
+    impl Leveled for DebugRecord {
+        fn level(&self) -> Level {
+            Level::Debug
+        }
+    }
+
+    fn sink_logger() -> (SinkLogger, Arc<Mutex<Vec<Row>>>, Arc<Mutex<Vec<String>>>) {
+        let rows = Arc::new(Mutex::new(vec![]));
+        let errors = Arc::new(Mutex::new(vec![]));
+        let error_handler: ErrorHandler = {
+            let errors = errors.clone();
+            Arc::new(move |e| errors.lock().unwrap().push(e.to_string()))
+        };
+        let logger = SinkLogger::new(VecSink(rows.clone()), error_handler);
+        (logger, rows, errors)
+    }
+
+    #[test]
+    fn test_report_unserializable() {
+        let (mut logger, rows, errors) = sink_logger();
+        let tags = BTreeMap::from([("a", 1)]);
+        logger.log(&TaggedRecord { tags });
+        logger.log(&TestRecord { n: 0 });
+
+        let rows = rows.lock().unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].table, "test");
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("`tagged`"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_levels() {
+        let (logger, rows, _) = sink_logger();
+        table_log::GLOBAL_LOG
+            .lock()
+            .unwrap()
+            .register(logger.register());
+        log_leveled(&DebugRecord { n: 0 });
+        log_leveled(&TestRecord { n: 1 });
+        table_log::GLOBAL_LOG.lock().unwrap().remove_logger();
+
+        let rows = rows.lock().unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].fields, ["1"]);
+    }
+}
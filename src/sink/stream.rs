@@ -0,0 +1,313 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    io::{self, BufWriter, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    row::{Header, Row},
+    stats,
+};
+
+use super::{write_json_line, write_line, RecordSink, SinkFormat};
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+pub(crate) trait Connector: Send {
+    type Stream: Write + Send;
+    fn connect(&self) -> io::Result<Self::Stream>;
+}
+
+/// Sends each row as a line prefixed by its table name over a reconnecting stream
+///
+/// In CSV format, the header of a table is sent before its first row on every connection and
+/// whenever it changes. Rows are kept until they are flushed to the stream, so the ones a broken
+/// stream lost are sent again after reconnecting. Rows the stream took before it broke are not
+/// sent again, even if the collector never got them.
+pub(crate) struct StreamSink<C: Connector> {
+    connector: C,
+    format: SinkFormat,
+    stream: Option<BufWriter<C::Stream>>,
+    next_connect: Instant,
+    /// The header last sent per table on this connection
+    announced: HashMap<Cow<'static, str>, Header>,
+    pending: VecDeque<Row>,
+    /// The leading rows of `pending` written to the stream but not flushed yet
+    sent: usize,
+    max_pending: usize,
+}
+impl<C: Connector> StreamSink<C> {
+    pub fn new(connector: C, format: SinkFormat, max_pending: usize) -> Self {
+        Self {
+            connector,
+            format,
+            stream: None,
+            next_connect: Instant::now(),
+            announced: HashMap::new(),
+            pending: VecDeque::new(),
+            sent: 0,
+            max_pending,
+        }
+    }
+
+    fn connect(&mut self) -> bool {
+        if self.stream.is_some() {
+            return true;
+        }
+        let now = Instant::now();
+        if now < self.next_connect {
+            return false;
+        }
+        match self.connector.connect() {
+            Ok(stream) => {
+                self.stream = Some(BufWriter::new(stream));
+                self.announced.clear();
+                true
+            }
+            Err(_) => {
+                self.next_connect = now + RECONNECT_INTERVAL;
+                false
+            }
+        }
+    }
+
+    /// Drops the stream; the rows not flushed to it are sent again on the next one
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.sent = 0;
+        self.next_connect = Instant::now() + RECONNECT_INTERVAL;
+    }
+
+    fn drain(&mut self) -> io::Result<()> {
+        while self.sent < self.pending.len() {
+            let mut lines = vec![];
+            encode(
+                &mut lines,
+                self.format,
+                &mut self.announced,
+                &self.pending[self.sent],
+            )?;
+            let stream = self.stream.as_mut().unwrap();
+            // Flushing here rather than letting the buffer flush the rows sent before on its own,
+            // which would leave them pending to be sent twice
+            if stream.capacity() < stream.buffer().len() + lines.len() {
+                stream.flush()?;
+                self.pending.drain(..self.sent);
+                self.sent = 0;
+            }
+            stream.write_all(&lines)?;
+            self.sent += 1;
+        }
+        Ok(())
+    }
+
+    /// Drains the pending rows and flushes them to the stream, forgetting them once flushed
+    fn drain_and_flush(&mut self) -> io::Result<()> {
+        self.drain()?;
+        self.stream.as_mut().unwrap().flush()?;
+        self.pending.drain(..self.sent);
+        self.sent = 0;
+        Ok(())
+    }
+}
+impl<C: Connector> RecordSink for StreamSink<C> {
+    fn write_row(&mut self, row: &Row) -> io::Result<()> {
+        if self.pending.len() == self.max_pending {
+            self.pending.pop_front();
+            // Rows written to the stream are only lost if it breaks before they are flushed
+            match self.sent {
+                0 => stats::count_dropped(1),
+                _ => self.sent -= 1,
+            }
+        }
+        self.pending.push_back(row.clone());
+        if !self.connect() {
+            return Ok(());
+        }
+        let res = self.drain();
+        if res.is_err() {
+            self.disconnect();
+        }
+        res
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.connect() {
+            return Ok(());
+        }
+        let res = self.drain_and_flush();
+        if res.is_err() {
+            self.disconnect();
+        }
+        res
+    }
+}
+
+/// Writes the line of `row`, preceded by its header if the table had none or another one
+fn encode(
+    stream: &mut impl Write,
+    format: SinkFormat,
+    announced: &mut HashMap<Cow<'static, str>, Header>,
+    row: &Row,
+) -> io::Result<()> {
+    match format {
+        SinkFormat::Csv => {
+            let known = announced.get(row.table.as_ref()) == Some(&row.header);
+            if !row.header.is_empty() && !known {
+                write_line(stream, &row.table, &row.header)?;
+                announced.insert(row.table.clone(), row.header.clone());
+            }
+            write_line(stream, &row.table, &row.fields)
+        }
+        SinkFormat::JsonLines => write_json_line(stream, row),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    };
+
+    use super::*;
+
+    /// Collects what is written until `broken` is set
+    #[derive(Clone, Default)]
+    struct Flaky {
+        received: Arc<Mutex<Vec<u8>>>,
+        broken: Arc<AtomicBool>,
+    }
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.broken.load(Ordering::SeqCst) {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            self.received.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    impl Connector for Flaky {
+        type Stream = Flaky;
+        fn connect(&self) -> io::Result<Flaky> {
+            Ok(self.clone())
+        }
+    }
+
+    #[test]
+    fn test_resend_after_reconnect() {
+        let stream = Flaky::default();
+        let mut sink = StreamSink::new(stream.clone(), SinkFormat::Csv, 16);
+        let row = |s: &str| Row {
+            table: Cow::Borrowed("test"),
+            header: vec!["s".to_string()].into(),
+            fields: vec![s.to_string()],
+        };
+        sink.write_row(&row("a")).unwrap();
+        sink.flush().unwrap();
+
+        // Buffered before the stream broke
+        stream.broken.store(true, Ordering::SeqCst);
+        sink.write_row(&row("b")).unwrap();
+        assert!(sink.flush().is_err());
+
+        stream.broken.store(false, Ordering::SeqCst);
+        sink.next_connect = Instant::now();
+        sink.write_row(&row("c")).unwrap();
+        sink.flush().unwrap();
+        assert_eq!(
+            received(&stream),
+            "test,s\ntest,a\ntest,s\ntest,b\ntest,c\n"
+        );
+    }
+
+    fn received(stream: &Flaky) -> String {
+        String::from_utf8(stream.received.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn test_header_change() {
+        let stream = Flaky::default();
+        let mut sink = StreamSink::new(stream.clone(), SinkFormat::Csv, 16);
+        let row = |header: &[&str], fields: &[&str]| Row {
+            table: Cow::Borrowed("test"),
+            header: header
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .into(),
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+        };
+        sink.write_row(&row(&["s"], &["a"])).unwrap();
+        sink.write_row(&row(&["s"], &["b"])).unwrap();
+        sink.write_row(&row(&["s", "n"], &["c", "0"])).unwrap();
+        sink.write_row(&row(&["s"], &["d"])).unwrap();
+        sink.flush().unwrap();
+        assert_eq!(
+            received(&stream),
+            "test,s\ntest,a\ntest,b\ntest,s,n\ntest,c,0\ntest,s\ntest,d\n"
+        );
+    }
+
+    #[test]
+    fn test_no_resend_of_flushed_rows() {
+        let stream = Flaky::default();
+        let mut sink = StreamSink::new(stream.clone(), SinkFormat::Csv, 10_000);
+        let row = |n: usize| Row {
+            table: Cow::Borrowed("test"),
+            header: Header::default(),
+            fields: vec![n.to_string()],
+        };
+        // More than the stream buffer holds, so most rows reach the stream before the flush
+        for n in 0..2000 {
+            sink.write_row(&row(n)).unwrap();
+        }
+        stream.broken.store(true, Ordering::SeqCst);
+        // Fails on writing or on flushing, depending on how full the buffer is
+        let _ = sink.write_row(&row(2000));
+        let _ = sink.flush();
+
+        stream.broken.store(false, Ordering::SeqCst);
+        sink.next_connect = Instant::now();
+        sink.flush().unwrap();
+        let expected = (0..=2000)
+            .map(|n| format!("test,{n}\n"))
+            .collect::<String>();
+        assert_eq!(received(&stream), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket_retry() {
+        use std::io::Read;
+
+        use crate::sink::unix::UnixConnector;
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("collector.sock");
+        let connector = UnixConnector::new(socket.clone()).unwrap();
+        let mut sink = StreamSink::new(connector, SinkFormat::JsonLines, 16);
+        let row = Row {
+            table: Cow::Borrowed("test"),
+            header: vec!["s".to_string()].into(),
+            fields: vec!["a".to_string()],
+        };
+        // Kept until the collector listens
+        sink.write_row(&row).unwrap();
+        sink.flush().unwrap();
+
+        let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        sink.next_connect = Instant::now();
+        sink.flush().unwrap();
+        drop(sink);
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        assert_eq!(received, "{\"table\":\"test\",\"s\":\"a\"}\n");
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+use crate::row::Row;
+
+use super::RecordSink;
+
+const SEVERITY_INFO: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Facility {
+    User = 1,
+    Daemon = 3,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyslogTransport {
+    /// `/dev/log`
+    #[cfg(unix)]
+    DevLog,
+    /// Usually port 514
+    Udp(SocketAddr),
+}
+
+enum Socket {
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram),
+    Udp(UdpSocket),
+}
+
+/// Sends each row as `table=<name> col1=v1 col2=v2 ...` at INFO severity
+pub(crate) struct SyslogSink {
+    socket: Socket,
+    prefix: String,
+}
+impl SyslogSink {
+    pub fn new(
+        transport: SyslogTransport,
+        facility: Facility,
+        process_name: &str,
+    ) -> io::Result<Self> {
+        let socket = match transport {
+            #[cfg(unix)]
+            SyslogTransport::DevLog => {
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket.connect("/dev/log")?;
+                Socket::Unix(socket)
+            }
+            SyslogTransport::Udp(addr) => {
+                let local: SocketAddr = match addr {
+                    SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+                    SocketAddr::V6(_) => ([0; 8], 0).into(),
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(addr)?;
+                Socket::Udp(socket)
+            }
+        };
+        let pri = facility as u8 * 8 + SEVERITY_INFO;
+        let prefix = format!("<{pri}>{process_name}[{}]: ", std::process::id());
+        Ok(Self { socket, prefix })
+    }
+}
+impl RecordSink for SyslogSink {
+    fn write_row(&mut self, row: &Row) -> io::Result<()> {
+        let mut msg = self.prefix.clone();
+        msg.push_str("table=");
+        push_value(&mut msg, &row.table);
+        for (column, value) in row.header.iter().zip(&row.fields) {
+            msg.push(' ');
+            msg.extend(column.chars().map(|c| match c {
+                '=' | '"' => '_',
+                c if c.is_whitespace() || c.is_control() => '_',
+                c => c,
+            }));
+            msg.push('=');
+            push_value(&mut msg, value);
+        }
+        match &self.socket {
+            #[cfg(unix)]
+            Socket::Unix(socket) => socket.send(msg.as_bytes())?,
+            Socket::Udp(socket) => socket.send(msg.as_bytes())?,
+        };
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn push_value(msg: &mut String, value: &str) {
+    let quote = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c == '=' || c == '"' || c.is_whitespace() || c.is_control());
+    if !quote {
+        msg.push_str(value);
+        return;
+    }
+    msg.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => msg.push_str("\\\""),
+            '\\' => msg.push_str("\\\\"),
+            '\n' => msg.push_str("\\n"),
+            '\r' => msg.push_str("\\r"),
+            '\t' => msg.push_str("\\t"),
+            c => msg.push(c),
+        }
+    }
+    msg.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_syslog_sink() {
+        let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut sink = SyslogSink::new(
+            SyslogTransport::Udp(collector.local_addr().unwrap()),
+            Facility::Local0,
+            "app",
+        )
+        .unwrap();
+        sink.write_row(&Row {
+            table: "test".into(),
+            header: vec!["s".to_string(), "n".to_string(), "e".to_string()].into(),
+            fields: vec!["a \"b\"=c".to_string(), "0".to_string(), String::new()],
+        })
+        .unwrap();
+
+        let mut buf = [0; 1024];
+        let n = collector.recv(&mut buf).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buf[..n]).unwrap(),
+            format!(
+                r#"<134>app[{}]: table=test s="a \"b\"=c" n=0 e="""#,
+                std::process::id()
+            )
+        );
+    }
+}
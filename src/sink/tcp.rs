@@ -0,0 +1,25 @@
+use std::{
+    io,
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+use super::stream::Connector;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub(crate) struct TcpConnector {
+    addr: SocketAddr,
+}
+impl TcpConnector {
+    pub fn new(addr: SocketAddr) -> Self {
+        Self { addr }
+    }
+}
+impl Connector for TcpConnector {
+    type Stream = TcpStream;
+
+    fn connect(&self) -> io::Result<Self::Stream> {
+        TcpStream::connect_timeout(&self.addr, CONNECT_TIMEOUT)
+    }
+}
@@ -0,0 +1,40 @@
+use std::{io, path::PathBuf};
+
+use super::stream::Connector;
+
+pub(crate) struct UnixConnector {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    path: PathBuf,
+}
+impl UnixConnector {
+    #[cfg(unix)]
+    pub fn new(path: PathBuf) -> io::Result<Self> {
+        Ok(Self { path })
+    }
+
+    #[cfg(not(unix))]
+    pub fn new(_path: PathBuf) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Unix domain sockets are not supported on this platform",
+        ))
+    }
+}
+
+#[cfg(unix)]
+impl Connector for UnixConnector {
+    type Stream = std::os::unix::net::UnixStream;
+
+    fn connect(&self) -> io::Result<Self::Stream> {
+        std::os::unix::net::UnixStream::connect(&self.path)
+    }
+}
+
+#[cfg(not(unix))]
+impl Connector for UnixConnector {
+    type Stream = io::Sink;
+
+    fn connect(&self) -> io::Result<Self::Stream> {
+        unreachable!()
+    }
+}
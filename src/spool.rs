@@ -0,0 +1,283 @@
+//! Keeps rows of tables whose files became unwritable in local spool files until the files recover
+//!
+//! A table's writer holds back the bytes of the write that failed, so the rows already handed to
+//! it are not lost; later rows go to `<spool dir>/<table>.csv`. Once the held bytes make it to the
+//! table's file, the spooled rows are replayed into the current epoch in order.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    row::Row,
+    storage::{self, Backend, OpenMode, Storage},
+    table_dir,
+};
+
+/// Default cap of a table's spool file
+pub(crate) const MAX_BYTES: u64 = 64 * 1024 * 1024;
+/// Spool files are cut back to this share of the cap once they exceed it
+const TRIM_TO_PERCENT: u64 = 75;
+
+/// Holds back what fails to be written, if enabled, and writes it out on the next flush
+///
+/// Toggled through shared references since [`csv::Writer`] only lends those out.
+pub(crate) struct Holding<W> {
+    inner: W,
+    hold: AtomicBool,
+    held: Vec<u8>,
+    /// The error that started the current outage, until taken
+    failure: Mutex<Option<io::Error>>,
+}
+impl<W> Holding<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hold: AtomicBool::new(false),
+            held: vec![],
+            failure: Mutex::new(None),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn set_hold(&self, hold: bool) {
+        self.hold.store(hold, Ordering::Relaxed);
+    }
+
+    /// Whether bytes are held back from the inner writer
+    pub fn outage(&self) -> bool {
+        !self.held.is_empty()
+    }
+
+    pub fn take_failure(&self) -> Option<io::Error> {
+        self.failure.lock().unwrap().take()
+    }
+
+    /// Gives up on writing the held bytes, ending the outage
+    pub fn take_held(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.held)
+    }
+
+    fn hold(&mut self, buf: &[u8], e: io::Error) -> io::Result<usize> {
+        if !self.hold.load(Ordering::Relaxed) {
+            return Err(e);
+        }
+        if self.held.is_empty() {
+            *self.failure.lock().unwrap() = Some(e);
+        }
+        self.held.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+impl<W: Write> Write for Holding<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.outage() {
+            self.held.extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+        match self.inner.write(buf) {
+            Err(e) if e.kind() != io::ErrorKind::Interrupted => self.hold(buf, e),
+            res => res,
+        }
+    }
+
+    /// Retries the held bytes first
+    fn flush(&mut self) -> io::Result<()> {
+        while self.outage() {
+            match self.inner.write(&self.held) {
+                Ok(0) => return Ok(()),
+                Ok(n) => {
+                    self.held.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(_) => return Ok(()),
+            }
+        }
+        self.inner.flush()
+    }
+}
+
+/// The spool files of the tables in an outage
+pub(crate) struct Spool {
+    dir: PathBuf,
+    max_bytes: u64,
+    storage: Backend,
+    tables: HashMap<String, SpoolFile>,
+}
+impl Spool {
+    pub fn new(dir: PathBuf, max_bytes: u64, storage: Backend) -> Self {
+        Self {
+            dir,
+            max_bytes,
+            storage,
+            tables: HashMap::new(),
+        }
+    }
+
+    fn path(&self, table_name: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.csv", table_dir::dir_name(table_name)))
+    }
+
+    pub fn is_spooling(&self, table_name: &str) -> bool {
+        self.tables.contains_key(table_name)
+    }
+
+    /// Spools the rows of the table from now on
+    pub fn start(&mut self, table_name: &str) {
+        let path = self.path(table_name);
+        self.tables
+            .entry(table_name.to_string())
+            .or_insert_with(|| SpoolFile {
+                path,
+                lens: VecDeque::new(),
+                bytes: 0,
+            });
+    }
+
+    /// Picks up the spool file left by an earlier run so its rows are replayed first
+    pub fn adopt(&mut self, table_name: &str) -> io::Result<()> {
+        let path = self.path(table_name);
+        let mut reader = match spooled(&self.storage, &path) {
+            Ok(reader) => reader,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut lens = VecDeque::new();
+        for record in reader.records() {
+            lens.push_back(encode(&parse_spooled(&record?)?)?.len() as u64);
+        }
+        let bytes = lens.iter().sum();
+        self.tables
+            .insert(table_name.to_string(), SpoolFile { path, lens, bytes });
+        Ok(())
+    }
+
+    /// Returns the number of the oldest rows dropped to stay under the cap
+    pub fn push(&mut self, table_name: &str, row: &Row) -> io::Result<usize> {
+        self.start(table_name);
+        let file = self.tables.get_mut(table_name).unwrap();
+        file.append(&self.storage, &encode(row)?)?;
+        if file.bytes <= self.max_bytes {
+            return Ok(0);
+        }
+        file.trim(&self.storage, self.max_bytes * TRIM_TO_PERCENT / 100)
+    }
+
+    /// Stops spooling the table and returns its spooled rows, oldest first
+    ///
+    /// The table keeps spooling if reading its spool file fails.
+    pub fn take(&mut self, table_name: &str) -> io::Result<Vec<Row>> {
+        let Some(file) = self.tables.get(table_name) else {
+            return Ok(vec![]);
+        };
+        let rows = match spooled(&self.storage, &file.path) {
+            Ok(mut reader) => reader
+                .records()
+                .map(|record| parse_spooled(&record?))
+                .collect::<io::Result<Vec<_>>>()?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        let file = self.tables.remove(table_name).unwrap();
+        storage::remove_if_exists(&self.storage, &file.path)?;
+        Ok(rows)
+    }
+}
+
+struct SpoolFile {
+    path: PathBuf,
+    /// Byte lengths of the spooled rows, oldest first
+    lens: VecDeque<u64>,
+    bytes: u64,
+}
+impl SpoolFile {
+    fn append(&mut self, storage: &Backend, record: &[u8]) -> io::Result<()> {
+        storage
+            .open(&self.path, OpenMode::Append)?
+            .write_all(record)?;
+        self.lens.push_back(record.len() as u64);
+        self.bytes += record.len() as u64;
+        Ok(())
+    }
+
+    /// Drops the oldest rows until at most `bytes` are left
+    fn trim(&mut self, storage: &Backend, bytes: u64) -> io::Result<usize> {
+        let mut skip = 0;
+        let mut dropped = 0;
+        while bytes < self.bytes - skip {
+            let Some(len) = self.lens.pop_front() else {
+                break;
+            };
+            skip += len;
+            dropped += 1;
+        }
+        let spooled = storage.read(&self.path)?;
+        let tmp = self.path.with_extension("csv.tmp");
+        storage
+            .open(&tmp, OpenMode::Truncate)?
+            .write_all(&spooled[skip as usize..])?;
+        storage.rename(&tmp, &self.path)?;
+        self.bytes -= skip;
+        Ok(dropped)
+    }
+}
+
+/// One spooled record
+fn encode(row: &Row) -> io::Result<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(vec![]);
+    write_spooled(&mut writer, row)?;
+    writer.into_inner().map_err(|e| e.into_error())
+}
+
+/// Writes the table, the header length, the header and the fields of the row as one record
+pub(crate) fn write_spooled<W: Write>(writer: &mut csv::Writer<W>, row: &Row) -> csv::Result<()> {
+    let header_len = row.header.len().to_string();
+    let record = [row.table.as_ref(), header_len.as_str()]
+        .into_iter()
+        .chain(row.header.iter().map(String::as_str))
+        .chain(row.fields.iter().map(String::as_str));
+    writer.write_record(record)
+}
+
+/// Reverses [`write_spooled`]
+pub(crate) fn parse_spooled(record: &csv::StringRecord) -> io::Result<Row> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Corrupted spill file");
+    let table = record.get(0).ok_or_else(invalid)?;
+    let header_len: usize = record
+        .get(1)
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(invalid)?;
+    if record.len() < 2 + header_len {
+        return Err(invalid());
+    }
+    let mut values = record.iter().skip(2).map(String::from);
+    let header = values.by_ref().take(header_len).collect::<Vec<_>>().into();
+    let fields = values.collect();
+    Ok(Row {
+        table: table.to_string().into(),
+        header,
+        fields,
+    })
+}
+
+/// Reads the spool file at `path` from `storage`
+fn spooled(storage: &Backend, path: &Path) -> io::Result<csv::Reader<io::Cursor<Vec<u8>>>> {
+    Ok(spool_reader(io::Cursor::new(storage.read(path)?)))
+}
+
+pub(crate) fn spool_reader<R: io::Read>(read: R) -> csv::Reader<R> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(read)
+}
@@ -0,0 +1,100 @@
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+use crate::{shared, telemetry};
+
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+static WRITTEN: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Counters of the tables open in a logger
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoggerStats {
+    pub tables: BTreeMap<String, TableStats>,
+    /// Records dropped per table, including tables never opened
+    pub dropped: BTreeMap<String, u64>,
+}
+
+/// Counters of a table since it was opened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableStats {
+    /// Rows written across epochs
+    pub records_written: u64,
+    /// Rows written to the current epoch, including those of an epoch it resumed
+    pub epoch_records: usize,
+    pub epoch: usize,
+    pub rotations: u64,
+    /// Bytes written out across epochs, headers included
+    pub bytes_written: u64,
+    /// Size of the current epoch's file as far as written out
+    pub epoch_bytes: u64,
+    /// `None` until the table is first flushed
+    pub last_flush: Option<SystemTime>,
+}
+
+/// Counters of the registered file logger, collected under its lock
+///
+/// Empty unless the registered logger writes to files.
+pub fn stats() -> LoggerStats {
+    shared::with_registered(|logger| logger.stats()).unwrap_or_default()
+}
+
+/// Records not written since the process started, by any logger
+///
+/// Counts records dropped while paused, by the filter chain, caps, table locks, schema
+/// mismatches and invalid table names, as well as rows dropped by full queues and failing sinks.
+/// Rows filtered out by [`crate::RowFilter`]s or of disabled tables are not counted.
+pub fn dropped_records() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// Errors reported to error handlers since the process started
+pub fn error_count() -> u64 {
+    ERRORS.load(Ordering::Relaxed)
+}
+
+pub(crate) fn count_dropped(n: usize) {
+    telemetry::dropped(n);
+    DROPPED.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+pub(crate) fn count_error() {
+    ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn count_written() {
+    WRITTEN.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn count_bytes(n: usize) {
+    BYTES.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+/// Process-wide counts of records and bytes written and records dropped
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Totals {
+    pub records: u64,
+    pub bytes: u64,
+    pub dropped: u64,
+}
+impl Totals {
+    pub fn now() -> Self {
+        Self {
+            records: WRITTEN.load(Ordering::Relaxed),
+            bytes: BYTES.load(Ordering::Relaxed),
+            dropped: DROPPED.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            records: self.records - earlier.records,
+            bytes: self.bytes - earlier.bytes,
+            dropped: self.dropped - earlier.dropped,
+        }
+    }
+}
@@ -0,0 +1,532 @@
+//! Where the files of tables live
+//!
+//! Every file the logger writes or resumes from goes through its storage, down to locks and
+//! sidecars. Readers of an output directory, e.g. [`crate::reader`] and [`crate::verify`], only
+//! read the real filesystem.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{network_fs::NetworkFs, private_dirs::PrivateFs};
+
+#[cfg(any(test, feature = "test-util"))]
+pub use memory::MemStorage;
+
+/// How to open a file for writing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Discard any existing content
+    Truncate,
+    /// Write after the existing content
+    Append,
+    /// Fail with [`io::ErrorKind::AlreadyExists`] if the file exists
+    CreateNew,
+}
+
+pub trait Storage {
+    /// Opens `path` for writing, creating its missing parent directories
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<StorageFile>;
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+    fn remove(&self, path: &Path) -> io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    /// Paths of the files and directories right under `dir`
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+    /// Makes the entries of `dir` durable, e.g. of files created or removed in it
+    fn sync_dir(&self, dir: &Path) -> io::Result<()>;
+    /// Replaces the contents of `path`
+    fn replace(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.open(path, OpenMode::Truncate)?.write_all(contents)
+    }
+}
+
+/// The real filesystem
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+impl Storage for RealFs {
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<StorageFile> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(StorageFile::real(open_options(mode).open(path)?))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    /// Only Unix can open directories to sync them
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        #[cfg(unix)]
+        File::open(dir)?.sync_all()?;
+        #[cfg(not(unix))]
+        let _ = dir;
+        Ok(())
+    }
+}
+
+/// The storage of a logger; a thin enum so the default build dispatches statically
+#[derive(Debug, Clone, Default)]
+pub(crate) enum Backend {
+    #[default]
+    Real,
+    Private(PrivateFs),
+    Network(NetworkFs),
+    #[cfg(any(test, feature = "test-util"))]
+    Memory(MemStorage),
+}
+impl Backend {
+    /// Creates a directory ahead of files opened outside of the storage, e.g. locks
+    pub(crate) fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        match self {
+            Backend::Real => std::fs::create_dir_all(dir),
+            Backend::Private(storage) => storage.create_dir_all(dir),
+            Backend::Network(storage) => storage.create_dir_all(dir),
+            #[cfg(any(test, feature = "test-util"))]
+            Backend::Memory(_) => Ok(()),
+        }
+    }
+
+    pub(crate) fn exists(&self, path: &Path) -> bool {
+        match self {
+            Backend::Real | Backend::Private(_) => path.exists(),
+            Backend::Network(storage) => storage.exists(path),
+            #[cfg(any(test, feature = "test-util"))]
+            Backend::Memory(storage) => storage.exists(path),
+        }
+    }
+
+    /// Takes the advisory lock of `path`, released once dropped
+    ///
+    /// Returns `None` if another holder has it, unless `wait`.
+    pub(crate) fn lock(&self, path: &Path, wait: bool) -> io::Result<Option<LockFile>> {
+        #[cfg(any(test, feature = "test-util"))]
+        if let Backend::Memory(storage) = self {
+            return Ok(storage.lock(path, wait).map(LockFile::Memory));
+        }
+        self.create_dir_all(path.parent().expect("Lock files are in a directory"))?;
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        if wait {
+            file.lock()?;
+            return Ok(Some(LockFile::Real(file)));
+        }
+        match file.try_lock() {
+            Ok(()) => Ok(Some(LockFile::Real(file))),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(e),
+        }
+    }
+
+    /// Whether files of the storage are on a filesystem, which verifying tools can repair
+    pub(crate) fn on_disk(&self) -> bool {
+        #[cfg(any(test, feature = "test-util"))]
+        if let Backend::Memory(_) = self {
+            return false;
+        }
+        true
+    }
+
+    /// Deletes what network filesystem mode moved into the trash, if it can yet
+    pub(crate) fn purge_trash(&self) {
+        if let Backend::Network(storage) = self {
+            storage.purge_trash();
+        }
+    }
+}
+impl Storage for Backend {
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<StorageFile> {
+        match self {
+            Backend::Real => RealFs.open(path, mode),
+            Backend::Private(storage) => storage.open(path, mode),
+            Backend::Network(storage) => storage.open(path, mode),
+            #[cfg(any(test, feature = "test-util"))]
+            Backend::Memory(storage) => storage.open(path, mode),
+        }
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self {
+            Backend::Real => RealFs.read(path),
+            Backend::Private(storage) => storage.read(path),
+            Backend::Network(storage) => storage.read(path),
+            #[cfg(any(test, feature = "test-util"))]
+            Backend::Memory(storage) => storage.read(path),
+        }
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        match self {
+            Backend::Real => RealFs.remove(path),
+            Backend::Private(storage) => storage.remove(path),
+            Backend::Network(storage) => storage.remove(path),
+            #[cfg(any(test, feature = "test-util"))]
+            Backend::Memory(storage) => storage.remove(path),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        match self {
+            Backend::Real => RealFs.rename(from, to),
+            Backend::Private(storage) => storage.rename(from, to),
+            Backend::Network(storage) => storage.rename(from, to),
+            #[cfg(any(test, feature = "test-util"))]
+            Backend::Memory(storage) => storage.rename(from, to),
+        }
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        match self {
+            Backend::Real => RealFs.read_dir(dir),
+            Backend::Private(storage) => storage.read_dir(dir),
+            Backend::Network(storage) => storage.read_dir(dir),
+            #[cfg(any(test, feature = "test-util"))]
+            Backend::Memory(storage) => storage.read_dir(dir),
+        }
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        match self {
+            Backend::Real => RealFs.sync_dir(dir),
+            Backend::Private(storage) => storage.sync_dir(dir),
+            Backend::Network(storage) => storage.sync_dir(dir),
+            #[cfg(any(test, feature = "test-util"))]
+            Backend::Memory(storage) => storage.sync_dir(dir),
+        }
+    }
+
+    fn replace(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        match self {
+            Backend::Network(storage) => storage.replace(path, contents),
+            _ => self.open(path, OpenMode::Truncate)?.write_all(contents),
+        }
+    }
+}
+
+pub(crate) fn open_options(mode: OpenMode) -> OpenOptions {
+    let mut options = File::options();
+    match mode {
+        OpenMode::Truncate => options.create(true).truncate(true).write(true),
+        OpenMode::Append => options.create(true).append(true),
+        OpenMode::CreateNew => options.create_new(true).write(true),
+    };
+    options
+}
+
+/// Removes `path` if it exists; returns whether it did
+pub(crate) fn remove_if_exists(storage: &impl Storage, path: &Path) -> io::Result<bool> {
+    match storage.remove(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Removes `dir` if it is empty; returns whether it did
+///
+/// Only the real filesystem has directories of their own. Fails quietly, as a logger in another
+/// process may be creating a file in it.
+pub(crate) fn remove_dir_if_empty(storage: &impl Storage, dir: &Path) -> bool {
+    match storage.read_dir(dir) {
+        Ok(entries) if entries.is_empty() => std::fs::remove_dir(dir).is_ok(),
+        _ => false,
+    }
+}
+
+/// A lock taken by [`Backend::lock`]
+#[derive(Debug)]
+pub(crate) enum LockFile {
+    Real(#[allow(dead_code)] File),
+    #[cfg(any(test, feature = "test-util"))]
+    Memory(#[allow(dead_code)] memory::MemLock),
+}
+
+/// A file opened for writing by a [`Storage`]
+#[derive(Debug)]
+pub struct StorageFile(Inner);
+#[derive(Debug)]
+enum Inner {
+    Real(File),
+    #[cfg(any(test, feature = "test-util"))]
+    Memory(memory::MemFile),
+}
+impl StorageFile {
+    pub(crate) fn real(file: File) -> Self {
+        Self(Inner::Real(file))
+    }
+
+    /// Only files on the real filesystem have anything to sync
+    pub(crate) fn sync_data(&self) -> io::Result<()> {
+        match &self.0 {
+            Inner::Real(file) => file.sync_data(),
+            #[cfg(any(test, feature = "test-util"))]
+            Inner::Memory(_) => Ok(()),
+        }
+    }
+
+    /// Syncs the metadata of the file as well
+    pub(crate) fn sync_all(&self) -> io::Result<()> {
+        match &self.0 {
+            Inner::Real(file) => file.sync_all(),
+            #[cfg(any(test, feature = "test-util"))]
+            Inner::Memory(file) => {
+                file.synced.lock().unwrap().push(file.path.clone());
+                Ok(())
+            }
+        }
+    }
+}
+impl io::Write for StorageFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Inner::Real(file) => file.write(buf),
+            #[cfg(any(test, feature = "test-util"))]
+            Inner::Memory(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Inner::Real(file) => file.flush(),
+            #[cfg(any(test, feature = "test-util"))]
+            Inner::Memory(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+mod memory {
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        io,
+        path::{Path, PathBuf},
+        sync::{Arc, Condvar, Mutex},
+    };
+
+    use super::{Inner, OpenMode, Storage, StorageFile};
+
+    type Files = Arc<Mutex<BTreeMap<PathBuf, Vec<u8>>>>;
+    type Synced = Arc<Mutex<Vec<PathBuf>>>;
+    type Unavailable = Arc<Mutex<Option<PathBuf>>>;
+    type Locks = Arc<(Mutex<BTreeSet<PathBuf>>, Condvar)>;
+
+    /// Files kept in memory for tests to make exact assertions on
+    ///
+    /// Clones share the same files. Directories exist as long as files are in them.
+    #[derive(Debug, Clone, Default)]
+    pub struct MemStorage {
+        files: Files,
+        synced: Synced,
+        unavailable: Unavailable,
+        locks: Locks,
+    }
+    impl MemStorage {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn read_to_string(&self, path: impl AsRef<Path>) -> Option<String> {
+            let files = self.files.lock().unwrap();
+            let file = files.get(path.as_ref())?;
+            Some(String::from_utf8(file.clone()).expect("Not UTF-8"))
+        }
+
+        pub fn exists(&self, path: impl AsRef<Path>) -> bool {
+            self.files.lock().unwrap().contains_key(path.as_ref())
+        }
+
+        /// Paths of every file
+        pub fn paths(&self) -> Vec<PathBuf> {
+            self.files.lock().unwrap().keys().cloned().collect()
+        }
+
+        /// Paths of the files and directories synced with metadata so far, in order
+        pub fn synced(&self) -> Vec<PathBuf> {
+            self.synced.lock().unwrap().clone()
+        }
+
+        /// Fails opening and writing files under `dir` while set, like an unmounted network
+        /// share
+        pub fn set_unavailable(&self, dir: Option<PathBuf>) {
+            *self.unavailable.lock().unwrap() = dir;
+        }
+
+        fn check_available(unavailable: &Unavailable, path: &Path) -> io::Result<()> {
+            match &*unavailable.lock().unwrap() {
+                Some(dir) if path.starts_with(dir) => Err(io::Error::other("storage unavailable")),
+                _ => Ok(()),
+            }
+        }
+
+        /// Takes the lock of `path`; `None` if it is held, unless `wait`
+        pub(crate) fn lock(&self, path: &Path, wait: bool) -> Option<MemLock> {
+            let (held, released) = &*self.locks;
+            let mut held = held.lock().unwrap();
+            while held.contains(path) {
+                if !wait {
+                    return None;
+                }
+                held = released.wait(held).unwrap();
+            }
+            held.insert(path.to_owned());
+            Some(MemLock {
+                locks: self.locks.clone(),
+                path: path.to_owned(),
+            })
+        }
+    }
+    impl Storage for MemStorage {
+        fn open(&self, path: &Path, mode: OpenMode) -> io::Result<StorageFile> {
+            Self::check_available(&self.unavailable, path)?;
+            let mut files = self.files.lock().unwrap();
+            match mode {
+                OpenMode::Truncate => {
+                    files.insert(path.to_owned(), vec![]);
+                }
+                OpenMode::Append => {
+                    files.entry(path.to_owned()).or_default();
+                }
+                OpenMode::CreateNew => {
+                    if files.contains_key(path) {
+                        return Err(io::ErrorKind::AlreadyExists.into());
+                    }
+                    files.insert(path.to_owned(), vec![]);
+                }
+            }
+            let file = MemFile {
+                files: self.files.clone(),
+                synced: self.synced.clone(),
+                unavailable: self.unavailable.clone(),
+                path: path.to_owned(),
+            };
+            Ok(StorageFile(Inner::Memory(file)))
+        }
+
+        fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+            let files = self.files.lock().unwrap();
+            Ok(files.get(path).cloned().ok_or(io::ErrorKind::NotFound)?)
+        }
+
+        fn remove(&self, path: &Path) -> io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            files.remove(path).ok_or(io::ErrorKind::NotFound)?;
+            Ok(())
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            let file = files.remove(from).ok_or(io::ErrorKind::NotFound)?;
+            files.insert(to.to_owned(), file);
+            Ok(())
+        }
+
+        fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+            let files = self.files.lock().unwrap();
+            let entries = files
+                .keys()
+                .filter_map(|path| path.strip_prefix(dir).ok()?.components().next())
+                .map(|entry| dir.join(entry))
+                .collect::<BTreeSet<_>>();
+            if entries.is_empty() {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+            Ok(entries.into_iter().collect())
+        }
+
+        fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+            self.synced.lock().unwrap().push(dir.to_owned());
+            Ok(())
+        }
+    }
+
+    /// Writes straight into the shared files
+    #[derive(Debug)]
+    pub(super) struct MemFile {
+        files: Files,
+        pub(super) synced: Synced,
+        unavailable: Unavailable,
+        pub(super) path: PathBuf,
+    }
+    impl io::Write for MemFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            MemStorage::check_available(&self.unavailable, &self.path)?;
+            let mut files = self.files.lock().unwrap();
+            files
+                .entry(self.path.clone())
+                .or_default()
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A held lock of a [`MemStorage`]
+    #[derive(Debug)]
+    pub(crate) struct MemLock {
+        locks: Locks,
+        path: PathBuf,
+    }
+    impl Drop for MemLock {
+        fn drop(&mut self) {
+            let (held, released) = &*self.locks;
+            held.lock().unwrap().remove(&self.path);
+            released.notify_all();
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_mem_storage() {
+            let storage = MemStorage::new();
+            let path = Path::new("/logs/test/0.csv");
+            let mut file = storage.open(path, OpenMode::CreateNew).unwrap();
+            io::Write::write_all(&mut file, b"a\n").unwrap();
+            let err = storage.open(path, OpenMode::CreateNew).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+            let mut file = storage.open(path, OpenMode::Append).unwrap();
+            io::Write::write_all(&mut file, b"b\n").unwrap();
+            assert_eq!(storage.read(path).unwrap(), b"a\nb\n");
+
+            assert_eq!(
+                storage.read_dir(Path::new("/logs")).unwrap(),
+                [PathBuf::from("/logs/test")]
+            );
+            let renamed = Path::new("/logs/test/1.csv");
+            storage.rename(path, renamed).unwrap();
+            assert!(!storage.exists(path));
+            assert_eq!(storage.read_to_string(renamed).unwrap(), "a\nb\n");
+
+            storage.open(renamed, OpenMode::Truncate).unwrap();
+            assert_eq!(storage.read_to_string(renamed).unwrap(), "");
+            storage.remove(renamed).unwrap();
+            assert!(storage.paths().is_empty());
+            let err = storage.read_dir(Path::new("/logs")).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        }
+    }
+}
@@ -1,34 +1,415 @@
-use std::io;
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
 
-use table_log::SerWrap;
+use crate::{
+    clock::Clock,
+    dedup::Held,
+    lock::TableLock,
+    recreate::Recreation,
+    row::{Header, Row},
+    sequence::SEQUENCE_COLUMN,
+    spool::Holding,
+    stats::{self, TableStats},
+    storage::StorageFile,
+    telemetry::{self, MeteredWriter},
+};
+
+pub type LogWriter = csv::Writer<Holding<MeteredWriter<StorageFile>>>;
+
+/// A table closed for going unwritten, reopened in append mode by its next row
+#[derive(Debug, Clone)]
+pub struct IdleTable {
+    pub output_dir: PathBuf,
+    pub epoch: usize,
+    pub records_written: usize,
+    /// Bytes of the epoch's file
+    pub bytes: u64,
+    pub header: Option<Header>,
+    pub next_sequence: Option<u64>,
+    /// Rows written since the table was opened, across epochs
+    pub lifetime_records: u64,
+    /// Bytes written out since the table was opened, across epochs
+    pub lifetime_bytes: u64,
+    pub rotations: u64,
+    pub last_flush: Option<SystemTime>,
+    pub written_at: Instant,
+}
+impl IdleTable {
+    pub fn stats(&self) -> TableStats {
+        TableStats {
+            records_written: self.lifetime_records,
+            epoch_records: self.records_written,
+            epoch: self.epoch,
+            rotations: self.rotations,
+            bytes_written: self.lifetime_bytes,
+            epoch_bytes: self.bytes,
+            last_flush: self.last_flush,
+        }
+    }
+}
 
 pub struct Table {
+    /// The output directory holding the table's directory
+    output_dir: PathBuf,
+    /// Held for as long as the table is open
+    lock: Option<TableLock>,
     records_written: usize,
+    /// Rows written since the table was opened, across epochs
+    lifetime_records: u64,
+    /// Bytes of the epochs before the current one since the table was opened
+    past_bytes: u64,
+    /// Bytes the current epoch held when the table resumed it
+    resumed_bytes: u64,
+    /// Records and bytes as of the last [`Table::take_flushed`]
+    reported: (u64, u64),
+    rotations: u64,
     epoch: usize,
-    writer: csv::Writer<std::fs::File>,
+    writer: LogWriter,
+    /// The header of the epoch once its first row is written
+    header: Option<Header>,
+    /// Whether the header was read back from the file of an epoch of an earlier run, whose first
+    /// line is a row if the epoch is headerless
+    inherited: bool,
+    /// The columns of the last record logged, before the logger added or renamed any
+    record_header: Option<Header>,
+    /// The columns of a record whose row was found to fit under the header of the epoch
+    fitting: Option<Header>,
+    /// The header last renamed and what it was renamed to
+    renamed: Option<(Header, Header)>,
+    next_sequence: Option<u64>,
+    held: Option<Held>,
+    /// Whether rows were written since the last flush
+    dirty: bool,
+    /// Whether the writer holds back what fails to be written instead of failing
+    hold: bool,
+    flushed_at: Instant,
+    written_at: Instant,
+    last_flush: Option<SystemTime>,
+    /// Backs off from recreating the table's files while a cleaner keeps deleting them
+    recreation: Recreation,
+    clock: Arc<dyn Clock>,
 }
 impl Table {
-    pub fn new(writer: csv::Writer<std::fs::File>, epoch: usize) -> Self {
+    pub fn new(
+        output_dir: PathBuf,
+        writer: LogWriter,
+        epoch: usize,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
         Self {
+            output_dir,
+            lock: None,
             records_written: 0,
+            lifetime_records: 0,
+            past_bytes: 0,
+            resumed_bytes: 0,
+            reported: (0, 0),
+            rotations: 0,
             epoch,
             writer,
+            header: None,
+            inherited: false,
+            record_header: None,
+            fitting: None,
+            renamed: None,
+            next_sequence: None,
+            held: None,
+            dirty: false,
+            hold: false,
+            flushed_at: clock.instant(),
+            written_at: clock.instant(),
+            last_flush: None,
+            recreation: Recreation::default(),
+            clock,
         }
     }
 
-    pub fn replace(&mut self, writer: csv::Writer<std::fs::File>) {
+    /// Continues an epoch that already holds `records_written` rows
+    pub fn resume(
+        output_dir: PathBuf,
+        writer: LogWriter,
+        epoch: usize,
+        records_written: usize,
+        header: Option<Header>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            output_dir,
+            lock: None,
+            records_written,
+            lifetime_records: 0,
+            past_bytes: 0,
+            resumed_bytes: 0,
+            reported: (0, 0),
+            rotations: 0,
+            epoch,
+            writer,
+            header,
+            inherited: false,
+            record_header: None,
+            fitting: None,
+            renamed: None,
+            next_sequence: None,
+            held: None,
+            dirty: false,
+            hold: false,
+            flushed_at: clock.instant(),
+            written_at: clock.instant(),
+            last_flush: None,
+            recreation: Recreation::default(),
+            clock,
+        }
+    }
+
+    /// Takes the header of the resumed epoch as read back from its file
+    pub fn with_inherited_header(mut self) -> Self {
+        self.inherited = self.header.is_some();
+        self
+    }
+
+    /// Whether to recreate the deleted file of the table now, which then counts as a recreation
+    pub fn allow_recreation(&mut self) -> bool {
+        self.recreation.allow(self.clock.instant())
+    }
+
+    /// Keeps the lock of the table until the table is dropped
+    pub fn with_lock(mut self, lock: TableLock) -> Self {
+        self.lock = Some(lock);
+        self
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    /// Counts the bytes of the resumed epoch as part of it
+    pub fn with_resumed_bytes(mut self, bytes: u64) -> Self {
+        self.resumed_bytes = bytes;
+        self
+    }
+
+    /// Numbers the rows from `next` on, continuing across epochs
+    pub fn with_sequence(mut self, next: u64) -> Self {
+        self.next_sequence = Some(next);
+        self
+    }
+
+    /// Prepends the sequence number if the table numbers its rows
+    pub fn number(&mut self, row: &mut Row) {
+        let Some(next) = &mut self.next_sequence else {
+            return;
+        };
+        if !row.header.is_empty() {
+            row.header.insert(0, SEQUENCE_COLUMN.to_string());
+        }
+        row.fields.insert(0, next.to_string());
+        *next += 1;
+    }
+
+    pub fn next_sequence(&self) -> Option<u64> {
+        self.next_sequence
+    }
+
+    pub fn replace(&mut self, writer: LogWriter) {
+        writer.get_ref().set_hold(self.hold);
+        self.past_bytes += self.writer.get_ref().get_ref().written();
+        self.resumed_bytes = 0;
         self.writer = writer;
         self.epoch += 1;
+        self.rotations += 1;
         self.records_written = 0;
+        self.header = None;
+        self.inherited = false;
+        self.fitting = None;
+        self.dirty = false;
     }
 
-    pub fn serialize(&mut self, record: &dyn table_log::LogRecord) -> Result<(), csv::Error> {
-        let record = SerWrap(record);
-        self.writer.serialize(record)?;
-        self.records_written += 1;
+    /// Starts the epoch over in the writer `open` returns, e.g. instead of rotating out an epoch
+    /// without rows
+    ///
+    /// Flushes the old writer first so none of its bytes land in the new file.
+    pub fn restart(&mut self, open: impl FnOnce() -> io::Result<LogWriter>) -> io::Result<()> {
+        self.writer.flush()?;
+        let writer = open()?;
+        writer.get_ref().set_hold(self.hold);
+        self.resumed_bytes = 0;
+        self.writer = writer;
+        self.header = None;
+        self.inherited = false;
+        self.fitting = None;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Holds back what fails to be written until a flush gets it out
+    pub fn set_hold(&mut self, hold: bool) {
+        self.hold = hold;
+        self.writer.get_ref().set_hold(hold);
+    }
+
+    /// Whether written rows are held back since the file stopped taking them
+    pub fn outage(&self) -> bool {
+        self.writer.get_ref().outage()
+    }
+
+    /// The error that started the outage, once
+    pub fn take_failure(&self) -> Option<io::Error> {
+        self.writer.get_ref().take_failure()
+    }
+
+    /// Gives up on the rows held back in an outage, after a last try at writing them, e.g. to
+    /// spool them
+    ///
+    /// The header comes back out too if the outage began with the epoch, so it is skipped.
+    pub fn take_held_rows(&mut self, table_name: &Cow<'static, str>) -> Vec<Row> {
+        let _ = self.writer.flush();
+        let held = self.writer.get_mut().take_held();
+        let header = self.header.clone().unwrap_or_default();
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(held.as_slice());
+        let mut rows = reader
+            .records()
+            .map_while(Result::ok)
+            .map(|record| record.iter().map(String::from).collect::<Vec<_>>())
+            .peekable();
+        rows.next_if(|fields| *fields == *header);
+        rows.map(|fields| Row {
+            table: table_name.clone(),
+            header: header.clone(),
+            fields,
+        })
+        .collect()
+    }
+
+    pub fn header(&self) -> Option<&[String]> {
+        self.header.as_deref().map(Vec::as_slice)
+    }
+
+    pub fn shared_header(&self) -> Option<&Header> {
+        self.header.as_ref()
+    }
+
+    pub fn record_header(&self) -> Option<&[String]> {
+        self.record_header.as_deref().map(Vec::as_slice)
+    }
+
+    pub fn note_record_header(&mut self, header: &Header) {
+        if self.record_header.as_ref() != Some(header) {
+            self.record_header = Some(header.clone());
+        }
+    }
+
+    /// Whether a row of a record of `record_header` was found to fit under the header of the
+    /// epoch, by identity
+    ///
+    /// Rows of records with the same columns come out with the same columns, so they fit as well.
+    pub fn fits(&self, record_header: &Header) -> bool {
+        self.fitting
+            .as_ref()
+            .is_some_and(|fitting| fitting.ptr_eq(record_header))
+    }
+
+    /// Remembers that rows of records of `record_header` fit under the header of the epoch
+    pub fn fit(&mut self, record_header: Header) {
+        self.fitting = Some(record_header);
+    }
+
+    /// Renames the header of `row` with `rename` unless it equals the header renamed last
+    pub fn rename(&mut self, row: &mut Row, rename: impl FnOnce(&mut Row)) {
+        if let Some((header, renamed)) = &self.renamed {
+            if *header == row.header {
+                row.header = renamed.clone();
+                return;
+            }
+        }
+        let header = row.header.clone();
+        rename(row);
+        self.renamed = Some((header, row.header.clone()));
+    }
+
+    /// Whether the header is that of an epoch resumed from an earlier run, with no row written
+    /// under it since
+    pub fn header_inherited(&self) -> bool {
+        self.inherited && self.lifetime_records == 0
+    }
+
+    /// Whether the row does not fit under the header of the epoch
+    pub fn header_changed(&self, row: &Row) -> bool {
+        // The first line of a resumed headerless epoch is a row rather than a header
+        if row.header.is_empty() && self.header_inherited() {
+            return false;
+        }
+        self.header
+            .as_ref()
+            .is_some_and(|header| *header != row.header)
+    }
+
+    /// Writes lines above the header before the first row of the epoch
+    pub fn write_preamble(&mut self, preamble: &str) -> io::Result<()> {
+        debug_assert!(self.header.is_none());
+        // Nothing of the epoch is buffered by the CSV writer yet
+        self.writer.get_mut().write_all(preamble.as_bytes())
+    }
+
+    /// Writes the header before the first row of the epoch unless the row has none
+    pub fn write_row(&mut self, row: &Row) -> Result<(), csv::Error> {
+        // The first line of a resumed headerless epoch is a row rather than a header
+        if row.header.is_empty() && self.header_inherited() {
+            self.header = Some(Header::default());
+        }
+        if self.header.is_none() {
+            if !row.header.is_empty() {
+                self.writer.write_record(row.header.iter())?;
+            }
+            self.header = Some(row.header.clone());
+        }
+        self.writer.write_record(&row.fields)?;
+        self.count_written(&row.table);
+        Ok(())
+    }
+
+    /// Writes the fields of a row of the columns the epoch already has, see [`Self::write_row`]
+    pub fn write_fields(
+        &mut self,
+        table: &Cow<'static, str>,
+        fields: &[&str],
+    ) -> Result<(), csv::Error> {
+        self.writer.write_record(fields)?;
+        self.count_written(table);
         Ok(())
     }
 
+    fn count_written(&mut self, table: &Cow<'static, str>) {
+        self.records_written += 1;
+        self.lifetime_records += 1;
+        self.dirty = true;
+        self.written_at = self.clock.instant();
+        telemetry::record_written(table);
+        stats::count_written();
+    }
+
+    /// Counts `record` if it repeats the held row
+    pub fn repeat(&mut self, record: &Row) -> bool {
+        self.held.as_mut().is_some_and(|held| held.repeat(record))
+    }
+
+    /// Holds a row back instead of writing it until a different record comes
+    pub fn hold(&mut self, held: Held) {
+        self.held = Some(held);
+    }
+
+    /// The held row with its repeat count, ready to be written
+    pub fn take_held(&mut self) -> Option<Row> {
+        self.held.take().map(Held::into_row)
+    }
+
     pub fn epoch(&self) -> usize {
         self.epoch
     }
@@ -37,7 +418,156 @@ impl Table {
         self.records_written
     }
 
+    /// Whether rows were written since the last flush
+    pub fn dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Size of the current epoch's file as far as written out, headers included
+    pub fn bytes_written(&self) -> u64 {
+        self.resumed_bytes + self.writer.get_ref().get_ref().written()
+    }
+
+    /// Bytes written out since the table was opened, across epochs
+    pub fn lifetime_bytes(&self) -> u64 {
+        self.past_bytes + self.writer.get_ref().get_ref().written()
+    }
+
+    /// Records and bytes written out since the last call
+    pub fn take_flushed(&mut self) -> (u64, u64) {
+        let bytes = self.lifetime_bytes();
+        let (records, reported_bytes) = self.reported;
+        self.reported = (self.lifetime_records, bytes);
+        (self.lifetime_records - records, bytes - reported_bytes)
+    }
+
+    pub fn stats(&self) -> TableStats {
+        TableStats {
+            records_written: self.lifetime_records,
+            epoch_records: self.records_written,
+            epoch: self.epoch,
+            rotations: self.rotations,
+            bytes_written: self.lifetime_bytes(),
+            epoch_bytes: self.bytes_written(),
+            last_flush: self.last_flush,
+        }
+    }
+
+    /// Syncs the flushed rows of the epoch to disk
+    pub fn sync(&self) -> io::Result<()> {
+        self.writer.get_ref().get_ref().get_ref().sync_data()
+    }
+
+    /// Like [`Table::sync`] but also syncs the file's metadata, e.g. its size
+    pub fn sync_all(&self) -> io::Result<()> {
+        self.writer.get_ref().get_ref().get_ref().sync_all()
+    }
+
+    pub fn flushed_at(&self) -> Instant {
+        self.flushed_at
+    }
+
+    pub fn written_at(&self) -> Instant {
+        self.written_at
+    }
+
+    /// Drops the writer and the lock, keeping what reopening the epoch and the stats need
+    pub fn into_idle(self) -> IdleTable {
+        IdleTable {
+            records_written: self.records_written,
+            bytes: self.bytes_written(),
+            lifetime_records: self.lifetime_records,
+            lifetime_bytes: self.lifetime_bytes(),
+            rotations: self.rotations,
+            last_flush: self.last_flush,
+            written_at: self.written_at,
+            epoch: self.epoch,
+            header: self.header,
+            next_sequence: self.next_sequence,
+            output_dir: self.output_dir,
+        }
+    }
+
+    /// Carries on counting from where the table was closed for going idle
+    pub fn with_idle_stats(mut self, idle: &IdleTable) -> Self {
+        self.lifetime_records = idle.lifetime_records;
+        self.past_bytes = idle.lifetime_bytes;
+        self.reported = (idle.lifetime_records, idle.lifetime_bytes);
+        self.rotations = idle.rotations;
+        self.last_flush = idle.last_flush;
+        self
+    }
+
+    /// Skips the writer unless rows were written since the last flush or are held back
     pub fn flush(&mut self) -> io::Result<()> {
-        self.writer.flush()
+        self.flushed_at = self.clock.instant();
+        self.last_flush = Some(self.clock.now());
+        if !self.dirty && !self.outage() {
+            return Ok(());
+        }
+        self.writer.flush()?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        clock::SystemClock,
+        log_file_path, open_log_writer,
+        storage::{Backend, OpenMode},
+    };
+
+    use super::*;
+
+    fn row(n: usize) -> Row {
+        Row {
+            table: "test".into(),
+            header: vec!["n".to_string()].into(),
+            fields: vec![n.to_string()],
+        }
+    }
+
+    #[test]
+    fn test_bytes_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |epoch| log_file_path(dir.path(), "test", epoch);
+        let size = |epoch| std::fs::metadata(path(epoch)).unwrap().len();
+        let open = |epoch, mode| open_log_writer(&Backend::Real, &path(epoch), mode).unwrap();
+
+        let mut table = Table::new(
+            dir.path().to_owned(),
+            open(0, OpenMode::CreateNew),
+            0,
+            Arc::new(SystemClock),
+        );
+        table.write_row(&row(0)).unwrap();
+        table.write_row(&row(10)).unwrap();
+        table.flush().unwrap();
+        assert_eq!(table.bytes_written(), "n\n0\n10\n".len() as u64);
+        assert_eq!(table.bytes_written(), size(0));
+
+        table.replace(open(1, OpenMode::CreateNew));
+        assert_eq!(table.bytes_written(), 0);
+        table.write_row(&row(1)).unwrap();
+        table.flush().unwrap();
+        assert_eq!(table.bytes_written(), size(1));
+        assert_eq!(table.lifetime_bytes(), size(0) + size(1));
+
+        let header = Some(vec!["n".to_string()].into());
+        let mut table = Table::resume(
+            dir.path().to_owned(),
+            open(1, OpenMode::Append),
+            1,
+            1,
+            header,
+            Arc::new(SystemClock),
+        )
+        .with_resumed_bytes(size(1));
+        table.write_row(&row(2)).unwrap();
+        table.flush().unwrap();
+        assert_eq!(table.bytes_written(), size(1));
+        assert_eq!(table.lifetime_bytes(), "2\n".len() as u64);
     }
 }
@@ -0,0 +1,454 @@
+//! Directories of tables, named after the tables unless Windows would not allow the name
+//!
+//! Reserved device names such as `CON` or `com1.csv`, and names ending in a dot or a space, are
+//! wrapped in underscores, e.g. `_CON_`. So are names that are themselves wrapped forms, keeping
+//! the mapping one to one. Escaped names are recorded in `tables.json` in the output directory so
+//! readers list the tables under their own names.
+//!
+//! With [`Layout::Flat`] the files of a table are instead prefixed with its escaped name in the
+//! output directory. With [`Layout::DateBuckets`] the epoch files of a table are in date
+//! directories in its directory.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Datelike, Utc};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    layout::{self, Layout},
+    long_path::long_dir,
+    manifest::Manifest,
+    storage::{OpenMode, RealFs, Storage},
+};
+
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$", "COM0", "COM1", "COM2", "COM3", "COM4",
+    "COM5", "COM6", "COM7", "COM8", "COM9", "COM¹", "COM²", "COM³", "LPT0", "LPT1", "LPT2", "LPT3",
+    "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9", "LPT¹", "LPT²", "LPT³",
+];
+
+/// The directory a logger with `namespace` writes its tables to under `output_dir`
+///
+/// Namespaces are escaped like table names, failing with [`io::ErrorKind::InvalidInput`] for
+/// names no table could have. See [`crate::CsvLoggerBuilder::namespace`].
+pub fn namespace_dir(output_dir: impl AsRef<Path>, namespace: Option<&str>) -> io::Result<PathBuf> {
+    let output_dir = output_dir.as_ref();
+    match namespace {
+        Some(namespace) if !crate::is_valid_table_name(namespace) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid namespace `{namespace}`"),
+        )),
+        Some(namespace) => Ok(output_dir.join(dir_name(namespace).as_ref())),
+        None => Ok(output_dir.to_path_buf()),
+    }
+}
+
+/// Where the files of tables are in an output directory: by its layout and, with
+/// [`Layout::DateBuckets`], in the bucket each epoch was created in
+///
+/// A logger keeps its own. Readers and imports outside a logger go by the `layout` marker, see
+/// [`TableFiles::of`]. Clones share the buckets and manifests.
+#[derive(Clone)]
+pub(crate) struct TableFiles {
+    layout: Layout,
+    /// The time buckets are picked by
+    clock: Arc<dyn Clock>,
+    /// By output directory, table and epoch
+    buckets: Arc<Mutex<BTreeMap<(PathBuf, String, usize), PathBuf>>>,
+    /// The manifests as last stored through these files, by path
+    manifests: Arc<Mutex<HashMap<PathBuf, Manifest>>>,
+}
+impl TableFiles {
+    pub fn new(layout: Layout, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            layout,
+            clock,
+            buckets: Arc::default(),
+            manifests: Arc::default(),
+        }
+    }
+
+    /// Per the `layout` marker of `output_dir`, picking buckets by the system time
+    pub fn of(output_dir: impl AsRef<Path>) -> Self {
+        Self::new(layout::of(output_dir.as_ref()), Arc::new(SystemClock))
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// The manifests stored through these files, updated in place rather than read back, see
+    /// [`crate::manifest::update`]
+    pub fn manifests(&self) -> MutexGuard<'_, HashMap<PathBuf, Manifest>> {
+        self.manifests.lock().unwrap()
+    }
+
+    /// The directory holding the files of a table, the output directory itself if flat
+    ///
+    /// Verbatim on Windows if long, see [`long_dir`]
+    pub fn table_dir(&self, output_dir: impl AsRef<Path>, table_name: &str) -> PathBuf {
+        let output_dir = output_dir.as_ref();
+        match self.layout {
+            Layout::PerTableDir | Layout::DateBuckets => {
+                long_dir(output_dir.join(dir_name(table_name).as_ref()))
+            }
+            Layout::Flat => long_dir(output_dir.to_path_buf()),
+        }
+    }
+
+    /// A file of a table, e.g. `epoch` or `0.csv`
+    pub fn table_file(
+        &self,
+        output_dir: impl AsRef<Path>,
+        table_name: &str,
+        file: &str,
+    ) -> PathBuf {
+        let dir = self.table_dir(output_dir, table_name);
+        match self.layout {
+            Layout::PerTableDir | Layout::DateBuckets => dir.join(file),
+            Layout::Flat => dir.join(format!(
+                "{}.{}",
+                flat_name(table_name),
+                file.trim_start_matches('.')
+            )),
+        }
+    }
+
+    /// The name of a file in [`Self::table_dir`] as [`Self::table_file`] was given it, unless it
+    /// is not the table's
+    pub fn file_of<'a>(&self, table_name: &str, file_name: &'a str) -> Option<&'a str> {
+        match self.layout {
+            Layout::PerTableDir | Layout::DateBuckets => Some(file_name),
+            Layout::Flat => file_name
+                .strip_prefix(flat_name(table_name).as_str())?
+                .strip_prefix('.'),
+        }
+    }
+
+    /// The log file of an epoch
+    ///
+    /// With [`Layout::DateBuckets`] it is in the bucket the epoch was created in, or else in
+    /// today's bucket, which is kept for the epoch if `create`.
+    pub fn epoch_file(
+        &self,
+        storage: &impl Storage,
+        output_dir: impl AsRef<Path>,
+        table_name: &str,
+        epoch: usize,
+        create: bool,
+    ) -> PathBuf {
+        let output_dir = output_dir.as_ref();
+        let file = format!("{epoch}.csv");
+        if self.layout != Layout::DateBuckets {
+            return self.table_file(output_dir, table_name, &file);
+        }
+        let key = (output_dir.to_path_buf(), table_name.to_string(), epoch);
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(bucket) = buckets.get(&key) {
+            return bucket.join(file);
+        }
+        let dir = self.table_dir(output_dir, table_name);
+        // Created by an earlier run, also if compressed since
+        let compressed = format!("{file}.");
+        let found = bucket_dirs(storage, &dir).into_iter().find(|bucket| {
+            storage
+                .read_dir(bucket)
+                .unwrap_or_default()
+                .iter()
+                .any(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name == file || name.starts_with(&compressed))
+                })
+        });
+        let bucket = match found {
+            Some(bucket) => bucket,
+            None => {
+                let bucket = dir.join(bucket_of(self.clock.now()));
+                if !create {
+                    return bucket.join(file);
+                }
+                bucket
+            }
+        };
+        buckets.insert(key, bucket.clone());
+        bucket.join(file)
+    }
+
+    /// Drops the bucket kept for a deleted epoch
+    pub fn forget_bucket(&self, output_dir: impl AsRef<Path>, table_name: &str, epoch: usize) {
+        let key = (
+            output_dir.as_ref().to_path_buf(),
+            table_name.to_string(),
+            epoch,
+        );
+        self.buckets.lock().unwrap().remove(&key);
+    }
+
+    /// The directories holding the epoch files of a table: its directory, or its date buckets
+    pub fn epoch_dirs(
+        &self,
+        storage: &impl Storage,
+        output_dir: impl AsRef<Path>,
+        table_name: &str,
+    ) -> Vec<PathBuf> {
+        let dir = self.table_dir(output_dir, table_name);
+        match self.layout {
+            Layout::PerTableDir | Layout::Flat => vec![dir],
+            Layout::DateBuckets => bucket_dirs(storage, &dir),
+        }
+    }
+}
+impl fmt::Debug for TableFiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TableFiles")
+            .field("layout", &self.layout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// `<YYYY>/<MM>/<DD>` of the UTC date of `time`
+fn bucket_of(time: SystemTime) -> PathBuf {
+    let date = DateTime::<Utc>::from(time);
+    PathBuf::from(format!("{:04}", date.year()))
+        .join(format!("{:02}", date.month()))
+        .join(format!("{:02}", date.day()))
+}
+
+/// The date buckets in the directory of a table, in date order
+fn bucket_dirs(storage: &impl Storage, table_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![table_dir.to_path_buf()];
+    for digits in [4, 2, 2] {
+        dirs = dirs
+            .iter()
+            .flat_map(|dir| storage.read_dir(dir).unwrap_or_default())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| {
+                        name.len() == digits && name.bytes().all(|b| b.is_ascii_digit())
+                    })
+            })
+            .collect();
+    }
+    dirs.sort_unstable();
+    dirs
+}
+
+/// The prefix of the files of a table in a flat output directory
+fn flat_name(table_name: &str) -> String {
+    dir_name(table_name).replace('%', "%25").replace('.', "%2E")
+}
+
+/// Splits the name of a file in a flat output directory into the directory name of its table, as
+/// [`dir_name`] would give it, and the name [`table_file`] was given
+pub(crate) fn split_flat(file_name: &str) -> Option<(String, &str)> {
+    let (prefix, file) = file_name.split_once('.')?;
+    let dir_name = prefix.replace("%2E", ".").replace("%25", "%");
+    Some((dir_name, file))
+}
+
+/// The name of the directory of a table
+pub(crate) fn dir_name(table_name: &str) -> Cow<'_, str> {
+    match needs_escape(table_name) {
+        true => Cow::Owned(format!("_{table_name}_")),
+        false => Cow::Borrowed(table_name),
+    }
+}
+
+fn needs_escape(name: &str) -> bool {
+    if name.ends_with(['.', ' ']) {
+        return true;
+    }
+    // Windows ignores extensions and spaces before them
+    let stem = name
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches(' ');
+    if RESERVED
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        return true;
+    }
+    name.strip_prefix('_')
+        .and_then(|name| name.strip_suffix('_'))
+        .is_some_and(needs_escape)
+}
+
+pub(crate) const MAPPING_FILE: &str = "tables.json";
+
+fn mapping_path(output_dir: impl AsRef<Path>) -> PathBuf {
+    output_dir.as_ref().join(MAPPING_FILE)
+}
+
+/// Directory names mapped to the escaped table names
+fn read_mapping(storage: &impl Storage, output_dir: &Path) -> BTreeMap<String, String> {
+    storage
+        .read(&mapping_path(output_dir))
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Adds the table to `tables.json` if its directory is named differently
+pub(crate) fn record(
+    storage: &impl Storage,
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> io::Result<()> {
+    let output_dir = output_dir.as_ref();
+    let Cow::Owned(dir_name) = dir_name(table_name) else {
+        return Ok(());
+    };
+    let mut mapping = read_mapping(storage, output_dir);
+    if mapping
+        .get(&dir_name)
+        .is_some_and(|name| name == table_name)
+    {
+        return Ok(());
+    }
+    mapping.insert(dir_name, table_name.to_string());
+    let path = mapping_path(output_dir);
+    let tmp = path.with_extension("json.tmp");
+    let mut file = storage.open(&tmp, OpenMode::Truncate)?;
+    file.write_all(serde_json::to_string(&mapping)?.as_bytes())?;
+    drop(file);
+    storage.rename(&tmp, &path)
+}
+
+/// The name of the table in a directory, per `tables.json` or else by unescaping
+pub(crate) fn table_names(output_dir: &Path) -> impl Fn(&str) -> String {
+    let mapping = read_mapping(&RealFs, output_dir);
+    move |dir_name| match mapping.get(dir_name) {
+        Some(table_name) => table_name.clone(),
+        None => unescape(dir_name).to_string(),
+    }
+}
+
+fn unescape(dir_name: &str) -> &str {
+    dir_name
+        .strip_prefix('_')
+        .and_then(|name| name.strip_suffix('_'))
+        .filter(|name| needs_escape(name))
+        .unwrap_or(dir_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_dir_name() {
+        for reserved in RESERVED {
+            assert_eq!(dir_name(reserved), format!("_{reserved}_"));
+        }
+        assert_eq!(dir_name("con"), "_con_");
+        assert_eq!(dir_name("Aux.csv"), "_Aux.csv_");
+        assert_eq!(dir_name("nul .tar.gz"), "_nul .tar.gz_");
+        assert_eq!(dir_name("lpt¹"), "_lpt¹_");
+        assert_eq!(dir_name("a."), "_a._");
+        assert_eq!(dir_name("a "), "_a _");
+        assert_eq!(dir_name("_CON_"), "__CON__");
+        assert_eq!(dir_name("__CON__"), "___CON___");
+
+        for plain in [
+            "console", "com10", "lpt", "conin", "a.b", "_a_", "_", "__", "x.con",
+        ] {
+            assert_eq!(dir_name(plain), plain);
+        }
+    }
+
+    #[test]
+    fn test_mapping() {
+        let dir = tempfile::tempdir().unwrap();
+        record(&RealFs, dir.path(), "events").unwrap();
+        assert!(!mapping_path(dir.path()).exists());
+        record(&RealFs, dir.path(), "con").unwrap();
+        record(&RealFs, dir.path(), "a.").unwrap();
+        let table_name = table_names(dir.path());
+        assert_eq!(table_name("_con_"), "con");
+        assert_eq!(table_name("_a._"), "a.");
+        assert_eq!(table_name("events"), "events");
+        assert_eq!(table_name("_PRN_"), "PRN");
+        assert_eq!(table_name("_a_"), "_a_");
+    }
+
+    #[test]
+    fn test_flat_name() {
+        for (table_name, flat) in [
+            ("events", "events"),
+            ("a.b", "a%2Eb"),
+            ("a%2Eb", "a%252Eb"),
+            ("%", "%25"),
+            ("con", "_con_"),
+            ("a.", "_a%2E_"),
+        ] {
+            assert_eq!(flat_name(table_name), flat);
+            let file = format!("{flat}.0.csv");
+            let (dir, file) = split_flat(&file).unwrap();
+            assert_eq!(dir, dir_name(table_name));
+            assert_eq!(file, "0.csv");
+        }
+        assert_eq!(split_flat("layout"), None);
+    }
+
+    #[test]
+    fn test_flat_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = TableFiles::new(Layout::Flat, Arc::new(SystemClock));
+        assert_eq!(files.table_dir(dir.path(), "a.b"), dir.path());
+        assert_eq!(
+            files.table_file(dir.path(), "a.b", "0.csv"),
+            dir.path().join("a%2Eb.0.csv")
+        );
+        assert_eq!(
+            files.table_file(dir.path(), "a.b", ".lock"),
+            dir.path().join("a%2Eb.lock")
+        );
+        assert_eq!(files.file_of("a.b", "a%2Eb.epoch"), Some("epoch"));
+        assert_eq!(files.file_of("a", "a%2Eb.epoch"), None);
+        assert_eq!(files.file_of("a", "ab.epoch"), None);
+    }
+
+    #[test]
+    fn test_date_buckets() {
+        let dir = tempfile::tempdir().unwrap();
+        let day = 24 * 60 * 60;
+        let now = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(19_000 * day);
+        assert_eq!(bucket_of(now), Path::new("2022/01/08"));
+        let files = TableFiles::new(Layout::DateBuckets, Arc::new(ManualClock::new(now)));
+
+        let table_dir = dir.path().join("test");
+        let stale = table_dir.join("2022/01/07/3.csv.gz");
+        std::fs::create_dir_all(stale.parent().unwrap()).unwrap();
+        std::fs::write(&stale, "").unwrap();
+        std::fs::create_dir_all(table_dir.join("2022/01/notes")).unwrap();
+        assert_eq!(
+            files.epoch_file(&RealFs, dir.path(), "test", 3, false),
+            table_dir.join("2022/01/07/3.csv")
+        );
+        assert_eq!(
+            files.epoch_file(&RealFs, dir.path(), "test", 4, true),
+            table_dir.join("2022/01/08/4.csv")
+        );
+        assert_eq!(
+            files.epoch_dirs(&RealFs, dir.path(), "test"),
+            [table_dir.join("2022/01/07")]
+        );
+        assert_eq!(
+            files.table_file(dir.path(), "test", "epoch"),
+            table_dir.join("epoch")
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use std::{
+    fs::File,
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+
+use crate::{
+    clock::Clock,
+    error::{self, CsvLoggerError, ErrorHandler},
+    row::Row,
+    sink::RecordSink,
+    spool, stats, telemetry,
+};
+
+/// Forwards every row written to the local files to a remote [`RecordSink`] as well
+///
+/// Rows the remote sink fails to accept are spilled to a local file and replayed in order once
+/// the remote sink recovers. Rows dropped because the queue to the background thread is full are
+/// counted in [`crate::dropped_records`] and reported as [`CsvLoggerError::TeeQueueFull`].
+pub struct FailoverTee {
+    remote: Box<dyn RecordSink>,
+    spill_path: PathBuf,
+    queue_capacity: usize,
+    retry_interval: Duration,
+    lag: Arc<LagCounters>,
+}
+impl FailoverTee {
+    pub fn new(remote: impl RecordSink + 'static, spill_path: PathBuf) -> Self {
+        Self {
+            remote: Box::new(remote),
+            spill_path,
+            queue_capacity: 1024,
+            retry_interval: Duration::from_secs(1),
+            lag: Arc::new(LagCounters::default()),
+        }
+    }
+
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    pub fn retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    pub fn lag(&self) -> TeeLagHandle {
+        TeeLagHandle {
+            counters: self.lag.clone(),
+        }
+    }
+
+    pub(crate) fn spawn(self, error_handler: ErrorHandler, clock: Arc<dyn Clock>) -> TeeWorker {
+        let (tx, rx) = crossbeam_channel::bounded(self.queue_capacity);
+        let lag = self.lag.clone();
+        let mut forwarder = Forwarder {
+            remote: self.remote,
+            spill_path: self.spill_path,
+            spill: None,
+            spilled: 0,
+            replayed: 0,
+            retry_interval: self.retry_interval,
+            next_replay: clock.instant(),
+            lag: self.lag,
+            error_handler: error_handler.clone(),
+            clock: clock.clone(),
+        };
+        std::thread::Builder::new()
+            .name("CsvLogger::tee()".to_string())
+            .spawn(move || forwarder.run(rx))
+            .expect("Failed to spawn the tee worker thread");
+        TeeWorker {
+            tx,
+            lag,
+            unreported: AtomicU64::new(0),
+            error_handler,
+            clock,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TeeLag {
+    /// Rows waiting in the queue to the background thread
+    pub queued: usize,
+    /// Rows in the spill file not yet replayed to the remote sink
+    pub spilled: u64,
+    /// Rows dropped because the queue was full
+    pub dropped: u64,
+}
+
+#[derive(Clone)]
+pub struct TeeLagHandle {
+    counters: Arc<LagCounters>,
+}
+impl TeeLagHandle {
+    pub fn get(&self) -> TeeLag {
+        TeeLag {
+            queued: self.counters.queued.load(Ordering::Relaxed),
+            spilled: self.counters.spilled.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct LagCounters {
+    queued: AtomicUsize,
+    spilled: AtomicU64,
+    dropped: AtomicU64,
+}
+
+enum TeeMessage {
+    Row(Row),
+    Flush,
+}
+
+/// Forwards rows to the background thread without blocking
+///
+/// Rows dropped because the queue is full are reported as [`CsvLoggerError::TeeQueueFull`] once
+/// rows are taken again or on flush.
+pub(crate) struct TeeWorker {
+    tx: Sender<TeeMessage>,
+    lag: Arc<LagCounters>,
+    /// Rows dropped since the last report
+    unreported: AtomicU64,
+    error_handler: ErrorHandler,
+    clock: Arc<dyn Clock>,
+}
+impl TeeWorker {
+    pub fn send(&self, row: Row) {
+        self.lag.queued.fetch_add(1, Ordering::Relaxed);
+        if self.tx.try_send(TeeMessage::Row(row)).is_err() {
+            self.lag.queued.fetch_sub(1, Ordering::Relaxed);
+            self.lag.dropped.fetch_add(1, Ordering::Relaxed);
+            self.unreported.fetch_add(1, Ordering::Relaxed);
+            stats::count_dropped(1);
+            return;
+        }
+        self.report_dropped();
+    }
+
+    pub fn flush(&self) {
+        self.report_dropped();
+        let _ = self.tx.try_send(TeeMessage::Flush);
+    }
+
+    fn report_dropped(&self) {
+        if self.unreported.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let dropped = self.unreported.swap(0, Ordering::Relaxed);
+        if dropped != 0 {
+            let error = CsvLoggerError::TeeQueueFull { dropped };
+            error::report_at(&self.error_handler, self.clock.now(), error);
+        }
+    }
+}
+
+struct Forwarder {
+    remote: Box<dyn RecordSink>,
+    spill_path: PathBuf,
+    spill: Option<csv::Writer<File>>,
+    /// Rows in the spill file
+    spilled: u64,
+    /// Rows in the spill file that have already reached the remote sink
+    replayed: u64,
+    retry_interval: Duration,
+    next_replay: Instant,
+    lag: Arc<LagCounters>,
+    error_handler: ErrorHandler,
+    clock: Arc<dyn Clock>,
+}
+impl Forwarder {
+    fn run(&mut self, rx: Receiver<TeeMessage>) {
+        // Catch up on rows spilled by a previous run
+        self.spilled = self.count_spilled().unwrap_or_default();
+        self.update_lag();
+        loop {
+            let msg = if self.spilled == 0 {
+                match rx.recv() {
+                    Ok(msg) => Some(msg),
+                    Err(_) => break,
+                }
+            } else {
+                match rx.recv_timeout(self.retry_interval) {
+                    Ok(msg) => Some(msg),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            };
+            match msg {
+                Some(TeeMessage::Row(row)) => {
+                    self.lag.queued.fetch_sub(1, Ordering::Relaxed);
+                    self.forward(row);
+                }
+                Some(TeeMessage::Flush) => {
+                    if self.spilled == 0 {
+                        if let Err(e) = self.remote.flush() {
+                            self.report(e);
+                        }
+                    }
+                }
+                None => (),
+            }
+            if self.spilled != 0 && self.next_replay <= self.clock.instant() {
+                self.replay();
+            }
+        }
+        if let Some(spill) = &mut self.spill {
+            let _ = spill.flush();
+        }
+        let _ = self.remote.flush();
+    }
+
+    fn forward(&mut self, row: Row) {
+        if self.spilled != 0 {
+            self.spill(&row);
+            return;
+        }
+        if let Err(e) = self.remote.write_row(&row) {
+            self.report(e);
+            self.next_replay = self.clock.instant() + self.retry_interval;
+            self.spill(&row);
+        }
+    }
+
+    fn spill(&mut self, row: &Row) {
+        let res = (|| -> Result<(), csv::Error> {
+            if self.spill.is_none() {
+                let file = File::options()
+                    .create(true)
+                    .append(true)
+                    .open(&self.spill_path)?;
+                self.spill = Some(csv::WriterBuilder::new().flexible(true).from_writer(file));
+            }
+            let spill = self.spill.as_mut().unwrap();
+            spool::write_spooled(spill, row)?;
+            spill.flush()?;
+            Ok(())
+        })();
+        match res {
+            Ok(()) => {
+                self.spilled += 1;
+                self.update_lag();
+            }
+            Err(e) => {
+                self.lag.dropped.fetch_add(1, Ordering::Relaxed);
+                stats::count_dropped(1);
+                self.report(e.into());
+            }
+        }
+    }
+
+    fn replay(&mut self) {
+        if let Err(e) = self.try_replay() {
+            self.report(e);
+            self.next_replay = self.clock.instant() + self.retry_interval;
+            return;
+        }
+        // Everything has reached the remote sink
+        self.spill = None;
+        if let Err(e) = std::fs::remove_file(&self.spill_path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                self.report(e);
+            }
+        }
+        self.spilled = 0;
+        self.replayed = 0;
+        self.update_lag();
+    }
+
+    fn try_replay(&mut self) -> io::Result<()> {
+        let mut reader = spool::spool_reader(File::open(&self.spill_path)?);
+        for record in reader.records().skip(self.replayed as usize) {
+            let record = record?;
+            let row = spool::parse_spooled(&record)?;
+            self.remote.write_row(&row)?;
+            self.replayed += 1;
+            self.update_lag();
+        }
+        self.remote.flush()
+    }
+
+    fn count_spilled(&self) -> io::Result<u64> {
+        let mut reader = spool::spool_reader(File::open(&self.spill_path)?);
+        let mut n = 0;
+        for record in reader.records() {
+            record?;
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    fn update_lag(&self) {
+        let spilled = self.spilled - self.replayed;
+        self.lag.spilled.store(spilled, Ordering::Relaxed);
+        telemetry::tee_lag(spilled);
+    }
+
+    fn report(&self, e: io::Error) {
+        let error = CsvLoggerError::Sink { source: e };
+        error::report_at(&self.error_handler, self.clock.now(), error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, sync::Mutex};
+
+    use super::*;
+    use crate::clock::SystemClock;
+
+    #[test]
+    fn test_report_dropped() {
+        let errors = Arc::new(Mutex::new(vec![]));
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let worker = TeeWorker {
+            tx,
+            lag: Arc::new(LagCounters::default()),
+            unreported: AtomicU64::new(0),
+            error_handler: Arc::new({
+                let errors = errors.clone();
+                move |e| errors.lock().unwrap().push(e.to_string())
+            }),
+            clock: Arc::new(SystemClock),
+        };
+        let row = || Row {
+            table: Cow::Borrowed("test"),
+            header: vec!["n".to_string()].into(),
+            fields: vec!["0".to_string()],
+        };
+        for _ in 0..3 {
+            worker.send(row());
+        }
+        assert_eq!(worker.lag.dropped.load(Ordering::Relaxed), 2);
+        assert!(errors.lock().unwrap().is_empty());
+
+        // Reported once the queue takes rows again
+        rx.recv().unwrap();
+        worker.send(row());
+        worker.flush();
+        assert_eq!(
+            *errors.lock().unwrap(),
+            ["Dropped 2 rows not forwarded to the tee: its queue was full"]
+        );
+    }
+}
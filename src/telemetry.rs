@@ -0,0 +1,126 @@
+use std::io;
+
+use crate::stats;
+
+pub(crate) use imp::*;
+
+pub(crate) struct MeteredWriter<W> {
+    inner: W,
+    written: u64,
+}
+impl<W> MeteredWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, written: 0 }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Bytes passed on to the inner writer
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+}
+impl<W: io::Write> io::Write for MeteredWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        bytes_written(n);
+        stats::count_bytes(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "metrics")]
+mod imp {
+    use std::borrow::Cow;
+
+    pub fn record_written(table: &Cow<'static, str>) {
+        ::metrics::counter!("csv_logger_records_total", "table" => label(table)).increment(1);
+    }
+
+    pub fn bytes_written(n: usize) {
+        ::metrics::counter!("csv_logger_bytes_written_total").increment(n as u64);
+    }
+
+    pub fn rotated() {
+        ::metrics::counter!("csv_logger_rotations_total").increment(1);
+    }
+
+    pub fn schema_changed(table: &Cow<'static, str>) {
+        ::metrics::counter!("csv_logger_schema_changes_total", "table" => label(table))
+            .increment(1);
+    }
+
+    pub fn error(kind: &'static str) {
+        ::metrics::counter!("csv_logger_errors_total", "kind" => kind).increment(1);
+    }
+
+    pub fn dropped(n: usize) {
+        ::metrics::counter!("csv_logger_dropped_total").increment(n as u64);
+    }
+
+    pub fn rate_limited() {
+        ::metrics::counter!("csv_logger_rate_limited_total").increment(1);
+    }
+
+    pub fn sampled_out() {
+        ::metrics::counter!("csv_logger_sampled_out_total").increment(1);
+    }
+
+    pub fn truncated(n: usize) {
+        ::metrics::counter!("csv_logger_truncated_fields_total").increment(n as u64);
+    }
+
+    pub fn tee_lag(rows: u64) {
+        ::metrics::gauge!("csv_logger_tee_lag_rows").set(rows as f64);
+    }
+
+    /// Static table names are not copied
+    fn label(table: &Cow<'static, str>) -> ::metrics::SharedString {
+        match table {
+            Cow::Borrowed(table) => (*table).into(),
+            Cow::Owned(table) => table.clone().into(),
+        }
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+mod imp {
+    use std::borrow::Cow;
+
+    #[inline(always)]
+    pub fn record_written(_table: &Cow<'static, str>) {}
+
+    #[inline(always)]
+    pub fn bytes_written(_n: usize) {}
+
+    #[inline(always)]
+    pub fn rotated() {}
+
+    #[inline(always)]
+    pub fn schema_changed(_table: &Cow<'static, str>) {}
+
+    #[inline(always)]
+    pub fn error(_kind: &'static str) {}
+
+    #[inline(always)]
+    pub fn dropped(_n: usize) {}
+
+    #[inline(always)]
+    pub fn rate_limited() {}
+
+    #[inline(always)]
+    pub fn sampled_out() {}
+
+    #[inline(always)]
+    pub fn truncated(_n: usize) {}
+
+    #[inline(always)]
+    pub fn tee_lag(_rows: u64) {}
+}
@@ -0,0 +1,143 @@
+use std::time::{Duration, SystemTime};
+
+use chrono::SecondsFormat;
+
+use crate::row::Row;
+
+/// Adds a column holding the time each row was logged before the record's own fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimestampConfig {
+    pub column: String,
+    pub format: TimestampFormat,
+    pub zone: TimestampZone,
+}
+impl Default for TimestampConfig {
+    fn default() -> Self {
+        Self {
+            column: "ts".to_string(),
+            format: TimestampFormat::default(),
+            zone: TimestampZone::default(),
+        }
+    }
+}
+impl TimestampConfig {
+    pub(crate) fn prepend(&self, row: &mut Row, now: SystemTime) {
+        if !row.header.is_empty() {
+            row.header.insert(0, self.column.clone());
+        }
+        row.fields.insert(0, self.format(now));
+    }
+
+    fn format(&self, time: SystemTime) -> String {
+        match self.format {
+            TimestampFormat::Rfc3339 => match self.zone {
+                TimestampZone::Utc => chrono::DateTime::<chrono::Utc>::from(time)
+                    .to_rfc3339_opts(SecondsFormat::Millis, true),
+                TimestampZone::Local => chrono::DateTime::<chrono::Local>::from(time)
+                    .to_rfc3339_opts(SecondsFormat::Millis, false),
+            },
+            TimestampFormat::UnixMillis => time
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis()
+                .to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// `2024-01-31T12:00:00.000Z`
+    #[default]
+    Rfc3339,
+    UnixMillis,
+}
+
+/// Time zone of [`TimestampFormat::Rfc3339`] timestamps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampZone {
+    #[default]
+    Utc,
+    Local,
+}
+
+/// Parses an RFC 3339 timestamp or milliseconds since the Unix epoch
+pub(crate) fn parse(s: &str) -> Option<SystemTime> {
+    if let Ok(millis) = s.parse::<u64>() {
+        return SystemTime::UNIX_EPOCH.checked_add(Duration::from_millis(millis));
+    }
+    let time = chrono::DateTime::parse_from_rfc3339(s).ok()?;
+    Some(time.with_timezone(&chrono::Utc).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, path::Path};
+
+    use table_log::Logger;
+
+    use crate::{log_file_path, CsvLoggerBuilder, RotationPolicy};
+
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct TestRecord<'caller> {
+        pub s: &'caller str,
+        pub n: usize,
+    }
+    impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+        fn table_name(&self) -> &'static str {
+            "test"
+        }
+    }
+
+    fn log_with(dir: &Path, config: TimestampConfig) -> Vec<csv::StringRecord> {
+        let mut logger = CsvLoggerBuilder::new(
+            dir.to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .timestamp(config)
+        .build();
+        logger.log(&TestRecord { s: "a", n: 0 });
+        logger.flush();
+        csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_path(log_file_path(dir, "test", 0))
+            .unwrap()
+            .into_records()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn test_timestamp_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let before = SystemTime::now() - Duration::from_millis(1);
+        let lines = log_with(dir.path(), TimestampConfig::default());
+        assert_eq!(lines[0].iter().collect::<Vec<_>>(), ["ts", "s", "n"]);
+        assert_eq!(lines[1].iter().skip(1).collect::<Vec<_>>(), ["a", "0"]);
+        let ts = &lines[1][0];
+        assert!(ts.ends_with('Z'));
+        assert!(before <= parse(ts).unwrap());
+    }
+
+    #[test]
+    fn test_timestamp_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let lines = log_with(
+            dir.path(),
+            TimestampConfig {
+                column: "logged_at".to_string(),
+                format: TimestampFormat::UnixMillis,
+                zone: TimestampZone::Utc,
+            },
+        );
+        assert_eq!(&lines[0][0], "logged_at");
+        let ts = &lines[1][0];
+        assert!(ts.chars().all(|c| c.is_ascii_digit()));
+        assert!(parse(ts).unwrap() <= SystemTime::now());
+    }
+}
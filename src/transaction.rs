@@ -0,0 +1,271 @@
+//! Logging rows to several tables so that they are flushed together
+//!
+//! Rows are serialized as they are logged and held until the closure returns. They are then
+//! written and their tables flushed under one lock of the logger, so the scheduled flush never
+//! sees some of them without the others. The files are still written one after another.
+//!
+//! The rows are admitted together: if the logger would drop any of them by its table, e.g. for an
+//! invalid name, a filter or a pause, or any record fails to serialize, all of them are dropped.
+//! Every row is checked before any is admitted, so a rejected row leaves the rate limits and
+//! samplers of the others' tables as they were.
+
+use std::{borrow::Cow, collections::BTreeSet};
+
+use table_log::SerWrap;
+
+use crate::{
+    is_valid_table_name,
+    row::{Row, RowFormat, SerializeError},
+    shared, sink, stats, CsvLogger, CsvLoggerHandle,
+};
+
+/// Rows logged inside [`transaction`], written once its closure returns
+pub struct Transaction {
+    /// `None` if there is no logger to write the rows to
+    format: Option<RowFormat>,
+    rows: Vec<(Row, bool)>,
+    /// Records that failed to serialize, which drop the rows of the transaction
+    unserialized: Vec<SerializeError>,
+}
+impl Transaction {
+    pub(crate) fn new(format: Option<RowFormat>) -> Self {
+        Self {
+            format,
+            rows: vec![],
+            unserialized: vec![],
+        }
+    }
+
+    pub fn log<'caller>(&mut self, record: &impl table_log::LogRecord<'caller>) {
+        let table = Cow::Borrowed(record.table_name());
+        self.push(table, &SerWrap(record));
+    }
+
+    /// Like [`crate::log_to`]
+    pub fn log_to(&mut self, table: &str, record: &impl serde::Serialize) {
+        self.push(Cow::Owned(table.to_string()), record);
+    }
+
+    fn push(&mut self, table: Cow<'static, str>, record: &(impl serde::Serialize + ?Sized)) {
+        let Some(format) = self.format else {
+            return;
+        };
+        match format.serialize(table, record) {
+            Ok(row) => self.rows.push(row),
+            Err(e) => self.unserialized.push(e),
+        }
+    }
+
+    /// Runs `f` on a new transaction, which holds the rows `f` logged
+    pub(crate) fn run<T>(format: Option<RowFormat>, f: impl FnOnce(&mut Self) -> T) -> (T, Self) {
+        let mut tx = Self::new(format);
+        let out = f(&mut tx);
+        (out, tx)
+    }
+
+    /// Writes the rows and flushes their tables once every row is admitted, dropping them all
+    /// otherwise
+    pub(crate) fn commit(self, logger: &mut CsvLogger) {
+        if !self.unserialized.is_empty() {
+            for e in self.unserialized {
+                logger.drop_unserialized(e);
+            }
+            for (row, _) in &self.rows {
+                logger.drop_record(&row.table);
+            }
+            return;
+        }
+        let tables: Vec<&str> = self
+            .rows
+            .iter()
+            .map(|(row, _)| row.table.as_ref())
+            .collect();
+        if let Some(rejected) = logger.first_rejected(&tables) {
+            for (i, table) in tables.into_iter().enumerate() {
+                // Reported as by `log_to`
+                if i == rejected && !is_valid_table_name(table) {
+                    logger.check_table_name(table);
+                } else {
+                    logger.drop_record(table);
+                }
+            }
+            return;
+        }
+        // Filters that cannot tell beforehand may still reject a row
+        let rejected = self
+            .rows
+            .iter()
+            .position(|(row, _)| !logger.admit_checked(&row.table));
+        if let Some(rejected) = rejected {
+            for (i, (row, _)) in self.rows.iter().enumerate() {
+                if i != rejected {
+                    logger.drop_record(&row.table);
+                }
+            }
+            return;
+        }
+        let mut tables = BTreeSet::new();
+        for (row, is_map) in self.rows {
+            tables.insert(row.table.clone());
+            logger.log_admitted(row, is_map);
+        }
+        for table in tables {
+            logger.flush_table(&table);
+        }
+    }
+}
+
+/// Logs the rows `f` logs on the transaction to the registered logger, flushing them together once
+/// `f` returns
+///
+/// Nothing is written if `f` panics or any of the rows is not admitted. A registered logger writing to a [`crate::OutputTarget`]
+/// other than files gets the rows through its sink and flushes it.
+pub fn transaction<T>(f: impl FnOnce(&mut Transaction) -> T) -> T {
+    let Some(logger) = shared::registered() else {
+        let format = sink::is_registered().then(RowFormat::default);
+        let (out, tx) = Transaction::run(format, f);
+        if !tx.unserialized.is_empty() {
+            stats::count_dropped(tx.rows.len() + tx.unserialized.len());
+            for e in tx.unserialized {
+                sink::report_registered(e.into());
+            }
+        } else if !tx.rows.is_empty() {
+            sink::write_registered(tx.rows.into_iter().map(|(row, _)| row), true);
+        }
+        return out;
+    };
+    let handle = CsvLoggerHandle { inner: logger };
+    handle.transaction(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, panic::AssertUnwindSafe};
+
+    use serial_test::serial;
+
+    use crate::{CsvLoggerBuilder, CsvLoggerHandle, RotationPolicy};
+
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct Event {
+        pub id: u64,
+    }
+
+    #[test]
+    fn test_transaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .build();
+        let handle = CsvLoggerHandle::new(logger);
+        let read = |table: &str| {
+            std::fs::read_to_string(crate::log_file_path(dir.path(), table, 0)).unwrap_or_default()
+        };
+
+        let id = handle.transaction(|tx| {
+            tx.log_to("order", &Event { id: 1 });
+            tx.log_to("payment", &Event { id: 1 });
+            tx.log_to("order", &Event { id: 2 });
+            // Nothing is written before the closure returns
+            assert_eq!(read("order"), "");
+            7
+        });
+        assert_eq!(id, 7);
+        // Flushed without waiting for a flush
+        assert_eq!(read("order"), "id\n1\n2\n");
+        assert_eq!(read("payment"), "id\n1\n");
+
+        let panicked = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            handle.transaction(|tx| {
+                tx.log_to("order", &Event { id: 3 });
+                panic!("Half way through");
+            })
+        }));
+        assert!(panicked.is_err());
+        handle.flush();
+        assert_eq!(read("order"), "id\n1\n2\n");
+
+        // Invalid table names are reported as by `log_to` and drop the other rows as well
+        handle.transaction(|tx| {
+            tx.log_to("order", &Event { id: 4 });
+            tx.log_to("../order", &Event { id: 4 });
+        });
+        handle.flush();
+        assert_eq!(read("order"), "id\n1\n2\n");
+        assert_eq!(handle.stats().dropped.get("../order"), Some(&1));
+        assert_eq!(handle.stats().dropped.get("order"), Some(&1));
+    }
+
+    #[test]
+    fn test_rejected_row_takes_no_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .table_rate_limit("order", 1.0)
+        .table_rate_limit("payment", 1.0)
+        .build();
+        let handle = CsvLoggerHandle::new(logger);
+        let read = |table: &str| {
+            std::fs::read_to_string(crate::log_file_path(dir.path(), table, 0)).unwrap_or_default()
+        };
+
+        handle.log_to("payment", &Event { id: 1 });
+        // The second row of `payment` is over its limit
+        handle.transaction(|tx| {
+            tx.log_to("order", &Event { id: 2 });
+            tx.log_to("payment", &Event { id: 2 });
+        });
+        // So is the second row of `order`
+        handle.transaction(|tx| {
+            tx.log_to("order", &Event { id: 3 });
+            tx.log_to("order", &Event { id: 4 });
+        });
+        handle.flush();
+        assert_eq!(read("order"), "");
+        assert_eq!(handle.stats().dropped.get("order"), Some(&3));
+
+        // The token of `order` was left alone
+        handle.log_to("order", &Event { id: 5 });
+        handle.flush();
+        assert_eq!(read("order"), "id\n5\n");
+        assert_eq!(read("payment"), "id\n1\n");
+    }
+
+    #[test]
+    #[serial]
+    fn test_global_transaction() {
+        let dir = tempfile::tempdir().unwrap();
+        crate::init(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        );
+        let read = |table: &str| {
+            std::fs::read_to_string(crate::log_file_path(dir.path(), table, 0)).unwrap_or_default()
+        };
+
+        transaction(|tx| {
+            tx.log_to("order", &Event { id: 1 });
+            tx.log_to("payment", &Event { id: 1 });
+            assert_eq!(read("order"), "");
+        });
+        assert_eq!(read("order"), "id\n1\n");
+        assert_eq!(read("payment"), "id\n1\n");
+
+        table_log::GLOBAL_LOG.lock().unwrap().remove_logger();
+    }
+}
@@ -0,0 +1,472 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use csv::StringRecord;
+
+use crate::{
+    checksum, manifest,
+    metadata::SkipMetadata,
+    reader::{complete_len, epoch_files, open_decoded, Compression},
+    storage::RealFs,
+    table_dir::TableFiles,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub epochs: Vec<EpochReport>,
+}
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.epochs.iter().all(|e| e.is_ok())
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.epochs.iter().map(|e| e.rows).sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpochReport {
+    pub epoch: usize,
+    pub path: PathBuf,
+    /// `None` if the file is empty
+    pub header: Option<StringRecord>,
+    /// Whether the header matches the first header among the epochs
+    pub header_consistent: bool,
+    /// Complete rows
+    pub rows: u64,
+    /// Line numbers of complete rows whose column count differs from the header
+    pub bad_rows: Vec<u64>,
+    /// Whether the last row is incomplete
+    pub truncated: bool,
+    /// Whether the file matches its checksum sidecar; `None` without a sidecar
+    pub checksum_ok: Option<bool>,
+    /// Whether the rows match the manifest; `None` unless the manifest has the epoch closed
+    pub manifest_ok: Option<bool>,
+}
+impl EpochReport {
+    pub fn is_ok(&self) -> bool {
+        self.header_consistent
+            && self.bad_rows.is_empty()
+            && !self.truncated
+            && self.checksum_ok != Some(false)
+            && self.manifest_ok != Some(false)
+    }
+}
+
+/// Checks the epoch files of a table without modifying them
+pub fn check_table(output_dir: impl AsRef<Path>, table_name: &str) -> io::Result<Report> {
+    let output_dir = output_dir.as_ref();
+    let files = TableFiles::of(output_dir);
+    let manifest = manifest::load(&RealFs, &files, output_dir, table_name).unwrap_or_default();
+    let mut epochs = vec![];
+    let mut reference: Option<StringRecord> = None;
+    for file in epoch_files(&files, output_dir, table_name)? {
+        let mut report = match check_epoch(file.epoch, file.path) {
+            Ok(Some(report)) => report,
+            Ok(None) => continue,
+            Err(e) => return Err(e),
+        };
+        report.manifest_ok = manifest
+            .get(file.epoch)
+            .filter(|entry| entry.closed)
+            .map(|entry| entry.records == report.rows);
+        if let Some(header) = &report.header {
+            let reference = reference.get_or_insert_with(|| header.clone());
+            report.header_consistent = reference == header;
+        }
+        epochs.push(report);
+    }
+    Ok(Report { epochs })
+}
+
+fn check_epoch(epoch: usize, path: PathBuf) -> io::Result<Option<EpochReport>> {
+    let Some(read) = open_decoded(&path)? else {
+        return Ok(None);
+    };
+    let checksum_ok = match checksum::read_sidecar(&path)? {
+        Some(expected) => Some(checksum::sha256_file(&path)? == expected),
+        None => None,
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(LastByte {
+            read: SkipMetadata::new(read),
+            last: None,
+        });
+    let mut header = StringRecord::new();
+    let has_header = reader.read_record(&mut header)?;
+    // Skipped once the header is read
+    let metadata = reader.get_ref().read.skipped_lines();
+    let mut report = EpochReport {
+        epoch,
+        path,
+        header_consistent: true,
+        rows: 0,
+        bad_rows: vec![],
+        truncated: false,
+        checksum_ok,
+        manifest_ok: None,
+        header: None,
+    };
+    if !has_header {
+        return Ok(Some(report));
+    }
+    let mut last_bad = false;
+    for record in reader.records() {
+        let record = record?;
+        last_bad = record.len() != header.len();
+        if last_bad {
+            let line = record.position().map(|p| p.line()).unwrap_or_default();
+            report.bad_rows.push(metadata + line);
+        }
+        report.rows += 1;
+    }
+    let missing_newline = reader.get_ref().last != Some(b'\n');
+    if missing_newline || last_bad {
+        report.truncated = true;
+        if 0 < report.rows {
+            report.rows -= 1;
+            if last_bad {
+                report.bad_rows.pop();
+            }
+        }
+    }
+    report.header = Some(header);
+    Ok(Some(report))
+}
+
+/// Remembers the last byte read
+struct LastByte<R> {
+    read: R,
+    last: Option<u8>,
+}
+impl<R: Read> Read for LastByte<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.read.read(buf)?;
+        if let Some(&last) = buf[..n].last() {
+            self.last = Some(last);
+        }
+        Ok(n)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChecksumStatus {
+    Match,
+    Mismatch {
+        expected: String,
+        actual: String,
+    },
+    /// No sidecar, as for the active epoch
+    Missing,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpochChecksum {
+    pub epoch: usize,
+    pub path: PathBuf,
+    pub status: ChecksumStatus,
+}
+
+/// Compares each epoch file of a table against its `.sha256` sidecar
+pub fn check_checksums(
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+) -> io::Result<Vec<EpochChecksum>> {
+    let output_dir = output_dir.as_ref();
+    let mut checksums = vec![];
+    for file in epoch_files(&TableFiles::of(output_dir), output_dir, table_name)? {
+        let status = match checksum::read_sidecar(&file.path)? {
+            Some(expected) => {
+                let actual = match checksum::sha256_file(&file.path) {
+                    Ok(actual) => actual,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e),
+                };
+                if actual == expected {
+                    ChecksumStatus::Match
+                } else {
+                    ChecksumStatus::Mismatch { expected, actual }
+                }
+            }
+            None => ChecksumStatus::Missing,
+        };
+        checksums.push(EpochChecksum {
+            epoch: file.epoch,
+            path: file.path,
+            status,
+        });
+    }
+    Ok(checksums)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairMode {
+    /// Only reports what would be removed
+    Dry,
+    /// Truncates the file and leaves a `.repaired` marker next to it
+    Fix,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repair {
+    pub epoch: usize,
+    pub path: PathBuf,
+    pub removed_bytes: u64,
+}
+
+/// Cuts a partial final row off each uncompressed epoch file of a table
+///
+/// Returns the files that had one. Fixing also corrects their counts in an intact manifest.
+pub fn repair_table(
+    output_dir: impl AsRef<Path>,
+    table_name: &str,
+    mode: RepairMode,
+) -> io::Result<Vec<Repair>> {
+    let output_dir = output_dir.as_ref();
+    let files = TableFiles::of(output_dir);
+    let mut repairs = vec![];
+    for file in epoch_files(&files, output_dir, table_name)? {
+        // Only the active epoch can be partially written and it is never compressed
+        if file.compression != Compression::None {
+            continue;
+        }
+        if let Some(repair) = repair_epoch(file.epoch, &file.path, mode)? {
+            repairs.push(repair);
+        }
+    }
+    if mode == RepairMode::Fix && !repairs.is_empty() {
+        if let Some(mut manifest) = manifest::load(&RealFs, &files, output_dir, table_name) {
+            for repair in &repairs {
+                let Some(report) = check_epoch(repair.epoch, repair.path.clone())? else {
+                    continue;
+                };
+                let bytes = std::fs::metadata(&repair.path)?.len();
+                manifest.recount(repair.epoch, report.rows, bytes);
+            }
+            manifest::store(&RealFs, &files, output_dir, table_name, &manifest)?;
+        }
+    }
+    Ok(repairs)
+}
+
+pub(crate) fn repair_epoch(
+    epoch: usize,
+    path: &Path,
+    mode: RepairMode,
+) -> io::Result<Option<Repair>> {
+    let mut file = match File::options()
+        .read(true)
+        .write(mode == RepairMode::Fix)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let len = file.seek(SeekFrom::End(0))?;
+    let complete = complete_len(&mut file)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    // A complete last line may still be short of fields
+    let mut keep = complete;
+    {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(SkipMetadata::new((&file).take(complete)));
+        let mut header_len = None;
+        let mut last = None;
+        for record in reader.records() {
+            let record = record?;
+            match header_len {
+                None => header_len = Some(record.len()),
+                Some(_) => last = Some(record),
+            }
+        }
+        if let (Some(header_len), Some(last)) = (header_len, last) {
+            if last.len() < header_len {
+                let metadata = reader.get_ref().skipped();
+                keep = last.position().map_or(keep, |p| metadata + p.byte());
+            }
+        }
+    }
+    if keep == len {
+        return Ok(None);
+    }
+    let removed_bytes = len - keep;
+    if mode == RepairMode::Fix {
+        file.set_len(keep)?;
+        file.sync_all()?;
+        let mut marker = File::options()
+            .create(true)
+            .append(true)
+            .open(path.with_extension("repaired"))?;
+        writeln!(marker, "removed {removed_bytes} bytes")?;
+    }
+    Ok(Some(Repair {
+        epoch,
+        path: path.to_owned(),
+        removed_bytes,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use table_log::Logger;
+
+    use crate::{log_file_path, CsvLogger, RotationPolicy};
+
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct TestRecord<'caller> {
+        pub s: &'caller str,
+        pub n: usize,
+    }
+    impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+        fn table_name(&self) -> &'static str {
+            "test"
+        }
+    }
+
+    fn write_two_epochs(dir: &Path) {
+        let mut logger = CsvLogger::new(
+            dir.to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        );
+        for (n, s) in ["a", "b", "c"].into_iter().enumerate() {
+            logger.log(&TestRecord { s, n });
+        }
+        logger.flush();
+    }
+
+    #[test]
+    fn test_check_table() {
+        let dir = tempfile::tempdir().unwrap();
+        write_two_epochs(dir.path());
+        let report = check_table(dir.path(), "test").unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.rows(), 3);
+
+        // Truncate in the middle of the last row
+        let path = log_file_path(dir.path(), "test", 0);
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_len(file.metadata().unwrap().len() - 3).unwrap();
+        let report = check_table(dir.path(), "test").unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.epochs.len(), 2);
+        assert!(report.epochs[0].truncated);
+        assert!(report.epochs[0].bad_rows.is_empty());
+        assert_eq!(report.epochs[0].rows, 1);
+        assert!(report.epochs[1].is_ok());
+        assert_eq!(report.epochs[1].rows, 1);
+    }
+
+    #[test]
+    fn test_repair_table() {
+        let dir = tempfile::tempdir().unwrap();
+        write_two_epochs(dir.path());
+        assert!(repair_table(dir.path(), "test", RepairMode::Fix)
+            .unwrap()
+            .is_empty());
+
+        let path = log_file_path(dir.path(), "test", 0);
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_len(file.metadata().unwrap().len() - 3).unwrap();
+        let repairs = repair_table(dir.path(), "test", RepairMode::Dry).unwrap();
+        assert_eq!(repairs.len(), 1);
+        assert_eq!(repairs[0].epoch, 0);
+        assert_eq!(repairs[0].removed_bytes, 1);
+        assert!(!check_table(dir.path(), "test").unwrap().is_ok());
+
+        repair_table(dir.path(), "test", RepairMode::Fix).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "s,n\na,0\n");
+        assert!(path.with_extension("repaired").exists());
+        let report = check_table(dir.path(), "test").unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.rows(), 2);
+    }
+
+    #[test]
+    fn test_repair_short_row() {
+        let dir = tempfile::tempdir().unwrap();
+        write_two_epochs(dir.path());
+        let path = log_file_path(dir.path(), "test", 1);
+        std::fs::write(&path, "s,n\nc,2\nd\n").unwrap();
+        let repairs = repair_table(dir.path(), "test", RepairMode::Fix).unwrap();
+        assert_eq!(repairs.len(), 1);
+        assert_eq!(repairs[0].removed_bytes, 2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "s,n\nc,2\n");
+    }
+
+    #[test]
+    fn test_check_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = crate::CsvLoggerBuilder::new(
+            dir.path().to_owned(),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(2).unwrap(),
+                max_epochs: 10,
+            },
+        )
+        .checksums(true)
+        .build();
+        for (n, s) in ["a", "b", "c"].into_iter().enumerate() {
+            logger.log(&TestRecord { s, n });
+        }
+        logger.flush();
+        let path = log_file_path(dir.path(), "test", 0);
+        let sidecar = checksum::sidecar_path(&path);
+        let start = std::time::Instant::now();
+        while !sidecar.exists() {
+            assert!(start.elapsed() < std::time::Duration::from_secs(5));
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let statuses = |dir: &Path| {
+            check_checksums(dir, "test")
+                .unwrap()
+                .into_iter()
+                .map(|c| c.status)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            statuses(dir.path()),
+            [ChecksumStatus::Match, ChecksumStatus::Missing]
+        );
+        assert!(check_table(dir.path(), "test").unwrap().is_ok());
+
+        // Tamper with a byte
+        let mut csv = std::fs::read(&path).unwrap();
+        csv[4] = b'z';
+        std::fs::write(&path, csv).unwrap();
+        let statuses = statuses(dir.path());
+        assert!(matches!(statuses[0], ChecksumStatus::Mismatch { .. }));
+        assert_eq!(statuses[1], ChecksumStatus::Missing);
+        let report = check_table(dir.path(), "test").unwrap();
+        assert_eq!(report.epochs[0].checksum_ok, Some(false));
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn test_inconsistent_header() {
+        let dir = tempfile::tempdir().unwrap();
+        write_two_epochs(dir.path());
+        std::fs::write(log_file_path(dir.path(), "test", 1), "s\nc\n").unwrap();
+        let report = check_table(dir.path(), "test").unwrap();
+        assert!(report.epochs[0].is_ok());
+        assert!(!report.epochs[1].header_consistent);
+    }
+}
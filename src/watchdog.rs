@@ -0,0 +1,61 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    clock::Clock,
+    error::CsvLoggerError,
+    flusher::{self, Schedule},
+};
+
+/// Records logged between checks
+const CHECK_EVERY: u64 = 64;
+
+/// When to warn that the flushing thread stopped flushing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogPolicy {
+    /// Flush intervals without a flush before warning
+    pub missed_intervals: u32,
+    /// Spawn the flushing thread again if it exited
+    pub respawn: bool,
+}
+
+/// Checks the heartbeat of the scheduled flushing thread from the logging path
+pub(crate) struct Watchdog {
+    threshold: Duration,
+    respawn: bool,
+    records: u64,
+    warned_at: Option<Instant>,
+}
+impl Watchdog {
+    pub fn new(policy: WatchdogPolicy, flush_interval: Duration) -> Self {
+        Self {
+            threshold: flush_interval * policy.missed_intervals,
+            respawn: policy.respawn,
+            records: 0,
+            warned_at: None,
+        }
+    }
+
+    /// Called for every record; warns at most once per threshold
+    pub fn check(&mut self, clock: &dyn Clock) -> Option<CsvLoggerError> {
+        self.records += 1;
+        if self.records % CHECK_EVERY != 0 {
+            return None;
+        }
+        let status = flusher::scheduled_status()?;
+        let now = clock.instant();
+        let since = status.since_heartbeat(now);
+        if since < self.threshold {
+            return None;
+        }
+        if self
+            .warned_at
+            .is_some_and(|warned_at| now.saturating_duration_since(warned_at) < self.threshold)
+        {
+            return None;
+        }
+        self.warned_at = Some(now);
+        let respawned =
+            self.respawn && !status.running() && matches!(Schedule::respawn(), Ok(Some(_)));
+        Some(CsvLoggerError::FlushStalled { since, respawned })
+    }
+}
@@ -0,0 +1,204 @@
+use std::{collections::HashMap, io, num::NonZeroUsize, path::PathBuf, time::Duration};
+
+use csv_logger::{
+    ConfigError, ConfigProblem, CsvLoggerBuilder, FreeSpacePolicy, RotationPolicy, TimestampConfig,
+    WatchdogPolicy,
+};
+
+fn builder() -> CsvLoggerBuilder {
+    CsvLoggerBuilder::new(
+        PathBuf::from("/logs"),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(10).unwrap(),
+            max_epochs: 2,
+        },
+    )
+}
+
+fn problems(builder: CsvLoggerBuilder) -> Vec<ConfigProblem> {
+    builder.validate().unwrap_err().problems
+}
+
+#[test]
+fn test_validate() {
+    assert!(builder().validate().is_ok());
+    assert!(builder()
+        .auto_flush(false)
+        .flush_interval(Duration::ZERO)
+        .validate()
+        .is_ok());
+
+    assert_eq!(
+        problems(builder().flush_interval(Duration::ZERO)),
+        vec![ConfigProblem::ZeroFlushInterval]
+    );
+    assert_eq!(
+        problems(
+            builder()
+                .table_sampling("a", 0.0)
+                .table_sampling("b", 1.5)
+                .table_sampling("c", 0.5)
+                .table_rate_limit("a", -1.0)
+        ),
+        vec![
+            ConfigProblem::RateLimit {
+                table: "a".to_string(),
+                max_records_per_sec: -1.0,
+            },
+            ConfigProblem::SampleRate {
+                table: "a".to_string(),
+                rate: 0.0,
+            },
+            ConfigProblem::SampleRate {
+                table: "b".to_string(),
+                rate: 1.5,
+            },
+        ]
+    );
+
+    let renames = HashMap::from([
+        ("a".to_string(), "x".to_string()),
+        ("b".to_string(), "x".to_string()),
+        ("c".to_string(), String::new()),
+    ]);
+    let renamed = problems(builder().column_renames("t", renames));
+    assert_eq!(renamed.len(), 2);
+    assert!(renamed.contains(&ConfigProblem::DuplicateColumnRename {
+        table: "t".to_string(),
+        name: "x".to_string(),
+    }));
+    assert!(renamed.contains(&ConfigProblem::EmptyColumnRename {
+        table: "t".to_string(),
+        column: "c".to_string(),
+    }));
+
+    let renames = HashMap::from([("at".to_string(), "epoch".to_string())]);
+    assert!(builder()
+        .column_renames("t", renames.clone())
+        .validate()
+        .is_ok());
+    assert_eq!(
+        problems(
+            builder()
+                .column_renames("t", renames)
+                .include_epoch_column(true)
+        ),
+        [ConfigProblem::InjectedColumnRename {
+            table: "t".to_string(),
+            column: "epoch".to_string(),
+        }]
+    );
+    let renames = HashMap::from([("at".to_string(), "ts".to_string())]);
+    assert_eq!(
+        problems(
+            builder()
+                .column_renames("t", renames)
+                .timestamp(TimestampConfig::default())
+        ),
+        [ConfigProblem::InjectedColumnRename {
+            table: "t".to_string(),
+            column: "ts".to_string(),
+        }]
+    );
+
+    let all = problems(
+        builder()
+            .table_flush_interval("a", Duration::ZERO)
+            .spool_dir(Some(PathBuf::from("/logs")))
+            .free_space(FreeSpacePolicy {
+                min_free_bytes: 1 << 20,
+                critical_free_bytes: 1 << 30,
+                pause: true,
+                check_interval: Duration::from_secs(1),
+            })
+            .health_interval(Some(Duration::ZERO))
+            .flush_watchdog(Some(WatchdogPolicy {
+                missed_intervals: 0,
+                respawn: false,
+            }))
+            .max_field_bytes(0)
+            .namespace(Some("../tenant".to_string()))
+            .app_info("app", "1.0")
+            .idle_close(Some(Duration::ZERO))
+            .spool_max_bytes(0)
+            .flatten_nested(true)
+            .max_nesting_depth(0)
+            .table_cap("a", 0),
+    );
+    for problem in [
+        ConfigProblem::ZeroTableFlushInterval {
+            table: "a".to_string(),
+        },
+        ConfigProblem::SpoolDirIsOutputDir {
+            dir: PathBuf::from("/logs"),
+        },
+        ConfigProblem::FreeSpaceThresholds {
+            min_free_bytes: 1 << 20,
+            critical_free_bytes: 1 << 30,
+        },
+        ConfigProblem::ZeroHealthInterval,
+        ConfigProblem::ZeroMissedIntervals,
+        ConfigProblem::ZeroMaxFieldBytes,
+        ConfigProblem::InvalidNamespace {
+            namespace: "../tenant".to_string(),
+        },
+        ConfigProblem::AppInfoWithoutMetadata,
+        ConfigProblem::ZeroIdleClose,
+        ConfigProblem::ZeroSpoolMaxBytes,
+        ConfigProblem::ZeroMaxNestingDepth,
+        ConfigProblem::ZeroTableCap {
+            table: "a".to_string(),
+        },
+    ] {
+        assert!(all.contains(&problem), "{problem:?} missing from {all:?}");
+    }
+    assert_eq!(all.len(), 12);
+}
+
+#[test]
+fn test_validate_expanded_spool_dir() {
+    std::env::set_var("CSV_LOGGER_TEST_SPOOL", "/logs");
+    let expanded = || {
+        CsvLoggerBuilder::new(
+            PathBuf::from("${CSV_LOGGER_TEST_SPOOL}"),
+            RotationPolicy {
+                max_records: NonZeroUsize::new(10).unwrap(),
+                max_epochs: 2,
+            },
+        )
+        .expand_path(true)
+    };
+    assert!(expanded().validate().is_ok());
+    assert_eq!(
+        problems(expanded().spool_dir(Some(PathBuf::from("/logs")))),
+        [ConfigProblem::SpoolDirIsOutputDir {
+            dir: PathBuf::from("/logs"),
+        }]
+    );
+}
+
+#[test]
+fn test_try_build_fails() {
+    let e = builder().table_cap("a", 0).try_build().unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let config = e.into_inner().unwrap().downcast::<ConfigError>().unwrap();
+    assert_eq!(
+        config.problems,
+        [ConfigProblem::ZeroTableCap {
+            table: "a".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn test_init_fails() {
+    let e = builder()
+        .flush_interval(Duration::ZERO)
+        .table_sampling("a", 2.0)
+        .init()
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let config = e.into_inner().unwrap().downcast::<ConfigError>().unwrap();
+    assert_eq!(config.problems.len(), 2);
+    assert!(!table_log::GLOBAL_LOG.lock().unwrap().has_logger());
+}
@@ -0,0 +1,61 @@
+use std::{num::NonZeroUsize, path::Path};
+
+use csv_logger::{set_context, CsvLoggerBuilder, RotationPolicy};
+use table_log::Logger;
+
+#[derive(serde::Serialize)]
+struct TestRecord<'caller> {
+    pub s: &'caller str,
+    pub n: usize,
+}
+impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+    fn table_name(&self) -> &'static str {
+        "test"
+    }
+}
+
+#[derive(serde::Serialize)]
+struct OtherRecord {
+    pub x: usize,
+}
+impl table_log::LogRecord<'_> for OtherRecord {
+    fn table_name(&self) -> &'static str {
+        "other"
+    }
+}
+
+fn read(dir: &Path, table: &str) -> String {
+    std::fs::read_to_string(dir.join(table).join("0.csv")).unwrap()
+}
+
+// The context is global so this is the only test in this binary
+#[test]
+fn test_context() {
+    let dir = tempfile::tempdir().unwrap();
+    set_context("region", "eu".to_string());
+    let mut logger = CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(10).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .with_pid()
+    .build();
+    set_context("build", "42".to_string());
+    logger.log(&TestRecord { s: "a", n: 0 });
+    logger.log(&OtherRecord { x: 1 });
+    set_context("region", "us".to_string());
+    logger.log(&TestRecord { s: "b", n: 1 });
+    logger.flush();
+
+    let pid = std::process::id();
+    assert_eq!(
+        read(dir.path(), "test"),
+        format!("s,n,pid,region,build\na,0,{pid},eu,42\nb,1,{pid},us,42\n")
+    );
+    assert_eq!(
+        read(dir.path(), "other"),
+        format!("x,pid,region,build\n1,{pid},eu,42\n")
+    );
+}
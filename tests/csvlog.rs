@@ -0,0 +1,144 @@
+#![cfg(feature = "cli")]
+
+use std::{num::NonZeroUsize, path::Path, time::Duration};
+
+use assert_cmd::Command;
+use csv_logger::{CsvLogger, RotationPolicy};
+use table_log::Logger;
+
+#[derive(serde::Serialize)]
+struct TestRecord<'caller> {
+    pub s: &'caller str,
+    pub n: usize,
+}
+impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+    fn table_name(&self) -> &'static str {
+        "test"
+    }
+}
+
+/// Epochs 0 and 1 hold two rows each and epoch 2 holds one
+fn write_three_epochs(dir: &Path) {
+    let mut logger = CsvLogger::new(
+        dir.to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(2).unwrap(),
+            max_epochs: 10,
+        },
+    );
+    for (n, s) in ["a", "b", "c", "d", "e"].into_iter().enumerate() {
+        logger.log(&TestRecord { s, n });
+    }
+    logger.flush();
+}
+
+fn csvlog() -> Command {
+    Command::cargo_bin("csvlog").unwrap()
+}
+
+#[test]
+fn test_tables() {
+    let dir = tempfile::tempdir().unwrap();
+    write_three_epochs(dir.path());
+    let bytes = "s,n\na,0\nb,1\n".len() * 2 + "s,n\ne,4\n".len();
+    csvlog()
+        .arg("tables")
+        .arg(dir.path())
+        .assert()
+        .success()
+        .stdout(format!(
+            "table,current_epoch,epoch_files,total_bytes\ntest,2,3,{bytes}\n"
+        ));
+}
+
+#[test]
+fn test_cat() {
+    let dir = tempfile::tempdir().unwrap();
+    write_three_epochs(dir.path());
+    csvlog()
+        .arg("cat")
+        .arg(dir.path())
+        .arg("test")
+        .assert()
+        .success()
+        .stdout("s,n\na,0\nb,1\nc,2\nd,3\ne,4\n");
+    csvlog()
+        .arg("cat")
+        .arg(dir.path())
+        .args(["test", "--last", "2"])
+        .assert()
+        .success()
+        .stdout("s,n\nd,3\ne,4\n");
+    csvlog()
+        .arg("cat")
+        .arg(dir.path())
+        .args(["test", "--epoch", "1"])
+        .assert()
+        .success()
+        .stdout("s,n\nc,2\nd,3\n");
+}
+
+#[test]
+fn test_merge() {
+    let dir = tempfile::tempdir().unwrap();
+    write_three_epochs(dir.path());
+    let out = dir.path().join("out.csv");
+    csvlog()
+        .arg("merge")
+        .arg(dir.path())
+        .arg("test")
+        .arg("-o")
+        .arg(&out)
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read_to_string(out).unwrap(),
+        "s,n\na,0\nb,1\nc,2\nd,3\ne,4\n"
+    );
+}
+
+#[test]
+fn test_verify() {
+    let dir = tempfile::tempdir().unwrap();
+    write_three_epochs(dir.path());
+    csvlog()
+        .arg("verify")
+        .arg(dir.path())
+        .arg("test")
+        .assert()
+        .code(0);
+
+    let path = dir.path().join("test").join("0.csv");
+    let file = std::fs::File::options().write(true).open(path).unwrap();
+    file.set_len(file.metadata().unwrap().len() - 2).unwrap();
+    csvlog()
+        .arg("verify")
+        .arg(dir.path())
+        .arg("test")
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains("0,1,ok,,true,none"));
+
+    csvlog()
+        .arg("verify")
+        .arg(dir.path().join("missing"))
+        .arg("test")
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_tail_follow() {
+    let dir = tempfile::tempdir().unwrap();
+    write_three_epochs(dir.path());
+    csvlog()
+        .arg("tail")
+        .arg("-f")
+        .args(["-n", "2"])
+        .arg(dir.path())
+        .arg("test")
+        .timeout(Duration::from_secs(1))
+        .assert()
+        .interrupted()
+        .stdout("s,n\nd,3\ne,4\n");
+}
@@ -0,0 +1,47 @@
+use std::{collections::BTreeMap, num::NonZeroUsize};
+
+use csv_logger::{dropped_records, error_count, CsvLoggerBuilder, RotationPolicy, SchemaPolicy};
+
+#[derive(serde::Serialize)]
+struct TestRecord<'caller> {
+    pub s: &'caller str,
+    pub n: usize,
+}
+
+#[derive(serde::Serialize)]
+struct OtherRecord {
+    pub x: usize,
+}
+
+// The counters are global so this is the only test in this binary
+#[test]
+fn test_drop_counters() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut logger = CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .table_rate_limit("limited", 1.0)
+    .schema_policy(SchemaPolicy::RejectMismatched)
+    .error_handler(|_| ())
+    .build();
+    assert_eq!((dropped_records(), error_count()), (0, 0));
+
+    for n in 0..3 {
+        logger.log_to("limited", &TestRecord { s: "a", n });
+    }
+    logger.log_to("test", &TestRecord { s: "a", n: 0 });
+    logger.log_to("test", &OtherRecord { x: 1 });
+    // Rate limited rows are reported on flush
+    assert_eq!((dropped_records(), error_count()), (3, 1));
+    logger.flush();
+    assert_eq!((dropped_records(), error_count()), (3, 2));
+
+    let dropped = BTreeMap::from([("limited".to_string(), 2), ("test".to_string(), 1)]);
+    assert_eq!(logger.stats().dropped, dropped);
+    assert_eq!(logger.stats().tables["limited"].records_written, 1);
+    assert_eq!(logger.stats().tables["test"].records_written, 1);
+}
@@ -0,0 +1,105 @@
+#![cfg(feature = "encryption")]
+
+use std::{num::NonZeroUsize, path::Path, time::Duration};
+
+use csv_logger::{reader::TableReader, CsvLoggerBuilder, RotationPolicy};
+
+#[derive(serde::Serialize)]
+struct SecretRecord {
+    pub n: usize,
+    pub s: String,
+}
+
+fn rows(output_dir: &Path) -> Result<Vec<Vec<String>>, csv::Error> {
+    TableReader::open(output_dir, None, "secret")
+        .unwrap()
+        .records()
+        .map(|record| Ok(record?.iter().map(String::from).collect()))
+        .collect()
+}
+
+#[test]
+fn test_encrypt_rotated() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = [7u8; 32];
+    let mut logger = CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(2).unwrap(),
+            max_epochs: 10,
+        },
+    )
+    .encrypt_rotated(key)
+    .build();
+    let expected = (0..5)
+        .map(|n| vec![n.to_string(), format!("secret {n}")])
+        .collect::<Vec<_>>();
+    for n in 0..5 {
+        let s = format!("secret {n}");
+        logger.log_to("secret", &SecretRecord { n, s });
+    }
+    logger.flush();
+
+    let table_dir = dir.path().join("secret");
+    let start = std::time::Instant::now();
+    while !table_dir.join("1.csv.enc").exists() || table_dir.join("1.csv").exists() {
+        assert!(start.elapsed() < Duration::from_secs(5), "Timed out");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(!table_dir.join("0.csv").exists());
+    let sealed = std::fs::read(table_dir.join("0.csv.enc")).unwrap();
+    assert!(!String::from_utf8_lossy(&sealed).contains("secret"));
+    // The active epoch stays plaintext
+    let active = std::fs::read_to_string(table_dir.join("2.csv")).unwrap();
+    assert_eq!(active, "n,s\n4,secret 4\n");
+    assert_eq!(rows(dir.path()).unwrap(), expected);
+
+    // Elsewhere the reader needs the key
+    let copy = tempfile::tempdir().unwrap();
+    std::fs::create_dir(copy.path().join("secret")).unwrap();
+    for file in ["0.csv.enc", "1.csv.enc", "2.csv"] {
+        std::fs::copy(table_dir.join(file), copy.path().join("secret").join(file)).unwrap();
+    }
+    assert!(rows(copy.path()).is_err());
+    csv_logger::set_decryption_keys(copy.path(), key);
+    assert_eq!(rows(copy.path()).unwrap(), expected);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_compress_and_encrypt_rotated() {
+    use csv_logger::verify::{check_checksums, ChecksumStatus};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut logger = CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(2).unwrap(),
+            max_epochs: 10,
+        },
+    )
+    .checksums(true)
+    .encrypt_rotated([7u8; 32])
+    .compress_before_encryption(csv_logger::RotatedCompression::Gzip)
+    .build();
+    for n in 0..3 {
+        let s = format!("secret {n}");
+        logger.log_to("secret", &SecretRecord { n, s });
+    }
+    logger.flush();
+
+    let table_dir = dir.path().join("secret");
+    let start = std::time::Instant::now();
+    while !table_dir.join("0.csv.gz.enc.sha256").exists() || table_dir.join("0.csv").exists() {
+        assert!(start.elapsed() < Duration::from_secs(5), "Timed out");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(!table_dir.join("0.csv.sha256").exists());
+    let checksums = check_checksums(dir.path(), "secret").unwrap();
+    assert_eq!(checksums[0].path, table_dir.join("0.csv.gz.enc"));
+    assert_eq!(checksums[0].status, ChecksumStatus::Match);
+    let expected = (0..3)
+        .map(|n| vec![n.to_string(), format!("secret {n}")])
+        .collect::<Vec<_>>();
+    assert_eq!(rows(dir.path()).unwrap(), expected);
+}
@@ -0,0 +1,79 @@
+use std::{num::NonZeroUsize, path::Path};
+
+use csv_logger::{min_level, ConfigError, ConfigProblem, CsvLoggerBuilder, Level, RotationPolicy};
+
+macro_rules! record {
+    ($name:ident, $table:literal) => {
+        #[derive(serde::Serialize)]
+        struct $name {
+            pub n: usize,
+        }
+        impl table_log::LogRecord<'_> for $name {
+            fn table_name(&self) -> &'static str {
+                $table
+            }
+        }
+    };
+}
+record!(KeptRecord, "kept");
+record!(SampledRecord, "sampled");
+record!(OtherRecord, "other");
+
+fn builder(dir: &Path) -> CsvLoggerBuilder {
+    CsvLoggerBuilder::new(
+        dir.to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .auto_flush(false)
+}
+
+// The environment and the registered logger are global so this is the only test in this binary
+#[test]
+fn test_env() {
+    let dir = tempfile::tempdir().unwrap();
+
+    std::env::set_var("CSV_LOGGER", "tables");
+    let e = builder(dir.path()).init().unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(
+        e.to_string(),
+        "Invalid `CSV_LOGGER=tables`: Unknown directive `tables`"
+    );
+
+    std::env::set_var("CSV_LOGGER", "sample=sampled:0");
+    let e = builder(dir.path()).init().unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+    let config = e.into_inner().unwrap().downcast::<ConfigError>().unwrap();
+    assert_eq!(
+        config.problems,
+        [ConfigProblem::SampleRate {
+            table: "sampled".to_string(),
+            rate: 0.0,
+        }]
+    );
+
+    std::env::set_var(
+        "CSV_LOGGER",
+        "tables=kept,sampled; sample=kept:0,sampled:0.000001; level=warn",
+    );
+    builder(dir.path())
+        .table_sampling("kept", 1.0)
+        .sample_seed(1)
+        .init()
+        .unwrap();
+    assert_eq!(min_level(), Level::Warn);
+    for n in 0..3 {
+        table_log::log!(&KeptRecord { n });
+        table_log::log!(&SampledRecord { n });
+        table_log::log!(&OtherRecord { n });
+    }
+    table_log::flush();
+
+    let kept = std::fs::read_to_string(dir.path().join("kept").join("0.csv")).unwrap();
+    assert_eq!(kept, "n\n0\n1\n2\n");
+    assert!(!dir.path().join("sampled").exists());
+    assert!(!dir.path().join("other").exists());
+}
@@ -0,0 +1,46 @@
+use std::{num::NonZeroUsize, path::PathBuf};
+
+use csv_logger::{CsvLoggerBuilder, RotationPolicy};
+
+#[derive(serde::Serialize)]
+struct ExpandRecord {
+    pub n: usize,
+}
+impl table_log::LogRecord<'_> for ExpandRecord {
+    fn table_name(&self) -> &'static str {
+        "expand"
+    }
+}
+
+fn builder() -> CsvLoggerBuilder {
+    CsvLoggerBuilder::new(
+        PathBuf::from("${CSV_LOGGER_TEST_STATE}/app"),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .expand_path(true)
+    .auto_flush(false)
+}
+
+// The environment and the registered logger are global so this is the only test in this binary
+#[test]
+fn test_expand_path() {
+    let e = builder().init().unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(
+        e.to_string(),
+        "Cannot expand `${CSV_LOGGER_TEST_STATE}/app`: `$CSV_LOGGER_TEST_STATE` is not set"
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("CSV_LOGGER_TEST_STATE", dir.path());
+    builder().init().unwrap();
+    table_log::log!(&ExpandRecord { n: 0 });
+    table_log::flush();
+
+    let path = dir.path().join("app").join("expand").join("0.csv");
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "n\n0\n");
+    assert!(!PathBuf::from("${CSV_LOGGER_TEST_STATE}").exists());
+}
@@ -0,0 +1,80 @@
+use std::{
+    num::NonZeroUsize,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use csv_logger::{Clock, CsvLoggerBuilder, ManualClock, RotationPolicy};
+
+#[derive(serde::Serialize)]
+struct AuditRecord {
+    pub n: usize,
+}
+impl table_log::LogRecord<'_> for AuditRecord {
+    fn table_name(&self) -> &'static str {
+        "audit"
+    }
+}
+
+#[derive(serde::Serialize)]
+struct MetricRecord {
+    pub n: usize,
+}
+impl table_log::LogRecord<'_> for MetricRecord {
+    fn table_name(&self) -> &'static str {
+        "metric"
+    }
+}
+
+fn read(dir: &Path, table: &str) -> String {
+    std::fs::read_to_string(dir.join(table).join("0.csv")).unwrap()
+}
+
+/// Advances the clock a tick at a time until the flushing thread has done its part
+fn advance_until(clock: &ManualClock, tick: Duration, done: impl Fn() -> bool) {
+    for _ in 0..500 {
+        clock.advance(tick);
+        std::thread::sleep(Duration::from_millis(10));
+        if done() {
+            return;
+        }
+    }
+    panic!("Not flushed at {:?}", clock.now());
+}
+
+// The flusher works on the registered logger so this is the only test in this binary
+#[test]
+fn test_table_flush_interval() {
+    let dir = tempfile::tempdir().unwrap();
+    let clock = ManualClock::new(SystemTime::now());
+    let tick = Duration::from_millis(50);
+    let flusher = CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .flush_interval(Duration::from_secs(60))
+    .table_flush_interval("audit", tick)
+    .clock(clock.clone())
+    .init()
+    .unwrap()
+    .unwrap();
+
+    table_log::log!(&AuditRecord { n: 0 });
+    table_log::log!(&MetricRecord { n: 0 });
+    // Real time alone flushes nothing
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(read(dir.path(), "audit"), "");
+    advance_until(&clock, tick, || read(dir.path(), "audit") == "n\n0\n");
+    assert_eq!(read(dir.path(), "metric"), "");
+
+    table_log::log!(&AuditRecord { n: 1 });
+    advance_until(&clock, tick, || read(dir.path(), "audit") == "n\n0\n1\n");
+    assert_eq!(read(dir.path(), "metric"), "");
+
+    clock.advance(Duration::from_secs(60));
+    advance_until(&clock, tick, || read(dir.path(), "metric") == "n\n0\n");
+    flusher.shutdown();
+}
@@ -0,0 +1,69 @@
+#![cfg(unix)]
+
+use std::{num::NonZeroUsize, path::Path};
+
+use csv_logger::{after_fork_in_child, CsvLoggerBuilder, RotationPolicy};
+use nix::{
+    sys::wait::{waitpid, WaitStatus},
+    unistd::{fork, ForkResult},
+};
+
+#[derive(serde::Serialize)]
+struct ForkRecord<'caller> {
+    pub process: &'caller str,
+    pub n: usize,
+}
+impl<'caller> table_log::LogRecord<'caller> for ForkRecord<'caller> {
+    fn table_name(&self) -> &'static str {
+        "fork"
+    }
+}
+
+fn read(dir: &Path) -> String {
+    std::fs::read_to_string(dir.join("fork").join("0.csv")).unwrap()
+}
+
+// Forking and the registered logger are process-wide so this is the only test in this binary
+#[test]
+fn test_after_fork_in_child() {
+    let dir = tempfile::tempdir().unwrap();
+    CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .auto_flush(false)
+    .init()
+    .unwrap();
+
+    // Buffered in the parent at the time of the fork
+    table_log::log!(&ForkRecord {
+        process: "parent",
+        n: 0
+    });
+    match unsafe { fork() }.unwrap() {
+        ForkResult::Child => {
+            let flusher = after_fork_in_child().unwrap();
+            table_log::log!(&ForkRecord {
+                process: "child",
+                n: 1
+            });
+            table_log::flush();
+            std::process::exit(if flusher.is_none() { 0 } else { 1 });
+        }
+        ForkResult::Parent { child } => {
+            assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Exited(child, 0));
+            table_log::log!(&ForkRecord {
+                process: "parent",
+                n: 2
+            });
+            table_log::flush();
+
+            assert_eq!(read(dir.path()), "process,n\nparent,0\nparent,2\n");
+            let child_dir = dir.path().join(format!("pid-{child}"));
+            assert_eq!(read(&child_dir), "process,n\nchild,1\n");
+        }
+    }
+}
@@ -0,0 +1,115 @@
+#![cfg(feature = "test-util")]
+
+use std::{
+    io,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use csv_logger::{
+    is_paused, storage::MemStorage, CsvLoggerBuilder, FreeSpacePolicy, LoggerEvent,
+    RecordingObserver, RotationPolicy,
+};
+
+#[derive(serde::Serialize)]
+struct SpaceRecord {
+    pub n: usize,
+}
+
+/// Free space as if the filesystem only held the log files of `storage`
+fn space(storage: &MemStorage, capacity: &Arc<AtomicU64>) -> impl Fn(&Path) -> io::Result<u64> {
+    let storage = storage.clone();
+    let capacity = capacity.clone();
+    move |_| {
+        let used = storage
+            .paths()
+            .iter()
+            .filter(|path| path.extension().is_some_and(|e| e == "csv"))
+            .map(|path| storage.read_to_string(path).unwrap().len() as u64)
+            .sum::<u64>();
+        Ok(capacity.load(Ordering::SeqCst) - used)
+    }
+}
+
+fn low_free_space(observer: &RecordingObserver) -> Vec<LoggerEvent> {
+    observer
+        .take_events()
+        .into_iter()
+        .filter(|e| matches!(e, LoggerEvent::LowFreeSpace { .. }))
+        .collect()
+}
+
+#[test]
+fn test_free_space() {
+    let storage = MemStorage::new();
+    let capacity = Arc::new(AtomicU64::new(100));
+    let observer = RecordingObserver::new();
+    let mut logger = CsvLoggerBuilder::new(
+        PathBuf::from("/logs"),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(1).unwrap(),
+            max_epochs: 10,
+        },
+    )
+    .storage(storage.clone())
+    .free_space(FreeSpacePolicy {
+        min_free_bytes: 92,
+        critical_free_bytes: 90,
+        pause: true,
+        check_interval: Duration::ZERO,
+    })
+    .space_provider(space(&storage, &capacity))
+    .observer(observer.clone())
+    .build();
+
+    // Four closed epochs of "n\nX\n"; `a` is three epochs ahead of its first
+    for n in 0..3 {
+        logger.log_to("a", &SpaceRecord { n });
+    }
+    logger.log_to("b", &SpaceRecord { n: 0 });
+    logger.flush();
+    assert_eq!(
+        low_free_space(&observer),
+        [LoggerEvent::LowFreeSpace {
+            available: 92,
+            deleted: vec![
+                PathBuf::from("/logs/a/0.csv"),
+                PathBuf::from("/logs/a/1.csv"),
+            ],
+            paused: false,
+        }]
+    );
+    logger.flush();
+    assert!(low_free_space(&observer).is_empty());
+
+    capacity.store(85, Ordering::SeqCst);
+    logger.flush();
+    assert_eq!(
+        low_free_space(&observer),
+        [LoggerEvent::LowFreeSpace {
+            available: 85,
+            deleted: vec![
+                PathBuf::from("/logs/a/2.csv"),
+                PathBuf::from("/logs/b/0.csv"),
+            ],
+            paused: true,
+        }]
+    );
+    assert!(!storage.exists("/logs/a/2.csv"));
+    assert!(storage.exists("/logs/a/3.csv"));
+    // Dropped by this logger alone, without pausing every logger
+    logger.log_to("b", &SpaceRecord { n: 1 });
+    assert_eq!(logger.stats().dropped.get("b"), Some(&1));
+    assert!(!is_paused());
+
+    capacity.store(100, Ordering::SeqCst);
+    logger.flush();
+    assert!(low_free_space(&observer).is_empty());
+    logger.log_to("b", &SpaceRecord { n: 2 });
+    assert_eq!(logger.stats().dropped.get("b"), Some(&1));
+}
@@ -0,0 +1,43 @@
+#![cfg(all(unix, not(target_os = "macos")))]
+
+use std::num::NonZeroUsize;
+
+use csv_logger::RotationPolicy;
+
+#[derive(serde::Serialize)]
+struct DefaultRecord {
+    pub n: usize,
+}
+impl table_log::LogRecord<'_> for DefaultRecord {
+    fn table_name(&self) -> &'static str {
+        "default"
+    }
+}
+
+// The environment and the registered logger are global so this is the only test in this binary
+#[test]
+fn test_init_default() {
+    let home = tempfile::tempdir().unwrap();
+    std::env::set_var("HOME", home.path());
+    std::env::remove_var("XDG_STATE_HOME");
+    assert_eq!(csv_logger::output_dir(), None);
+
+    csv_logger::init_default(
+        "app",
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    );
+    let output_dir = home.path().join(".local/state/app/logs");
+    assert_eq!(
+        csv_logger::output_dir().as_deref(),
+        Some(output_dir.as_path())
+    );
+    assert!(output_dir.is_dir());
+
+    table_log::log!(&DefaultRecord { n: 0 });
+    table_log::flush();
+    let csv = std::fs::read_to_string(output_dir.join("default").join("0.csv")).unwrap();
+    assert_eq!(csv, "n\n0\n");
+}
@@ -0,0 +1,42 @@
+use std::{num::NonZeroUsize, panic, path::Path};
+
+use csv_logger::{CsvLoggerBuilder, RotationPolicy};
+
+#[derive(serde::Serialize)]
+struct TwiceRecord {
+    pub n: usize,
+}
+impl table_log::LogRecord<'_> for TwiceRecord {
+    fn table_name(&self) -> &'static str {
+        "twice"
+    }
+}
+
+fn builder(dir: &Path) -> CsvLoggerBuilder {
+    CsvLoggerBuilder::new(
+        dir.to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .auto_flush(false)
+}
+
+// The registered logger is global so this is the only test in this binary
+#[test]
+fn test_init_twice() {
+    let first = tempfile::tempdir().unwrap();
+    let second = tempfile::tempdir().unwrap();
+    builder(first.path()).init().unwrap();
+
+    let res = panic::catch_unwind(|| builder(second.path()).flush_on_panic(true).init());
+    assert!(res.is_err());
+    // The second logger was never built, so it did not replace the first
+    assert_eq!(csv_logger::output_dir().unwrap(), first.path());
+
+    table_log::log!(&TwiceRecord { n: 0 });
+    table_log::flush();
+    let path = first.path().join("twice").join("0.csv");
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "n\n0\n");
+}
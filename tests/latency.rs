@@ -0,0 +1,38 @@
+#![cfg(feature = "latency-metrics")]
+
+use std::num::NonZeroUsize;
+
+use csv_logger::{latency_snapshot, CsvLoggerBuilder, RotationPolicy};
+
+#[derive(serde::Serialize)]
+struct TestRecord<'caller> {
+    pub s: &'caller str,
+    pub n: usize,
+}
+
+// The histogram is global so this is the only test in this binary
+#[test]
+fn test_latency_snapshot() {
+    assert_eq!(latency_snapshot().count, 0);
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut logger = CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .build();
+    for n in 0..1000 {
+        logger.log_to("test", &TestRecord { s: "a", n });
+    }
+    logger.flush();
+
+    let snapshot = latency_snapshot();
+    assert_eq!(snapshot.count, 1000);
+    assert!(!snapshot.max.is_zero());
+    assert!(snapshot.p50 <= snapshot.p95);
+    assert!(snapshot.p95 <= snapshot.p99);
+    assert!(snapshot.p99 <= snapshot.max);
+}
@@ -0,0 +1,80 @@
+use std::num::NonZeroUsize;
+
+use csv_logger::{
+    log_leveled, reader::TableReader, set_min_level, set_table_level, CsvLoggerBuilder, Level,
+    Leveled, RotationPolicy,
+};
+
+#[derive(serde::Serialize)]
+struct DebugRecord {
+    pub n: usize,
+}
+impl table_log::LogRecord<'_> for DebugRecord {
+    fn table_name(&self) -> &'static str {
+        "debug"
+    }
+}
+impl Leveled for DebugRecord {
+    fn level(&self) -> Level {
+        Level::Debug
+    }
+}
+
+#[derive(serde::Serialize)]
+struct InfoRecord {
+    pub n: usize,
+}
+impl table_log::LogRecord<'_> for InfoRecord {
+    fn table_name(&self) -> &'static str {
+        "info"
+    }
+}
+impl Leveled for InfoRecord {}
+
+fn rows(dir: &std::path::Path, table: &str) -> usize {
+    table_log::flush();
+    TableReader::open(dir, None, table)
+        .map(|reader| reader.records().count())
+        .unwrap_or_default()
+}
+
+// Levels are global so this is the only test in this binary
+#[test]
+fn test_levels() {
+    let dir = tempfile::tempdir().unwrap();
+    CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .auto_flush(false)
+    .init()
+    .unwrap();
+
+    log_leveled(&DebugRecord { n: 0 });
+    log_leveled(&InfoRecord { n: 0 });
+    assert_eq!(rows(dir.path(), "debug"), 0);
+    assert_eq!(rows(dir.path(), "info"), 1);
+
+    set_min_level(Level::Debug);
+    log_leveled(&DebugRecord { n: 1 });
+    assert_eq!(rows(dir.path(), "debug"), 1);
+
+    set_min_level(Level::Warn);
+    log_leveled(&DebugRecord { n: 2 });
+    log_leveled(&InfoRecord { n: 1 });
+    assert_eq!(rows(dir.path(), "debug"), 1);
+    assert_eq!(rows(dir.path(), "info"), 1);
+
+    set_table_level("debug", Some(Level::Trace));
+    log_leveled(&DebugRecord { n: 3 });
+    log_leveled(&InfoRecord { n: 2 });
+    assert_eq!(rows(dir.path(), "debug"), 2);
+    assert_eq!(rows(dir.path(), "info"), 1);
+
+    set_table_level("debug", None);
+    log_leveled(&DebugRecord { n: 4 });
+    assert_eq!(rows(dir.path(), "debug"), 2);
+}
@@ -0,0 +1,56 @@
+#![cfg(feature = "test-util")]
+
+use std::{num::NonZeroUsize, path::PathBuf};
+
+use csv_logger::{storage::MemStorage, CsvLoggerBuilder, RotationPolicy};
+
+#[derive(serde::Serialize)]
+struct NetworkRecord {
+    pub n: usize,
+    pub s: &'static str,
+}
+
+fn any_trashed(paths: &[PathBuf]) -> bool {
+    paths.iter().any(|path| path.starts_with("/logs/.trash"))
+}
+
+/// Logs with retention and a schema file, returning the files and their contents
+fn run(network_fs_mode: bool) -> Vec<(PathBuf, String)> {
+    let storage = MemStorage::new();
+    let mut logger = CsvLoggerBuilder::new(
+        PathBuf::from("/logs"),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(2).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .storage(storage.clone())
+    .single_line_fields(true)
+    .network_fs_mode(network_fs_mode)
+    .build();
+    for n in 0..9 {
+        let s = if n % 2 == 0 { "a\nb" } else { "c" };
+        logger.log_to("test", &NetworkRecord { n, s });
+    }
+    // Deleted epochs wait in the trash for the flush
+    assert_eq!(any_trashed(&storage.paths()), network_fs_mode);
+    logger.flush();
+    storage
+        .paths()
+        .into_iter()
+        .map(|path| {
+            let contents = storage.read_to_string(&path).unwrap();
+            (path, contents)
+        })
+        .collect()
+}
+
+#[test]
+fn test_network_fs_mode() {
+    let files = run(true);
+    assert_eq!(files, run(false));
+    let paths = files.into_iter().map(|(path, _)| path).collect::<Vec<_>>();
+    assert!(!any_trashed(&paths));
+    assert!(paths.contains(&PathBuf::from("/logs/test/epoch")));
+    assert!(!paths.contains(&PathBuf::from("/logs/test/0.csv")));
+}
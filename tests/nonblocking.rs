@@ -0,0 +1,65 @@
+#![cfg(feature = "tokio")]
+
+use std::{collections::HashMap, num::NonZeroUsize};
+
+use csv_logger::{dropped_records, nonblocking, Backpressure, CsvLoggerBuilder, RotationPolicy};
+
+#[derive(serde::Serialize)]
+struct TaskRecord {
+    pub task: usize,
+    pub n: usize,
+}
+impl table_log::LogRecord<'_> for TaskRecord {
+    fn table_name(&self) -> &'static str {
+        "task"
+    }
+}
+
+const TASKS: usize = 16;
+const RECORDS: usize = 100;
+
+// The writer is global so this is the only test in this binary
+#[tokio::test]
+async fn test_nonblocking() {
+    let dir = tempfile::tempdir().unwrap();
+    CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(TASKS * RECORDS).unwrap(),
+            max_epochs: 10,
+        },
+    )
+    .init_nonblocking(TASKS * RECORDS, Backpressure::Drop)
+    .unwrap();
+
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|task| {
+            tokio::spawn(async move {
+                for n in 0..RECORDS {
+                    nonblocking::log(&TaskRecord { task, n });
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    nonblocking::flush().await;
+
+    let csv = std::fs::read_to_string(dir.path().join("task").join("0.csv")).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("task,n"));
+    let mut next: HashMap<usize, usize> = HashMap::new();
+    for line in lines {
+        let (task, n) = line.split_once(',').unwrap();
+        let task: usize = task.parse().unwrap();
+        let n: usize = n.parse().unwrap();
+        let expected = next.entry(task).or_default();
+        assert_eq!(n, *expected);
+        *expected += 1;
+    }
+    assert_eq!(next.len(), TASKS);
+    assert!(next.values().all(|&n| n == RECORDS));
+    assert_eq!(dropped_records(), 0);
+}
@@ -0,0 +1,159 @@
+#![cfg(feature = "test-util")]
+
+use std::{num::NonZeroUsize, path::PathBuf};
+
+use csv_logger::{
+    storage::MemStorage, CsvLoggerBuilder, LoggerEvent, RecordingObserver, RotationPolicy,
+    TableFlush,
+};
+
+#[derive(serde::Serialize)]
+struct TestRecord<'caller> {
+    pub s: &'caller str,
+    pub n: usize,
+}
+impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+    fn table_name(&self) -> &'static str {
+        "test"
+    }
+}
+
+#[test]
+fn test_rotation_events() {
+    let observer = RecordingObserver::new();
+    let mut logger = CsvLoggerBuilder::new(
+        PathBuf::from("/logs"),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(2).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .storage(MemStorage::new())
+    .observer(observer.clone())
+    .build();
+
+    logger.log_record(&TestRecord { s: "a", n: 0 });
+    logger.log_record(&TestRecord { s: "b", n: 1 });
+    logger.log_record(&TestRecord { s: "c", n: 2 });
+    logger.log_record(&TestRecord { s: "d", n: 3 });
+    logger.flush();
+    logger.flush();
+
+    let expected = vec![
+        LoggerEvent::TableCreated {
+            table: "test".to_string(),
+            epoch: 0,
+        },
+        LoggerEvent::Rotated {
+            table: "test".to_string(),
+            old_path: PathBuf::from("/logs/test/0.csv"),
+            new_epoch: 1,
+        },
+        LoggerEvent::Rotated {
+            table: "test".to_string(),
+            old_path: PathBuf::from("/logs/test/1.csv"),
+            new_epoch: 2,
+        },
+        LoggerEvent::EpochDeleted {
+            table: "test".to_string(),
+            epoch: 0,
+            path: PathBuf::from("/logs/test/0.csv"),
+        },
+        LoggerEvent::Flushed {
+            tables: vec![TableFlush {
+                table: "test".to_string(),
+                records: 4,
+                bytes: ("s,n\na,0\nb,1\n".len() + "s,n\nc,2\nd,3\n".len()) as u64,
+            }],
+        },
+    ];
+    assert_eq!(observer.take_events(), expected);
+}
+
+#[test]
+fn test_recreated_after_deletion() {
+    let dir = tempfile::tempdir().unwrap();
+    let observer = RecordingObserver::new();
+    let mut logger = CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(10).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .observer(observer.clone())
+    .build();
+    let table_dir = dir.path().join("test");
+
+    logger.log_record(&TestRecord { s: "a", n: 0 });
+    logger.flush();
+    observer.take_events();
+    std::fs::remove_dir_all(&table_dir).unwrap();
+    logger.log_record(&TestRecord { s: "b", n: 1 });
+    logger.flush();
+    let path = table_dir.join("1.csv");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "s,n\nb,1\n");
+    assert_eq!(
+        std::fs::read_to_string(table_dir.join("epoch")).unwrap(),
+        "1"
+    );
+    let recreated = LoggerEvent::TableRecreated {
+        table: "test".to_string(),
+        epoch: 1,
+        path,
+    };
+    assert!(observer.take_events().contains(&recreated));
+
+    // Backs off from a cleaner that keeps deleting
+    std::fs::remove_dir_all(&table_dir).unwrap();
+    logger.log_record(&TestRecord { s: "c", n: 2 });
+    logger.flush();
+    assert!(!table_dir.exists());
+    assert!(!observer
+        .take_events()
+        .iter()
+        .any(|e| matches!(e, LoggerEvent::TableRecreated { .. })));
+}
+
+#[test]
+fn test_recreated_after_wipe() {
+    let dir = tempfile::tempdir().unwrap();
+    let observer = RecordingObserver::new();
+    let mut logger = CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(10).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .observer(observer.clone())
+    .build();
+    let tables = ["a", "b", "c"];
+
+    for table in tables {
+        logger.log_to(table, &TestRecord { s: table, n: 0 });
+    }
+    logger.flush();
+    observer.take_events();
+    for table in tables {
+        std::fs::remove_dir_all(dir.path().join(table)).unwrap();
+    }
+    // Each table backs off on its own, so one recreation does not hold the others back
+    for table in tables {
+        logger.log_to(table, &TestRecord { s: table, n: 1 });
+    }
+    logger.flush();
+    for table in tables {
+        let path = dir.path().join(table).join("1.csv");
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("s,n\n{table},1\n")
+        );
+    }
+    let recreated = observer
+        .take_events()
+        .into_iter()
+        .filter(|e| matches!(e, LoggerEvent::TableRecreated { .. }))
+        .count();
+    assert_eq!(recreated, 3);
+}
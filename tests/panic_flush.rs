@@ -0,0 +1,54 @@
+use std::{
+    num::NonZeroUsize,
+    panic,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use csv_logger::{CsvLoggerBuilder, RotationPolicy};
+
+#[derive(serde::Serialize)]
+struct PanicRecord {
+    pub n: usize,
+}
+impl table_log::LogRecord<'_> for PanicRecord {
+    fn table_name(&self) -> &'static str {
+        "panic"
+    }
+}
+
+// The panic hook and the registered logger are global so this is the only test in this binary
+#[test]
+fn test_flush_on_panic() {
+    let previous_called = Arc::new(AtomicBool::new(false));
+    panic::set_hook(Box::new({
+        let previous_called = previous_called.clone();
+        move |_| previous_called.store(true, Ordering::SeqCst)
+    }));
+
+    let dir = tempfile::tempdir().unwrap();
+    CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .auto_flush(false)
+    .flush_on_panic(true)
+    .init()
+    .unwrap();
+
+    let path = dir.path().join("panic").join("0.csv");
+    for n in 0..3 {
+        table_log::log!(&PanicRecord { n });
+    }
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+    let res = std::thread::spawn(|| panic!("unrelated")).join();
+    assert!(res.is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "n\n0\n1\n2\n");
+    assert!(previous_called.load(Ordering::SeqCst));
+}
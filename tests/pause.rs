@@ -0,0 +1,53 @@
+use std::{num::NonZeroUsize, path::Path};
+
+use csv_logger::{dropped_while_paused, pause, resume, CsvLoggerBuilder, RotationPolicy};
+
+#[derive(serde::Serialize)]
+struct PauseRecord {
+    pub n: usize,
+}
+impl table_log::LogRecord<'_> for PauseRecord {
+    fn table_name(&self) -> &'static str {
+        "pause"
+    }
+}
+
+fn path(dir: &Path, epoch: usize) -> std::path::PathBuf {
+    dir.join("pause").join(format!("{epoch}.csv"))
+}
+
+// Pausing is global so this is the only test in this binary
+#[test]
+fn test_pause() {
+    let dir = tempfile::tempdir().unwrap();
+    CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(3).unwrap(),
+            max_epochs: 4,
+        },
+    )
+    .auto_flush(false)
+    .init()
+    .unwrap();
+
+    table_log::log!(&PauseRecord { n: 0 });
+    pause();
+    for n in 1..5 {
+        table_log::log!(&PauseRecord { n });
+    }
+    table_log::flush();
+    assert_eq!(dropped_while_paused(), 4);
+    // Nothing was written while paused so the table did not rotate
+    assert!(!path(dir.path(), 1).exists());
+
+    resume();
+    table_log::log!(&PauseRecord { n: 5 });
+    table_log::flush();
+    // Same table and epoch as before the pause
+    assert_eq!(
+        std::fs::read_to_string(path(dir.path(), 0)).unwrap(),
+        "n\n0\n5\n"
+    );
+    assert_eq!(dropped_while_paused(), 4);
+}
@@ -0,0 +1,58 @@
+use std::num::NonZeroUsize;
+
+use csv_logger::{last_error, recent_errors, CsvLoggerBuilder, RotationPolicy, SchemaPolicy};
+
+#[derive(serde::Serialize)]
+struct TestRecord {
+    pub n: usize,
+}
+
+#[derive(serde::Serialize)]
+struct OtherRecord {
+    pub x: usize,
+}
+
+// Recent errors are global so this is the only test in this binary
+#[test]
+fn test_recent_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut logger = CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .schema_policy(SchemaPolicy::RejectMismatched)
+    .error_handler(|_| ())
+    .build();
+    assert!(last_error().is_none());
+
+    logger.log_to("../escape", &TestRecord { n: 0 });
+    logger.log_to("test", &TestRecord { n: 1 });
+    logger.log_to("test", &OtherRecord { x: 2 });
+
+    let errors = recent_errors();
+    let kinds = errors.iter().map(|e| e.kind).collect::<Vec<_>>();
+    assert_eq!(kinds, ["invalid_table_name", "schema_mismatch"]);
+    assert!(errors[0].message.contains("`../escape`"));
+    assert!(errors[0].at <= errors[1].at);
+    assert_eq!(last_error().as_ref(), errors.last());
+
+    // Recorded even if the handler panics
+    let mut logger = CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(100).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .error_handler(|e| panic!("{e}"))
+    .build();
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        logger.log_to("", &TestRecord { n: 3 });
+    }));
+    assert!(res.is_err());
+    assert_eq!(recent_errors().len(), 3);
+    assert_eq!(last_error().unwrap().kind, "invalid_table_name");
+}
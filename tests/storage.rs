@@ -0,0 +1,329 @@
+#![cfg(feature = "test-util")]
+
+use std::{
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use csv_logger::{
+    storage::MemStorage, ConflictPolicy, CsvLogger, CsvLoggerBuilder, Layout, ResumePolicy,
+    RotationPolicy,
+};
+
+#[derive(serde::Serialize)]
+struct TestRecord<'caller> {
+    pub s: &'caller str,
+    pub n: usize,
+}
+impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+    fn table_name(&self) -> &'static str {
+        "test"
+    }
+}
+
+fn builder(storage: &MemStorage) -> CsvLoggerBuilder {
+    CsvLoggerBuilder::new(
+        PathBuf::from("/logs"),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(2).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .storage(storage.clone())
+}
+
+fn epoch_path(epoch: usize) -> String {
+    format!("/logs/test/{epoch}.csv")
+}
+
+#[test]
+fn test_resume() {
+    let storage = MemStorage::new();
+    let mut logger = builder(&storage).sequence(true).build();
+    logger.log_record(&TestRecord { s: "a", n: 0 });
+    drop(logger);
+
+    let mut logger = builder(&storage)
+        .resume_policy(ResumePolicy::AppendToLast)
+        .sequence(true)
+        .build();
+    logger.log_record(&TestRecord { s: "b", n: 1 });
+    logger.flush();
+    // The sequence continues from the rows found in the epoch
+    assert_eq!(
+        storage.read_to_string(epoch_path(0)).unwrap(),
+        "seq,s,n\n0,a,0\n1,b,1\n"
+    );
+}
+
+#[test]
+fn test_lock() {
+    let storage = MemStorage::new();
+    let errors = Arc::new(Mutex::new(vec![]));
+    let mut first = builder(&storage)
+        .conflict_policy(ConflictPolicy::Error)
+        .build();
+    first.log_record(&TestRecord { s: "a", n: 0 });
+    let mut second = builder(&storage)
+        .conflict_policy(ConflictPolicy::Error)
+        .error_handler({
+            let errors = errors.clone();
+            move |e| errors.lock().unwrap().push(e.kind())
+        })
+        .build();
+    second.log_record(&TestRecord { s: "b", n: 1 });
+    assert_eq!(*errors.lock().unwrap(), ["table_locked"]);
+
+    drop(first);
+    second.log_record(&TestRecord { s: "c", n: 2 });
+    second.flush();
+    assert_eq!(storage.read_to_string(epoch_path(1)).unwrap(), "s,n\nc,2\n");
+}
+
+#[test]
+fn test_cap() {
+    let storage = MemStorage::new();
+    let mut logger = builder(&storage).table_cap("test", 1).build();
+    logger.log_record(&TestRecord { s: "a", n: 0 });
+    drop(logger);
+    assert_eq!(storage.read_to_string("/logs/test/count").unwrap(), "1");
+
+    // The count is read back after the restart
+    let mut logger = builder(&storage).table_cap("test", 1).build();
+    logger.log_record(&TestRecord { s: "b", n: 1 });
+    logger.flush();
+    assert_eq!(
+        storage.read_to_string(epoch_path(1)).unwrap(),
+        "s,n\n[cap of 1 records reached],\n"
+    );
+    assert_eq!(logger.stats().dropped["test"], 1);
+}
+
+#[test]
+fn test_checksums() {
+    let storage = MemStorage::new();
+    let mut logger = builder(&storage).checksums(true).build();
+    for (n, s) in ["a", "b", "c"].into_iter().enumerate() {
+        logger.log_record(&TestRecord { s, n });
+    }
+    let start = Instant::now();
+    let sidecar = loop {
+        if let Some(sidecar) = storage.read_to_string("/logs/test/0.csv.sha256") {
+            break sidecar;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "Timed out");
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let (digest, file_name) = sidecar.split_once("  ").unwrap();
+    assert_eq!(digest.len(), 64);
+    assert_eq!(file_name, "0.csv\n");
+}
+
+#[test]
+fn test_flat_rotation() {
+    let storage = MemStorage::new();
+    let mut logger = CsvLoggerBuilder::new(
+        PathBuf::from("/flat"),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(2).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .layout(Layout::Flat)
+    .storage(storage.clone())
+    .build();
+    for (n, s) in ["a", "b", "c", "d"].into_iter().enumerate() {
+        logger.log_record(&TestRecord { s, n });
+    }
+
+    assert_eq!(
+        storage.read_to_string("/flat/test.1.csv").unwrap(),
+        "s,n\nc,2\nd,3\n"
+    );
+    assert_eq!(storage.read_to_string("/flat/test.2.csv").unwrap(), "");
+    assert_eq!(storage.read_to_string("/flat/test.epoch").unwrap(), "2");
+    assert_eq!(
+        storage.paths(),
+        [
+            PathBuf::from("/flat/layout"),
+            PathBuf::from("/flat/test.1.csv"),
+            PathBuf::from("/flat/test.2.csv"),
+            PathBuf::from("/flat/test.epoch"),
+            PathBuf::from("/flat/test.manifest.json"),
+        ]
+    );
+}
+
+#[test]
+fn test_durable_rotation() {
+    let storage = MemStorage::new();
+    let mut logger = CsvLoggerBuilder::new(
+        PathBuf::from("/logs"),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(2).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .storage(storage.clone())
+    .durable_rotation(true)
+    .build();
+
+    logger.log_record(&TestRecord { s: "a", n: 0 });
+    logger.flush();
+    assert!(storage.synced().is_empty());
+
+    logger.log_record(&TestRecord { s: "b", n: 1 });
+    assert_eq!(
+        storage.synced(),
+        [
+            PathBuf::from(epoch_path(0)),
+            PathBuf::from("/logs/test"),
+            PathBuf::from("/logs/test"),
+        ]
+    );
+}
+
+fn spooling_logger(storage: &MemStorage, max_bytes: u64) -> CsvLogger {
+    CsvLoggerBuilder::new(
+        PathBuf::from("/logs"),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(10).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .storage(storage.clone())
+    .spool_dir(Some(PathBuf::from("/spool")))
+    .spool_max_bytes(max_bytes)
+    .error_handler(|_| ())
+    .build()
+}
+
+#[test]
+fn test_spool() {
+    let storage = MemStorage::new();
+    let errors = Arc::new(Mutex::new(vec![]));
+    let mut logger = CsvLoggerBuilder::new(
+        PathBuf::from("/logs"),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(10).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .storage(storage.clone())
+    .spool_dir(Some(PathBuf::from("/spool")))
+    .error_handler({
+        let errors = errors.clone();
+        move |e| errors.lock().unwrap().push(e.kind())
+    })
+    .build();
+
+    logger.log_record(&TestRecord { s: "a", n: 0 });
+    logger.log_record(&TestRecord { s: "b", n: 1 });
+    logger.flush();
+
+    // The rows handed to the file before it failed are held back
+    storage.set_unavailable(Some(PathBuf::from("/logs")));
+    logger.log_record(&TestRecord { s: "c", n: 2 });
+    logger.log_record(&TestRecord { s: "d", n: 3 });
+    logger.flush();
+    assert_eq!(*errors.lock().unwrap(), ["primary_unavailable"]);
+
+    logger.log_record(&TestRecord { s: "e", n: 4 });
+    logger.log_record(&TestRecord { s: "f", n: 5 });
+    logger.flush();
+    assert!(storage.exists("/spool/test.csv"));
+    assert_eq!(
+        storage.read_to_string(epoch_path(0)).unwrap(),
+        "s,n\na,0\nb,1\n"
+    );
+
+    storage.set_unavailable(None);
+    logger.log_record(&TestRecord { s: "g", n: 6 });
+    logger.flush();
+    assert_eq!(
+        storage.read_to_string(epoch_path(0)).unwrap(),
+        "s,n\na,0\nb,1\nc,2\nd,3\ne,4\nf,5\ng,6\n"
+    );
+    assert!(!storage.exists("/spool/test.csv"));
+    assert_eq!(errors.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_spool_drops_oldest() {
+    let storage = MemStorage::new();
+    // Three spooled rows of `test,2,s,n,x,N\n` exceed the cap
+    let mut logger = spooling_logger(&storage, 40);
+
+    logger.log_record(&TestRecord { s: "a", n: 0 });
+    logger.flush();
+    storage.set_unavailable(Some(PathBuf::from("/logs")));
+    logger.log_record(&TestRecord { s: "b", n: 1 });
+    logger.flush();
+    for (s, n) in [("c", 2), ("d", 3), ("e", 4), ("f", 5)] {
+        logger.log_record(&TestRecord { s, n });
+    }
+    storage.set_unavailable(None);
+    logger.flush();
+
+    assert_eq!(
+        storage.read_to_string(epoch_path(0)).unwrap(),
+        "s,n\na,0\nb,1\ne,4\nf,5\n"
+    );
+    assert_eq!(logger.stats().dropped["test"], 2);
+}
+
+#[test]
+fn test_spool_left_by_earlier_run() {
+    let storage = MemStorage::new();
+    let mut logger = spooling_logger(&storage, u64::MAX);
+    logger.log_record(&TestRecord { s: "a", n: 0 });
+    logger.flush();
+    storage.set_unavailable(Some(PathBuf::from("/logs")));
+    logger.log_record(&TestRecord { s: "b", n: 1 });
+    logger.flush();
+    logger.log_record(&TestRecord { s: "c", n: 2 });
+    drop(logger);
+
+    storage.set_unavailable(None);
+    // `b` was still held back from the file when the logger was dropped, so it went to the spool
+    let mut logger = spooling_logger(&storage, u64::MAX);
+    logger.log_record(&TestRecord { s: "d", n: 3 });
+    logger.flush();
+    assert_eq!(
+        storage.read_to_string(epoch_path(1)).unwrap(),
+        "s,n\nb,1\nc,2\nd,3\n"
+    );
+}
+
+#[test]
+fn test_open_during_outage() {
+    let storage = MemStorage::new();
+    let errors = Arc::new(Mutex::new(vec![]));
+    let mut logger = CsvLoggerBuilder::new(
+        PathBuf::from("/logs"),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(10).unwrap(),
+            max_epochs: 2,
+        },
+    )
+    .storage(storage.clone())
+    .spool_dir(Some(PathBuf::from("/spool")))
+    .error_handler({
+        let errors = errors.clone();
+        move |e| errors.lock().unwrap().push(e.kind())
+    })
+    .build();
+
+    storage.set_unavailable(Some(PathBuf::from("/logs")));
+    logger.log_record(&TestRecord { s: "a", n: 0 });
+    logger.flush();
+    assert_eq!(*errors.lock().unwrap(), ["table_file"]);
+    assert_eq!(logger.stats().dropped["test"], 1);
+
+    storage.set_unavailable(None);
+    logger.log_record(&TestRecord { s: "b", n: 1 });
+    logger.flush();
+    assert_eq!(storage.read_to_string(epoch_path(0)).unwrap(), "s,n\nb,1\n");
+}
@@ -0,0 +1,46 @@
+use std::{num::NonZeroUsize, path::Path};
+
+use csv_logger::{set_table_enabled, CsvLoggerBuilder, RotationPolicy};
+
+#[derive(serde::Serialize)]
+struct TestRecord<'caller> {
+    pub s: &'caller str,
+    pub n: usize,
+}
+impl<'caller> table_log::LogRecord<'caller> for TestRecord<'caller> {
+    fn table_name(&self) -> &'static str {
+        "test"
+    }
+}
+
+fn read(dir: &Path, epoch: usize) -> String {
+    std::fs::read_to_string(dir.join("test").join(format!("{epoch}.csv"))).unwrap()
+}
+
+// Enabled tables are global so this is the only test in this binary
+#[test]
+fn test_set_table_enabled() {
+    let dir = tempfile::tempdir().unwrap();
+    CsvLoggerBuilder::new(
+        dir.path().to_owned(),
+        RotationPolicy {
+            max_records: NonZeroUsize::new(10).unwrap(),
+            max_epochs: 10,
+        },
+    )
+    .auto_flush(false)
+    .init()
+    .unwrap();
+
+    table_log::log!(&TestRecord { s: "a", n: 0 });
+    set_table_enabled("test", false);
+    // Flushed and closed without an explicit flush
+    assert_eq!(read(dir.path(), 0), "s,n\na,0\n");
+
+    table_log::log!(&TestRecord { s: "b", n: 1 });
+    set_table_enabled("test", true);
+    table_log::log!(&TestRecord { s: "c", n: 2 });
+    table_log::flush();
+    assert_eq!(read(dir.path(), 0), "s,n\na,0\n");
+    assert_eq!(read(dir.path(), 1), "s,n\nc,2\n");
+}